@@ -0,0 +1,172 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nodeset::{Node, NodeSet};
+
+/// 3M nodes spread over 1000 racks of 3000 nodes
+fn large_nodeset() -> NodeSet {
+    (1..=1000)
+        .map(|rack| format!("rack{rack}-node[1-3000]"))
+        .collect::<Vec<_>>()
+        .join(",")
+        .parse()
+        .unwrap()
+}
+
+fn clone(c: &mut Criterion) {
+    let base = large_nodeset();
+    let removed: NodeSet = "rack500-node[1-10]".parse().unwrap();
+
+    let mut group = c.benchmark_group("clone");
+    group.bench_function("clone 3M", |b| b.iter(|| black_box(&base).clone()));
+    group.bench_function("clone + remove 10 from 3M", |b| {
+        b.iter(|| black_box(&base).clone().difference(black_box(&removed)))
+    });
+    group.finish();
+}
+
+fn contains(c: &mut Criterion) {
+    let sets: Vec<NodeSet> = (0..100)
+        .map(|i| {
+            format!("rack{i}-node[1-3000/{}]", i % 7 + 1)
+                .parse()
+                .unwrap()
+        })
+        .collect();
+    let node = "rack50-node1471";
+    let parsed: Node = node.parse().unwrap();
+
+    let mut group = c.benchmark_group("contains");
+    group.bench_function("contains(&str) x100", |b| {
+        b.iter(|| {
+            sets.iter()
+                .filter(|ns| ns.contains(black_box(node)).unwrap())
+                .count()
+        })
+    });
+    group.bench_function("contains_node(&Node) x100", |b| {
+        b.iter(|| {
+            sets.iter()
+                .filter(|ns| ns.contains_node(black_box(&parsed)))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn intersection(c: &mut Criterion) {
+    let up: NodeSet = "node[1-100000/3],gpu[1-1000]".parse().unwrap();
+    let wanted: NodeSet = "node[1-100000/2],gpu[500-600]".parse().unwrap();
+    let mut out = NodeSet::new();
+
+    let mut group = c.benchmark_group("intersection");
+    group.bench_function("intersection", |b| {
+        b.iter(|| black_box(&up).intersection(black_box(&wanted)))
+    });
+    group.bench_function("intersection_into", |b| {
+        b.iter(|| black_box(&up).intersection_into(black_box(&wanted), &mut out))
+    });
+    group.finish();
+}
+
+/// Nodeset with `count` distinct patterns starting from the `start`th one
+fn many_patterns(start: usize, count: usize) -> NodeSet {
+    (start..start + count)
+        .map(|mut i| {
+            // Digits are parsed as ids so use letters to generate distinct names
+            let mut name = String::new();
+            for _ in 0..4 {
+                name.push((b'a' + (i % 26) as u8) as char);
+                i /= 26;
+            }
+            format!("{name}-node[1-4]")
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+        .parse()
+        .unwrap()
+}
+
+fn patterns(c: &mut Criterion) {
+    let a = many_patterns(0, 50_000);
+    let b = many_patterns(25_000, 50_000);
+
+    let mut group = c.benchmark_group("patterns");
+    group.bench_function("display 50k patterns", |bench| {
+        bench.iter(|| black_box(&a).to_string())
+    });
+    group.bench_function("union 50k patterns", |bench| {
+        bench.iter(|| black_box(&a).union(black_box(&b)))
+    });
+    group.finish();
+}
+
+/// Every other id of `node[0-100000]`, which folds into 50k single-id ranges
+fn fragmented(c: &mut Criterion) {
+    let expanded = (0..=100_000)
+        .step_by(2)
+        .map(|i| format!("node{i}"))
+        .collect::<Vec<_>>()
+        .join(",");
+    let ns: NodeSet = expanded.parse().unwrap();
+
+    let mut group = c.benchmark_group("fragmented");
+    group.bench_function("display node[0-100000/2]", |b| {
+        b.iter(|| black_box(&ns).to_string())
+    });
+    group.bench_function("fold and display 50k nodes", |b| {
+        b.iter(|| black_box(&expanded).parse::<NodeSet>().unwrap().to_string())
+    });
+    group.finish();
+}
+
+fn expand(c: &mut Criterion) {
+    let ns: NodeSet = "node[1-1000000]".parse().unwrap();
+
+    let mut group = c.benchmark_group("expand");
+    group.bench_function("collect 1M nodes", |b| {
+        b.iter(|| black_box(&ns).iter().collect::<Vec<_>>())
+    });
+    group.bench_function("for_each_node 1M nodes", |b| {
+        b.iter(|| {
+            let mut len = 0;
+            black_box(&ns).for_each_node(|node| len += node.len());
+            len
+        })
+    });
+    group.finish();
+}
+
+/// 100 small sets spread over 10 prefixes
+fn union_all(c: &mut Criterion) {
+    let sets: Vec<NodeSet> = (0..100)
+        .map(|i| {
+            format!("rack{}-node[{}-{}]", i % 10, i * 10, i * 10 + 15)
+                .parse()
+                .unwrap()
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("union_all");
+    group.bench_function("union 100 sets one by one", |b| {
+        b.iter(|| {
+            black_box(&sets)
+                .iter()
+                .fold(NodeSet::new(), |acc, set| acc.union(set))
+        })
+    });
+    group.bench_function("union_all 100 sets", |b| {
+        b.iter(|| NodeSet::union_all(black_box(&sets)))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    clone,
+    contains,
+    expand,
+    intersection,
+    patterns,
+    fragmented,
+    union_all
+);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+//! Prints the groups of a source and their members as an HTML table
+//!
+//! Groups are read from the default clustershell configuration files. The
+//! source to use can be given as the first argument, otherwise the default
+//! source is used.
+//!
+//! ```sh
+//! cargo run --example groups_html -- [SOURCE] > groups.html
+//! ```
+
+use nodeset::{IdRangeList, Resolver};
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let resolver = Resolver::from_config()?;
+    let source = std::env::args().nth(1);
+
+    println!("<table>");
+    println!("  <tr><th>Group</th><th>Nodes</th><th>Members</th></tr>");
+    for group in resolver.groups_for_source::<IdRangeList>(source.as_deref()) {
+        let (group, members) = match group {
+            Ok(group) => group,
+            Err(e) => {
+                eprintln!("Skipping group: {e}");
+                continue;
+            }
+        };
+
+        println!(
+            "  <tr><td>@{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&group),
+            members.len(),
+            escape(&members.to_string())
+        );
+    }
+    println!("</table>");
+
+    Ok(())
+}
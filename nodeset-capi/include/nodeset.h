@@ -4,6 +4,30 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+/**
+ * The buffer is too small to hold the result and its terminating NUL byte
+ */
+#define NS_ERROR_BUFFER_TOO_SMALL -2
+
+/**
+ * An unexpected error occurred in the library
+ */
+#define NS_ERROR_INTERNAL -4
+
+/**
+ * A string contains a NUL byte or is not valid UTF-8
+ */
+#define NS_ERROR_INVALID_STRING -3
+
+/**
+ * A required pointer argument is NULL
+ */
+#define NS_ERROR_NULL_POINTER -1
+
+/**
+ * The operation succeeded
+ */
+#define NS_SUCCESS 0
 
 /**
  * An iterator over nodes in a nodeset
@@ -86,6 +110,30 @@ NodeSet *ns_difference(NodeSet *nodeset1, NodeSet *nodeset2);
  */
 char *ns_fold(NodeSet *nodeset, char **error);
 
+/**
+ * Write the folded representation of a nodeset to a buffer
+ *
+ * `ns_fold_buf()` writes the folded representation of `nodeset` followed by
+ * a NUL byte to `buf`, which holds `size` bytes. `*len` is set to the length
+ * of the folded representation, without the NUL byte, unless NULL was
+ * passed, so that a buffer of the right size can be allocated after a first
+ * call with a `size` of 0. The buffer is left untouched in case of error.
+ *
+ * # Safety
+ *
+ * `nodeset` must be a valid nodeset returned by this library and `buf` must
+ * point to at least `size` writable bytes. `buf` may be NULL if `size` is 0.
+ *
+ * # Return value
+ *
+ * Returns `NS_SUCCESS` on success, `NS_ERROR_BUFFER_TOO_SMALL` if `size` is
+ * not larger than the folded representation, `NS_ERROR_NULL_POINTER` if
+ * `nodeset` is NULL or `buf` is NULL with a non-zero `size`, and
+ * `NS_ERROR_INVALID_STRING` if a node name contains a NUL byte.
+ *
+ */
+int ns_fold_buf(const NodeSet *nodeset, char *buf, size_t size, size_t *len);
+
 /**
  * Free an error string returned by any function in this library
  *
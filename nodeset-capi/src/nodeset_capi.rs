@@ -3,9 +3,25 @@ use nodeset::{IdRangeList, NodeSetIter as RustNodeSetIter, Resolver};
 use std::ffi::{c_int, CStr, CString};
 
 use std::os::raw::c_char;
+use std::panic::catch_unwind;
 
 const DEFAULT_ERROR: &str = "Unknown error";
 
+/// The operation succeeded
+pub const NS_SUCCESS: c_int = 0;
+
+/// A required pointer argument is NULL
+pub const NS_ERROR_NULL_POINTER: c_int = -1;
+
+/// The buffer is too small to hold the result and its terminating NUL byte
+pub const NS_ERROR_BUFFER_TOO_SMALL: c_int = -2;
+
+/// A string contains a NUL byte or is not valid UTF-8
+pub const NS_ERROR_INVALID_STRING: c_int = -3;
+
+/// An unexpected error occurred in the library
+pub const NS_ERROR_INTERNAL: c_int = -4;
+
 type NodeSet = nodeset::NodeSet<IdRangeList>;
 
 #[no_mangle]
@@ -28,7 +44,7 @@ type NodeSet = nodeset::NodeSet<IdRangeList>;
 ///
 pub unsafe extern "C" fn init_default_resolver(error: *mut *mut c_char) -> c_int {
     let res = (|| -> Result<()> {
-        let _ = Resolver::set_global(Resolver::from_config()?);
+        Resolver::set_global(Resolver::from_config()?);
         Ok(())
     })();
 
@@ -65,6 +81,7 @@ pub unsafe extern "C" fn init_default_resolver(error: *mut *mut c_char) -> c_int
 ///
 pub unsafe extern "C" fn ns_parse(nodeset: *const c_char, error: *mut *mut c_char) -> *mut NodeSet {
     let res = (|| -> Result<*mut NodeSet> {
+        anyhow::ensure!(!nodeset.is_null(), "NULL nodeset");
         let nodeset = unsafe { CStr::from_ptr(nodeset) }.to_str()?;
         let nodeset: NodeSet = nodeset.parse()?;
         Ok(Box::into_raw(Box::new(nodeset)))
@@ -168,6 +185,64 @@ pub unsafe extern "C" fn ns_fold(nodeset: *mut NodeSet, error: *mut *mut c_char)
     }
 }
 
+#[no_mangle]
+/// Write the folded representation of a nodeset to a buffer
+///
+/// `ns_fold_buf()` writes the folded representation of `nodeset` followed by
+/// a NUL byte to `buf`, which holds `size` bytes. `*len` is set to the length
+/// of the folded representation, without the NUL byte, unless NULL was
+/// passed, so that a buffer of the right size can be allocated after a first
+/// call with a `size` of 0. The buffer is left untouched in case of error.
+///
+/// # Safety
+///
+/// `nodeset` must be a valid nodeset returned by this library and `buf` must
+/// point to at least `size` writable bytes. `buf` may be NULL if `size` is 0.
+///
+/// # Return value
+///
+/// Returns `NS_SUCCESS` on success, `NS_ERROR_BUFFER_TOO_SMALL` if `size` is
+/// not larger than the folded representation, `NS_ERROR_NULL_POINTER` if
+/// `nodeset` is NULL or `buf` is NULL with a non-zero `size`, and
+/// `NS_ERROR_INVALID_STRING` if a node name contains a NUL byte.
+///
+pub unsafe extern "C" fn ns_fold_buf(
+    nodeset: *const NodeSet,
+    buf: *mut c_char,
+    size: usize,
+    len: *mut usize,
+) -> c_int {
+    let Some(nodeset) = (unsafe { nodeset.as_ref() }) else {
+        return NS_ERROR_NULL_POINTER;
+    };
+    if buf.is_null() && size > 0 {
+        return NS_ERROR_NULL_POINTER;
+    }
+
+    let Ok(folded) = catch_unwind(|| nodeset.to_string()) else {
+        return NS_ERROR_INTERNAL;
+    };
+
+    if let Some(len) = unsafe { len.as_mut() } {
+        *len = folded.len();
+    }
+
+    if folded.as_bytes().contains(&0) {
+        return NS_ERROR_INVALID_STRING;
+    }
+
+    if folded.len() >= size {
+        return NS_ERROR_BUFFER_TOO_SMALL;
+    }
+
+    unsafe {
+        std::ptr::copy_nonoverlapping(folded.as_ptr(), buf as *mut u8, folded.len());
+        *buf.add(folded.len()) = 0;
+    }
+
+    NS_SUCCESS
+}
+
 #[no_mangle]
 /// Count the number of nodes in a nodeset
 ///
@@ -370,7 +445,6 @@ pub unsafe extern "C" fn ns_iter_next(
 }
 
 #[no_mangle]
-
 /// Returns whether the iterator has encountered an error
 ///
 /// `ns_iter_status()` returns -1 if the iterator has failed, 0 otherwise.
@@ -621,6 +695,64 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_ns_fold_buf() {
+        for input in [
+            "n[1-5]",
+            "r[1-2]n[01-10],\"rack a\"[1-2]",
+            "n[1-2],x1&x[1-3]",
+        ] {
+            let nodeset = parse_nodeset(input);
+
+            let mut len = 0;
+            let r = unsafe { ns_fold_buf(nodeset, std::ptr::null_mut(), 0, &mut len) };
+            assert_eq!(r, NS_ERROR_BUFFER_TOO_SMALL);
+
+            let mut buf = vec![b'x' as c_char; len];
+            let r = unsafe { ns_fold_buf(nodeset, buf.as_mut_ptr(), buf.len(), &mut len) };
+            assert_eq!(r, NS_ERROR_BUFFER_TOO_SMALL);
+            assert!(buf.iter().all(|&c| c == b'x' as c_char));
+
+            buf.push(b'x' as c_char);
+            let r = unsafe { ns_fold_buf(nodeset, buf.as_mut_ptr(), buf.len(), &mut len) };
+            assert_eq!(r, NS_SUCCESS);
+            let folded = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str().unwrap();
+            assert_eq!(folded.len(), len);
+
+            // The folded representation parses back to the same nodeset
+            let reparsed = parse_nodeset(folded);
+            assert_eq!(unsafe { ns_count(reparsed) }, unsafe { ns_count(nodeset) });
+            compare_nodeset(reparsed, folded);
+
+            unsafe {
+                ns_free_nodeset(reparsed);
+                ns_free_nodeset(nodeset);
+            }
+        }
+
+        let mut buf = [0 as c_char; 16];
+        let r = unsafe {
+            ns_fold_buf(
+                std::ptr::null(),
+                buf.as_mut_ptr(),
+                buf.len(),
+                std::ptr::null_mut(),
+            )
+        };
+        assert_eq!(r, NS_ERROR_NULL_POINTER);
+
+        let nodeset = parse_nodeset("n1");
+        let r = unsafe { ns_fold_buf(nodeset, std::ptr::null_mut(), 16, std::ptr::null_mut()) };
+        assert_eq!(r, NS_ERROR_NULL_POINTER);
+        unsafe { ns_free_nodeset(nodeset) };
+
+        // NUL bytes can not be represented in C strings
+        let nodeset = Box::into_raw(Box::new("\"a\0b\"".parse::<NodeSet>().unwrap()));
+        let r = unsafe { ns_fold_buf(nodeset, buf.as_mut_ptr(), buf.len(), std::ptr::null_mut()) };
+        assert_eq!(r, NS_ERROR_INVALID_STRING);
+        unsafe { ns_free_nodeset(nodeset) };
+    }
+
     #[test]
     fn test_parse_error() {
         let c_input = CString::new("n[1-5").unwrap();
@@ -636,6 +768,12 @@ mod tests {
         );
 
         unsafe { ns_free_error(error) };
+
+        let mut error: *mut c_char = std::ptr::null_mut();
+        let nodeset = unsafe { ns_parse(std::ptr::null(), &mut error) };
+        assert!(nodeset.is_null());
+        assert!(!error.is_null());
+        unsafe { ns_free_error(error) };
     }
 
     fn compare_nodeset(nodeset: *mut NodeSet, expected: &str) {
@@ -132,6 +132,44 @@ bool test_ns_fold()
     return true;
 }
 
+bool test_ns_fold_buf()
+{
+    NodeSet *nodeset = ns_parse("n[1-5],x1", NULL);
+    char small[4];
+    char *buf = NULL;
+    size_t len = 0;
+
+    if (ns_fold_buf(nodeset, small, sizeof(small), &len) != NS_ERROR_BUFFER_TOO_SMALL)
+    {
+        fprintf(stderr, "Error: ns_fold_buf did not detect a small buffer\n");
+        return false;
+    }
+
+    buf = malloc(len + 1);
+    if (ns_fold_buf(nodeset, buf, len + 1, NULL) != NS_SUCCESS)
+    {
+        fprintf(stderr, "Error: ns_fold_buf failed\n");
+        return false;
+    }
+
+    if (strcmp(buf, "n[1-5],x1") != 0)
+    {
+        fprintf(stderr, "Error: ns_fold_buf returned unexpected string\n");
+        return false;
+    }
+
+    if (ns_fold_buf(NULL, buf, len + 1, NULL) != NS_ERROR_NULL_POINTER)
+    {
+        fprintf(stderr, "Error: ns_fold_buf accepted a NULL nodeset\n");
+        return false;
+    }
+
+    free(buf);
+    ns_free_nodeset(nodeset);
+
+    return true;
+}
+
 bool test_ns_intersection()
 {
     NodeSet *nodeset1 = ns_parse("n[1-5]", NULL);
@@ -216,6 +254,7 @@ int main(int argc, char **argv)
     run_test(test_ns_parse, "ns_parse", &passed);
     run_test(test_ns_count, "ns_count", &passed);
     run_test(test_ns_fold, "ns_fold", &passed);
+    run_test(test_ns_fold_buf, "ns_fold_buf", &passed);
     run_test(test_ns_intersection, "ns_intersection", &passed);
     run_test(test_ns_union, "ns_union", &passed);
     run_test(test_ns_symmetric_difference, "ns_symmetric_difference", &passed);
@@ -1,34 +1,118 @@
+use super::expand::expand_properties;
 use super::nodeset::ConfigurationError;
-use super::parsers::Parser;
+use super::parsers::{is_valid_group_sigil, quote_group, Parser};
+use super::sources::{
+    default_cache_dir, AnsibleSource, CachedSource, CommandFailed, DshSource, ExecSource,
+    FailFastSource, GendersSource, HttpSource, NetgroupSource, SlurmSource,
+};
+use super::Node;
 use super::NodeSet;
+use super::NodeSetIntoIter;
 use crate::idrange::IdRange;
+use crate::idrange::IdRangeList;
 use crate::NodeSetParseError;
 use ini::Properties;
-use log::debug;
-use serde::Deserialize;
+use log::{debug, warn};
 use shellexpand::env_with_context_no_errors;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::fs;
+use std::hash::{BuildHasher, RandomState};
 use std::io::BufReader;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
-use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
 
 /// The default resolver used to parse NodeSet using the FromStr trait
-static GLOBAL_RESOLVER: OnceLock<Resolver> = OnceLock::new();
+static GLOBAL_RESOLVER: RwLock<Option<Arc<Resolver>>> = RwLock::new(None);
 
-/// Default group configuration paths
+/// Default group configuration paths, from the lowest to the highest
+/// precedence
 static CONFIG_PATHS: &[&str] = &[
-    "$HOME/.local/etc/clustershell",
     "/etc/clustershell",
+    "$HOME/.local/etc/clustershell",
     "$XDG_CONFIG_HOME/clustershell",
+    "$XDG_CONFIG_HOME/nodeset",
 ];
 
+/// Environment variable naming a group configuration directory which takes
+/// precedence over the default ones
+const CONFIG_PATH_VAR: &str = "NODESET_CONF";
+
+/// Maximum number of groups which may be resolved within one another
+const MAX_GROUP_DEPTH: usize = 64;
+
+/// Maximum number of names suggested when a group or source is unknown
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Returns the candidates which are close to `name`, closest first
+///
+/// A candidate is close to `name` if it is within an edit distance of 2, or
+/// if either name starts with the other.
+fn suggestions<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut close: Vec<_> = candidates
+        .into_iter()
+        .filter(|c| *c != name)
+        .filter_map(|c| {
+            let distance = edit_distance(name, c);
+            (distance <= 2 || c.starts_with(name) || name.starts_with(c)).then_some((distance, c))
+        })
+        .collect();
+
+    close.sort();
+    close
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, c)| c.to_string())
+        .collect()
+}
+
+/// Returns the Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diag
+            } else {
+                1 + diag.min(above).min(row[j])
+            };
+            diag = above;
+        }
+    }
+
+    row[b.len()]
+}
+
 /// An inventory of group sources used to resolve group names to node sets
 ///
+/// All the methods of a resolver work on a plain `&Resolver`, so that a
+/// process can use several group configurations at once, such as a server
+/// handling requests for different clusters:
+///
+/// ```rust,no_run
+/// use nodeset::{NodeSet, Resolver};
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let resolver = Resolver::from_config()?;
+///
+///     let ns: NodeSet = resolver.resolve(None, "group")?;
+///     let groups: NodeSet = resolver.list_groups(None);
+///
+///     Ok(())
+/// }
+/// ```
+///
 /// The FromStr implementation of NodeSet uses the global resolver which can be
 /// setup to read group sources from the default configuration file as follows:
 ///
@@ -36,17 +120,149 @@ static CONFIG_PATHS: &[&str] = &[
 /// use nodeset::{NodeSet, Resolver};
 ///
 /// fn main() -> Result<(), Box<dyn std::error::Error>> {
-///     Resolver::set_global(Resolver::from_config()?).unwrap();
+///     Resolver::set_global(Resolver::from_config()?);
 ///
 ///     let ns: NodeSet = "@group".parse()?;
 ///
 ///     Ok(())
 /// }
 /// ```
+///
+/// # Concurrency
+///
+/// Resolvers are `Send` and `Sync`: queries only take `&self` and may be run
+/// from several threads at once, group sources being required to be `Send`
+/// and `Sync` as well. Sources which remember their answers, such as cached or
+/// HTTP sources, synchronize internally. The global resolver can be replaced
+/// at any time, for instance to reload the configuration files with
+/// [`Resolver::reload_global`] or [`Resolver::watch_global`], while other
+/// threads use it: [`Resolver::get_global`] returns a handle to the resolver
+/// which was global at that time, or an empty resolver if none was set.
+///
+/// Resolvers are never modified once shared: [`Resolver::reload`] builds a
+/// complete new resolver from the configuration files, which then replaces
+/// the global one in a single step. A query, or the parsing of a nodeset
+/// with the global resolver, sees either the groups from before a reload or
+/// the groups from after it, never a mix of both, and a reload which fails
+/// leaves the global resolver untouched. The files themselves are read one
+/// after the other, so a group file being written while it is read may be
+/// seen partially written: group files should be replaced atomically, ie by
+/// renaming a new file over them.
 #[derive(Debug)]
 pub struct Resolver {
-    sources: HashMap<String, Box<dyn GroupSource>>,
+    sources: HashMap<String, Arc<dyn GroupSource>>,
     default_source: String,
+    source_priority: Vec<String>,
+    priority_mode: PriorityMode,
+    group_sigil: char,
+    group_index: Mutex<GroupIndex>,
+    memo: GroupMemo,
+    memoize: bool,
+    origin: Option<ConfigOrigin>,
+}
+
+/// How a resolver was built from the configuration files, so that it can be
+/// built again by [`Resolver::reload`]
+#[derive(Debug, Clone, Default)]
+struct ConfigOrigin {
+    /// How the configuration files are loaded
+    options: LoadOptions,
+    /// Directories which may hold a `groups.conf` file
+    config_dirs: Vec<PathBuf>,
+    /// Directories holding group files and additional sources
+    dirs: Vec<PathBuf>,
+    /// Sources added with [`ResolverBuilder::add_source`], which are kept
+    /// as is
+    added: Vec<String>,
+    /// Default source set with [`ResolverBuilder::set_default_source`]
+    default_source: Option<String>,
+    /// Group sigil set with [`ResolverBuilder::group_sigil`]
+    group_sigil: Option<char>,
+}
+
+/// Settings controlling how the configuration files are loaded
+#[derive(Debug, Clone, Copy)]
+struct LoadOptions {
+    /// Whether the `cache_ttl` settings of the sources are honored
+    cache: bool,
+    /// Whether environment variables which are not set expand to an empty
+    /// string in configuration values instead of failing
+    allow_undefined_variables: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            cache: true,
+            allow_undefined_variables: false,
+        }
+    }
+}
+
+/// Resolved groups of the sources without a reverse lookup, keyed by source
+/// and by the type of the resolved nodesets
+///
+/// Values are `Arc<Vec<(String, NodeSet<T>)>>`, built by
+/// [`Resolver::groups_of`] on the first lookup in a source.
+type GroupIndex = HashMap<(String, TypeId), Arc<dyn Any + Send + Sync>>;
+
+/// Number of independently locked shards of a [`GroupMemo`]
+const MEMO_SHARDS: usize = 16;
+
+/// Members of groups keyed by source and group, as returned by
+/// [`GroupSource::map`]
+type MemoShard = RwLock<HashMap<(String, String), Option<String>>>;
+
+/// Members of the groups mapped by the sources of a resolver, keyed by source
+/// and group, including the groups which a source does not define
+///
+/// The map is split into shards locked independently: lookups only take a
+/// read lock on the shard of their key, and sources are queried without
+/// holding any lock, so that resolving a group never waits for the
+/// resolution of unrelated ones.
+#[derive(Debug, Default)]
+struct GroupMemo {
+    hasher: RandomState,
+    shards: [MemoShard; MEMO_SHARDS],
+}
+
+impl GroupMemo {
+    fn shard(&self, source: &str, group: &str) -> &MemoShard {
+        &self.shards[self.hasher.hash_one((source, group)) as usize % MEMO_SHARDS]
+    }
+
+    /// Returns the memoized members of `group` from `source`, if any
+    fn get(&self, source: &str, group: &str) -> Option<Option<String>> {
+        self.shard(source, group)
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&(source.to_string(), group.to_string()))
+            .cloned()
+    }
+
+    fn insert(&self, source: &str, group: &str, members: Option<String>) {
+        self.shard(source, group)
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert((source.to_string(), group.to_string()), members);
+    }
+
+    fn clear(&self) {
+        for shard in &self.shards {
+            shard.write().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+    }
+}
+
+/// How a group is resolved across the source priority list when no source is
+/// specified
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PriorityMode {
+    /// Use the definition from the first source which defines the group
+    #[default]
+    FirstMatch,
+    /// Use the union of the definitions from all sources which define the group
+    Union,
 }
 
 impl Default for Resolver {
@@ -54,20 +270,120 @@ impl Default for Resolver {
         Self {
             sources: HashMap::default(),
             default_source: "local".to_string(),
+            source_priority: vec![],
+            priority_mode: PriorityMode::default(),
+            group_sigil: '@',
+            group_index: Mutex::default(),
+            memo: GroupMemo::default(),
+            memoize: true,
+            origin: None,
         }
     }
 }
 
 impl Resolver {
     /// Create a new resolver from the default configuration files
+    ///
+    /// The `groups.conf` files of the directories returned by
+    /// [`Resolver::config_paths`] are merged: the sources of all the files
+    /// are available, and the sources and settings (such as the default
+    /// source) of nearer files override those of the same name in farther
+    /// ones.
+    ///
+    /// `~`, `~user`, `$VAR` and `${VAR}` are expanded when the files are
+    /// loaded in the settings holding paths (`confdir`, `autodir`, `file`,
+    /// `dirs`, `cache`) and commands (`sinfo`, and the upcalls of `type =
+    /// exec` sources). Upcalls run through the shell are left for the shell to
+    /// expand. As in the shell, nothing is expanded between single quotes or
+    /// after a backslash, and `$GROUP`, `$NODE`, `$SOURCE` and `$CFGDIR` are
+    /// substituted later on. Variables which are not set are an error unless
+    /// [`ResolverBuilder::allow_undefined_variables`] is used. Group members
+    /// are never expanded.
     pub fn from_config() -> Result<Self, ConfigurationError> {
+        Self::from_config_with(LoadOptions::default())
+    }
+
+    /// Create a new resolver from the default configuration files with the
+    /// given loading options
+    fn from_config_with(options: LoadOptions) -> Result<Self, ConfigurationError> {
+        let mut config_dirs = Self::config_paths();
+        config_dirs.reverse();
+
+        Self::from_config_dirs(config_dirs, options)
+    }
+
+    /// Create a new resolver from an existing ClusterShell configuration
+    /// directory holding `groups.conf`
+    ///
+    /// The configuration is read as ClusterShell does, without looking at
+    /// the default locations. The following settings are supported:
+    ///
+    /// - in the `Main` section: `default`, `confdir` (or `groupsdir`) for
+    ///   directories of additional `*.conf` source files, `autodir` for
+    ///   directories of static YAML group files, and `group_sigil`
+    /// - in a source section: the `map`, `all`, `list` and `reverse` upcalls,
+    ///   with `$GROUP`, `$NODE`, `$SOURCE` and `$CFGDIR` substituted. Groups
+    ///   listed in a file are typically read with `map: sed -n
+    ///   's/^$GROUP:\(.*\)/\1/p' $CFGDIR/groups`
+    ///
+    /// Source sections may also use the extensions described in the README
+    /// (`type`, `cache_ttl`...).
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`ConfigurationError::File`] if `dir/groups.conf` cannot be
+    /// read or if one of the configuration files is invalid.
+    pub fn from_clustershell_config(dir: impl AsRef<Path>) -> Result<Self, ConfigurationError> {
+        let dir = dir.as_ref();
+        let conf = dir.join("groups.conf");
+        if let Err(e) = fs::metadata(&conf) {
+            return Err(ConfigurationError::File(
+                conf,
+                Box::new(ini::Error::Io(e).into()),
+            ));
+        }
+
+        Self::from_config_dirs(vec![dir.to_path_buf()], LoadOptions::default())
+    }
+
+    /// Returns the directories in which [`Resolver::from_config`] looks for
+    /// `groups.conf`, from the highest to the lowest precedence
+    ///
+    /// These are `$NODESET_CONF` if set, `$XDG_CONFIG_HOME/nodeset`,
+    /// `$XDG_CONFIG_HOME/clustershell` (`$XDG_CONFIG_HOME` defaulting to
+    /// `~/.config`), `~/.local/etc/clustershell` and `/etc/clustershell`.
+    /// Directories are returned whether they exist or not.
+    pub fn config_paths() -> Vec<PathBuf> {
+        std::env::var_os(CONFIG_PATH_VAR)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .into_iter()
+            .chain(
+                CONFIG_PATHS
+                    .iter()
+                    .rev()
+                    .filter_map(|path| resolve_config_path(Path::new(path))),
+            )
+            .collect()
+    }
+
+    /// Create a new resolver from the `groups.conf` files of `config_dirs`,
+    /// later files overriding the settings of earlier ones
+    fn from_config_dirs(
+        config_dirs: Vec<PathBuf>,
+        options: LoadOptions,
+    ) -> Result<Self, ConfigurationError> {
         let mut group_config = MainGroupConfig::default();
 
         let mut cfg_dir = None;
-        for &path in CONFIG_PATHS {
-            if let Some(file) = open_config_path(&Path::new(&path).join("groups.conf")) {
-                group_config.merge(MainGroupConfig::from_reader(BufReader::new(file))?);
-                cfg_dir = resolve_config_path(Path::new(&path));
+        for path in &config_dirs {
+            let conf = path.join("groups.conf");
+            if let Ok(file) = fs::File::open(&conf) {
+                group_config.merge(
+                    MainGroupConfig::from_reader(BufReader::new(file), options)
+                        .map_err(|e| ConfigurationError::File(conf, Box::new(e)))?,
+                );
+                cfg_dir = Some(path);
             }
         }
 
@@ -77,13 +393,30 @@ impl Resolver {
             }
         }
 
-        Resolver::from_dynamic_config(group_config)
+        let dirs = group_config
+            .autodirs()
+            .into_iter()
+            .chain(group_config.confdirs())
+            .map(PathBuf::from)
+            .collect();
+        let mut resolver = Resolver::from_dynamic_config(group_config, options)?;
+        resolver.origin = Some(ConfigOrigin {
+            options,
+            config_dirs,
+            dirs,
+            ..Default::default()
+        });
+
+        Ok(resolver)
     }
 
     /// Create a new resolver from a dynamic group configuration
     ///
     /// `set_cfgdir` must already have been called on the dynamic group configuration
-    fn from_dynamic_config(groups: MainGroupConfig) -> Result<Self, ConfigurationError> {
+    fn from_dynamic_config(
+        groups: MainGroupConfig,
+        options: LoadOptions,
+    ) -> Result<Self, ConfigurationError> {
         let mut resolver = Resolver {
             sources: Default::default(),
             default_source: groups
@@ -91,707 +424,3521 @@ impl Resolver {
                 .as_ref()
                 .and_then(|c| c.default.clone())
                 .unwrap_or_else(|| "default".to_string()),
+            group_sigil: groups
+                .config
+                .as_ref()
+                .and_then(|c| c.group_sigil)
+                .unwrap_or('@'),
+            ..Default::default()
         };
 
         for autodir in groups.autodirs() {
-            for path in find_files_with_ext(Path::new(&autodir), "yaml") {
-                if let Some(file) = open_config_path(&path) {
-                    let static_groups = StaticGroupConfig::from_reader(BufReader::new(file))?;
-                    resolver.add_sources(static_groups);
-                }
-            }
+            resolver.add_yaml_dir(&autodir)?;
+            resolver.add_json_dir(&autodir)?;
         }
         for confdir in groups.confdirs() {
             for path in find_files_with_ext(Path::new(&confdir), "conf") {
                 if let Some(file) = open_config_path(&path) {
-                    let dynamic_groups = MainGroupConfig::from_reader(BufReader::new(file))?;
-                    resolver.add_sources(dynamic_groups);
+                    let dynamic_groups =
+                        MainGroupConfig::from_reader(BufReader::new(file), options)
+                            .map_err(|e| ConfigurationError::File(path, Box::new(e)))?;
+                    resolver.extend_sources(dynamic_groups);
                 }
             }
         }
 
-        resolver.add_sources(groups);
+        resolver.extend_sources(groups);
 
         Ok(resolver)
     }
 
     /// Set the global resolver to use for parsing NodeSet using the FromStr trait
     ///
-    /// Returns an error if the global resolver is already set
-    pub fn set_global(resolver: Resolver) -> Result<(), Resolver> {
-        GLOBAL_RESOLVER.set(resolver)?;
-
-        Ok(())
+    /// The global resolver may be replaced at any time. Nodesets being parsed
+    /// while it is replaced, and the users of a resolver returned by
+    /// [`Resolver::get_global`], keep using the resolver which was global when
+    /// they started so that they never see a mix of both.
+    pub fn set_global(resolver: Resolver) {
+        *GLOBAL_RESOLVER.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(resolver));
     }
 
     /// Get the global resolver
-    pub fn get_global() -> &'static Resolver {
-        static DEFAULT_RESOLVER: OnceLock<Resolver> = OnceLock::new();
+    ///
+    /// An empty resolver is returned if the global resolver is not set.
+    pub fn get_global() -> Arc<Resolver> {
+        static DEFAULT_RESOLVER: OnceLock<Arc<Resolver>> = OnceLock::new();
 
         GLOBAL_RESOLVER
-            .get()
-            .unwrap_or(DEFAULT_RESOLVER.get_or_init(Resolver::default))
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .unwrap_or_else(|| DEFAULT_RESOLVER.get_or_init(Arc::default).clone())
+    }
+
+    /// Build the resolver again from the configuration files it was built
+    /// from, to pick up changes to the group files
+    ///
+    /// Only resolvers built from the configuration files, with
+    /// [`Resolver::from_config`] or [`ResolverBuilder::merge_config`], can be
+    /// reloaded, other resolvers fail with
+    /// [`ConfigurationError::NotReloadable`]. Sources added with
+    /// [`ResolverBuilder::add_source`] are shared with the new resolver, as
+    /// well as the source priority and the settings of the builder.
+    pub fn reload(&self) -> Result<Resolver, ConfigurationError> {
+        let origin = self
+            .origin
+            .as_ref()
+            .ok_or(ConfigurationError::NotReloadable)?;
+
+        let mut resolver = Resolver::from_config_dirs(origin.config_dirs.clone(), origin.options)?;
+        for name in &origin.added {
+            if let Some(source) = self.sources.get(name) {
+                resolver.sources.insert(name.clone(), source.clone());
+            }
+        }
+        if let Some(default_source) = &origin.default_source {
+            resolver.set_default_source(default_source)?;
+        }
+        if let Some(sigil) = origin.group_sigil {
+            resolver.set_group_sigil(sigil)?;
+        }
+        resolver.source_priority = self.source_priority.clone();
+        resolver.priority_mode = self.priority_mode;
+        resolver.memoize = self.memoize;
+        resolver.origin = Some(origin.clone());
+
+        Ok(resolver)
+    }
+
+    /// Reload the global resolver, as with [`Resolver::reload`]
+    ///
+    /// The global resolver is left untouched if it cannot be reloaded.
+    pub fn reload_global() -> Result<(), ConfigurationError> {
+        let resolver = Self::get_global().reload()?;
+        Self::set_global(resolver);
+
+        Ok(())
+    }
+
+    /// Reload the global resolver whenever its configuration files change
+    ///
+    /// The `groups.conf` files and the files of the directories they refer to
+    /// are checked every `interval` by a background thread, which stops when
+    /// the returned watcher is dropped. Their modification times and sizes
+    /// are compared rather than relying on file change notifications, which
+    /// are not available on network filesystems where group files are often
+    /// shared. Failures to reload are logged and the previous resolver is
+    /// kept until the files are fixed.
+    ///
+    /// The global resolver is replaced atomically, as described in
+    /// [Concurrency](Resolver#concurrency).
+    pub fn watch_global(interval: Duration) -> GlobalWatcher {
+        let (stop, stopped) = std::sync::mpsc::channel::<()>();
+
+        let mut state = config_state(&Self::get_global());
+        let thread = std::thread::spawn(move || {
+            while let Err(std::sync::mpsc::RecvTimeoutError::Timeout) =
+                stopped.recv_timeout(interval)
+            {
+                let resolver = Self::get_global();
+                let current = config_state(&resolver);
+                if current == state {
+                    continue;
+                }
+
+                state = current;
+                match resolver.reload() {
+                    Ok(resolver) => {
+                        debug!("Reloaded the group configuration");
+                        Self::set_global(resolver);
+                    }
+                    Err(e) => warn!("Failed to reload the group configuration: {e}"),
+                }
+            }
+        });
+
+        GlobalWatcher {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
     }
 
     /// Resolve a group name to a NodeSet
     ///
-    /// If `source` is None, the default group source of the resolver is used.
+    /// If `source` is None and no source priority has been set, the default
+    /// group source of the resolver is used. Otherwise, sources are consulted
+    /// as described in [`Resolver::set_source_priority`].
     pub fn resolve<T: IdRange + PartialEq + Clone + Display + Debug>(
         &self,
         source: Option<&str>,
-        group: &str,
+        group: impl AsRef<str>,
     ) -> Result<NodeSet<T>, NodeSetParseError> {
-        let source = source.unwrap_or(self.default_source.as_str());
+        Ok(self
+            .try_resolve(source, group.as_ref())?
+            .unwrap_or_default())
+    }
 
-        #[cfg(feature = "groups")]
-        let parser = Parser::with_resolver(self, Some(source));
+    /// Resolve a group name to the union of its definitions in all sources
+    ///
+    /// Unlike [`Resolver::resolve`], every source is consulted regardless of
+    /// the default source and of the source priority. Sources which do not
+    /// define the group are skipped, so that a group defined by no source
+    /// resolves to an empty set, while a source failing to resolve it is an
+    /// error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::{NodeSet, ResolverBuilder, StaticSource};
+    ///
+    /// let source = |nodes: &str| {
+    ///     Box::new(StaticSource::from_iter([(
+    ///         "compute".to_string(),
+    ///         nodes.to_string(),
+    ///     )]))
+    /// };
+    /// let resolver = ResolverBuilder::new()
+    ///     .add_source("site", source("node[1-4]"))
+    ///     .add_source("user", source("node[3-8]"))
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let ns: NodeSet = resolver.resolve_union("compute").unwrap();
+    /// assert_eq!(ns.to_string(), "node[1-8]");
+    /// ```
+    pub fn resolve_union<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+        group: impl AsRef<str>,
+    ) -> Result<NodeSet<T>, NodeSetParseError> {
+        let mut sources: Vec<&String> = self.sources.keys().collect();
+        sources.sort();
 
-        #[cfg(not(feature = "groups"))]
-        let parser = Parser::default();
+        let mut res = NodeSet::default();
+        for source in sources {
+            if let Some(nodeset) = self.resolve_in(source, group.as_ref())? {
+                res.extend_from_nodeset(&nodeset);
+            }
+        }
 
-        parser.parse(
-            &self
-                .sources
-                .get(source)
-                .ok_or_else(|| NodeSetParseError::Source(source.to_owned()))?
-                .map(group)?
-                .unwrap_or_default(),
-        )
+        Ok(res)
     }
 
-    /// List groups from a source
-    ///
-    /// If `source` is None, the default group source of the resolver is used.
-    pub fn list_groups<T: IdRange + PartialEq + Clone + Display + Debug>(
+    /// Resolve a group name as with [`Resolver::resolve`], returning None if
+    /// no consulted source defines the group
+    pub(crate) fn try_resolve<T: IdRange + PartialEq + Clone + Display + Debug>(
         &self,
         source: Option<&str>,
-    ) -> NodeSet<T> {
+        group: &str,
+    ) -> Result<Option<NodeSet<T>>, NodeSetParseError> {
+        if let Some(source) = source {
+            return self.resolve_in(source, group);
+        }
+
+        if self.source_priority.is_empty() {
+            return self.resolve_in(&self.default_source, group);
+        }
+
+        let mut res: Option<NodeSet<T>> = None;
+        for source in &self.source_priority {
+            if !self.sources.contains_key(source) {
+                continue;
+            }
+
+            let Some(nodeset) = self.resolve_in(source, group)? else {
+                continue;
+            };
+
+            match self.priority_mode {
+                PriorityMode::FirstMatch => return Ok(Some(nodeset)),
+                PriorityMode::Union => {
+                    res = Some(match res {
+                        Some(res) => res.union(&nodeset),
+                        None => nodeset,
+                    })
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Returns the error for a group which `source` (or the default source)
+    /// does not define, suggesting groups of the source with a similar name
+    #[cfg(feature = "groups")]
+    pub(crate) fn unknown_group(&self, source: Option<&str>, group: &str) -> NodeSetParseError {
         let source = source.unwrap_or(self.default_source.as_str());
+        let candidates: Vec<String> = self
+            .try_list_groups::<crate::IdRangeList>(Some(source))
+            .map(|groups| groups.iter().collect())
+            .unwrap_or_default();
+
+        NodeSetParseError::UnknownGroup {
+            group: group.to_string(),
+            group_source: source.to_string(),
+            suggestions: suggestions(group, candidates.iter().map(String::as_str)),
+        }
+    }
 
-        Parser::default()
-            .parse(
-                &self
-                    .sources
-                    .get(source)
-                    .map(|s| s.list())
-                    .unwrap_or_default(),
-            )
-            .unwrap_or_default()
+    /// Returns the source named `source`, or an error suggesting sources with
+    /// a similar name
+    fn source(&self, source: &str) -> Result<&dyn GroupSource, NodeSetParseError> {
+        self.sources.get(source).map(AsRef::as_ref).ok_or_else(|| {
+            NodeSetParseError::UnknownSource {
+                group_source: source.to_owned(),
+                suggestions: suggestions(source, self.sources.keys().map(String::as_str)),
+            }
+        })
     }
 
-    /// List groups from all sources
+    /// Resolve a group name and return an iterator over its nodes
     ///
-    /// Returns a list of tuples with the source name and the group name
-    pub fn list_all_groups<T: IdRange + PartialEq + Clone + Display + Debug>(
+    /// Sources are consulted as described in [`Resolver::resolve`].
+    ///
+    /// # Memory usage
+    ///
+    /// The members of the group are held in their folded representation,
+    /// which stores ids rather than node names (ie 4 bytes per node and
+    /// dimension with `IdRangeList`). Node names are formatted one at a time
+    /// as the iterator advances so iterating over a group of tens of millions
+    /// of nodes never holds more than one name in memory, and the ids of each
+    /// set of nodes are released once the set has been iterated over.
+    pub fn resolve_iter<T: IdRange + PartialEq + Clone + Display + Debug>(
         &self,
-    ) -> impl Iterator<Item = (&str, NodeSet<T>)> {
-        self.sources.iter().map(|(source, groups)| {
-            (
-                source.as_str(),
-                Parser::default().parse(&groups.list()).unwrap_or_default(),
-            )
-        })
+        source: Option<&str>,
+        group: impl AsRef<str>,
+    ) -> Result<NodeSetIntoIter<T>, NodeSetParseError> {
+        Ok(self.resolve(source, group)?.into_iter())
     }
 
-    /// List all sources
-    pub fn sources(&self) -> impl Iterator<Item = &String> {
-        self.sources.keys()
-    }
+    /// Parse a nodeset expression using this resolver
+    ///
+    /// Groups referenced in the expression, and in the definitions of these
+    /// groups, are resolved with this resolver rather than with the global
+    /// one, so that several group configurations can be used in the same
+    /// process.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::{NodeSet, ResolverBuilder, StaticSource};
+    ///
+    /// let resolver = ResolverBuilder::new()
+    ///     .add_source(
+    ///         "site",
+    ///         Box::new(StaticSource::from_iter([(
+    ///             "rack1".to_string(),
+    ///             "node[1-4]".to_string(),
+    ///         )])),
+    ///     )
+    ///     .set_default_source("site")
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// # #[cfg(feature = "groups")]
+    /// # {
+    /// let ns: NodeSet = resolver.parse("@rack1 - node2").unwrap();
+    /// assert_eq!(ns.to_string(), "node[1,3-4]");
+    /// # }
+    /// ```
+    pub fn parse<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+        expr: impl AsRef<str>,
+    ) -> Result<NodeSet<T>, NodeSetParseError> {
+        #[cfg(feature = "groups")]
+        let parser = Parser::with_resolver(self, None);
 
-    /// Returns the default group source for this resolver
-    pub fn default_source(&self) -> &str {
-        &self.default_source
+        #[cfg(not(feature = "groups"))]
+        let parser = Parser::default();
+
+        parser.parse::<T>(expr.as_ref())
     }
 
-    pub(crate) fn add_sources(
-        &mut self,
-        sources: impl IntoIterator<Item = (String, impl GroupSource + 'static)>,
-    ) {
-        sources.into_iter().for_each(|(name, source)| {
-            self.sources.insert(name, Box::new(source));
-        });
+    /// Parse a nodeset expression using this resolver and return an
+    /// iterator over its nodes
+    ///
+    /// Groups referenced in the expression are resolved as with
+    /// [`Resolver::parse`]. Memory usage is the same as with
+    /// [`Resolver::resolve_iter`].
+    pub fn iter_expr<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+        expr: impl AsRef<str>,
+    ) -> Result<NodeSetIntoIter<T>, NodeSetParseError> {
+        Ok(self.parse::<T>(expr)?.into_iter())
     }
-}
 
-/// Open a config file from a path, expanding environment variables.
-///
-/// Returns None if there was any failure
-fn open_config_path(path: &Path) -> Option<std::fs::File> {
-    resolve_config_path(path).and_then(|p| std::fs::File::open(p).ok())
-}
+    /// Resolve a group name from a specific source
+    ///
+    /// Returns None if the source does not define the group. Members are
+    /// parsed as a nodeset expression in which groups without a source refer
+    /// to `source`, while groups with a source (ie `@slurm:compute`) are
+    /// resolved from that source. Fails with [`NodeSetParseError::GroupCycle`]
+    /// if the group is already being resolved by the current thread, with
+    /// [`NodeSetParseError::GroupDepth`] if more than `MAX_GROUP_DEPTH` groups
+    /// would be resolved within one another, and with
+    /// [`NodeSetParseError::GroupDefinition`] holding the original error if
+    /// the members cannot be parsed or refer to missing sources or groups.
+    fn resolve_in<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+        source: &str,
+        group: &str,
+    ) -> Result<Option<NodeSet<T>>, NodeSetParseError> {
+        let Some(members) = self.map_group(source, group)? else {
+            return Ok(None);
+        };
 
-/// Expands environment variables in a path
-///
-/// Returns None in case of non-utf8 path
-fn resolve_config_path(path: &Path) -> Option<PathBuf> {
-    let context = |s: &str| match s {
-        "HOME" => std::env::var("HOME").ok(),
-        "XDG_CONFIG_HOME" => std::env::var("XDG_CONFIG_HOME").ok().or_else(|| {
-            std::env::var("HOME")
-                .ok()
-                .map(|h| Path::new(&h).join(".config").to_str().unwrap().to_string())
-        }),
-        _ => None,
-    };
+        self.parse_members(source, group, &members).map(Some)
+    }
 
-    Some(PathBuf::from(
-        env_with_context_no_errors(path.to_str()?, context).as_ref(),
-    ))
-}
+    /// Returns the members of `group` as defined by `source`, querying the
+    /// source only if they are not memoized yet
+    fn map_group(&self, source: &str, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        let group_source = self.source(source)?;
+        if !self.memoize {
+            return group_source.map(group);
+        }
 
-/// Returns a list of files with a given extension in a directory.
-///
-/// Returns an empty list in case of any failure
-fn find_files_with_ext(dir: &Path, ext: &str) -> Vec<PathBuf> {
-    let mut files = vec![];
+        if let Some(members) = self.memo.get(source, group) {
+            return Ok(members);
+        }
 
-    let Ok(it) = fs::read_dir(dir) else {
-        return files;
-    };
+        let members = group_source.map(group)?;
+        self.memo.insert(source, group, members.clone());
 
-    for entry in it {
-        let entry = entry.unwrap();
-        let path = entry.path();
+        Ok(members)
+    }
 
-        if path.is_file() && path.extension().map(|ext| ext.to_str()) == Some(Some(ext)) {
-            files.push(path);
+    /// Parse the members of `group` from `source`, detecting cycles and
+    /// excessive nesting in the groups they refer to
+    fn parse_members<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+        source: &str,
+        group: &str,
+        members: &str,
+    ) -> Result<NodeSet<T>, NodeSetParseError> {
+        thread_local! {
+            /// Groups being resolved by the current thread, outermost first
+            static RESOLVING: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
         }
-    }
 
-    files
-}
+        use itertools::Itertools;
 
-/// Trait for group resolution features of a group source
-pub(crate) trait GroupSource: Debug + Send + Sync {
-    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError>;
-    fn list(&self) -> String;
-}
+        let key = (source.to_string(), group.to_string());
+        RESOLVING.with_borrow_mut(|resolving| {
+            let path = |start: usize| {
+                resolving[start..]
+                    .iter()
+                    .chain(std::iter::once(&key))
+                    .map(|(source, group)| self.group_reference(Some(source), group))
+                    .join(" -> ")
+            };
+
+            if let Some(start) = resolving.iter().position(|k| *k == key) {
+                return Err(NodeSetParseError::GroupCycle(path(start)));
+            }
 
-/// Settings from the main group configuration file (groups.conf)
-#[derive(Debug, Default)]
-struct MainGroupConfig {
-    config: Option<ResolverOptions>,
-    sources: HashMap<String, DynamicGroupSource>,
-}
+            if resolving.len() >= MAX_GROUP_DEPTH {
+                return Err(NodeSetParseError::GroupDepth(path(0)));
+            }
 
-impl MainGroupConfig {
-    fn from_reader(mut reader: impl std::io::Read) -> Result<Self, ConfigurationError> {
-        use ini::Ini;
+            resolving.push(key.clone());
+            Ok(())
+        })?;
 
-        let parser = Ini::read_from_noescape(&mut reader)?;
-        let mut config = MainGroupConfig::default();
-        for (sec, prop) in parser.iter() {
-            match sec {
-                Some("Main") => {
-                    config.config = Some(prop.try_into()?);
-                }
-                Some(sources) => {
-                    for source in sources.split(',') {
-                        config.sources.insert(
-                            source.to_string(),
-                            DynamicGroupSource::from_props(prop, source.to_string())?,
-                        );
-                    }
-                }
-                None => {
-                    if let Some(key) = prop.iter().next().map(|(k, _)| k) {
-                        return Err(ConfigurationError::UnexpectedProperty(key.to_string()));
-                    }
+        let res = self.parser(source).parse(members);
+        RESOLVING.with_borrow_mut(|resolving| resolving.pop());
+
+        // Cycles and excessive nesting already report the groups involved
+        res.map_err(|e| {
+            let reference = self.group_reference(Some(source), group);
+            match e {
+                NodeSetParseError::GroupCycle(_) | NodeSetParseError::GroupDepth(_) => e,
+                NodeSetParseError::GroupDefinition(path, e) => {
+                    NodeSetParseError::GroupDefinition(format!("{reference} -> {path}"), e)
                 }
+                e => NodeSetParseError::GroupDefinition(reference, Box::new(e)),
             }
-        }
+        })
+    }
 
-        Ok(config)
+    /// Resolve several groups at once
+    ///
+    /// Groups are resolved as with [`Resolver::resolve`] and returned with
+    /// their name in the same order. When they are resolved from a single
+    /// source (`source` is given or no source priority has been set), the
+    /// source is queried for all of them in one batch, which lets sources
+    /// with a cache look it up for every group before fetching the missing
+    /// ones together.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`NodeSetParseError::Group`] holding the name of the first
+    /// group which could not be resolved, or with
+    /// [`NodeSetParseError::UnknownSource`] if the source does not exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::{NodeSet, ResolverBuilder, StaticSource};
+    ///
+    /// let resolver = ResolverBuilder::new()
+    ///     .add_source(
+    ///         "site",
+    ///         Box::new(StaticSource::from_iter([
+    ///             ("compute".to_string(), "node[1-4]".to_string()),
+    ///             ("login".to_string(), "login1".to_string()),
+    ///         ])),
+    ///     )
+    ///     .set_default_source("site")
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let groups: Vec<(String, NodeSet)> =
+    ///     resolver.resolve_many(None, &["login", "compute"]).unwrap();
+    /// assert_eq!(groups[0].0, "login");
+    /// assert_eq!(groups[1].1.to_string(), "node[1-4]");
+    /// ```
+    pub fn resolve_many<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+        source: Option<&str>,
+        groups: &[&str],
+    ) -> Result<Vec<(String, NodeSet<T>)>, NodeSetParseError> {
+        let resolved = match source {
+            Some(source) => self.resolve_batch(source, groups)?,
+            None if self.source_priority.is_empty() => {
+                self.resolve_batch(&self.default_source, groups)?
+            }
+            None => groups
+                .iter()
+                .map(|group| self.resolve(None, group))
+                .collect(),
+        };
+
+        groups
+            .iter()
+            .zip(resolved)
+            .map(|(group, nodes)| {
+                nodes
+                    .map(|nodes| (group.to_string(), nodes))
+                    .map_err(|e| NodeSetParseError::Group(group.to_string(), Box::new(e)))
+            })
+            .collect()
     }
 
-    fn autodirs(&self) -> Vec<String> {
-        self.config
-            .as_ref()
-            .map(|c| c.autodirs())
-            .unwrap_or_default()
+    /// Resolve groups from `source` in one batch, returning the result for
+    /// each group
+    fn resolve_batch<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+        source: &str,
+        groups: &[&str],
+    ) -> Result<Vec<Result<NodeSet<T>, NodeSetParseError>>, NodeSetParseError> {
+        let group_source = self.source(source)?;
+        let memoized: Vec<_> = groups
+            .iter()
+            .map(|group| self.memoize.then(|| self.memo.get(source, group)).flatten())
+            .collect();
+
+        // Groups which are not memoized are mapped together
+        let missing: Vec<&str> = groups
+            .iter()
+            .zip(&memoized)
+            .filter(|(_, members)| members.is_none())
+            .map(|(group, _)| *group)
+            .collect();
+        let mut mapped = group_source.map_many(&missing).into_iter();
+
+        Ok(groups
+            .iter()
+            .zip(memoized)
+            .map(|(group, members)| {
+                let members = match members {
+                    Some(members) => members,
+                    None => {
+                        let members = mapped.next().expect("one answer per missing group")?;
+                        if self.memoize {
+                            self.memo.insert(source, group, members.clone());
+                        }
+                        members
+                    }
+                };
+
+                match members {
+                    Some(members) => self.parse_members(source, group, &members),
+                    None => Ok(NodeSet::default()),
+                }
+            })
+            .collect())
     }
 
-    fn confdirs(&self) -> Vec<String> {
-        self.config
-            .as_ref()
-            .map(|c| c.confdirs())
-            .unwrap_or_default()
+    /// Set whether the members of the groups mapped by the sources are
+    /// memoized, which is the default
+    ///
+    /// When enabled, each source is only asked once for the members of a
+    /// group, which are then reused for every resolution of the group with
+    /// this resolver, such as when the group appears several times in an
+    /// expression or is referenced by other groups. Groups which a source
+    /// does not define are memoized as well, while failures are not.
+    ///
+    /// Long-running processes relying on the `cache_ttl` of the sources to
+    /// pick up changes should either disable memoization or regularly call
+    /// [`Resolver::clear_memoized`].
+    pub fn set_memoize(&mut self, memoize: bool) {
+        self.memoize = memoize;
+        self.memo.clear();
     }
 
-    fn set_cfgdir(&mut self, cfgdir: &str) -> Result<(), ConfigurationError> {
-        let context = |s: &str| match s {
-            "CFGDIR" => Some(cfgdir),
-            _ => None,
-        };
+    /// Forget the memoized members of groups, so that sources are asked for
+    /// them again
+    ///
+    /// See [`Resolver::set_memoize`].
+    pub fn clear_memoized(&self) {
+        self.memo.clear();
+    }
 
-        if let Some(config) = &mut self.config {
-            config.confdir = config
-                .confdir
-                .as_ref()
-                .map(|c| env_with_context_no_errors(&c, context).to_string());
-            config.autodir = config
-                .autodir
-                .as_ref()
-                .map(|c| env_with_context_no_errors(&c, context).to_string());
-        }
+    /// Returns a parser for the members of groups from `source`
+    #[cfg_attr(not(feature = "groups"), allow(unused_variables))]
+    fn parser<'a>(&'a self, source: &'a str) -> Parser<'a> {
+        #[cfg(feature = "groups")]
+        return Parser::with_resolver(self, Some(source));
 
-        for (_, group) in self.sources.iter_mut() {
-            group.set_cfgdir(cfgdir)?;
-        }
+        #[cfg(not(feature = "groups"))]
+        Parser::default()
+    }
 
-        Ok(())
+    /// Set the order in which sources are consulted to resolve groups when no
+    /// source is specified
+    ///
+    /// Depending on the [`PriorityMode`], either the definition from the first
+    /// source in `sources` which defines the group is used, or the union of the
+    /// definitions from all sources in `sources`. Ties are always broken in
+    /// favor of the source which comes first in `sources`. Sources which are
+    /// not part of `sources` are never consulted, and sources in `sources`
+    /// which do not exist are skipped.
+    ///
+    /// An empty list restores the default behavior of only using the default
+    /// source.
+    pub fn set_source_priority(&mut self, sources: &[&str]) {
+        self.source_priority = sources.iter().map(|s| s.to_string()).collect();
     }
 
-    /// Merge settings for another group configuration file into this one
-    fn merge(&mut self, other: Self) {
-        match (&mut self.config, other.config) {
-            (Some(ref mut main), Some(other_main)) => main.merge(other_main),
-            (None, Some(other_main)) => self.config = Some(other_main),
-            _ => (),
-        }
-        self.sources.extend(other.sources);
+    /// Set how groups are resolved across the source priority list
+    pub fn set_priority_mode(&mut self, mode: PriorityMode) {
+        self.priority_mode = mode;
     }
-}
 
-impl IntoIterator for MainGroupConfig {
-    type Item = (String, DynamicGroupSource);
-    type IntoIter = std::collections::hash_map::IntoIter<String, DynamicGroupSource>;
+    /// Set the character introducing group references in the nodesets parsed
+    /// with this resolver, `@` by default (ie `%rack1` and `%%source` with
+    /// `%`)
+    ///
+    /// `@` is then no longer special and nodesets using it fail to parse.
+    /// Fails if the sigil can be confused with node names or with other
+    /// elements of the syntax.
+    pub fn set_group_sigil(&mut self, sigil: char) -> Result<(), ConfigurationError> {
+        if !is_valid_group_sigil(sigil) {
+            return Err(ConfigurationError::InvalidValue(
+                "group_sigil".to_string(),
+                sigil.to_string(),
+            ));
+        }
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.sources.into_iter()
+        self.group_sigil = sigil;
+        Ok(())
     }
-}
 
-#[derive(Debug, Default)]
-struct ResolverOptions {
-    default: Option<String>,
-    confdir: Option<String>,
-    autodir: Option<String>,
-}
+    /// Returns the character introducing group references
+    pub fn group_sigil(&self) -> char {
+        self.group_sigil
+    }
 
-impl ResolverOptions {
-    /// Merge resolver options from `other` into `self`
-    fn merge(&mut self, other: Self) {
-        if let Some(default) = other.default {
-            self.default = Some(default);
-        }
-        if let Some(confdir) = other.confdir {
-            self.confdir = Some(confdir);
-        }
-        if let Some(autodir) = other.autodir {
-            self.autodir = Some(autodir);
+    /// Returns the reference to `group` of `source` as written in nodesets
+    /// parsed with this resolver (ie `@source:group`, or `@group` if `source`
+    /// is None)
+    ///
+    /// The group name is quoted if it cannot be written bare, so that the
+    /// reference can be parsed back to the same group.
+    ///
+    /// ```
+    /// use nodeset::Resolver;
+    ///
+    /// let resolver = Resolver::default();
+    /// assert_eq!(resolver.group_reference(None, "rack1"), "@rack1");
+    /// assert_eq!(resolver.group_reference(None, "state:idle"), "@\"state:idle\"");
+    /// assert_eq!(resolver.group_reference(Some("slurm"), "state:idle"), "@slurm:state:idle");
+    /// assert_eq!(resolver.group_reference(Some("cmdb"), "rack 1,a"), "@cmdb:\"rack 1,a\"");
+    /// ```
+    pub fn group_reference(&self, source: Option<&str>, group: &str) -> String {
+        match source {
+            Some(source) => format!("{}{source}:{}", self.group_sigil, quote_group(group, true)),
+            None => format!("{}{}", self.group_sigil, quote_group(group, false)),
         }
     }
 
-    fn autodirs(&self) -> Vec<String> {
-        self.autodir
-            .as_ref()
-            .and_then(|autodir| shlex::split(autodir))
-            .unwrap_or_default()
-    }
+    /// List groups from a source
+    ///
+    /// If `source` is None, the default group source of the resolver is used.
+    /// Sources which fail to list their groups are reported as having none.
+    pub fn list_groups<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+        source: Option<&str>,
+    ) -> NodeSet<T> {
+        let source = source.unwrap_or(self.default_source.as_str());
 
-    fn confdirs(&self) -> Vec<String> {
-        self.confdir
-            .as_ref()
-            .and_then(|confdir| shlex::split(confdir))
+        self.sources
+            .get(source)
+            .map(|s| Self::parse_list(source, s.as_ref()))
             .unwrap_or_default()
     }
-}
-
-impl TryFrom<&Properties> for ResolverOptions {
-    type Error = ConfigurationError;
-
-    fn try_from(props: &Properties) -> Result<Self, Self::Error> {
-        let mut res = Self::default();
 
-        for (k, v) in props.iter() {
-            match k {
-                "default" => {
-                    res.default = Some(v.to_string());
-                }
-                "confdir" => {
-                    res.confdir = Some(v.to_string());
-                }
-                "autodir" => {
-                    res.autodir = Some(v.to_string());
-                }
-                _ => {
-                    return Err(ConfigurationError::UnexpectedProperty(k.to_string()));
-                }
-            }
-        }
+    /// Iterate over the names of the groups of a source
+    ///
+    /// If `source` is None, the default group source of the resolver is used.
+    /// The source is only asked for its groups when the iterator is first
+    /// advanced, and the groups are not resolved, so listing names stays cheap
+    /// with sources which are slow to resolve groups.
+    pub fn group_names<'a>(&'a self, source: Option<&'a str>) -> impl Iterator<Item = String> + 'a {
+        std::iter::once_with(move || self.list_groups::<IdRangeList>(source)).flatten()
+    }
 
-        Ok(res)
+    /// List groups from all sources
+    ///
+    /// Returns a list of tuples with the source name and the group name
+    pub fn list_all_groups<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+    ) -> impl Iterator<Item = (&str, NodeSet<T>)> {
+        self.sources
+            .iter()
+            .map(|(source, groups)| (source.as_str(), Self::parse_list(source, groups.as_ref())))
     }
-}
 
-/// Settings from a dynamic group source (groups.conf.d/<source>.conf)
-#[derive(Debug)]
-struct DynamicGroupSource {
-    name: String,
-    map: String,
-    all: Option<String>,
-    list: Option<String>,
-}
+    /// Resolve all the groups of a source
+    ///
+    /// If `source` is None, the default group source of the resolver is used.
+    /// Groups are listed as with [`Resolver::list_groups`] and resolved as
+    /// with [`Resolver::resolve_many_iter`].
+    pub fn groups_for_source<'a, T: IdRange + PartialEq + Clone + Display + Debug + 'a>(
+        &'a self,
+        source: Option<&str>,
+    ) -> impl Iterator<Item = Result<(String, NodeSet<T>), NodeSetParseError>> + 'a {
+        let source = source.unwrap_or(self.default_source.as_str());
+        let groups = self.list_groups::<T>(Some(source)).into_iter().collect();
 
-impl DynamicGroupSource {
-    fn from_props(props: &Properties, name: String) -> Result<Self, ConfigurationError> {
-        let map = props
-            .get("map")
-            .ok_or_else(|| ConfigurationError::MissingProperty("map".to_string()))?
-            .to_string();
-        let all = props.get("all").map(|s| s.to_string());
-        let list = props.get("list").map(|s| s.to_string());
+        self.resolve_many_iter(Some(source), groups)
+    }
 
-        Ok(Self {
-            name,
-            map,
-            all,
-            list,
+    /// Resolve `groups` from a source as the iterator advances
+    ///
+    /// If `source` is None, the default group source of the resolver is used.
+    /// Groups are resolved from that source in batches as with
+    /// [`Resolver::resolve_many`] and returned with their name in the same
+    /// order. A group which fails to resolve yields a
+    /// [`NodeSetParseError::Group`] error naming it and does not stop the
+    /// iteration, while a failure of the source yields a single error for the
+    /// whole batch.
+    pub fn resolve_many_iter<'a, T: IdRange + PartialEq + Clone + Display + Debug + 'a>(
+        &'a self,
+        source: Option<&str>,
+        groups: Vec<String>,
+    ) -> impl Iterator<Item = Result<(String, NodeSet<T>), NodeSetParseError>> + 'a {
+        /// Number of groups queried from the source at once, small enough to
+        /// report progress regularly
+        const BATCH: usize = 64;
+
+        let source = source.unwrap_or(self.default_source.as_str()).to_string();
+        let batches: Vec<Vec<String>> = groups.chunks(BATCH).map(<[String]>::to_vec).collect();
+
+        batches.into_iter().flat_map(move |batch| {
+            let names: Vec<&str> = batch.iter().map(String::as_str).collect();
+            let resolved = match self.resolve_batch(&source, &names) {
+                Ok(resolved) => resolved,
+                Err(e) => return vec![Err(e)],
+            };
+
+            batch
+                .into_iter()
+                .zip(resolved)
+                .map(|(group, nodes)| match nodes {
+                    Ok(nodes) => Ok((group, nodes)),
+                    Err(e) => Err(NodeSetParseError::Group(group, Box::new(e))),
+                })
+                .collect()
         })
     }
 
-    fn set_cfgdir(&mut self, cfgdir: &str) -> Result<(), ConfigurationError> {
-        let context = |s: &str| match s {
-            "CFGDIR" => Some(cfgdir),
-            "SOURCE" => Some(self.name.as_str()),
-            _ => None,
-        };
-
-        self.map = env_with_context_no_errors(&self.map, context).to_string();
-        self.all = self
-            .all
-            .as_ref()
-            .map(|s| env_with_context_no_errors(s, context).to_string());
-        self.list = self
-            .list
-            .as_ref()
-            .map(|s| env_with_context_no_errors(s, context).to_string());
+    /// List groups from a source, failing if the source does not exist or
+    /// cannot list its groups
+    ///
+    /// If `source` is None, the default group source of the resolver is used.
+    /// Unlike [`Resolver::list_groups`], the failure is returned instead of
+    /// being logged.
+    pub fn try_list_groups<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+        source: Option<&str>,
+    ) -> Result<NodeSet<T>, NodeSetParseError> {
+        let source = source.unwrap_or(self.default_source.as_str());
+        let group_source = self.source(source)?;
 
-        Ok(())
+        Parser::for_group_names().parse(&group_source.list()?.join(" "))
     }
-}
 
-impl GroupSource for DynamicGroupSource {
-    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
-        let context = |s: &str| match s {
-            "GROUP" => Some(group),
-            "SOURCE" => Some(self.name.as_str()),
-            _ => None,
-        };
-        let map = env_with_context_no_errors(&self.map, context).to_string();
+    fn parse_list<T: IdRange + PartialEq + Clone + Display + Debug>(
+        name: &str,
+        source: &dyn GroupSource,
+    ) -> NodeSet<T> {
+        match source.list() {
+            Ok(list) => Parser::for_group_names()
+                .parse(&list.join(" "))
+                .unwrap_or_default(),
+            Err(e) => {
+                warn!("Failed to list groups from source '{name}': {e}");
+                NodeSet::default()
+            }
+        }
+    }
 
-        let output = Command::new("/bin/sh").arg("-c").arg(&map).output()?;
+    /// Returns all the nodes of a source
+    ///
+    /// If `source` is None, the default group source of the resolver is used.
+    /// Sources which define an `all` upcall return its output, other sources
+    /// return the union of all their groups.
+    pub fn all_nodes<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+        source: Option<&str>,
+    ) -> Result<NodeSet<T>, NodeSetParseError> {
+        let source = source.unwrap_or(self.default_source.as_str());
+        let group_source = self.source(source)?;
 
-        if !output.status.success() {
-            return Err(NodeSetParseError::Command(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Command '{}' returned non-zero exit code", map),
-            )));
+        if let Some(all) = group_source.all()? {
+            return self.parser(source).parse(&all);
         }
 
-        let res = String::from_utf8_lossy(&output.stdout);
-
-        debug!(
-            "Map command '{}' for @'{}':'{}' returned: {}",
-            map, self.name, group, res
-        );
+        let mut res = NodeSet::lazy();
+        for group in self.list_groups::<T>(Some(source)).iter() {
+            res.extend_from_nodeset(&self.resolve::<T>(Some(source), &group)?);
+        }
+        res.fold();
 
-        Ok(Some(res.trim().to_string()))
+        Ok(res)
     }
 
-    fn list(&self) -> String {
-        let Some(ref list_cmd) = self.list else {
-            return Default::default();
-        };
+    /// Returns the groups of a source which `node` belongs to
+    ///
+    /// If `source` is None, the default group source of the resolver is used.
+    /// Sources which define a `reverse` upcall return its output, other
+    /// sources resolve each of their groups to look for `node`.
+    pub fn node_groups<T: IdRange + PartialEq + Clone + Display + Debug>(
+        &self,
+        source: Option<&str>,
+        node: &str,
+    ) -> Result<NodeSet<T>, NodeSetParseError> {
+        let source = source.unwrap_or(self.default_source.as_str());
+        let group_source = self.source(source)?;
 
-        let context = |s: &str| {
-            debug!("Lookup {s}");
-            match s {
-                "SOURCE" => Some(self.name.as_str()),
-                _ => None,
-            }
+        if let Some(groups) = group_source.reverse(node)? {
+            return Parser::for_group_names().parse(&groups);
+        }
+
+        let node: Node = node.parse()?;
+        let mut res = NodeSet::lazy();
+        for group in self.list_groups::<T>(Some(source)).iter() {
+            if self
+                .resolve::<T>(Some(source), &group)?
+                .contains_node(&node)
+            {
+                res.extend_from_nodeset(&Parser::default().parse(&group)?);
+            }
+        }
+        res.fold();
+
+        Ok(res)
+    }
+
+    /// Returns the names of the groups of a source which `node` belongs to,
+    /// in lexical order
+    ///
+    /// If `source` is None, the default group source of the resolver is used.
+    /// Sources which define a `reverse` upcall, or which know the groups of
+    /// each node such as genders or Slurm sources, answer directly. For other
+    /// sources, all the groups are resolved on the first lookup and kept by
+    /// the resolver, so that later lookups in the source only look for the
+    /// node in the resolved groups. A lookup which fails is not remembered.
+    pub fn groups_of<T>(
+        &self,
+        source: Option<&str>,
+        node: &str,
+    ) -> Result<Vec<String>, NodeSetParseError>
+    where
+        T: IdRange + PartialEq + Clone + Display + Debug + Send + Sync + 'static,
+    {
+        let source = source.unwrap_or(self.default_source.as_str());
+        let group_source = self.source(source)?;
+
+        if let Some(groups) = group_source.reverse(node)? {
+            let groups: NodeSet<T> = Parser::for_group_names().parse(&groups)?;
+            return Ok(groups.iter().collect());
+        }
+
+        let node: Node = node.parse()?;
+        Ok(self
+            .group_index::<T>(source)?
+            .iter()
+            .filter(|(_, nodes)| nodes.contains_node(&node))
+            .map(|(group, _)| group.clone())
+            .collect())
+    }
+
+    /// Returns all the groups of `source` with their nodes, resolving them
+    /// if they are not indexed yet
+    #[allow(clippy::type_complexity)]
+    fn group_index<T>(
+        &self,
+        source: &str,
+    ) -> Result<Arc<Vec<(String, NodeSet<T>)>>, NodeSetParseError>
+    where
+        T: IdRange + PartialEq + Clone + Display + Debug + Send + Sync + 'static,
+    {
+        let key = (source.to_string(), TypeId::of::<T>());
+        if let Some(index) = self
+            .lock_group_index()
+            .get(&key)
+            .and_then(|index| index.clone().downcast().ok())
+        {
+            return Ok(index);
+        }
+
+        // The lock is not held while resolving as groups may refer to other
+        // sources
+        let index = Arc::new(
+            self.groups_for_source::<T>(Some(source))
+                .collect::<Result<Vec<_>, _>>()?,
+        );
+        self.lock_group_index().insert(key, index.clone());
+
+        Ok(index)
+    }
+
+    fn lock_group_index(&self) -> MutexGuard<'_, GroupIndex> {
+        self.group_index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Removes the caches written to disk by group sources
+    ///
+    /// This removes the `$XDG_CACHE_HOME/nodeset` directory, which holds the
+    /// documents fetched by HTTP sources and the answers of the sources with
+    /// `cache_disk` enabled. Returns the removed directory, if it existed.
+    pub fn clear_cache() -> std::io::Result<Option<PathBuf>> {
+        let Some(dir) = default_cache_dir() else {
+            return Ok(None);
         };
-        let list = env_with_context_no_errors(&list_cmd, context).to_string();
 
-        let output = Command::new("/bin/sh")
-            .arg("-c")
-            .arg(&list)
-            .output()
-            .unwrap();
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => Ok(Some(dir)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
 
-        if !output.status.success() {
-            panic!("Command '{}' returned non-zero exit code", list);
+    /// List all sources
+    pub fn sources(&self) -> impl Iterator<Item = &String> {
+        self.sources.keys()
+    }
+
+    /// Returns the default group source for this resolver
+    pub fn default_source(&self) -> &str {
+        &self.default_source
+    }
+
+    /// Describe all sources, sorted by name
+    ///
+    /// Describing sources does not query them: their number of groups is
+    /// only computed by [`SourceInfo::group_count`].
+    pub fn source_info(&self) -> Vec<SourceInfo<'_>> {
+        let mut info: Vec<_> = self
+            .sources
+            .iter()
+            .map(|(name, source)| SourceInfo {
+                name,
+                kind: source.kind(),
+                origin: source.origin(),
+                default: *name == self.default_source,
+                source: source.as_ref(),
+            })
+            .collect();
+        info.sort_by_key(|info| info.name);
+
+        info
+    }
+
+    /// Load static group sources from the YAML files (`*.yaml`) of a directory
+    ///
+    /// Each top-level key of a file defines a source, which maps group names
+    /// to their members as in clustershell's group files. Members may refer
+    /// to other groups (ie `@othergroup` or `@source:group`). Sources defined
+    /// in several files are replaced by the definition from the last file in
+    /// lexical order. A directory which does not exist is ignored.
+    pub fn add_yaml_dir(&mut self, dir: impl AsRef<Path>) -> Result<(), ConfigurationError> {
+        self.add_static_dir(dir.as_ref(), "yaml")
+    }
+
+    /// Load static group sources from the JSON files (`*.json`) of a directory
+    ///
+    /// Files have the same shape as the YAML files loaded by
+    /// [`add_yaml_dir`](Self::add_yaml_dir): an object mapping source names to
+    /// objects mapping group names to a nodeset or a list of nodesets.
+    pub fn add_json_dir(&mut self, dir: impl AsRef<Path>) -> Result<(), ConfigurationError> {
+        self.add_static_dir(dir.as_ref(), "json")
+    }
+
+    fn add_static_dir(&mut self, dir: &Path, ext: &str) -> Result<(), ConfigurationError> {
+        for path in find_files_with_ext(dir, ext) {
+            if let Some(file) = open_config_path(&path) {
+                let mut static_groups = StaticGroupConfig::from_reader(BufReader::new(file))
+                    .map_err(|e| ConfigurationError::File(path.clone(), Box::new(e)))?;
+                for source in static_groups.sources.values_mut() {
+                    source.file = Some(path.clone());
+                }
+                self.add_sources(static_groups);
+            }
         }
 
-        let res = String::from_utf8_lossy(&output.stdout);
+        Ok(())
+    }
 
-        debug!(
-            "List command '{}' for @'{}':* returned: {}",
-            list, self.name, res
+    pub(crate) fn add_sources(
+        &mut self,
+        sources: impl IntoIterator<Item = (String, impl GroupSource + 'static)>,
+    ) {
+        sources.into_iter().for_each(|(name, source)| {
+            self.sources.insert(name, Arc::new(source));
+        });
+        self.lock_group_index().clear();
+        self.memo.clear();
+    }
+
+    fn extend_sources(
+        &mut self,
+        sources: impl IntoIterator<Item = (String, Box<dyn GroupSource>)>,
+    ) {
+        self.sources.extend(
+            sources
+                .into_iter()
+                .map(|(name, source)| (name, Arc::from(source))),
         );
+    }
 
-        res.trim().to_string()
+    fn set_default_source(&mut self, source: &str) -> Result<(), ConfigurationError> {
+        if !self.sources.contains_key(source) {
+            return Err(ConfigurationError::InvalidValue(
+                "default".to_string(),
+                source.to_string(),
+            ));
+        }
+
+        self.default_source = source.to_string();
+        Ok(())
     }
 }
 
-/// Settings from a static group source configuration file (groups.d/*.yaml)
-#[derive(Deserialize, Debug)]
-struct StaticGroupConfig {
-    #[serde(flatten)]
-    sources: HashMap<String, StaticGroupSource>,
+/// Description of a group source returned by [`Resolver::source_info`]
+#[derive(Debug, Clone)]
+pub struct SourceInfo<'a> {
+    /// The name of the source
+    pub name: &'a str,
+    /// The kind of the source, as returned by [`GroupSource::kind`]
+    pub kind: &'a str,
+    /// Where the groups come from, as returned by [`GroupSource::origin`]
+    pub origin: Option<String>,
+    /// Whether this is the default source of the resolver
+    pub default: bool,
+    source: &'a dyn GroupSource,
 }
 
-impl StaticGroupConfig {
-    fn from_reader(reader: impl std::io::Read) -> Result<Self, ConfigurationError> {
-        let config: Self = serde_yaml::from_reader(reader)?;
-        Ok(config)
+impl SourceInfo<'_> {
+    /// Returns the number of groups of the source
+    ///
+    /// The groups are listed from the source, which may be slow.
+    pub fn group_count(&self) -> Result<u64, NodeSetParseError> {
+        let groups: NodeSet<IdRangeList> =
+            Parser::for_group_names().parse(&self.source.list()?.join(" "))?;
+        Ok(groups.len_u64())
     }
 }
 
-impl IntoIterator for StaticGroupConfig {
-    type Item = (String, StaticGroupSource);
-    type IntoIter = std::collections::hash_map::IntoIter<String, StaticGroupSource>;
+/// Reloads the global resolver when its configuration files change
+///
+/// Created by [`Resolver::watch_global`]. Dropping the watcher stops it.
+#[derive(Debug)]
+pub struct GlobalWatcher {
+    stop: Option<std::sync::mpsc::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.sources.into_iter()
+impl Drop for GlobalWatcher {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
     }
 }
 
-#[derive(Deserialize, Debug)]
-struct StaticGroupSource {
-    #[serde(flatten)]
-    groups: HashMap<String, SingleOrVec>,
+/// Modification times and sizes of the configuration files of a resolver
+type ConfigState = Vec<(PathBuf, Option<(SystemTime, u64)>)>;
+
+/// Returns the state of the configuration files which `resolver` was built
+/// from, including the `groups.conf` files which do not exist yet
+fn config_state(resolver: &Resolver) -> ConfigState {
+    let stat = |path: &Path| {
+        fs::metadata(path)
+            .and_then(|m| Ok((m.modified()?, m.len())))
+            .ok()
+    };
+
+    let Some(origin) = &resolver.origin else {
+        return vec![];
+    };
+
+    let mut state: ConfigState = origin
+        .config_dirs
+        .iter()
+        .map(|dir| {
+            let path = dir.join("groups.conf");
+            let stat = stat(&path);
+            (path, stat)
+        })
+        .collect();
+
+    for dir in &origin.dirs {
+        state.push((dir.clone(), stat(dir)));
+        let mut files: Vec<_> = ["conf", "yaml", "json"]
+            .iter()
+            .flat_map(|ext| find_files_with_ext(dir, ext))
+            .collect();
+        files.sort();
+        state.extend(files.into_iter().map(|path| {
+            let stat = stat(&path);
+            (path, stat)
+        }));
+    }
+
+    state
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
-enum SingleOrVec {
-    Single(String),
-    Vec(Vec<String>),
+/// Builds a [`Resolver`] from group sources defined in memory
+///
+/// Sources added with [`add_source`](Self::add_source) are used exactly as
+/// sources read from configuration files. By default, configuration files
+/// are ignored and the resolver only holds the added sources. With
+/// [`merge_config`](Self::merge_config), the sources of the default
+/// configuration files are loaded as well and added sources replace the
+/// configured sources with the same name.
+///
+/// # Examples
+///
+/// ```
+/// use nodeset::{NodeSet, ResolverBuilder, StaticSource};
+///
+/// let groups = [("compute", "node[1-4]"), ("gpu", "node[3-4]")];
+/// let resolver = ResolverBuilder::new()
+///     .add_source(
+///         "cmdb",
+///         Box::new(
+///             groups
+///                 .iter()
+///                 .map(|(group, nodes)| (group.to_string(), nodes.to_string()))
+///                 .collect::<StaticSource>(),
+///         ),
+///     )
+///     .set_default_source("cmdb")
+///     .build()
+///     .unwrap();
+///
+/// let gpu: NodeSet = resolver.resolve(None, "gpu").unwrap();
+/// let groups: NodeSet = resolver.list_groups(Some("cmdb"));
+///
+/// assert_eq!(gpu.to_string(), "node[3-4]");
+/// assert_eq!(groups.to_string(), "compute,gpu");
+/// assert_eq!(resolver.sources().collect::<Vec<_>>(), vec!["cmdb"]);
+/// ```
+#[derive(Debug, Default)]
+pub struct ResolverBuilder {
+    sources: Vec<(String, Box<dyn GroupSource>)>,
+    default_source: Option<String>,
+    group_sigil: Option<char>,
+    merge_config: bool,
+    no_cache: bool,
+    allow_undefined_variables: bool,
 }
 
-impl From<&SingleOrVec> for String {
-    fn from(s: &SingleOrVec) -> Self {
-        match s {
-            SingleOrVec::Single(s) => s.clone(),
-            SingleOrVec::Vec(v) => v.join(","),
-        }
+impl ResolverBuilder {
+    /// Creates a builder without any source, ignoring configuration files
+    pub fn new() -> Self {
+        Self::default()
     }
-}
 
-impl GroupSource for StaticGroupSource {
-    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
-        Ok(self.groups.get(group).map(|v| v.into()))
+    /// Adds a group source named `name`
+    ///
+    /// A source added with the same name as a previous one replaces it.
+    pub fn add_source(mut self, name: impl Into<String>, source: Box<dyn GroupSource>) -> Self {
+        self.sources.push((name.into(), source));
+        self
     }
 
-    fn list(&self) -> String {
-        use itertools::Itertools;
-        self.groups.keys().join(" ")
+    /// Sets the source used to resolve groups without a source
+    ///
+    /// Defaults to the default source of the configuration files when they
+    /// are merged, and to `default` otherwise.
+    pub fn set_default_source(mut self, name: impl Into<String>) -> Self {
+        self.default_source = Some(name.into());
+        self
+    }
+
+    /// Sets the character introducing group references, as with
+    /// [`Resolver::set_group_sigil`]
+    ///
+    /// Defaults to the `group_sigil` of the configuration files when they are
+    /// merged, and to `@` otherwise.
+    pub fn group_sigil(mut self, sigil: char) -> Self {
+        self.group_sigil = Some(sigil);
+        self
+    }
+
+    /// Loads the sources of the default configuration files, as with
+    /// [`Resolver::from_config`], in addition to the added sources
+    pub fn merge_config(mut self) -> Self {
+        self.merge_config = true;
+        self
+    }
+
+    /// Ignores the caches of the sources read from configuration files
+    ///
+    /// Sources with a `cache_ttl` setting are queried for every request, and
+    /// HTTP sources fetch their document again.
+    pub fn disable_cache(mut self) -> Self {
+        self.no_cache = true;
+        self
+    }
+
+    /// Expands environment variables which are not set to an empty string
+    /// in the configuration files instead of failing
+    ///
+    /// See [`Resolver::from_config`] for the settings in which variables are
+    /// expanded.
+    pub fn allow_undefined_variables(mut self) -> Self {
+        self.allow_undefined_variables = true;
+        self
+    }
+
+    /// Builds the resolver
+    ///
+    /// Fails if the configuration files cannot be read when they are merged,
+    /// if the default source set with
+    /// [`set_default_source`](Self::set_default_source) does not exist, or if
+    /// the sigil set with [`group_sigil`](Self::group_sigil) is invalid.
+    pub fn build(self) -> Result<Resolver, ConfigurationError> {
+        let resolver = if self.merge_config {
+            Resolver::from_config_with(LoadOptions {
+                cache: !self.no_cache,
+                allow_undefined_variables: self.allow_undefined_variables,
+            })?
+        } else {
+            Resolver {
+                default_source: "default".to_string(),
+                ..Default::default()
+            }
+        };
+
+        self.build_from(resolver)
+    }
+
+    fn build_from(self, mut resolver: Resolver) -> Result<Resolver, ConfigurationError> {
+        if let Some(origin) = &mut resolver.origin {
+            origin.added = self.sources.iter().map(|(name, _)| name.clone()).collect();
+            origin.default_source = self.default_source.clone();
+            origin.group_sigil = self.group_sigil;
+        }
+
+        resolver.extend_sources(self.sources);
+        resolver.lock_group_index().clear();
+        resolver.memo.clear();
+
+        if let Some(default_source) = &self.default_source {
+            resolver.set_default_source(default_source)?;
+        }
+
+        if let Some(sigil) = self.group_sigil {
+            resolver.set_group_sigil(sigil)?;
+        }
+
+        Ok(resolver)
     }
 }
 
-#[cfg(test)]
-#[derive(Debug)]
-pub(crate) struct DummySource {
-    map: HashMap<String, String>,
+/// Open a config file from a path, expanding environment variables.
+///
+/// Returns None if there was any failure
+fn open_config_path(path: &Path) -> Option<std::fs::File> {
+    resolve_config_path(path).and_then(|p| std::fs::File::open(p).ok())
 }
-#[cfg(test)]
-impl DummySource {
-    pub(crate) fn new() -> Self {
-        Self {
-            map: HashMap::new(),
+
+/// Expands environment variables in a path
+///
+/// Returns None in case of non-utf8 path
+pub(crate) fn resolve_config_path(path: &Path) -> Option<PathBuf> {
+    let context = |s: &str| match s {
+        "HOME" => std::env::var("HOME").ok(),
+        "XDG_CONFIG_HOME" => std::env::var("XDG_CONFIG_HOME").ok().or_else(|| {
+            std::env::var("HOME")
+                .ok()
+                .map(|h| Path::new(&h).join(".config").to_str().unwrap().to_string())
+        }),
+        _ => None,
+    };
+
+    Some(PathBuf::from(
+        env_with_context_no_errors(path.to_str()?, context).as_ref(),
+    ))
+}
+
+/// Returns a list of files with a given extension in a directory.
+///
+/// Returns an empty list in case of any failure
+fn find_files_with_ext(dir: &Path, ext: &str) -> Vec<PathBuf> {
+    let mut files = vec![];
+
+    let Ok(it) = fs::read_dir(dir) else {
+        return files;
+    };
+
+    for entry in it {
+        let entry = entry.unwrap();
+        let path = entry.path();
+
+        if path.is_file() && path.extension().map(|ext| ext.to_str()) == Some(Some(ext)) {
+            files.push(path);
         }
     }
 
-    pub(crate) fn add(&mut self, group: &str, nodes: &str) {
-        self.map.insert(group.to_string(), nodes.to_string());
-    }
+    files.sort();
+    files
 }
 
-#[cfg(test)]
-impl GroupSource for DummySource {
-    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
-        Ok(self.map.get(group).cloned())
+/// Trait for group resolution features of a group source
+///
+/// The built-in sources (upcalls, YAML files, genders, Slurm...) implement
+/// this trait, and other backends can implement it to be added to a
+/// resolver with [`ResolverBuilder::add_source`].
+///
+/// Sources are stored as `Box<dyn GroupSource>` and shared between threads, so
+/// they must be `Send` and `Sync`, and the trait must stay object safe:
+/// methods take `&self` (or `&mut self` for configuration) and have no type
+/// parameters. Sources caching their groups use interior mutability such as a
+/// `Mutex` or a `OnceLock`.
+///
+/// Members, nodes and group lists are returned as strings in nodeset syntax
+/// and are parsed by the resolver, so members may be folded and may refer to
+/// other groups (`@group` or `@source:group`).
+///
+/// # Errors
+///
+/// A group which is not defined by the source is not an error: `map` returns
+/// `Ok(None)`. Errors report failures of the backend, usually as
+/// [`NodeSetParseError::Command`] with an [`std::io::ErrorKind`] describing
+/// the failure, and are returned to the callers of
+/// [`Resolver::resolve`](crate::Resolver::resolve) and to parsers referring
+/// to the group.
+///
+/// # Examples
+///
+/// ```
+/// use nodeset::{GroupSource, NodeSet, NodeSetParseError, ResolverBuilder};
+///
+/// /// Groups `rackN` hold the 4 nodes of each rack
+/// #[derive(Debug)]
+/// struct Racks(u32);
+///
+/// impl GroupSource for Racks {
+///     fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+///         Ok(group
+///             .strip_prefix("rack")
+///             .and_then(|n| n.parse::<u32>().ok())
+///             .filter(|n| (1..=self.0).contains(n))
+///             .map(|n| format!("r{n}n[1-4]")))
+///     }
+///
+///     fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+///         Ok(vec![format!("rack[1-{}]", self.0)])
+///     }
+/// }
+///
+/// let resolver = ResolverBuilder::new()
+///     .add_source("racks", Box::new(Racks(2)))
+///     .set_default_source("racks")
+///     .build()
+///     .unwrap();
+///
+/// let rack: NodeSet = resolver.resolve(None, "rack2").unwrap();
+/// let all: NodeSet = resolver.all_nodes(None).unwrap();
+///
+/// assert_eq!(rack.to_string(), "r2n[1-4]");
+/// assert_eq!(all.to_string(), "r[1-2]n[1-4]");
+/// ```
+pub trait GroupSource: Debug + Send + Sync {
+    /// Returns the members of `group`, or None if the source does not define it
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError>;
+
+    /// Returns the names of all the groups defined by the source
+    ///
+    /// Names may be folded (ie `rack[1-4]`). Names with characters which are
+    /// not allowed in nodesets, such as spaces or commas, must be quoted (ie
+    /// `"rack 1"`).
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError>;
+
+    /// Returns the members of each group of `groups`, in the same order
+    ///
+    /// The resolver calls this method to resolve several groups at once.
+    /// Sources which can answer for many groups more efficiently than one at
+    /// a time may override it, the default implementation calls
+    /// [`GroupSource::map`] for each group.
+    fn map_many(&self, groups: &[&str]) -> Vec<Result<Option<String>, NodeSetParseError>> {
+        groups.iter().map(|group| self.map(group)).collect()
     }
 
-    fn list(&self) -> String {
-        use itertools::Itertools;
+    /// Returns all the nodes of the source, or None if the source cannot
+    /// provide them directly
+    ///
+    /// The resolver then resolves all the groups of the source.
+    fn all(&self) -> Result<Option<String>, NodeSetParseError> {
+        Ok(None)
+    }
+
+    /// Returns the groups `node` belongs to, or None if the source cannot
+    /// provide them directly
+    ///
+    /// The resolver then looks for the node in all the groups of the source.
+    fn reverse(&self, _node: &str) -> Result<Option<String>, NodeSetParseError> {
+        Ok(None)
+    }
+
+    /// Returns the kind of the source (ie `upcall`, `exec`, `yaml` or
+    /// `http`), for diagnostics
+    fn kind(&self) -> &str {
+        "custom"
+    }
 
-        self.map.keys().join(" ")
+    /// Returns where the groups of the source come from (ie a file, a
+    /// command or a URL), for diagnostics
+    fn origin(&self) -> Option<String> {
+        None
+    }
+
+    /// Substitutes `$CFGDIR` in the settings of the source
+    ///
+    /// This is only called for sources read from configuration files.
+    fn set_cfgdir(&mut self, _cfgdir: &str) -> Result<(), ConfigurationError> {
+        Ok(())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{collections::parsers::Parser, IdRangeList};
+/// Settings from the main group configuration file (groups.conf)
+#[derive(Debug, Default)]
+struct MainGroupConfig {
+    config: Option<ResolverOptions>,
+    sources: HashMap<String, Box<dyn GroupSource>>,
+}
+
+impl MainGroupConfig {
+    fn from_reader(
+        mut reader: impl std::io::Read,
+        options: LoadOptions,
+    ) -> Result<Self, ConfigurationError> {
+        use ini::Ini;
+
+        let parser = Ini::read_from_noescape(&mut reader)?;
+        let mut config = MainGroupConfig::default();
+        for (sec, prop) in parser.iter() {
+            match sec {
+                Some("Main") => {
+                    let prop = expand_properties(prop, options.allow_undefined_variables)?;
+                    config.config = Some((&prop).try_into()?);
+                }
+                Some(sources) => {
+                    let prop = expand_properties(prop, options.allow_undefined_variables)?;
+                    for source in sources.split(',').map(str::trim) {
+                        config.sources.insert(
+                            source.to_string(),
+                            source_from_props(&prop, source, options.cache)?,
+                        );
+                    }
+                }
+                None => {
+                    if let Some(key) = prop.iter().next().map(|(k, _)| k) {
+                        return Err(ConfigurationError::UnexpectedProperty(key.to_string()));
+                    }
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn autodirs(&self) -> Vec<String> {
+        self.config
+            .as_ref()
+            .map(|c| c.autodirs())
+            .unwrap_or_default()
+    }
+
+    fn confdirs(&self) -> Vec<String> {
+        self.config
+            .as_ref()
+            .map(|c| c.confdirs())
+            .unwrap_or_default()
+    }
+
+    fn set_cfgdir(&mut self, cfgdir: &str) -> Result<(), ConfigurationError> {
+        let context = |s: &str| match s {
+            "CFGDIR" => Some(cfgdir),
+            _ => None,
+        };
+
+        if let Some(config) = &mut self.config {
+            config.confdir = config
+                .confdir
+                .as_ref()
+                .map(|c| env_with_context_no_errors(&c, context).to_string());
+            config.autodir = config
+                .autodir
+                .as_ref()
+                .map(|c| env_with_context_no_errors(&c, context).to_string());
+        }
+
+        for (_, group) in self.sources.iter_mut() {
+            group.set_cfgdir(cfgdir)?;
+        }
+
+        Ok(())
+    }
+
+    /// Merge settings for another group configuration file into this one
+    fn merge(&mut self, other: Self) {
+        match (&mut self.config, other.config) {
+            (Some(ref mut main), Some(other_main)) => main.merge(other_main),
+            (None, Some(other_main)) => self.config = Some(other_main),
+            _ => (),
+        }
+        self.sources.extend(other.sources);
+    }
+}
+
+impl IntoIterator for MainGroupConfig {
+    type Item = (String, Box<dyn GroupSource>);
+    type IntoIter = std::collections::hash_map::IntoIter<String, Box<dyn GroupSource>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.sources.into_iter()
+    }
+}
+
+#[derive(Debug, Default)]
+struct ResolverOptions {
+    default: Option<String>,
+    confdir: Option<String>,
+    autodir: Option<String>,
+    group_sigil: Option<char>,
+}
+
+impl ResolverOptions {
+    /// Merge resolver options from `other` into `self`
+    fn merge(&mut self, other: Self) {
+        if let Some(default) = other.default {
+            self.default = Some(default);
+        }
+        if let Some(confdir) = other.confdir {
+            self.confdir = Some(confdir);
+        }
+        if let Some(autodir) = other.autodir {
+            self.autodir = Some(autodir);
+        }
+        if let Some(group_sigil) = other.group_sigil {
+            self.group_sigil = Some(group_sigil);
+        }
+    }
+
+    fn autodirs(&self) -> Vec<String> {
+        self.autodir
+            .as_ref()
+            .and_then(|autodir| shlex::split(autodir))
+            .unwrap_or_default()
+    }
+
+    fn confdirs(&self) -> Vec<String> {
+        self.confdir
+            .as_ref()
+            .and_then(|confdir| shlex::split(confdir))
+            .unwrap_or_default()
+    }
+}
+
+impl TryFrom<&Properties> for ResolverOptions {
+    type Error = ConfigurationError;
+
+    fn try_from(props: &Properties) -> Result<Self, Self::Error> {
+        let mut res = Self::default();
+
+        for (k, v) in props.iter() {
+            match k {
+                "default" => {
+                    res.default = Some(v.to_string());
+                }
+                // groupsdir is the name used by ClusterShell before 1.7
+                "confdir" | "groupsdir" => {
+                    res.confdir = Some(v.to_string());
+                }
+                "autodir" => {
+                    res.autodir = Some(v.to_string());
+                }
+                "group_sigil" => {
+                    let mut chars = v.chars();
+                    res.group_sigil = match (chars.next(), chars.next()) {
+                        (Some(c), None) if is_valid_group_sigil(c) => Some(c),
+                        _ => {
+                            return Err(ConfigurationError::InvalidValue(
+                                k.to_string(),
+                                v.to_string(),
+                            ))
+                        }
+                    };
+                }
+                _ => {
+                    return Err(ConfigurationError::UnexpectedProperty(k.to_string()));
+                }
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+/// Builds a group source from a groups.conf section according to its `type`
+///
+/// Sections without a type define ClusterShell upcalls run through the shell.
+/// Sources are wrapped in a cache if their section sets `cache_ttl`, unless
+/// `cache` is false.
+fn source_from_props(
+    props: &Properties,
+    name: &str,
+    cache: bool,
+) -> Result<Box<dyn GroupSource>, ConfigurationError> {
+    let source: Box<dyn GroupSource> = match props.get("type") {
+        None => Box::new(DynamicGroupSource::from_props(props, name.to_string())?),
+        Some("exec") => Box::new(ExecSource::from_props(props, name.to_string())?),
+        Some("http") => {
+            let source = HttpSource::from_props(props, name.to_string())?;
+            Box::new(if cache {
+                source
+            } else {
+                source.without_cache()
+            })
+        }
+        Some("genders") => Box::new(GendersSource::from_props(props)?),
+        Some("slurm") => Box::new(SlurmSource::from_props(props, name.to_string())?),
+        Some("ansible") => Box::new(AnsibleSource::from_props(props)?),
+        Some("dsh") => Box::new(DshSource::from_props(props)?),
+        Some("netgroup") => Box::new(NetgroupSource::from_props(props, name.to_string())?),
+        Some(t) => {
+            return Err(ConfigurationError::InvalidValue(
+                "type".to_string(),
+                t.to_string(),
+            ))
+        }
+    };
+
+    let source = FailFastSource::from_props(props, name, source)?;
+    if !cache {
+        return Ok(source);
+    }
+
+    CachedSource::from_props(props, name, source)
+}
+
+/// Settings from a dynamic group source (groups.conf.d/<source>.conf)
+#[derive(Debug)]
+struct DynamicGroupSource {
+    name: String,
+    map: String,
+    all: Option<String>,
+    list: Option<String>,
+    reverse: Option<String>,
+}
+
+impl DynamicGroupSource {
+    fn from_props(props: &Properties, name: String) -> Result<Self, ConfigurationError> {
+        let map = props
+            .get("map")
+            .ok_or_else(|| ConfigurationError::MissingProperty("map".to_string()))?
+            .to_string();
+        let all = props.get("all").map(|s| s.to_string());
+        let list = props.get("list").map(|s| s.to_string());
+        let reverse = props.get("reverse").map(|s| s.to_string());
+
+        Ok(Self {
+            name,
+            map,
+            all,
+            list,
+            reverse,
+        })
+    }
+}
+
+impl DynamicGroupSource {
+    /// Run an upcall command through the shell and return its trimmed output
+    ///
+    /// `$SOURCE` and the variables provided by `context` are substituted in
+    /// `cmd` before it is executed.
+    fn upcall<'a>(
+        &'a self,
+        kind: &str,
+        cmd: &str,
+        context: impl Fn(&str) -> Option<&'a str>,
+    ) -> Result<String, NodeSetParseError> {
+        let cmd = env_with_context_no_errors(cmd, |s: &str| match s {
+            "SOURCE" => Some(self.name.as_str()),
+            s => context(s),
+        })
+        .to_string();
+
+        let output = Command::new("/bin/sh").arg("-c").arg(&cmd).output()?;
+
+        if !output.status.success() {
+            return Err(NodeSetParseError::Command(std::io::Error::other(
+                CommandFailed(format!("Command '{}' returned non-zero exit code", cmd)),
+            )));
+        }
+
+        let res = String::from_utf8_lossy(&output.stdout);
+
+        debug!(
+            "{} command '{}' for source '{}' returned: {}",
+            kind, cmd, self.name, res
+        );
+
+        Ok(res.trim().to_string())
+    }
+}
+
+impl GroupSource for DynamicGroupSource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        self.upcall("Map", &self.map, |s| (s == "GROUP").then_some(group))
+            .map(Some)
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        let Some(ref list) = self.list else {
+            return Ok(Default::default());
+        };
+
+        Ok(self
+            .upcall("List", list, |_| None)?
+            .split_whitespace()
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn all(&self) -> Result<Option<String>, NodeSetParseError> {
+        self.all
+            .as_ref()
+            .map(|all| self.upcall("All", all, |_| None))
+            .transpose()
+    }
+
+    fn reverse(&self, node: &str) -> Result<Option<String>, NodeSetParseError> {
+        self.reverse
+            .as_ref()
+            .map(|reverse| self.upcall("Reverse", reverse, |s| (s == "NODE").then_some(node)))
+            .transpose()
+    }
+
+    fn set_cfgdir(&mut self, cfgdir: &str) -> Result<(), ConfigurationError> {
+        let context = |s: &str| match s {
+            "CFGDIR" => Some(cfgdir),
+            "SOURCE" => Some(self.name.as_str()),
+            _ => None,
+        };
+
+        self.map = env_with_context_no_errors(&self.map, context).to_string();
+        self.all = self
+            .all
+            .as_ref()
+            .map(|s| env_with_context_no_errors(s, context).to_string());
+        self.list = self
+            .list
+            .as_ref()
+            .map(|s| env_with_context_no_errors(s, context).to_string());
+        self.reverse = self
+            .reverse
+            .as_ref()
+            .map(|s| env_with_context_no_errors(s, context).to_string());
+
+        Ok(())
+    }
+
+    fn kind(&self) -> &str {
+        "upcall"
+    }
+
+    fn origin(&self) -> Option<String> {
+        Some(self.map.clone())
+    }
+}
+
+/// Settings from a static group source configuration file (groups.d/*.yaml or
+/// groups.d/*.json)
+///
+/// Each top-level key of the file defines a source, which maps group names to
+/// their members. Members are either a nodeset string or a list of nodesets.
+#[derive(Debug)]
+struct StaticGroupConfig {
+    sources: HashMap<String, StaticSource>,
+}
+
+impl StaticGroupConfig {
+    fn from_reader(reader: impl std::io::Read) -> Result<Self, ConfigurationError> {
+        Self::from_value(serde_yaml::from_reader(reader)?)
+    }
+
+    fn from_value(config: serde_yaml::Value) -> Result<Self, ConfigurationError> {
+        use serde_yaml::Value;
+
+        let invalid = |key: String, reason: &str| {
+            Err(ConfigurationError::InvalidGroup(key, reason.to_string()))
+        };
+
+        let mut sources = HashMap::new();
+        let config = match config {
+            Value::Mapping(config) => config,
+            Value::Null => Default::default(),
+            _ => return invalid(String::new(), "expected a mapping of sources"),
+        };
+
+        for (source, groups) in config {
+            let Some(source) = yaml_key(&source) else {
+                return invalid(format!("{source:?}"), "invalid source name");
+            };
+
+            let groups = StaticSource::from_value(groups, &source)?;
+            sources.insert(source, groups);
+        }
+
+        Ok(Self { sources })
+    }
+}
+
+/// Converts a scalar YAML value to a string
+fn yaml_key(value: &serde_yaml::Value) -> Option<String> {
+    use serde_yaml::Value;
+
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+impl IntoIterator for StaticGroupConfig {
+    type Item = (String, StaticSource);
+    type IntoIter = std::collections::hash_map::IntoIter<String, StaticSource>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.sources.into_iter()
+    }
+}
+
+/// A group source whose groups are held in memory
+///
+/// This is the kind of source defined by the YAML and JSON group files. It
+/// can also be built from group names and their members with
+/// [`FromIterator`] to be added to a [`ResolverBuilder`]. Members are
+/// nodeset expressions which may refer to other groups (ie `@othergroup` or
+/// `@source:group`).
+///
+/// # Examples
+///
+/// ```
+/// use nodeset::StaticSource;
+///
+/// let source = StaticSource::from_iter([
+///     ("login".to_string(), "login[1-2]".to_string()),
+///     ("compute".to_string(), "node[1-10]".to_string()),
+/// ]);
+/// ```
+#[derive(Debug, Default)]
+pub struct StaticSource {
+    groups: HashMap<String, String>,
+    /// The group file the source was read from
+    file: Option<PathBuf>,
+}
+
+impl FromIterator<(String, String)> for StaticSource {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(groups: I) -> Self {
+        Self {
+            groups: groups.into_iter().collect(),
+            file: None,
+        }
+    }
+}
+
+impl StaticSource {
+    /// Reads the groups of a source from a mapping of group names to members
+    ///
+    /// `source` is only used to report the location of invalid definitions.
+    pub(crate) fn from_value(
+        groups: serde_yaml::Value,
+        source: &str,
+    ) -> Result<Self, ConfigurationError> {
+        use serde_yaml::Value;
+
+        let invalid = |key: String, reason: &str| {
+            Err(ConfigurationError::InvalidGroup(key, reason.to_string()))
+        };
+
+        let groups = match groups {
+            Value::Mapping(groups) => groups,
+            Value::Null => Default::default(),
+            _ => return invalid(source.to_string(), "expected a mapping of groups"),
+        };
+
+        let mut members = HashMap::new();
+        for (group, nodes) in groups {
+            let Some(group) = yaml_key(&group) else {
+                return invalid(format!("{source}.{group:?}"), "invalid group name");
+            };
+
+            let nodes = match nodes {
+                Value::Sequence(nodes) => nodes.iter().map(yaml_key).collect::<Option<Vec<_>>>(),
+                nodes => yaml_key(&nodes).map(|n| vec![n]),
+            };
+            let Some(nodes) = nodes else {
+                return invalid(
+                    format!("{source}.{group}"),
+                    "expected a nodeset or a list of nodesets",
+                );
+            };
+
+            members.insert(group, nodes.join(","));
+        }
+
+        Ok(Self {
+            groups: members,
+            file: None,
+        })
+    }
+}
+
+impl GroupSource for StaticSource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        Ok(self.groups.get(group).cloned())
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        use itertools::Itertools;
+        Ok(self
+            .groups
+            .keys()
+            .sorted()
+            .map(|group| quote_group(group, true).into_owned())
+            .collect())
+    }
+
+    fn kind(&self) -> &str {
+        match &self.file {
+            Some(file) if file.extension().is_some_and(|ext| ext == "json") => "json",
+            Some(_) => "yaml",
+            None => "static",
+        }
+    }
+
+    fn origin(&self) -> Option<String> {
+        self.file.as_ref().map(|file| file.display().to_string())
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct DummySource {
+    map: HashMap<String, String>,
+}
+#[cfg(test)]
+impl DummySource {
+    pub(crate) fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, group: &str, nodes: &str) {
+        self.map.insert(group.to_string(), nodes.to_string());
+    }
+}
+
+#[cfg(test)]
+impl GroupSource for DummySource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        Ok(self.map.get(group).cloned())
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        use itertools::Itertools;
+
+        Ok(self
+            .map
+            .keys()
+            .sorted()
+            .map(|group| quote_group(group, true).into_owned())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::IdRangeList;
+
+    use super::*;
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn test_static_config() {
+        let config = include_str!("tests/cluster.yaml");
+        let mut resolver = Resolver::default();
+        resolver.add_sources(StaticGroupConfig::from_reader(config.as_bytes()).unwrap());
+        let parser = Parser::with_resolver(&resolver, Some("roles"));
+        assert_eq!(
+            parser.parse::<IdRangeList>("@login").unwrap().to_string(),
+            "login[1-2]"
+        );
+
+        assert_eq!(
+            parser.parse::<IdRangeList>("@*").unwrap(),
+            parser
+                .parse::<IdRangeList>(
+                    "node[0001-0288],mds[1-4],oss[0-15],server0001,login[1-2],mgmt[1-2]"
+                )
+                .unwrap()
+        );
+
+        match parser.parse::<IdRangeList>("@login:aa") {
+            Err(NodeSetParseError::UnknownSource { .. }) => (),
+            e => panic!("Expected Source error, got {e:?}",),
+        }
+
+        assert_eq!(
+            parser
+                .parse::<IdRangeList>("@roles:cpu_only")
+                .unwrap()
+                .to_string(),
+            "node[0009-0288]"
+        );
+
+        match parser.parse::<IdRangeList>("@roles:non_existent") {
+            Err(NodeSetParseError::UnknownGroup { group, .. }) => assert_eq!(group, "non_existent"),
+            e => panic!("Expected UnknownGroup error, got {e:?}"),
+        }
+
+        match parser.parse::<IdRangeList>("@non_existent:non_existent") {
+            Err(NodeSetParseError::UnknownSource { .. }) => (),
+            _ => panic!("Expected Source error"),
+        }
+
+        let ns1 = parser.parse::<IdRangeList>("@rack[1-2]:hsw").unwrap();
+        let ns2 = "mgmt[1-2],oss[0-15],mds[1-4]".parse().unwrap();
+        assert_eq!(ns1, ns2);
+
+        let ns1 = parser.parse::<IdRangeList>("@rack[1-2]:*").unwrap();
+        let ns2 = "mgmt[1-2],oss[0-15],mds[1-4],node[0001-0288]"
+            .parse()
+            .unwrap();
+        assert_eq!(ns1, ns2);
+
+        let ns1 = parser.parse::<IdRangeList>("@network:net[1,3]").unwrap();
+        let ns2 = "node[10-19,30-39]".parse().unwrap();
+        assert_eq!(ns1, ns2);
+
+        // Group names as nodes
+        assert_eq!(
+            parser
+                .parse::<IdRangeList>("@@network")
+                .unwrap()
+                .to_string(),
+            "net[1-3]"
+        );
+        assert_eq!(
+            parser
+                .parse::<IdRangeList>("@@lustre:")
+                .unwrap()
+                .to_string(),
+            "data,mds,oss,rbh"
+        );
+        assert_eq!(
+            parser
+                .parse::<IdRangeList>("@@rack[1-2],@@network!net2")
+                .unwrap()
+                .to_string(),
+            "hsw,ivy,net[1,3]"
+        );
+        assert_eq!(
+            parser.parse::<IdRangeList>("@@").unwrap(),
+            "adm,all,compute,cpu_only,gpu,login,servers,storage"
+                .parse()
+                .unwrap()
+        );
+        match parser.parse::<IdRangeList>("@@non_existent") {
+            Err(NodeSetParseError::UnknownSource { .. }) => (),
+            e => panic!("Expected Source error, got {e:?}"),
+        }
+
+        assert_eq!(
+            resolver.list_groups::<IdRangeList>(Some("numerical")),
+            "1-2,03".parse::<NodeSet>().unwrap()
+        );
+
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(Some("numerical"), "1")
+                .unwrap(),
+            "node[10-19]".parse::<NodeSet>().unwrap()
+        );
+    }
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn test_nested_groups() {
+        let config = include_str!("tests/nested.yaml");
+        let mut resolver = Resolver::default();
+        resolver.add_sources(StaticGroupConfig::from_reader(config.as_bytes()).unwrap());
+
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(Some("racks"), "compute")
+                .unwrap(),
+            "node[01-04,06-19,21-32,99]".parse::<NodeSet>().unwrap()
+        );
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(Some("racks"), "available")
+                .unwrap(),
+            "node[02-04,06-19,21-29,34,36-40]"
+                .parse::<NodeSet>()
+                .unwrap()
+        );
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(Some("racks"), "all")
+                .unwrap(),
+            "node[02-04,06-19,21-29,34,36-40],login[1-2]"
+                .parse::<NodeSet>()
+                .unwrap()
+        );
+
+        match resolver.resolve::<IdRangeList>(Some("cycle"), "a") {
+            Err(NodeSetParseError::GroupCycle(cycle)) => {
+                assert_eq!(cycle, "@cycle:a -> @cycle:b -> @cycle:c -> @cycle:a")
+            }
+            e => panic!("Expected GroupCycle error, got {e:?}"),
+        }
+        let parser = Parser::with_resolver(&resolver, Some("cycle"));
+        assert!(matches!(
+            parser.parse::<IdRangeList>("node1,@self"),
+            Err(NodeSetParseError::GroupCycle(_))
+        ));
+
+        // Resolution is not affected by previous cycles
+        assert_eq!(
+            parser.parse::<IdRangeList>("@racks:rack1").unwrap(),
+            "node[01-16]".parse::<NodeSet>().unwrap()
+        );
+    }
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn test_cross_source_groups() {
+        let source = |groups: &[(&str, &str)]| {
+            groups
+                .iter()
+                .map(|(group, nodes)| (group.to_string(), nodes.to_string()))
+                .collect::<StaticSource>()
+        };
+
+        let mut resolver = Resolver::default();
+        resolver.add_sources([
+            (
+                "site".to_string(),
+                source(&[
+                    ("all_compute", "@slurm:compute,@legacy:compute"),
+                    ("compute", "site[1-2]"),
+                    ("gpu", "@slurm:gpu"),
+                    ("nested", "@gpu"),
+                    ("unknown_source", "@nosuch:compute"),
+                    ("loop", "@slurm:loop"),
+                ]),
+            ),
+            (
+                "slurm".to_string(),
+                source(&[
+                    ("compute", "n[1-4],@gpu"),
+                    ("gpu", "n[5-6]"),
+                    ("loop", "@legacy:loop"),
+                ]),
+            ),
+            (
+                "legacy".to_string(),
+                source(&[("compute", "old[1-2]"), ("loop", "@site:loop")]),
+            ),
+        ]);
+        resolver.set_default_source("site").unwrap();
+
+        // Groups without a source refer to the source of the defining group
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(None, "all_compute")
+                .unwrap()
+                .to_string(),
+            "n[1-6],old[1-2]"
+        );
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(None, "nested")
+                .unwrap()
+                .to_string(),
+            "n[5-6]"
+        );
+
+        match resolver.resolve::<IdRangeList>(None, "loop") {
+            Err(NodeSetParseError::GroupCycle(cycle)) => assert_eq!(
+                cycle,
+                "@site:loop -> @slurm:loop -> @legacy:loop -> @site:loop"
+            ),
+            e => panic!("Expected GroupCycle error, got {e:?}"),
+        }
+
+        match resolver.resolve::<IdRangeList>(None, "unknown_source") {
+            Err(NodeSetParseError::GroupDefinition(path, e)) => {
+                assert_eq!(path, "@site:unknown_source");
+                assert!(
+                    matches!(*e, NodeSetParseError::UnknownSource { .. }),
+                    "{e:?}"
+                );
+            }
+            e => panic!("Expected GroupDefinition error, got {e:?}"),
+        }
+
+        // Errors report all the groups being resolved
+        resolver.add_sources([("slurm".to_string(), source(&[("compute", "n[1-4]")]))]);
+        let e = resolver.resolve::<IdRangeList>(None, "nested").unwrap_err();
+        match &e {
+            NodeSetParseError::GroupDefinition(path, e) => {
+                assert_eq!(path, "@site:nested -> @site:gpu");
+                assert!(
+                    matches!(&**e, NodeSetParseError::UnknownGroup { group, group_source, .. }
+                        if group == "gpu" && group_source == "slurm"),
+                    "{e:?}"
+                );
+            }
+            e => panic!("Expected GroupDefinition error, got {e:?}"),
+        }
+        assert_eq!(
+            e.to_string(),
+            "in the definition of @site:nested -> @site:gpu: unknown group 'gpu' in source 'slurm'"
+        );
+    }
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn test_nested_groups_depth() {
+        let chain = |len: usize| {
+            (0..len)
+                .map(|i| (format!("g{i}"), format!("node{i},@g{}", i + 1)))
+                .chain(std::iter::once((format!("g{len}"), "last".to_string())))
+                .collect::<StaticSource>()
+        };
+
+        let mut resolver = Resolver::default();
+        resolver.add_sources([("deep".to_string(), chain(MAX_GROUP_DEPTH - 1))]);
+        let ns = resolver.resolve::<IdRangeList>(Some("deep"), "g0").unwrap();
+        assert_eq!(ns.len(), MAX_GROUP_DEPTH);
+        assert!(ns.contains("last").unwrap());
+
+        resolver.add_sources([("deep".to_string(), chain(MAX_GROUP_DEPTH))]);
+        match resolver.resolve::<IdRangeList>(Some("deep"), "g0") {
+            Err(NodeSetParseError::GroupDepth(path)) => {
+                assert!(path.starts_with("@deep:g0 -> @deep:g1 -> "), "{path}");
+                assert!(
+                    path.ends_with(&format!("@deep:g{MAX_GROUP_DEPTH}")),
+                    "{path}"
+                );
+            }
+            e => panic!("Expected GroupDepth error, got {e:?}"),
+        }
+
+        // The resolution stack is unwound after an error
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(Some("deep"), "g60")
+                .unwrap()
+                .len(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_yaml_dir() {
+        use tempfile::TempDir;
+
+        let tmp_dir = TempDir::new().unwrap();
+        std::fs::write(
+            tmp_dir.path().join("cmdb.yaml"),
+            "cmdb:\n  \
+               compute: [node1, 'node[2-4]']\n  \
+               gpu: node[5-6]\n  \
+               all: '@compute,@gpu,@site:login'\n  \
+               10: [10, 11]\n  \
+               'rack 1:a': '@gpu'\n\
+             site:\n  \
+               login: login[1-2]\n",
+        )
+        .unwrap();
+        std::fs::write(tmp_dir.path().join("ignored.yml"), "not: [valid").unwrap();
+
+        let mut resolver = Resolver::default();
+        resolver.add_yaml_dir(tmp_dir.path()).unwrap();
+        resolver.default_source = "cmdb".to_string();
+
+        assert_eq!(
+            resolver.resolve::<IdRangeList>(None, "compute").unwrap(),
+            "node[1-4]".parse::<NodeSet>().unwrap()
+        );
+        assert_eq!(
+            resolver.resolve::<IdRangeList>(None, "10").unwrap(),
+            "10-11".parse::<NodeSet>().unwrap()
+        );
+        #[cfg(feature = "groups")]
+        assert_eq!(
+            resolver.resolve::<IdRangeList>(None, "all").unwrap(),
+            "node[1-6],login[1-2]".parse::<NodeSet>().unwrap()
+        );
+        #[cfg(feature = "groups")]
+        assert_eq!(
+            resolver.resolve::<IdRangeList>(None, "rack 1:a").unwrap(),
+            "node[5-6]".parse::<NodeSet>().unwrap()
+        );
+        assert!(resolver
+            .list_groups::<IdRangeList>(None)
+            .iter()
+            .any(|group| group == "rack 1:a"));
+
+        std::fs::write(
+            tmp_dir.path().join("invalid.yaml"),
+            "site:\n  login: {nested: map}\n",
+        )
+        .unwrap();
+        match Resolver::default().add_yaml_dir(tmp_dir.path()) {
+            Err(ConfigurationError::File(path, e)) => {
+                assert_eq!(path, tmp_dir.path().join("invalid.yaml"));
+                assert!(
+                    matches!(*e, ConfigurationError::InvalidGroup(ref key, _) if key == "site.login"),
+                    "{e}"
+                );
+            }
+            e => panic!("Expected File error, got {e:?}"),
+        }
+
+        std::fs::write(tmp_dir.path().join("invalid.yaml"), "site: [login]\n").unwrap();
+        match Resolver::default().add_yaml_dir(tmp_dir.path()) {
+            Err(ConfigurationError::File(_, e)) => {
+                assert!(matches!(*e, ConfigurationError::InvalidGroup(ref key, _) if key == "site"))
+            }
+            e => panic!("Expected File error, got {e:?}"),
+        }
+    }
+
+    #[test]
+    fn test_json_dir() {
+        use tempfile::TempDir;
+
+        let tmp_dir = TempDir::new().unwrap();
+        std::fs::write(
+            tmp_dir.path().join("cmdb.json"),
+            "{\n\t\"cmdb\": {\"compute\": [\"node1\", \"node[2-4]\"], \"gpu\": \"node[5-6]\"}\n}\n",
+        )
+        .unwrap();
+
+        let mut resolver = Resolver::default();
+        resolver.add_json_dir(tmp_dir.path()).unwrap();
+        resolver.default_source = "cmdb".to_string();
+
+        assert_eq!(
+            resolver.resolve::<IdRangeList>(None, "compute").unwrap(),
+            "node[1-4]".parse::<NodeSet>().unwrap()
+        );
+        assert_eq!(
+            resolver.list_groups::<IdRangeList>(None),
+            "compute,gpu".parse::<NodeSet>().unwrap()
+        );
+
+        std::fs::write(tmp_dir.path().join("invalid.json"), "{\"site\": [").unwrap();
+        match Resolver::default().add_json_dir(tmp_dir.path()) {
+            Err(ConfigurationError::File(path, e)) => {
+                assert_eq!(path, tmp_dir.path().join("invalid.json"));
+                assert!(matches!(*e, ConfigurationError::InvalidYamlFile(_)), "{e}");
+            }
+            e => panic!("Expected File error, got {e:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_dynamic_config() {
+        use tempfile::TempDir;
+
+        let config = include_str!("tests/groups.conf");
+        let mut dynamic =
+            MainGroupConfig::from_reader(config.as_bytes(), LoadOptions::default()).unwrap();
+
+        let tmp_dir = TempDir::new().unwrap();
+
+        std::fs::create_dir(tmp_dir.path().join("groups.d")).unwrap();
+        std::fs::write(
+            tmp_dir.path().join("groups.d").join("local.cfg"),
+            include_str!("tests/local.cfg"),
+        )
+        .unwrap();
+
+        std::fs::create_dir(tmp_dir.path().join("groups.conf.d")).unwrap();
+        std::fs::write(
+            tmp_dir.path().join("groups.conf.d").join("multi.conf"),
+            include_str!("tests/multi.conf"),
+        )
+        .unwrap();
+
+        dynamic
+            .set_cfgdir(tmp_dir.path().to_str().unwrap())
+            .unwrap();
+
+        assert_eq!(
+            dynamic.autodirs(),
+            vec![
+                "/etc/clustershell/groups.d",
+                &format!("{}/groups.d", tmp_dir.path().to_str().unwrap())
+            ]
+        );
+        assert_eq!(
+            dynamic.confdirs(),
+            vec![
+                "/etc/clustershell/groups.conf.d",
+                &format!("{}/groups.conf.d", tmp_dir.path().to_str().unwrap())
+            ]
+        );
+
+        let resolver = Resolver::from_dynamic_config(dynamic, LoadOptions::default()).unwrap();
+
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(None, "oss")
+                .unwrap()
+                .to_string(),
+            "example[4-5]"
+        );
+
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(Some("local"), "mds")
+                .unwrap()
+                .to_string(),
+            "example6"
+        );
+
+        assert_eq!(
+            resolver.list_groups::<IdRangeList>(Some("local")),
+            "compute,gpu,all,adm,io,mds,oss,[1-2],03"
+                .parse::<NodeSet>()
+                .unwrap()
+        );
+
+        assert_eq!(
+            resolver.resolve::<IdRangeList>(Some("local"), "1").unwrap(),
+            "example[32-33]".parse::<NodeSet>().unwrap()
+        );
+
+        assert_eq!(
+            resolver.list_groups::<IdRangeList>(Some("rack1")),
+            "rack1_switches[1-4],rack1_nodes[1-4]"
+                .parse::<NodeSet>()
+                .unwrap()
+        );
+
+        assert_eq!(
+            resolver.list_groups::<IdRangeList>(Some("rack2")),
+            "rack2_switches[1-4],rack2_nodes[1-4]"
+                .parse::<NodeSet>()
+                .unwrap()
+        );
+
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(Some("rack1"), "nodes")
+                .unwrap(),
+            "rack1_nodes[1-4]".parse::<NodeSet>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_source_cache() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let calls = tmp_dir.path().join("calls");
+        let config = format!(
+            "[cached]\nmap: echo $GROUP >> '{0}'; echo node[1-2]\ncache_ttl: 60\n\
+             [uncached]\nmap: echo $GROUP >> '{0}'; echo node[1-2]\n",
+            calls.display()
+        );
+
+        for (cache, expected) in [(true, "a\nb\nb\n"), (false, "a\na\nb\nb\n")] {
+            std::fs::remove_file(&calls).ok();
+            let options = LoadOptions {
+                cache,
+                ..Default::default()
+            };
+            let dynamic = MainGroupConfig::from_reader(config.as_bytes(), options).unwrap();
+            let mut resolver = Resolver::from_dynamic_config(dynamic, options).unwrap();
+            resolver.set_memoize(false);
+
+            for source in ["cached", "cached", "uncached", "uncached"] {
+                let group = if source == "cached" { "a" } else { "b" };
+                assert_eq!(
+                    resolver
+                        .resolve::<IdRangeList>(Some(source), group)
+                        .unwrap()
+                        .to_string(),
+                    "node[1-2]"
+                );
+            }
+            assert_eq!(std::fs::read_to_string(&calls).unwrap(), expected);
+        }
+
+        for value in ["cache_ttl: -1", "cache_ttl: 60\ncache_disk: maybe"] {
+            let config = format!("[cached]\nmap: echo node1\n{value}\n");
+            assert!(matches!(
+                MainGroupConfig::from_reader(config.as_bytes(), LoadOptions::default()),
+                Err(ConfigurationError::InvalidValue(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_upcalls() {
+        use tempfile::TempDir;
+
+        let config = include_str!("tests/upcalls.conf");
+        let mut dynamic =
+            MainGroupConfig::from_reader(config.as_bytes(), LoadOptions::default()).unwrap();
+
+        let tmp_dir = TempDir::new().unwrap();
+        std::fs::write(
+            tmp_dir.path().join("cluster.cfg"),
+            "compute: node[1-4]\ngpu: node[5-8]\neven: node[2-8/2]\n",
+        )
+        .unwrap();
+        std::fs::create_dir(tmp_dir.path().join("groups.conf.d")).unwrap();
+        std::fs::write(
+            tmp_dir.path().join("groups.conf.d").join("extra.conf"),
+            "[extra]\nmap: echo extra_$GROUP\nlist: echo a b\n",
+        )
+        .unwrap();
+
+        dynamic
+            .set_cfgdir(tmp_dir.path().to_str().unwrap())
+            .unwrap();
+        let resolver = Resolver::from_dynamic_config(dynamic, LoadOptions::default()).unwrap();
+
+        assert_eq!(resolver.default_source(), "cluster");
+        let mut sources = resolver.sources().map(|s| s.as_str()).collect::<Vec<_>>();
+        sources.sort();
+        assert_eq!(
+            sources,
+            vec!["broken", "cluster", "exec", "extra", "noreverse"]
+        );
+
+        assert_eq!(
+            resolver.resolve::<IdRangeList>(None, "gpu").unwrap(),
+            "node[5-8]".parse::<NodeSet>().unwrap()
+        );
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(Some("extra"), "a")
+                .unwrap()
+                .to_string(),
+            "extra_a"
+        );
+        assert_eq!(
+            resolver.list_groups::<IdRangeList>(None),
+            "compute,gpu,even".parse::<NodeSet>().unwrap()
+        );
+
+        assert_eq!(
+            resolver.all_nodes::<IdRangeList>(None).unwrap(),
+            "node[1-8]".parse::<NodeSet>().unwrap()
+        );
+        assert_eq!(
+            resolver
+                .all_nodes::<IdRangeList>(Some("noreverse"))
+                .unwrap(),
+            "node[1-8]".parse::<NodeSet>().unwrap()
+        );
+
+        assert_eq!(
+            resolver.node_groups::<IdRangeList>(None, "node3").unwrap(),
+            "compute,even".parse::<NodeSet>().unwrap()
+        );
+        assert_eq!(
+            resolver
+                .node_groups::<IdRangeList>(Some("noreverse"), "node4")
+                .unwrap(),
+            "compute,even".parse::<NodeSet>().unwrap()
+        );
+        assert_eq!(
+            resolver.groups_of::<IdRangeList>(None, "node3").unwrap(),
+            vec!["compute", "even"]
+        );
+        assert_eq!(
+            resolver
+                .groups_of::<IdRangeList>(Some("noreverse"), "node4")
+                .unwrap(),
+            vec!["compute", "even"]
+        );
+        assert_eq!(
+            resolver
+                .groups_of::<IdRangeList>(Some("noreverse"), "node5")
+                .unwrap(),
+            vec!["gpu"]
+        );
+
+        assert!(resolver
+            .list_groups::<IdRangeList>(Some("broken"))
+            .is_empty());
+        // The source is not queried again after failing to list its groups
+        assert!(matches!(
+            resolver.resolve::<IdRangeList>(Some("broken"), "a"),
+            Err(NodeSetParseError::SourceUnavailable(source, _)) if source == "broken"
+        ));
+
+        #[cfg(feature = "groups")]
+        {
+            let parser = Parser::with_resolver(&resolver, Some("cluster"));
+            assert_eq!(
+                parser.parse::<IdRangeList>("@*").unwrap(),
+                "node[1-8]".parse::<NodeSet>().unwrap()
+            );
+            assert_eq!(
+                parser.parse::<IdRangeList>("@extra:*").unwrap(),
+                "extra_a,extra_b".parse::<NodeSet>().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_source_priority() {
+        let mut site = DummySource::new();
+        site.add("compute", "node[1-10]");
+        site.add("login", "login[1-2]");
+        let mut user = DummySource::new();
+        user.add("compute", "node[1-4]");
+
+        let mut resolver = Resolver::default();
+        resolver.add_sources(vec![("site".to_string(), site), ("user".to_string(), user)]);
+
+        resolver.set_source_priority(&["missing", "user", "site"]);
+        assert_eq!(
+            resolver.resolve::<IdRangeList>(None, "compute").unwrap(),
+            "node[1-4]".parse::<NodeSet>().unwrap()
+        );
+        assert_eq!(
+            resolver.resolve::<IdRangeList>(None, "login").unwrap(),
+            "login[1-2]".parse::<NodeSet>().unwrap()
+        );
+        assert!(resolver
+            .resolve::<IdRangeList>(None, "unknown")
+            .unwrap()
+            .is_empty());
+
+        resolver.set_source_priority(&["site", "user"]);
+        assert_eq!(
+            resolver.resolve::<IdRangeList>(None, "compute").unwrap(),
+            "node[1-10]".parse::<NodeSet>().unwrap()
+        );
+
+        resolver.set_source_priority(&["user"]);
+        assert!(resolver
+            .resolve::<IdRangeList>(None, "login")
+            .unwrap()
+            .is_empty());
+
+        let mut user = DummySource::new();
+        user.add("compute", "node[20-21]");
+        resolver.add_sources(vec![("user".to_string(), user)]);
+        resolver.set_source_priority(&["user", "site"]);
+        resolver.set_priority_mode(PriorityMode::Union);
+        assert_eq!(
+            resolver.resolve::<IdRangeList>(None, "compute").unwrap(),
+            "node[1-10,20-21]".parse::<NodeSet>().unwrap()
+        );
+
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(Some("site"), "compute")
+                .unwrap(),
+            "node[1-10]".parse::<NodeSet>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_resolve_union() {
+        let mut site = DummySource::new();
+        site.add("compute", "node[1-10]");
+        site.add("login", "login[1-2]");
+        let mut user = DummySource::new();
+        user.add("compute", "node[8-12],gpu1");
+        let mut other = DummySource::new();
+        other.add("bad", "node[");
+
+        let mut resolver = Resolver::default();
+        resolver.add_sources(vec![("site".to_string(), site), ("user".to_string(), user)]);
+        resolver.add_sources(vec![("other".to_string(), other)]);
+        resolver.set_source_priority(&["user"]);
+
+        assert_eq!(
+            resolver.resolve_union::<IdRangeList>("compute").unwrap(),
+            "gpu1,node[1-12]".parse::<NodeSet>().unwrap()
+        );
+        assert_eq!(
+            resolver.resolve_union::<IdRangeList>("login").unwrap(),
+            "login[1-2]".parse::<NodeSet>().unwrap()
+        );
+        assert!(resolver
+            .resolve_union::<IdRangeList>("unknown")
+            .unwrap()
+            .is_empty());
+        assert!(resolver.resolve_union::<IdRangeList>("bad").is_err());
+    }
+
+    #[test]
+    fn test_groups_for_source() {
+        let mut site = DummySource::new();
+        site.add("compute", "node[1-4]");
+        site.add("bad", "node[");
+        let mut user = DummySource::new();
+        user.add("login", "login1");
+
+        let mut resolver = Resolver::default();
+        resolver.add_sources(vec![("site".to_string(), site), ("user".to_string(), user)]);
+        resolver.default_source = "user".to_string();
+
+        let mut groups = resolver
+            .groups_for_source::<IdRangeList>(Some("site"))
+            .collect::<Vec<_>>();
+        groups.sort_by_key(|g| g.is_ok());
+        assert!(groups[0].is_err());
+        assert_eq!(
+            groups[1].as_ref().unwrap(),
+            &("compute".to_string(), "node[1-4]".parse().unwrap())
+        );
+
+        assert_eq!(
+            resolver
+                .groups_for_source::<IdRangeList>(None)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+            vec![("login".to_string(), "login1".parse().unwrap())]
+        );
+        assert_eq!(
+            resolver
+                .groups_for_source::<IdRangeList>(Some("missing"))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_resolve_many_iter() {
+        let mut site = DummySource::new();
+        site.add("compute", "node[1-4]");
+        site.add("login", "login1");
+        site.add("bad", "node[");
 
-    use super::*;
+        let mut resolver = Resolver::default();
+        resolver.add_sources(vec![("site".to_string(), site)]);
+        resolver.default_source = "site".to_string();
+
+        let groups = ["login", "bad", "compute"].map(str::to_string).to_vec();
+        let resolved: Vec<_> = resolver
+            .resolve_many_iter::<IdRangeList>(None, groups)
+            .collect();
+        assert_eq!(resolved.len(), 3);
+        assert_eq!(
+            resolved[0].as_ref().unwrap(),
+            &("login".to_string(), "login1".parse().unwrap())
+        );
+        assert!(matches!(
+            &resolved[1],
+            Err(NodeSetParseError::Group(group, _)) if group == "bad"
+        ));
+        assert_eq!(
+            resolved[2].as_ref().unwrap(),
+            &("compute".to_string(), "node[1-4]".parse().unwrap())
+        );
+    }
 
     #[test]
-    fn test_static_config() {
-        let config = include_str!("tests/cluster.yaml");
+    fn test_resolve_iter() {
+        let mut source = DummySource::new();
+        source.add("compute", "node[1-3]");
+        source.add("rack", "r[1-2]n[1-2],login");
+
         let mut resolver = Resolver::default();
-        resolver.add_sources(StaticGroupConfig::from_reader(config.as_bytes()).unwrap());
-        let parser = Parser::with_resolver(&resolver, Some("roles"));
+        resolver.add_sources(vec![("site".to_string(), source)]);
+        resolver.default_source = "site".to_string();
+
         assert_eq!(
-            parser.parse::<IdRangeList>("@login").unwrap().to_string(),
-            "login[1-2]"
+            resolver
+                .resolve_iter::<IdRangeList>(None, "compute")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec!["node1", "node2", "node3"]
         );
+        assert!(resolver
+            .resolve_iter::<IdRangeList>(Some("missing"), "compute")
+            .is_err());
 
+        #[cfg(feature = "groups")]
         assert_eq!(
-            parser.parse::<IdRangeList>("@*").unwrap(),
-            parser
-                .parse::<IdRangeList>(
-                    "node[0001-0288],mds[1-4],oss[0-15],server0001,login[1-2],mgmt[1-2]"
-                )
+            resolver
+                .iter_expr::<IdRangeList>("@rack - r1n1,x")
                 .unwrap()
+                .collect::<Vec<_>>(),
+            vec!["login", "r1n2", "r2n1", "r2n2", "x"]
         );
+    }
 
-        match parser.parse::<IdRangeList>("@login:aa") {
-            Err(NodeSetParseError::Source(_)) => (),
-            e => panic!("Expected Source error, got {e:?}",),
-        }
+    #[test]
+    fn test_resolver_builder() {
+        use itertools::Itertools;
+
+        let cmdb = || {
+            StaticSource::from_iter([
+                ("compute".to_string(), "node[1-4]".to_string()),
+                ("gpu".to_string(), "node[3-4]".to_string()),
+            ])
+        };
 
+        let resolver = ResolverBuilder::new()
+            .add_source("cmdb", Box::new(cmdb()))
+            .set_default_source("cmdb")
+            .build()
+            .unwrap();
+        assert_eq!(resolver.default_source(), "cmdb");
+        assert_eq!(resolver.sources().collect::<Vec<_>>(), vec!["cmdb"]);
         assert_eq!(
-            parser
-                .parse::<IdRangeList>("@roles:cpu_only")
+            resolver.list_groups::<IdRangeList>(None),
+            "compute,gpu".parse::<NodeSet>().unwrap()
+        );
+        assert_eq!(
+            resolver
+                .resolve::<IdRangeList>(None, "gpu")
                 .unwrap()
                 .to_string(),
-            "node[0009-0288]"
+            "node[3-4]"
         );
-
         assert_eq!(
-            parser
-                .parse::<IdRangeList>("@roles:non_existent")
+            resolver
+                .all_nodes::<IdRangeList>(Some("cmdb"))
                 .unwrap()
                 .to_string(),
-            ""
+            "node[1-4]"
         );
 
-        match parser.parse::<IdRangeList>("@non_existent:non_existent") {
-            Err(NodeSetParseError::Source(_)) => (),
-            _ => panic!("Expected Source error"),
-        }
+        // Added sources are merged with configured ones and replace them
+        let config = include_str!("tests/groups.conf");
+        let configured = Resolver::from_dynamic_config(
+            MainGroupConfig::from_reader(config.as_bytes(), LoadOptions::default()).unwrap(),
+            LoadOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(configured.sources().collect::<Vec<_>>(), vec!["local"]);
+        let configured_default = configured.default_source().to_string();
+        let resolver = ResolverBuilder::new()
+            .add_source("cmdb", Box::new(cmdb()))
+            .add_source("local", Box::new(cmdb()))
+            .build_from(configured)
+            .unwrap();
+        assert_eq!(resolver.default_source(), configured_default);
+        assert_eq!(
+            resolver.sources().sorted().collect::<Vec<_>>(),
+            vec!["cmdb", "local"]
+        );
+        assert_eq!(
+            resolver.list_groups::<IdRangeList>(Some("local")),
+            "compute,gpu".parse::<NodeSet>().unwrap()
+        );
 
-        let ns1 = parser.parse::<IdRangeList>("@rack[1-2]:hsw").unwrap();
-        let ns2 = "mgmt[1-2],oss[0-15],mds[1-4]".parse().unwrap();
-        assert_eq!(ns1, ns2);
+        assert!(matches!(
+            ResolverBuilder::new()
+                .add_source("cmdb", Box::new(cmdb()))
+                .set_default_source("missing")
+                .build(),
+            Err(ConfigurationError::InvalidValue(..))
+        ));
+    }
 
-        let ns1 = parser.parse::<IdRangeList>("@rack[1-2]:*").unwrap();
-        let ns2 = "mgmt[1-2],oss[0-15],mds[1-4],node[0001-0288]"
-            .parse()
+    #[cfg(feature = "groups")]
+    #[test]
+    fn test_group_sigil() {
+        let cmdb = StaticSource::from_iter([
+            ("rack1".to_string(), "node[1-2]".to_string()),
+            ("rack2".to_string(), "node[3-4]".to_string()),
+            ("all".to_string(), "%rack1,%cmdb:rack2".to_string()),
+        ]);
+        let resolver = ResolverBuilder::new()
+            .add_source("cmdb", Box::new(cmdb))
+            .set_default_source("cmdb")
+            .group_sigil('%')
+            .build()
             .unwrap();
-        assert_eq!(ns1, ns2);
+        assert_eq!(resolver.group_sigil(), '%');
 
-        let ns1 = parser.parse::<IdRangeList>("@network:net[1,3]").unwrap();
-        let ns2 = "node[10-19,30-39]".parse().unwrap();
-        assert_eq!(ns1, ns2);
+        let parse = |s| {
+            Parser::with_resolver(&resolver, None)
+                .parse::<IdRangeList>(s)
+                .map(|ns| ns.to_string())
+        };
+        assert_eq!(parse("%rack1").unwrap(), "node[1-2]");
+        assert_eq!(parse("%cmdb:rack[1-2]").unwrap(), "node[1-4]");
+        assert_eq!(parse("%all").unwrap(), "node[1-4]");
+        assert_eq!(parse("%%").unwrap(), "all,rack[1-2]");
+        assert_eq!(parse("%rack* - %rack2").unwrap(), "node[1-2]");
+        assert!(matches!(
+            parse("@rack1"),
+            Err(NodeSetParseError::Generic(_))
+        ));
+
+        for sigil in ['a', '1', ' ', '[', ',', '*', '-'] {
+            assert!(
+                matches!(
+                    Resolver::default().set_group_sigil(sigil),
+                    Err(ConfigurationError::InvalidValue(..))
+                ),
+                "{sigil}"
+            );
+        }
 
-        assert_eq!(
-            resolver.list_groups::<IdRangeList>(Some("numerical")),
-            "1-2,03".parse::<NodeSet>().unwrap()
+        // The sigil may be set in the Main section of groups.conf
+        let config = MainGroupConfig::from_reader(
+            "[Main]\ngroup_sigil: %\n".as_bytes(),
+            LoadOptions::default(),
         );
+        let resolver =
+            Resolver::from_dynamic_config(config.unwrap(), LoadOptions::default()).unwrap();
+        assert_eq!(resolver.group_sigil(), '%');
+        assert!(matches!(
+            MainGroupConfig::from_reader(
+                "[Main]\ngroup_sigil: %%\n".as_bytes(),
+                LoadOptions::default()
+            ),
+            Err(ConfigurationError::InvalidValue(..))
+        ));
+    }
 
-        assert_eq!(
+    #[test]
+    fn test_reload() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let write_groups = |nodes: &str| {
+            std::fs::write(
+                tmp_dir.path().join("groups.d").join("local.yaml"),
+                format!("local:\n  compute: '{nodes}'\n"),
+            )
+            .unwrap()
+        };
+        std::fs::write(
+            tmp_dir.path().join("groups.conf"),
+            "[Main]\ndefault: local\nautodir: $CFGDIR/groups.d\n",
+        )
+        .unwrap();
+        std::fs::create_dir(tmp_dir.path().join("groups.d")).unwrap();
+        write_groups("node[1-2]");
+
+        let configured =
+            Resolver::from_config_dirs(vec![tmp_dir.path().to_path_buf()], LoadOptions::default());
+        let mut resolver = ResolverBuilder::new()
+            .add_source(
+                "cmdb",
+                Box::new(StaticSource::from_iter([(
+                    "compute".to_string(),
+                    "node9".to_string(),
+                )])),
+            )
+            .build_from(configured.unwrap())
+            .unwrap();
+        resolver.set_source_priority(&["local", "cmdb"]);
+        resolver.set_priority_mode(PriorityMode::Union);
+        let state = config_state(&resolver);
+
+        write_groups("node[1-4]");
+        assert_ne!(config_state(&resolver), state);
+        let reloaded = resolver.reload().unwrap();
+
+        let compute = |resolver: &Resolver| {
             resolver
-                .resolve::<IdRangeList>(Some("numerical"), "1")
-                .unwrap(),
-            "node[10-19]".parse::<NodeSet>().unwrap()
-        );
+                .resolve::<IdRangeList>(None, "compute")
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(compute(&resolver), "node[1-2,9]");
+        assert_eq!(compute(&reloaded), "node[1-4,9]");
+        assert_eq!(reloaded.default_source(), "local");
+        assert_eq!(config_state(&reloaded), config_state(&resolver));
+
+        assert!(matches!(
+            Resolver::default().reload(),
+            Err(ConfigurationError::NotReloadable)
+        ));
     }
 
     #[test]
-    fn test_parse_dynamic_config() {
-        use tempfile::TempDir;
+    fn test_config_layers() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let layer = |name: &str, conf: &str| {
+            let dir = tmp_dir.path().join(name);
+            std::fs::create_dir(&dir).unwrap();
+            std::fs::write(dir.join("groups.conf"), conf).unwrap();
+            dir
+        };
 
-        let config = include_str!("tests/groups.conf");
-        let mut dynamic = MainGroupConfig::from_reader(config.as_bytes()).unwrap();
+        let system = layer(
+            "system",
+            "[Main]\ndefault: site\n[site]\nmap: echo system\nlist: echo compute\n\
+             [other]\nmap: echo other\n",
+        );
+        let user = layer(
+            "user",
+            "[Main]\ndefault: mine\n[site]\nmap: echo user\nlist: echo compute\n\
+             [mine]\nmap: echo mine\n",
+        );
+        let missing = tmp_dir.path().join("missing");
+
+        // Nearer layers come last
+        let resolver =
+            Resolver::from_config_dirs(vec![system.clone(), missing, user], LoadOptions::default())
+                .unwrap();
+        assert_eq!(resolver.default_source(), "mine");
+        let mut sources: Vec<_> = resolver.sources().collect();
+        sources.sort();
+        assert_eq!(sources, vec!["mine", "other", "site"]);
+        let resolve = |source| {
+            resolver
+                .resolve::<IdRangeList>(Some(source), "compute")
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(resolve("site"), "user");
+        assert_eq!(resolve("other"), "other");
+        assert_eq!(resolve("mine"), "mine");
 
-        let tmp_dir = TempDir::new().unwrap();
+        let resolver = Resolver::from_config_dirs(vec![system], LoadOptions::default()).unwrap();
+        assert_eq!(resolver.default_source(), "site");
+    }
 
-        std::fs::create_dir(tmp_dir.path().join("groups.d")).unwrap();
+    #[test]
+    fn test_clustershell_config() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        std::fs::create_dir(dir.join("groups.conf.d")).unwrap();
+        std::fs::create_dir(dir.join("groups.d")).unwrap();
         std::fs::write(
-            tmp_dir.path().join("groups.d").join("local.cfg"),
-            include_str!("tests/local.cfg"),
+            dir.join("groups.conf"),
+            "[Main]\n\
+             default: local\n\
+             confdir: $CFGDIR/groups.conf.d\n\
+             autodir: $CFGDIR/groups.d\n\
+             \n\
+             [local]\n\
+             map: sed -n 's/^$GROUP:\\(.*\\)/\\1/p' $CFGDIR/groups\n\
+             all: sed -n 's/^all:\\(.*\\)/\\1/p' $CFGDIR/groups\n\
+             list: sed -n 's/^\\([0-9A-Za-z_-]*\\):.*/\\1/p' $CFGDIR/groups\n",
         )
         .unwrap();
-
-        std::fs::create_dir(tmp_dir.path().join("groups.conf.d")).unwrap();
         std::fs::write(
-            tmp_dir.path().join("groups.conf.d").join("multi.conf"),
-            include_str!("tests/multi.conf"),
+            dir.join("groups"),
+            "compute: node[1-4]\nlogin: login1\nall: node[1-4],login1\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("groups.conf.d/extra.conf"),
+            "[extra]\nmap: echo extra[1-2]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("groups.d/static.yaml"),
+            "static:\n  rack1: r1n[1-2]\n",
         )
         .unwrap();
 
-        dynamic
-            .set_cfgdir(tmp_dir.path().to_str().unwrap())
-            .unwrap();
-
+        let resolver = Resolver::from_clustershell_config(dir).unwrap();
+        assert_eq!(resolver.default_source(), "local");
+        let resolve = |source: Option<&str>, group| {
+            resolver
+                .resolve::<IdRangeList>(source, group)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(resolve(None, "compute"), "node[1-4]");
+        assert_eq!(resolve(Some("extra"), "any"), "extra[1-2]");
+        assert_eq!(resolve(Some("static"), "rack1"), "r1n[1-2]");
         assert_eq!(
-            dynamic.autodirs(),
-            vec![
-                "/etc/clustershell/groups.d",
-                &format!("{}/groups.d", tmp_dir.path().to_str().unwrap())
-            ]
+            resolver.list_groups::<IdRangeList>(None).to_string(),
+            "all,compute,login"
         );
         assert_eq!(
-            dynamic.confdirs(),
-            vec![
-                "/etc/clustershell/groups.conf.d",
-                &format!("{}/groups.conf.d", tmp_dir.path().to_str().unwrap())
-            ]
+            resolver.all_nodes::<IdRangeList>(None).unwrap().to_string(),
+            "login1,node[1-4]"
         );
 
-        let resolver = Resolver::from_dynamic_config(dynamic).unwrap();
+        assert!(matches!(
+            Resolver::from_clustershell_config(dir.join("missing")),
+            Err(ConfigurationError::File(..))
+        ));
+    }
+
+    #[test]
+    fn test_config_expansion() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            tmp_dir.path().join("groups.conf"),
+            "[Main]\n\
+             autodir: ${CARGO_MANIFEST_DIR}/src/collections/tests\n\
+             \n\
+             [exec]\n\
+             type: exec\n\
+             map: echo n${NODESET_TEST_UNDEFINED_VAR}1 '$GROUP'\n",
+        )
+        .unwrap();
+        let load = |allow_undefined_variables| {
+            Resolver::from_config_dirs(
+                vec![tmp_dir.path().to_path_buf()],
+                LoadOptions {
+                    allow_undefined_variables,
+                    ..Default::default()
+                },
+            )
+        };
+
+        match load(false) {
+            Err(ConfigurationError::File(_, e)) => assert!(
+                matches!(
+                    e.as_ref(),
+                    ConfigurationError::UndefinedVariable(key, var)
+                        if key == "map" && var == "NODESET_TEST_UNDEFINED_VAR"
+                ),
+                "{e}"
+            ),
+            r => panic!("Expected File error, got {r:?}"),
+        }
 
+        let resolver = load(true).unwrap();
         assert_eq!(
             resolver
-                .resolve::<IdRangeList>(None, "oss")
+                .resolve::<IdRangeList>(Some("exec"), "x")
                 .unwrap()
                 .to_string(),
-            "example[4-5]"
+            "n1,x"
         );
-
         assert_eq!(
             resolver
-                .resolve::<IdRangeList>(Some("local"), "mds")
+                .resolve::<IdRangeList>(Some("roles"), "adm")
                 .unwrap()
                 .to_string(),
-            "example6"
+            "mgmt[1-2]"
         );
+    }
+
+    #[test]
+    fn test_source_info() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            tmp_dir.path().join("racks.yaml"),
+            "racks:\n  rack1: n[1-2]\n  rack2: n[3-4]\n",
+        )
+        .unwrap();
+
+        let mut resolver = ResolverBuilder::new()
+            .add_source(
+                "site",
+                Box::new(StaticSource::from_iter([(
+                    "compute".to_string(),
+                    "node[1-4]".to_string(),
+                )])),
+            )
+            .set_default_source("site")
+            .build()
+            .unwrap();
+        resolver.add_yaml_dir(tmp_dir.path()).unwrap();
 
+        let info = resolver.source_info();
         assert_eq!(
-            resolver.list_groups::<IdRangeList>(Some("local")),
-            "compute,gpu,all,adm,io,mds,oss,[1-2],03"
-                .parse::<NodeSet>()
-                .unwrap()
+            info.iter()
+                .map(|info| (info.name, info.kind, info.default))
+                .collect::<Vec<_>>(),
+            vec![("racks", "yaml", false), ("site", "static", true)]
         );
-
         assert_eq!(
-            resolver.resolve::<IdRangeList>(Some("local"), "1").unwrap(),
-            "example[32-33]".parse::<NodeSet>().unwrap()
+            info[0].origin.as_deref(),
+            tmp_dir.path().join("racks.yaml").to_str()
         );
+        assert_eq!(info[1].origin, None);
+        assert_eq!(info[0].group_count().unwrap(), 2);
+        assert_eq!(info[1].group_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_resolve_many() {
+        let resolver = ResolverBuilder::new()
+            .add_source(
+                "site",
+                Box::new(StaticSource::from_iter([
+                    ("compute".to_string(), "node[1-4]".to_string()),
+                    ("login".to_string(), "login[1-2]".to_string()),
+                    ("broken".to_string(), "node[1-".to_string()),
+                ])),
+            )
+            .set_default_source("site")
+            .build()
+            .unwrap();
 
+        let groups = resolver
+            .resolve_many::<IdRangeList>(None, &["login", "missing", "compute"])
+            .unwrap();
         assert_eq!(
-            resolver.list_groups::<IdRangeList>(Some("rack1")),
-            "rack1_switches[1-4],rack1_nodes[1-4]"
-                .parse::<NodeSet>()
-                .unwrap()
+            groups
+                .iter()
+                .map(|(group, nodes)| format!("{group}={nodes}"))
+                .collect::<Vec<_>>(),
+            vec!["login=login[1-2]", "missing=", "compute=node[1-4]"]
         );
-
+        assert!(resolver
+            .resolve_many::<IdRangeList>(Some("site"), &[])
+            .unwrap()
+            .is_empty());
+
+        assert!(matches!(
+            resolver.resolve_many::<IdRangeList>(None, &["compute", "broken"]),
+            Err(NodeSetParseError::Group(group, _)) if group == "broken"
+        ));
+        assert!(matches!(
+            resolver.resolve_many::<IdRangeList>(Some("other"), &["compute"]),
+            Err(NodeSetParseError::UnknownSource { .. })
+        ));
+
+        let groups: Vec<_> = resolver
+            .groups_for_source::<IdRangeList>(None)
+            .map(|res| res.map(|(group, _)| group).map_err(|_| ()))
+            .collect();
         assert_eq!(
-            resolver.list_groups::<IdRangeList>(Some("rack2")),
-            "rack2_switches[1-4],rack2_nodes[1-4]"
-                .parse::<NodeSet>()
-                .unwrap()
+            groups,
+            vec![Err(()), Ok("compute".to_string()), Ok("login".to_string())]
         );
+    }
+
+    #[test]
+    fn test_groups_of() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        /// A static source which counts the groups it maps
+        #[derive(Debug)]
+        struct Counting(StaticSource, Arc<AtomicUsize>);
+
+        impl GroupSource for Counting {
+            fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+                self.1.fetch_add(1, Ordering::Relaxed);
+                self.0.map(group)
+            }
 
+            fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+                self.0.list()
+            }
+        }
+
+        let maps = Arc::new(AtomicUsize::new(0));
+        let resolver = ResolverBuilder::new()
+            .add_source(
+                "cmdb",
+                Box::new(Counting(
+                    StaticSource::from_iter([
+                        ("compute".to_string(), "node[1-4]".to_string()),
+                        ("gpu".to_string(), "node[3-4]".to_string()),
+                        ("login".to_string(), "login1".to_string()),
+                    ]),
+                    maps.clone(),
+                )),
+            )
+            .set_default_source("cmdb")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            resolver.groups_of::<IdRangeList>(None, "node3").unwrap(),
+            vec!["compute", "gpu"]
+        );
         assert_eq!(
             resolver
-                .resolve::<IdRangeList>(Some("rack1"), "nodes")
+                .groups_of::<IdRangeList>(Some("cmdb"), "login1")
                 .unwrap(),
-            "rack1_nodes[1-4]".parse::<NodeSet>().unwrap()
+            vec!["login"]
+        );
+        assert!(resolver
+            .groups_of::<IdRangeList>(None, "other1")
+            .unwrap()
+            .is_empty());
+
+        // Groups are only resolved by the first lookup
+        assert_eq!(maps.load(Ordering::Relaxed), 3);
+
+        // Listing names does not resolve groups
+        let mut names = resolver.group_names(None);
+        assert_eq!(names.next().as_deref(), Some("compute"));
+        assert_eq!(names.collect::<Vec<_>>(), vec!["gpu", "login"]);
+        assert_eq!(resolver.group_names(Some("missing")).count(), 0);
+        assert_eq!(maps.load(Ordering::Relaxed), 3);
+
+        assert!(matches!(
+            resolver.groups_of::<IdRangeList>(Some("missing"), "node1"),
+            Err(NodeSetParseError::UnknownSource { .. })
+        ));
+    }
+
+    /// A static source which counts the groups it maps, blocking on `slow`
+    /// until it is released
+    #[derive(Debug)]
+    struct Memoized {
+        groups: StaticSource,
+        maps: Arc<Mutex<HashMap<String, usize>>>,
+        slow: Mutex<std::sync::mpsc::Receiver<()>>,
+    }
+
+    impl Memoized {
+        fn new(slow: std::sync::mpsc::Receiver<()>) -> Self {
+            Self {
+                groups: StaticSource::from_iter([
+                    ("rack1".to_string(), "node[1-4]".to_string()),
+                    ("down".to_string(), "node2".to_string()),
+                    ("all".to_string(), "@rack1,@down".to_string()),
+                    ("slow".to_string(), "node9".to_string()),
+                ]),
+                maps: Arc::default(),
+                slow: Mutex::new(slow),
+            }
+        }
+
+        /// Returns a function counting how many times each group was mapped
+        fn counter(&self) -> impl Fn(&str) -> usize {
+            let maps = self.maps.clone();
+            move |group| maps.lock().unwrap().get(group).copied().unwrap_or(0)
+        }
+    }
+
+    impl GroupSource for Memoized {
+        fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+            *self
+                .maps
+                .lock()
+                .unwrap()
+                .entry(group.to_string())
+                .or_default() += 1;
+            if group == "slow" {
+                self.slow.lock().unwrap().recv().unwrap();
+            }
+            self.groups.map(group)
+        }
+
+        fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+            self.groups.list()
+        }
+    }
+
+    #[test]
+    fn test_resolver_memoize() {
+        let (_release, slow) = std::sync::mpsc::channel();
+        let source = Memoized::new(slow);
+        let maps = source.counter();
+        let mut resolver = Resolver::default();
+        resolver.add_sources([("site".to_string(), source)]);
+
+        for _ in 0..3 {
+            let nodes: NodeSet = resolver.resolve(Some("site"), "rack1").unwrap();
+            assert_eq!(nodes.to_string(), "node[1-4]");
+            assert!(resolver
+                .resolve::<IdRangeList>(Some("site"), "missing")
+                .unwrap()
+                .is_empty());
+        }
+        assert_eq!(maps("rack1"), 1);
+        assert_eq!(maps("missing"), 1);
+
+        #[cfg(feature = "groups")]
+        {
+            let nodes: NodeSet = resolver
+                .parse("@site:rack1,@site:rack1!@site:down,@site:all")
+                .unwrap();
+            assert_eq!(nodes.to_string(), "node[1-4]");
+            assert_eq!(maps("rack1"), 1);
+            assert_eq!(maps("down"), 1);
+        }
+
+        let groups = resolver
+            .resolve_many::<IdRangeList>(Some("site"), &["rack1", "down"])
+            .unwrap();
+        assert_eq!(groups[1].1.to_string(), "node2");
+        assert_eq!(maps("rack1"), 1);
+        assert_eq!(maps("down"), 1);
+
+        resolver.clear_memoized();
+        resolver
+            .resolve::<IdRangeList>(Some("site"), "rack1")
+            .unwrap();
+        assert_eq!(maps("rack1"), 2);
+
+        resolver.set_memoize(false);
+        resolver
+            .resolve::<IdRangeList>(Some("site"), "rack1")
+            .unwrap();
+        resolver
+            .resolve::<IdRangeList>(Some("site"), "rack1")
+            .unwrap();
+        assert_eq!(maps("rack1"), 4);
+    }
+
+    #[test]
+    fn test_resolver_memoize_threads() {
+        let (release, slow) = std::sync::mpsc::channel();
+        let source = Memoized::new(slow);
+        let maps = source.counter();
+        let mut resolver = Resolver::default();
+        resolver.add_sources([("site".to_string(), source)]);
+        let resolver = &resolver;
+        let group = if cfg!(feature = "groups") {
+            "all"
+        } else {
+            "rack1"
+        };
+
+        std::thread::scope(|s| {
+            // Dropped on failure so that the slow source never waits forever
+            let release = release;
+
+            // Other groups are resolved while the source is mapping `slow`
+            let slow = s.spawn(move || resolver.resolve::<IdRangeList>(Some("site"), "slow"));
+
+            let workers: Vec<_> = (0..8)
+                .map(|_| {
+                    s.spawn(move || {
+                        for _ in 0..100 {
+                            let nodes: NodeSet = resolver.resolve(Some("site"), group).unwrap();
+                            assert_eq!(nodes.to_string(), "node[1-4]");
+                        }
+                    })
+                })
+                .collect();
+            for worker in workers {
+                worker.join().unwrap();
+            }
+
+            release.send(()).unwrap();
+            assert_eq!(slow.join().unwrap().unwrap().to_string(), "node9");
+        });
+
+        // Threads may map a group concurrently before it is memoized
+        let mapped = maps(group);
+        assert!((1..=8).contains(&mapped));
+        assert_eq!(maps("slow"), 1);
+
+        resolver
+            .resolve::<IdRangeList>(Some("site"), group)
+            .unwrap();
+        assert_eq!(maps(group), mapped);
+    }
+
+    #[test]
+    fn test_resolver_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Resolver>();
+
+        let cluster = |rack: &str| {
+            ResolverBuilder::new()
+                .add_source(
+                    "site",
+                    Box::new(StaticSource::from_iter([
+                        ("rack1".to_string(), rack.to_string()),
+                        ("all".to_string(), "@rack1".to_string()),
+                    ])),
+                )
+                .set_default_source("site")
+                .build()
+                .unwrap()
+        };
+        let resolvers = [cluster("a[1-4]"), cluster("b[1-2]")];
+
+        std::thread::scope(|s| {
+            for (resolver, expected) in resolvers.iter().zip(["a[1-4]", "b[1-2]"]) {
+                for _ in 0..4 {
+                    s.spawn(move || {
+                        for _ in 0..100 {
+                            let nodes: NodeSet = resolver.resolve(None, "rack1").unwrap();
+                            assert_eq!(nodes.to_string(), expected);
+                            let groups: NodeSet = resolver.list_groups(None);
+                            assert_eq!(groups.to_string(), "all,rack1");
+                            #[cfg(feature = "groups")]
+                            {
+                                let nodes: NodeSet =
+                                    NodeSet::parse_with_resolver(resolver, "@rack1,x1").unwrap();
+                                assert_eq!(nodes.to_string(), format!("{expected},x1"));
+                                let nodes: NodeSet = resolver.parse("@all").unwrap();
+                                assert_eq!(nodes.to_string(), expected);
+                            }
+                        }
+                    });
+                }
+            }
+        });
+    }
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn test_resolver_builder_nested() {
+        let resolver = ResolverBuilder::new()
+            .add_source(
+                "site",
+                Box::new(StaticSource::from_iter([
+                    ("compute".to_string(), "node[1-4]".to_string()),
+                    ("all".to_string(), "@compute,@other:login".to_string()),
+                ])),
+            )
+            .add_source(
+                "other",
+                Box::new(StaticSource::from_iter([(
+                    "login".to_string(),
+                    "login1".to_string(),
+                )])),
+            )
+            .set_default_source("site")
+            .build()
+            .unwrap();
+
+        let parser = Parser::with_resolver(&resolver, None);
+        assert_eq!(
+            parser.parse::<IdRangeList>("@all").unwrap().to_string(),
+            "login1,node[1-4]"
         );
     }
 }
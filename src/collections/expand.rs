@@ -0,0 +1,266 @@
+use super::nodeset::ConfigurationError;
+use super::sources::parse_bool;
+use ini::Properties;
+
+/// Settings of groups.conf holding commands or lists of paths, which are
+/// split into words by the shell or like the shell
+const WORDS_PROPERTIES: &[&str] = &[
+    "confdir",
+    "groupsdir",
+    "autodir",
+    "map",
+    "all",
+    "list",
+    "reverse",
+    "sinfo",
+    "dirs",
+];
+
+/// Settings of groups.conf holding the upcall commands of a source
+const COMMAND_PROPERTIES: &[&str] = &["map", "all", "list", "reverse"];
+
+/// Settings of groups.conf holding a single path, which is used as is
+const PATH_PROPERTIES: &[&str] = &["file", "cache"];
+
+/// Variables substituted when the configuration is loaded or when groups
+/// are resolved, which are left untouched
+const UPCALL_VARIABLES: &[&str] = &["GROUP", "NODE", "SOURCE", "CFGDIR"];
+
+/// Expands environment variables and home directories in the settings of a
+/// groups.conf section which hold paths or commands
+///
+/// Other settings are left untouched, as well as the commands of sources
+/// which run them through the shell, which expands them itself. Variables
+/// which are not set are an error unless `allow_undefined` is set, in which
+/// case they expand to an empty string.
+pub(crate) fn expand_properties(
+    props: &Properties,
+    allow_undefined: bool,
+) -> Result<Properties, ConfigurationError> {
+    let var = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .or_else(|| allow_undefined.then(String::new))
+    };
+    let shell = match props.get("type") {
+        None => true,
+        Some("exec") => props.get("shell").and_then(parse_bool).unwrap_or(false),
+        Some(_) => false,
+    };
+    let mut res = Properties::new();
+
+    for (k, v) in props.iter() {
+        let words = WORDS_PROPERTIES.contains(&k);
+        let expanded = !(shell && COMMAND_PROPERTIES.contains(&k));
+        let value = if expanded && (words || PATH_PROPERTIES.contains(&k)) {
+            expand_value(v, words, &var, &user_home)
+                .map_err(|name| ConfigurationError::UndefinedVariable(k.to_string(), name))?
+        } else {
+            v.to_string()
+        };
+
+        res.append(k, value);
+    }
+
+    Ok(res)
+}
+
+/// Expands `~`, `~user`, `$VAR` and `${VAR}` in a configuration value
+///
+/// With `words`, the value is a list of words as parsed by the shell: `~`
+/// is expanded at the start of each word, and nothing is expanded between
+/// single quotes or after a backslash, which are kept for the shell to
+/// interpret. Otherwise, the value is a single path in which `~` is only
+/// expanded at the start and `\$` stands for a literal `$`.
+///
+/// Variables substituted later on, such as `$GROUP`, are left untouched.
+/// Returns the name of the first variable which is not set on failure.
+fn expand_value(
+    value: &str,
+    words: bool,
+    var: &dyn Fn(&str) -> Option<String>,
+    home: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.char_indices().peekable();
+    let mut word_start = true;
+    let mut quoted = false;
+
+    while let Some((i, c)) = chars.next() {
+        let at_word_start = word_start;
+        word_start = words && c.is_whitespace() && !quoted;
+
+        match c {
+            _ if quoted => {
+                quoted = c != '\'';
+                res.push(c);
+            }
+            '\'' if words => {
+                quoted = true;
+                res.push(c);
+            }
+            '\\' => match chars.next() {
+                Some((_, '$')) if !words => res.push('$'),
+                Some((_, next)) => {
+                    res.push(c);
+                    res.push(next);
+                }
+                None => res.push(c),
+            },
+            '~' if at_word_start => {
+                let end = value[i + 1..]
+                    .find(|c: char| c == '/' || (words && c.is_whitespace()))
+                    .map_or(value.len(), |pos| i + 1 + pos);
+                match home(&value[i + 1..end]) {
+                    Some(dir) => {
+                        res.push_str(&dir);
+                        while chars.next_if(|&(j, _)| j < end).is_some() {}
+                    }
+                    None => res.push(c),
+                }
+            }
+            '$' => {
+                let rest = &value[i + 1..];
+                let (name, len) = match rest.strip_prefix('{') {
+                    Some(braced) => match braced.find('}') {
+                        Some(end) => (&braced[..end], end + 2),
+                        None => ("", 0),
+                    },
+                    None => {
+                        let end = rest
+                            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                            .unwrap_or(rest.len());
+                        (&rest[..end], end)
+                    }
+                };
+
+                if !is_variable_name(name) || UPCALL_VARIABLES.contains(&name) {
+                    res.push(c);
+                    continue;
+                }
+
+                res.push_str(&var(name).ok_or_else(|| name.to_string())?);
+                while chars.next_if(|&(j, _)| j <= i + len).is_some() {}
+            }
+            _ => res.push(c),
+        }
+    }
+
+    Ok(res)
+}
+
+/// Returns whether `name` is the name of an environment variable as
+/// understood by the shell (ie not `1` or `?`)
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns the home directory of `user` from `/etc/passwd`, or `$HOME` for
+/// an empty user
+fn user_home(user: &str) -> Option<String> {
+    if user.is_empty() {
+        return std::env::var("HOME").ok();
+    }
+
+    std::fs::read_to_string("/etc/passwd")
+        .ok()?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() >= 6 && fields[0] == user)
+        .map(|fields| fields[5].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(value: &str, words: bool) -> Result<String, String> {
+        let var = |name: &str| match name {
+            "HOME" => Some("/home/me".to_string()),
+            "CLUSTER_NAME" => Some("alpha".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        let home = |user: &str| match user {
+            "" => Some("/home/me".to_string()),
+            "admin" => Some("/srv/admin".to_string()),
+            _ => None,
+        };
+
+        expand_value(value, words, &var, &home)
+    }
+
+    #[test]
+    fn test_expand_value() {
+        for (value, expected) in [
+            ("~/.config/groups.d", "/home/me/.config/groups.d"),
+            ("~", "/home/me"),
+            ("~admin/groups", "/srv/admin/groups"),
+            ("~nobody/groups", "~nobody/groups"),
+            ("/etc/~/groups", "/etc/~/groups"),
+            ("$HOME/groups ~/more", "/home/me/groups /home/me/more"),
+            ("/etc/$CLUSTER_NAME/groups", "/etc/alpha/groups"),
+            ("/etc/${CLUSTER_NAME}_groups", "/etc/alpha_groups"),
+            ("a${EMPTY}b", "ab"),
+            ("get-groups $CLUSTER_NAME $GROUP", "get-groups alpha $GROUP"),
+            (
+                "sed -n 's/^$GROUP:\\(.*\\)/\\1/p' $CFGDIR/groups",
+                "sed -n 's/^$GROUP:\\(.*\\)/\\1/p' $CFGDIR/groups",
+            ),
+            (
+                "awk '{print $1, $UNSET}' ~/nodes",
+                "awk '{print $1, $UNSET}' /home/me/nodes",
+            ),
+            ("echo \\$UNSET $1 $? $$ ${", "echo \\$UNSET $1 $? $$ ${"),
+            ("echo \"$CLUSTER_NAME\" '~'", "echo \"alpha\" '~'"),
+        ] {
+            assert_eq!(expand(value, true).as_deref(), Ok(expected), "{value}");
+        }
+
+        assert_eq!(
+            expand("/tmp/cost\\$/~", false).as_deref(),
+            Ok("/tmp/cost$/~")
+        );
+        assert_eq!(expand("'$CLUSTER_NAME'", false).as_deref(), Ok("'alpha'"));
+        assert_eq!(expand("~admin", false).as_deref(), Ok("/srv/admin"));
+
+        assert_eq!(expand("map $UNSET", true), Err("UNSET".to_string()));
+        assert_eq!(expand("${UNSET}/file", false), Err("UNSET".to_string()));
+    }
+
+    #[test]
+    fn test_expand_properties() {
+        let mut props = Properties::new();
+        props.append("type", "exec");
+        props.append("map", "echo $NODESET_TEST_UNSET_VAR$GROUP");
+        props.append("cache_ttl", "$NODESET_TEST_UNSET_VAR");
+
+        assert!(matches!(
+            expand_properties(&props, false),
+            Err(ConfigurationError::UndefinedVariable(key, name))
+                if key == "map" && name == "NODESET_TEST_UNSET_VAR"
+        ));
+
+        let expanded = expand_properties(&props, true).unwrap();
+        assert_eq!(expanded.get("map"), Some("echo $GROUP"));
+        assert_eq!(expanded.get("cache_ttl"), Some("$NODESET_TEST_UNSET_VAR"));
+
+        // Commands run through the shell are expanded by the shell
+        for shell in [None, Some("yes")] {
+            let mut props = Properties::new();
+            if let Some(shell) = shell {
+                props.append("type", "exec");
+                props.append("shell", shell);
+            }
+            props.append("map", "f=~/groups; echo $f $GROUP");
+            assert_eq!(
+                expand_properties(&props, false).unwrap().get("map"),
+                Some("f=~/groups; echo $f $GROUP")
+            );
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use super::nodeset::write_quoted;
+use super::parsers::is_nodeset_char;
+use super::parsers::ParseOptions;
+use std::fmt;
+
+/// Customizes how nodesets are written by [`NodeSet::display_with`]
+///
+/// Each method controls one part of a folded nodeset: the names around the
+/// ids (ie `node` and `-ib` in `node[1-2]-ib`), the ids themselves, the
+/// delimiters around lists of ranges and the separators between sets of
+/// nodes. The default implementations write nodesets as their `Display`
+/// implementation does, which is what [`DefaultFormatter`] uses.
+///
+/// `ParseOptions` implements this trait to write nodesets with its
+/// delimiters, and [`Separated`] changes the separator of another formatter.
+///
+/// # Examples
+///
+/// ```
+/// use nodeset::{NodeFormatter, NodeSet};
+/// use std::fmt;
+///
+/// /// Writes 4-digit ids after a dash, bracketing single ids as well
+/// struct Dashed;
+///
+/// impl NodeFormatter for Dashed {
+///     fn write_name(&self, f: &mut dyn fmt::Write, name: &str) -> fmt::Result {
+///         write!(f, "{name}-")
+///     }
+///
+///     fn write_id(&self, f: &mut dyn fmt::Write, id: u32, _pad: usize) -> fmt::Result {
+///         write!(f, "{id:04}")
+///     }
+///
+///     fn bracket_single(&self) -> bool {
+///         true
+///     }
+/// }
+///
+/// let ns: NodeSet = "node[1-3,5]".parse().unwrap();
+///
+/// assert_eq!(ns.display_with(&Dashed).to_string(), "node-[0001-0003,0005]");
+/// ```
+///
+/// [`NodeSet::display_with`]: crate::NodeSet::display_with
+pub trait NodeFormatter {
+    /// Writes a name preceding or following the ids of a node
+    ///
+    /// Names which could not be parsed back are written between double
+    /// quotes by default. Sets of nodes without a name (ie `1-3`) are
+    /// written with an empty name.
+    fn write_name(&self, f: &mut dyn fmt::Write, name: &str) -> fmt::Result {
+        if name.chars().all(is_nodeset_char) {
+            f.write_str(name)
+        } else {
+            write_quoted(f, name)
+        }
+    }
+
+    /// Writes an id which is `pad` digits long in the folded nodeset
+    ///
+    /// `pad` includes the leading zeros of zero-padded ids (ie 3 for `007`
+    /// and 2 for `10`).
+    fn write_id(&self, f: &mut dyn fmt::Write, id: u32, pad: usize) -> fmt::Result {
+        write!(f, "{id:0>pad$}")
+    }
+
+    /// Delimiter opening a list of ranges (`[` by default)
+    fn open(&self) -> char {
+        '['
+    }
+
+    /// Delimiter closing a list of ranges (`]` by default)
+    fn close(&self) -> char {
+        ']'
+    }
+
+    /// Whether single ids are written between delimiters as well (ie
+    /// `node[1]`, disabled by default)
+    ///
+    /// Sets of nodes without a name (ie `1-3`) are never delimited.
+    fn bracket_single(&self) -> bool {
+        false
+    }
+
+    /// Separator written between sets of nodes with different names (`,` by
+    /// default)
+    fn separator(&self) -> char {
+        ','
+    }
+
+    /// Separator written between sets of nodes with the same names which
+    /// cannot be folded together (ie `r1n[1-2],r2n1`, `,` by default)
+    fn product_separator(&self) -> char {
+        ','
+    }
+}
+
+/// Formatter writing nodesets as their `Display` implementation
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultFormatter;
+
+impl NodeFormatter for DefaultFormatter {}
+
+/// Writes nodesets with the delimiters of the options
+impl NodeFormatter for ParseOptions {
+    fn open(&self) -> char {
+        self.open
+    }
+
+    fn close(&self) -> char {
+        self.close
+    }
+}
+
+/// Formatter writing nodesets as another formatter does, with a different
+/// separator between sets of nodes with different names
+///
+/// # Examples
+///
+/// ```
+/// use nodeset::{NodeSet, ParseOptions, Separated};
+///
+/// let options = ParseOptions::new().delimiters('{', '}');
+/// let ns: NodeSet = NodeSet::parse_with("login,node{1-2}", &options).unwrap();
+///
+/// assert_eq!(
+///     ns.display_with(&Separated::new(options, ' ')).to_string(),
+///     "login node{1-2}"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Separated<F> {
+    formatter: F,
+    separator: char,
+}
+
+impl<F: NodeFormatter> Separated<F> {
+    /// Writes nodesets with `formatter`, separating sets of nodes with
+    /// different names with `separator`
+    pub fn new(formatter: F, separator: char) -> Self {
+        Self {
+            formatter,
+            separator,
+        }
+    }
+}
+
+impl<F: NodeFormatter> NodeFormatter for Separated<F> {
+    fn write_name(&self, f: &mut dyn fmt::Write, name: &str) -> fmt::Result {
+        self.formatter.write_name(f, name)
+    }
+
+    fn write_id(&self, f: &mut dyn fmt::Write, id: u32, pad: usize) -> fmt::Result {
+        self.formatter.write_id(f, id, pad)
+    }
+
+    fn open(&self) -> char {
+        self.formatter.open()
+    }
+
+    fn close(&self) -> char {
+        self.formatter.close()
+    }
+
+    fn bracket_single(&self) -> bool {
+        self.formatter.bracket_single()
+    }
+
+    fn separator(&self) -> char {
+        self.separator
+    }
+
+    fn product_separator(&self) -> char {
+        self.formatter.product_separator()
+    }
+}
@@ -29,7 +29,7 @@ pub(crate) enum ProductCoords {
 }
 
 impl ProductCoords {
-    pub(crate) fn iter(&self) -> ProductCoordsIter {
+    pub(crate) fn iter(&self) -> ProductCoordsIter<'_> {
         ProductCoordsIter {
             coords: self,
             idx: 0,
@@ -148,7 +148,7 @@ where
         }
     }
 
-    fn iter(&self) -> std::slice::Iter<T> {
+    fn iter(&self) -> std::slice::Iter<'_, T> {
         self.ranges.iter()
     }
 
@@ -160,8 +160,53 @@ where
         }
     }
 
-    fn len(&self) -> usize {
-        self.ranges.iter().map(|r| r.len()).product()
+    /// Returns the number of elements in the product, saturating at
+    /// `u64::MAX`
+    fn len(&self) -> u64 {
+        self.ranges
+            .iter()
+            .try_fold(1u64, |acc, r| acc.checked_mul(r.len() as u64))
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Returns the coordinates of the `n`th element of the product in
+    /// iteration order
+    ///
+    /// The last dimension varies the fastest so `n` is decomposed as a
+    /// mixed-radix number whose digits are the indexes in each range.
+    fn nth(&self, mut n: u64) -> Option<Vec<u32>> {
+        if self.ranges.is_empty() || n >= self.len() {
+            return None;
+        }
+
+        let mut coords = vec![0; self.ranges.len()];
+        for (coord, range) in coords.iter_mut().zip(&self.ranges).rev() {
+            let len = range.len() as u64;
+            *coord = range.iter().nth((n % len) as usize)?;
+            n /= len;
+        }
+
+        Some(coords)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.ranges.shrink_to_fit();
+        self.ranges.iter_mut().for_each(T::shrink_to_fit);
+    }
+
+    #[cfg(feature = "heap-size")]
+    fn heap_size(&self) -> usize {
+        self.ranges.capacity() * std::mem::size_of::<T>()
+            + self.ranges.iter().map(T::heap_size).sum::<usize>()
+    }
+
+    fn contains(&self, coords: &[u32]) -> bool {
+        self.ranges.len() == coords.len()
+            && self
+                .ranges
+                .iter()
+                .zip(coords.iter())
+                .all(|(r, &id)| r.contains(id))
     }
 
     fn num_axis(&self) -> usize {
@@ -174,7 +219,7 @@ where
     T: Display,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(&self.ranges.iter().join(""))
+        self.ranges.iter().try_for_each(|r| write!(f, "{r}"))
     }
 }
 
@@ -191,7 +236,13 @@ where
     T: Display,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(&self.products.iter().join(","))
+        for (i, p) in self.products.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{p}")?;
+        }
+        Ok(())
     }
 }
 
@@ -240,8 +291,50 @@ where
         self.products.extend(other.products.iter().cloned());
     }
 
-    pub fn len(&self) -> usize {
-        self.products.iter().map(|x| x.len()).sum()
+    /// Returns the number of elements in the set, saturating at `u64::MAX`
+    pub fn len(&self) -> u64 {
+        self.products
+            .iter()
+            .fold(0u64, |acc, x| acc.saturating_add(x.len()))
+    }
+
+    /// Returns the coordinates of the `n`th element of the set in iteration
+    /// order, skipping the preceding products by their length
+    pub fn nth(&self, mut n: u64) -> Option<Vec<u32>> {
+        for p in &self.products {
+            let len = p.len();
+            if n < len {
+                return p.nth(n);
+            }
+            n -= len;
+        }
+
+        None
+    }
+
+    /// Releases unused memory held by the set
+    pub fn shrink_to_fit(&mut self) {
+        self.products.shrink_to_fit();
+        self.products
+            .iter_mut()
+            .for_each(IdRangeProduct::shrink_to_fit);
+    }
+
+    /// Returns an estimate of the number of bytes allocated on the heap by the
+    /// set
+    #[cfg(feature = "heap-size")]
+    pub fn heap_size(&self) -> usize {
+        self.products.capacity() * std::mem::size_of::<IdRangeProduct<T>>()
+            + self
+                .products
+                .iter()
+                .map(IdRangeProduct::heap_size)
+                .sum::<usize>()
+    }
+
+    /// Returns true if the set contains the element with the given coordinates
+    pub fn contains(&self, coords: &[u32]) -> bool {
+        self.products.iter().any(|p| p.contains(coords))
     }
 
     fn sort(&mut self, skip: usize) {
@@ -261,7 +354,7 @@ where
     }
 
     /// Split all products into products with one element each
-    fn full_split(&mut self) {
+    pub(crate) fn full_split(&mut self) {
         trace!("Splitting products into single elements");
 
         let mut orig_products = vec![];
@@ -435,13 +528,14 @@ where
         }
     }
 
-    /// Merge products with common ranges.
-    fn merge(&mut self) {
+    /// Merge products with common ranges along the last `max_axis` axis.
+    fn merge(&mut self, max_axis: usize) {
         let mut dellst = vec![false; self.products.len()];
         let mut merge_axis = self.products[0].num_axis();
+        let last_axis = merge_axis.saturating_sub(max_axis);
         let mut merged = 0;
         // Prioritize merging axis per axis from the last to the first
-        while merge_axis > 0 {
+        while merge_axis > last_axis {
             merge_axis -= 1;
             let mut idx1 = 0;
             while idx1 + 1 < self.products.len() {
@@ -512,20 +606,39 @@ where
         }
     }
 
+    /// Returns the number of dimensions of the elements of the set
+    pub(crate) fn num_axis(&self) -> usize {
+        self.products.first().map_or(0, |p| p.num_axis())
+    }
+
     pub fn fold(&mut self) -> &mut Self {
+        self.fold_dimensions(usize::MAX)
+    }
+
+    /// Folds the set, merging products along the last `max_axis` axis only
+    ///
+    /// Products are fully split when some axis are not merged so that ranges
+    /// along these axis hold a single id.
+    pub(crate) fn fold_dimensions(&mut self, max_axis: usize) -> &mut Self {
         self.prepare_sort();
 
+        if max_axis < self.num_axis() {
+            self.full_split();
+            self.merge(max_axis);
+            return self;
+        }
+
         // This is a heuristic to determine whether to do a full split or a
         // minimal split. The minimal split algorithm is O(n^2) in the number of
         // products, so it's not worth using it if the total number of elements
         // is smaller than the number of products squared. In that case, we do a
         // full split, which is O(n) in the number of elements
-        if self.len() > self.products.len() * self.products.len() {
+        if self.len() > (self.products.len() as u64).saturating_mul(self.products.len() as u64) {
             self.minimal_split();
         } else {
             self.full_split();
         }
-        self.merge();
+        self.merge(max_axis);
 
         self
     }
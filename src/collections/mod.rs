@@ -1,12 +1,31 @@
 mod config;
+mod expand;
+mod format;
 mod idset;
 mod nodeset;
 mod parsers;
+mod sources;
 
+pub use config::GlobalWatcher;
+pub use config::GroupSource;
+pub use config::PriorityMode;
 pub use config::Resolver;
+pub use config::ResolverBuilder;
+pub use config::SourceInfo;
+pub use config::StaticSource;
+pub use format::DefaultFormatter;
+pub use format::NodeFormatter;
+pub use format::Separated;
 pub(crate) use idset::IdSet;
 pub(crate) use idset::IdSetIter;
+pub use nodeset::ConfigurationError;
+pub use nodeset::FormatTooLarge;
+pub use nodeset::Node;
+pub use nodeset::NodeIds;
 pub use nodeset::NodeSet;
+pub use nodeset::NodeSetIntoIter;
 pub use nodeset::NodeSetIter;
 pub use nodeset::NodeSetParseError;
+pub use nodeset::PrefixOrder;
+pub use parsers::ParseOptions;
 pub use parsers::Parser;
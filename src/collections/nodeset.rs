@@ -1,12 +1,28 @@
+use super::format::NodeFormatter;
+use super::idset::IdRangeProduct;
+use super::parsers::is_nodeset_char;
+use super::parsers::ParseOptions;
 use super::parsers::Parser;
+use crate::idrange::count_ranges;
+use crate::idrange::debug_ranges;
+use crate::idrange::fold_ranges;
+use crate::idrange::id_bounds;
+use crate::idrange::split_ranges;
 use crate::idrange::CachedTranslation;
 use crate::idrange::IdRange;
 use crate::idrange::RangeStepError;
+use crate::idrange::SingleId;
 use crate::{IdSet, IdSetIter};
+use auto_enums::auto_enum;
+use itertools::{EitherOrBoth, Itertools};
+use std::borrow::Borrow;
 use std::collections::BTreeMap;
 use std::fmt;
+use std::fmt::Write as _;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-#[cfg(feature = "groups")]
 use crate::Resolver;
 
 /// An unordered collection of nodes indexed in one or more dimensions.
@@ -18,11 +34,153 @@ use crate::Resolver;
 /// By default `IdRangeList` are used as they are faster to build for one shot
 /// operations which are the most common, especially when using the CLI.
 /// However, if many updates are performed on a large NodeSet `IdRangeTree` may
-/// more efficient especially for one-dimensional NodeSets.
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// more efficient especially for one-dimensional NodeSets. Other backends can
+/// be provided by implementing [`IdRange`](crate::IdRange), and
+/// [`NodeSet::convert`] switches a set from one backend to another.
+///
+/// # Examples
+///
+/// ```
+/// use nodeset::{IdRangeTree, NodeSet};
+///
+/// let list: NodeSet = "node[1-4]".parse().unwrap();
+/// let mut tree: NodeSet<IdRangeTree> = "node[1-4]".parse().unwrap();
+///
+/// for i in 5..=8 {
+///     tree = tree.union(&format!("node{i}").parse().unwrap());
+/// }
+///
+/// assert_eq!(list.convert::<IdRangeTree>(), "node[1-4]".parse().unwrap());
+/// assert_eq!(tree.to_string(), "node[1-8]");
+/// ```
+///
+/// Cloning a NodeSet is cheap: clones share the indices of each set of nodes
+/// with the same dimension names (ie `node[1-10]` and `node[1-10]-ib` are two
+/// different sets) and a set is only copied when an operation modifies it.
+///
+/// Unlike `Display` which folds the set, `Debug` shows how the ids of each
+/// set of nodes are stored, as ranges of numerical ids with their padding,
+/// in a stable format:
+///
+/// ```
+/// use nodeset::NodeSet;
+///
+/// let ns: NodeSet = "node[00-04,10],r[1-2]n[1,3],login".parse().unwrap();
+///
+/// assert_eq!(
+///     format!("{ns:?}"),
+///     r#"NodeSet { prefixes: {"login": (), "node": [0-4 pad=2, 10], "r[]n": [[1-2] x [1, 3]]} }"#
+/// );
+/// ```
+#[derive(Clone)]
 pub struct NodeSet<T = crate::IdRangeList> {
-    pub(crate) bases: BTreeMap<NodeSetDimensions, IdSetKind<T>>,
+    pub(crate) bases: BTreeMap<NodeSetDimensions, Arc<IdSetKind<T>>>,
     lazy: bool,
+    /// Dimensions of `bases` in the order in which they were first added,
+    /// used to display nodesets with `PrefixOrder::Insertion`
+    order: Arc<Vec<NodeSetDimensions>>,
+}
+
+impl<T> fmt::Debug for NodeSet<T>
+where
+    T: IdRange,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /// Ids of a set of nodes
+        struct Ids<'a, T>(&'a IdSetKind<T>);
+
+        impl<T: IdRange> fmt::Debug for Ids<'_, T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match self.0 {
+                    IdSetKind::None => f.write_str("()"),
+                    IdSetKind::Single(range) => debug_ranges(f, range.iter()),
+                    IdSetKind::Multiple(set) => {
+                        f.write_str("[")?;
+                        for (i, product) in set.products.iter().enumerate() {
+                            if i > 0 {
+                                f.write_str(", ")?;
+                            }
+                            for (j, range) in product.ranges.iter().enumerate() {
+                                if j > 0 {
+                                    f.write_str(" x ")?;
+                                }
+                                debug_ranges(f, range.iter())?;
+                            }
+                        }
+                        f.write_str("]")
+                    }
+                }
+            }
+        }
+
+        /// Sets of nodes keyed by their dimension names
+        struct Prefixes<'a, T>(&'a BTreeMap<NodeSetDimensions, Arc<IdSetKind<T>>>);
+
+        impl<T: IdRange> fmt::Debug for Prefixes<'_, T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.debug_map()
+                    .entries(
+                        self.0
+                            .iter()
+                            .map(|(dims, set)| (dims.debug_name(), Ids(set.as_ref()))),
+                    )
+                    .finish()
+            }
+        }
+
+        let mut s = f.debug_struct("NodeSet");
+        s.field("prefixes", &Prefixes(&self.bases));
+        if self.lazy {
+            s.field("lazy", &self.lazy);
+        }
+        s.finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for NodeSet<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bases == other.bases && self.lazy == other.lazy
+    }
+}
+
+impl<T: Eq> Eq for NodeSet<T> {}
+
+/// Hashes the canonical string of the set, see [`NodeSet::canonical_string`]
+impl<T> std::hash::Hash for NodeSet<T>
+where
+    T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+{
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.canonical_string().hash(state);
+    }
+}
+
+/// Orders sets by their canonical string, see [`NodeSet::canonical_string`]
+///
+/// Sets holding the same nodes which are folded differently (ie with
+/// [`NodeSet::try_fold_dimensions`]) are not equal and are ordered by their
+/// internal representation.
+impl<T> Ord for NodeSet<T>
+where
+    T: IdRange + Eq + Clone + fmt::Display + fmt::Debug,
+{
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match self.canonical_string().cmp(&other.canonical_string()) {
+            std::cmp::Ordering::Equal if self != other => {
+                format!("{self:?}").cmp(&format!("{other:?}"))
+            }
+            ord => ord,
+        }
+    }
+}
+
+impl<T> PartialOrd for NodeSet<T>
+where
+    T: IdRange + Eq + Clone + fmt::Display + fmt::Debug,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -32,7 +190,91 @@ pub(crate) enum IdSetKind<T> {
     Multiple(IdSet<T>),
 }
 
+impl<T> IdSetKind<T>
+where
+    T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+{
+    /// Returns the number of nodes in the set, saturating at `u64::MAX`
+    fn len(&self) -> u64 {
+        match self {
+            IdSetKind::None => 1,
+            IdSetKind::Single(set) => set.len() as u64,
+            IdSetKind::Multiple(set) => set.len(),
+        }
+    }
+
+    /// Returns the number of gaps between the folded ranges of the set
+    fn gap_count(&self) -> usize {
+        let gaps = |range: &T| count_ranges(range.iter()).saturating_sub(1);
+
+        match self {
+            IdSetKind::None => 0,
+            IdSetKind::Single(set) => gaps(set),
+            IdSetKind::Multiple(set) => {
+                set.products.len().saturating_sub(1)
+                    + set
+                        .products
+                        .iter()
+                        .flat_map(|p| p.ranges.iter())
+                        .map(gaps)
+                        .sum::<usize>()
+            }
+        }
+    }
+
+    /// Returns the number of contiguous ranges of the set, saturating at
+    /// `usize::MAX`
+    fn range_count(&self) -> usize {
+        let ranges = |range: &T| count_ranges(range.iter());
+
+        match self {
+            IdSetKind::None => 1,
+            IdSetKind::Single(set) => ranges(set),
+            IdSetKind::Multiple(set) => set
+                .products
+                .iter()
+                .map(|p| {
+                    p.ranges
+                        .iter()
+                        .map(ranges)
+                        .fold(1usize, |acc, n| acc.saturating_mul(n))
+                })
+                .fold(0usize, |acc, n| acc.saturating_add(n)),
+        }
+    }
+
+    /// Copies the ids of the set to another range backend
+    fn convert<U: IdRange>(&self) -> IdSetKind<U> {
+        let convert = |range: &T| U::from(range.iter().collect::<Vec<_>>());
+
+        match self {
+            IdSetKind::None => IdSetKind::None,
+            IdSetKind::Single(set) => IdSetKind::Single(convert(set)),
+            IdSetKind::Multiple(set) => IdSetKind::Multiple(IdSet {
+                products: set
+                    .products
+                    .iter()
+                    .map(|p| IdRangeProduct {
+                        ranges: p.ranges.iter().map(convert).collect(),
+                    })
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Adds the ids of `other` to `self` without folding them
+    fn extend(&mut self, other: &Self) {
+        match (self, other) {
+            (IdSetKind::None, IdSetKind::None) => {}
+            (IdSetKind::Single(set), IdSetKind::Single(oset)) => set.push(oset),
+            (IdSetKind::Multiple(set), IdSetKind::Multiple(oset)) => set.extend(oset),
+            _ => panic!("Mismatched set kinds"),
+        }
+    }
+}
+
 impl NodeSet<crate::IdRangeList> {
+    /// Creates an empty NodeSet, same as `NodeSet::default()`
     pub fn new() -> Self {
         Self::default()
     }
@@ -43,6 +285,87 @@ impl<T> Default for NodeSet<T> {
         Self {
             bases: BTreeMap::new(),
             lazy: false,
+            order: Arc::default(),
+        }
+    }
+}
+
+/// A set of nodes sharing the same dimension names
+type Base<'a, T> = (&'a NodeSetDimensions, &'a Arc<IdSetKind<T>>);
+
+/// Order in which nodes with different prefixes are displayed or iterated
+///
+/// Nodes with the same dimension names (ie `node[1-10]`) are always ordered
+/// by their ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrefixOrder {
+    /// Sort by dimension names (ie `a5,a1b2`)
+    #[default]
+    Lexical,
+    /// Sort by the first node of each prefix, comparing ids numerically
+    /// (ie `a1b2,a5`)
+    Numeric,
+    /// Keep the order in which prefixes were first added to the nodeset
+    Insertion,
+}
+
+impl std::str::FromStr for PrefixOrder {
+    type Err = NodeSetParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lexical" => Ok(PrefixOrder::Lexical),
+            "numeric" => Ok(PrefixOrder::Numeric),
+            "insertion" => Ok(PrefixOrder::Insertion),
+            _ => Err(NodeSetParseError::Generic(s.to_string())),
+        }
+    }
+}
+
+/// Returns the name of the first node of a set of nodes sharing the same
+/// dimension names
+fn first_node<T>((dims, set): Base<'_, T>) -> String
+where
+    T: IdRange + fmt::Display + PartialEq + Clone + fmt::Display + fmt::Debug,
+{
+    match set.as_ref() {
+        IdSetKind::None => dims.dimnames[0].clone(),
+        IdSetKind::Single(range) => range
+            .iter()
+            .next()
+            .map(|id| dims.fmt_single_node(id, &mut None))
+            .unwrap_or_default(),
+        IdSetKind::Multiple(set) => set.nth(0).map(|ids| dims.fmt_node(ids)).unwrap_or_default(),
+    }
+}
+
+/// Compares strings so that runs of digits are compared by their numerical
+/// value (ie `n2` is before `n10`)
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    let (mut a, mut b) = (a, b);
+
+    loop {
+        let (Some(ca), Some(cb)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+
+        let ord = if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            let (na, ra) = a.split_at(a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len()));
+            let (nb, rb) = b.split_at(b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len()));
+            (a, b) = (ra, rb);
+
+            let (ta, tb) = (na.trim_start_matches('0'), nb.trim_start_matches('0'));
+            ta.len()
+                .cmp(&tb.len())
+                .then_with(|| ta.cmp(tb))
+                .then_with(|| na.len().cmp(&nb.len()))
+        } else {
+            (a, b) = (&a[ca.len_utf8()..], &b[cb.len_utf8()..]);
+            ca.cmp(&cb)
+        };
+
+        if ord.is_ne() {
+            return ord;
         }
     }
 }
@@ -55,12 +378,32 @@ pub struct NodeSetIter<'a, T>
 where
     T: IdRange + fmt::Display + PartialEq + Clone + fmt::Display + fmt::Debug,
 {
-    dim_iter:
-        std::iter::Peekable<std::collections::btree_map::Iter<'a, NodeSetDimensions, IdSetKind<T>>>,
+    dim_iter: std::iter::Peekable<BasesIter<'a, T>>,
     set_iter: IdSetIterKind<'a, T>,
     cache: Option<CachedTranslation>,
 }
 
+/// Sets of nodes visited by a `NodeSetIter`
+///
+/// The bases are walked in place in lexical order and only collected when
+/// another order is requested.
+#[derive(Debug, Clone)]
+enum BasesIter<'a, T> {
+    Lexical(std::collections::btree_map::Iter<'a, NodeSetDimensions, Arc<IdSetKind<T>>>),
+    Ordered(std::vec::IntoIter<Base<'a, T>>),
+}
+
+impl<'a, T> Iterator for BasesIter<'a, T> {
+    type Item = Base<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            BasesIter::Lexical(it) => it.next(),
+            BasesIter::Ordered(it) => it.next(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 enum IdSetIterKind<'a, T>
 where
@@ -75,9 +418,9 @@ impl<'b, T> NodeSetIter<'b, T>
 where
     T: IdRange + fmt::Display + PartialEq + Clone + fmt::Display + fmt::Debug,
 {
-    fn new(dims: &'b BTreeMap<NodeSetDimensions, IdSetKind<T>>) -> Self {
+    fn new(dims: BasesIter<'b, T>) -> Self {
         let mut it = Self {
-            dim_iter: dims.iter().peekable(),
+            dim_iter: dims.peekable(),
             set_iter: IdSetIterKind::None,
             cache: None,
         };
@@ -95,7 +438,7 @@ where
         self.set_iter = self
             .dim_iter
             .peek()
-            .map(|s| match s.1 {
+            .map(|s| match s.1.as_ref() {
                 IdSetKind::None => IdSetIterKind::None,
                 IdSetKind::Single(s) => IdSetIterKind::Single(s.iter()),
                 IdSetKind::Multiple(s) => IdSetIterKind::Multiple(s.iter()),
@@ -121,33 +464,14 @@ where
                 }
                 IdSetIterKind::Single(set_iter) => {
                     if let Some(coord) = set_iter.next() {
-                        let cache = self
-                            .cache
-                            .as_ref()
-                            .map(|c| c.interpolate(coord))
-                            .unwrap_or_else(|| CachedTranslation::new(coord));
-
-                        let mut res = String::with_capacity(
-                            dim.dimnames.iter().map(|s| s.len()).sum::<usize>()
-                                + cache.padding() as usize
-                                + 1,
-                        );
-
-                        dim.fmt_ranges(&mut res, [&cache])
-                            .expect("string format should succeed");
-
-                        self.cache = Some(cache);
-                        return Some(res);
+                        return Some(dim.fmt_single_node(coord, &mut self.cache));
                     } else {
                         self.next_dims();
                     }
                 }
                 IdSetIterKind::Multiple(set_iter) => {
                     if let Some(coords) = set_iter.next() {
-                        let mut res = String::new();
-                        dim.fmt_ranges(&mut res, coords.iter().map(CachedTranslation::new))
-                            .expect("string format should succeed");
-                        return Some(res);
+                        return Some(dim.fmt_node(coords.iter()));
                     } else {
                         self.next_dims();
                     }
@@ -157,615 +481,4625 @@ where
     }
 }
 
-impl<T> NodeSet<T>
+/// An owning iterator over nodes in a `NodeSet`
+///
+/// This iterator is created by the `into_iter` method on `NodeSet`. Node
+/// names are formatted one at a time and each set of nodes sharing the same
+/// dimension names is released once all its nodes have been yielded.
+#[derive(Debug)]
+pub struct NodeSetIntoIter<T> {
+    bases: std::collections::btree_map::IntoIter<NodeSetDimensions, Arc<IdSetKind<T>>>,
+    current: Option<(NodeSetDimensions, OwnedIdsIter<T>)>,
+    cache: Option<CachedTranslation>,
+}
+
+/// Ids of a set of nodes sharing the same dimension names
+#[derive(Debug)]
+enum OwnedIdsIter<T> {
+    None,
+    Single(std::vec::IntoIter<u32>),
+    Multiple {
+        set: Arc<IdSetKind<T>>,
+        /// Index of the next product of the set
+        product: usize,
+        /// Ids of each axis of the current product
+        axes: Vec<Vec<u32>>,
+        /// Position in each axis of the next node of the current product
+        pos: Vec<usize>,
+    },
+}
+
+impl<T> OwnedIdsIter<T>
 where
-    T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+    T: IdRange,
 {
-    /// Returns the number of elements in the set
-    pub fn len(&self) -> usize {
-        self.bases
-            .values()
-            .map(|set| match set {
-                IdSetKind::None => 1,
-                IdSetKind::Single(set) => set.len(),
-                IdSetKind::Multiple(set) => set.len(),
-            })
-            .sum()
-    }
-
-    /// Returns true if the set contains no element
-    pub fn is_empty(&self) -> bool {
-        self.bases.is_empty()
+    fn new(set: Arc<IdSetKind<T>>) -> Self {
+        match set.as_ref() {
+            IdSetKind::None => OwnedIdsIter::None,
+            IdSetKind::Single(ids) => {
+                OwnedIdsIter::Single(ids.iter().collect::<Vec<_>>().into_iter())
+            }
+            IdSetKind::Multiple(_) => OwnedIdsIter::Multiple {
+                set,
+                product: 0,
+                axes: vec![],
+                pos: vec![],
+            },
+        }
     }
 
-    /// Returns an iterator over all elements of the set
-    pub fn iter(&self) -> NodeSetIter<'_, T> {
-        NodeSetIter::new(&self.bases)
-    }
+    /// Returns the coordinates of the next node of a set with several
+    /// dimensions
+    fn next_coords(&mut self) -> Option<Vec<u32>> {
+        let OwnedIdsIter::Multiple {
+            set,
+            product,
+            axes,
+            pos,
+        } = self
+        else {
+            return None;
+        };
+        let IdSetKind::Multiple(ids) = set.as_ref() else {
+            return None;
+        };
 
-    /// Folds and deduplicates the internal representation of the set
-    ///
-    /// This method is automatically called after each operation unless the
-    /// NodeSet is in lazy mode. When in lazy mode, the NodeSet must be folded
-    /// again before calling len() otherwise nodes may be counted multiple
-    /// times.
-    pub(crate) fn fold(&mut self) -> &mut Self {
-        self.bases.values_mut().for_each(|s| match s {
-            IdSetKind::None => {}
-            IdSetKind::Single(set) => {
-                set.sort();
+        while pos.first().is_none_or(|&p| p >= axes[0].len()) {
+            let next = ids.products.get(*product)?;
+            *product += 1;
+            *axes = next.ranges.iter().map(|r| r.iter().collect()).collect();
+            *pos = vec![0; axes.len()];
+            if axes.iter().any(Vec::is_empty) {
+                pos.clear();
             }
-            IdSetKind::Multiple(set) => {
-                set.fold();
+        }
+
+        let coords = axes.iter().zip(pos.iter()).map(|(a, &p)| a[p]).collect();
+
+        // Advance the last axis first and carry over to the previous ones
+        for i in (0..pos.len()).rev() {
+            pos[i] += 1;
+            if pos[i] < axes[i].len() || i == 0 {
+                break;
             }
-        });
+            pos[i] = 0;
+        }
 
-        self.lazy = false;
-        self
+        Some(coords)
     }
+}
 
-    /// Adds elements from `other` to `self`
-    pub(crate) fn extend_from_nodeset(&mut self, other: &Self) {
-        for (dimname, oset) in other.bases.iter() {
-            match self.bases.get_mut(dimname) {
-                None => {
-                    self.bases.insert(dimname.clone(), oset.clone());
+impl<T> Iterator for NodeSetIntoIter<T>
+where
+    T: IdRange,
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.current.is_none() {
+                let (dims, set) = self.bases.next()?;
+                self.current = Some((dims, OwnedIdsIter::new(set)));
+                self.cache = None;
+            }
+
+            let (dims, ids) = self.current.as_mut()?;
+            let node = match ids {
+                OwnedIdsIter::None => {
+                    let node = dims.dimnames[0].clone();
+                    self.current = None;
+                    return Some(node);
                 }
-                Some(set) => match set {
-                    IdSetKind::None => {
-                        let IdSetKind::None = oset else {
-                            panic!("Mismatched set kinds");
-                        };
-                    }
-                    IdSetKind::Single(set) => {
-                        let IdSetKind::Single(oset) = oset else {
-                            panic!("Mismatched set kinds");
-                        };
-                        set.push(oset);
-                    }
-                    IdSetKind::Multiple(set) => {
-                        let IdSetKind::Multiple(oset) = oset else {
-                            panic!("Mismatched set kinds");
-                        };
-                        set.extend(oset);
-                    }
-                },
+                OwnedIdsIter::Single(ids) => ids
+                    .next()
+                    .map(|coord| dims.fmt_single_node(coord, &mut self.cache)),
+                OwnedIdsIter::Multiple { .. } => ids.next_coords().map(|c| dims.fmt_node(c)),
             };
+
+            match node {
+                Some(node) => return Some(node),
+                None => self.current = None,
+            }
         }
     }
+}
 
-    /// Returns a new set containing elements found in `self` and `other`
-    pub fn union(&self, other: &Self) -> Self {
-        let mut res = self.clone();
+impl<T> IntoIterator for NodeSet<T>
+where
+    T: IdRange,
+{
+    type Item = String;
+    type IntoIter = NodeSetIntoIter<T>;
 
-        res.extend_from_nodeset(other);
-        if !self.lazy {
-            res.fold();
+    /// Returns an iterator over all elements of the set, consuming the set
+    fn into_iter(self) -> Self::IntoIter {
+        NodeSetIntoIter {
+            bases: self.bases.into_iter(),
+            current: None,
+            cache: None,
         }
+    }
+}
 
-        res
+impl<T> NodeSet<T>
+where
+    T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+{
+    /// Returns the number of elements in the set
+    ///
+    /// Sets with multiple dimensions may hold more nodes than can be counted
+    /// with a `usize`, in which case the count saturates at `usize::MAX`. Use
+    /// [`NodeSet::len_u64`] for a count which does not depend on the target.
+    pub fn len(&self) -> usize {
+        usize::try_from(self.len_u64()).unwrap_or(usize::MAX)
     }
 
-    /// Returns a new set containing elements found in `self` but not in `other`
-    pub fn difference(&self, other: &Self) -> Self {
-        let mut dimnames = BTreeMap::<NodeSetDimensions, IdSetKind<T>>::new();
-        for (dimname, set) in self.bases.iter() {
-            if let Some(oset) = other.bases.get(dimname) {
-                match (set, oset) {
-                    (IdSetKind::None, IdSetKind::None) => continue,
-                    (IdSetKind::Single(set), IdSetKind::Single(oset)) => {
-                        let result = T::from_sorted(set.difference(oset));
-                        if !result.is_empty() {
-                            dimnames.insert(dimname.clone(), IdSetKind::Single(result));
-                        }
-                    }
-                    (IdSetKind::Multiple(set), IdSetKind::Multiple(oset)) => {
-                        if let Some(nset) = set.difference(oset) {
-                            dimnames.insert(dimname.clone(), IdSetKind::Multiple(nset));
-                        }
-                    }
-                    _ => {
-                        panic!("Mismatched set kinds");
-                    }
-                }
-            } else {
-                dimnames.insert(dimname.clone(), set.clone());
-            }
+    /// Returns the number of elements in the set as a `u64`
+    ///
+    /// The count is computed from the folded ranges without expanding the set
+    /// and saturates at `u64::MAX`, which can only be reached by sets with
+    /// multiple dimensions (ie `a[0-65535]b[0-65535]c[0-65535]d[0-65535]`).
+    pub fn len_u64(&self) -> u64 {
+        self.bases
+            .values()
+            .fold(0u64, |acc, set| acc.saturating_add(set.len()))
+    }
+
+    /// Returns the number of nodes of each prefix in the set
+    ///
+    /// Prefixes are returned in lexical order. Sets of nodes sharing the same
+    /// prefix but with different dimensions (ie `node[1-2]` and `node1b[1-2]`)
+    /// are counted together. Counts are computed from the folded ranges
+    /// without expanding the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "compute[1-8]b[1-2],compute9,login[1-4]".parse().unwrap();
+    ///
+    /// assert_eq!(
+    ///     ns.prefix_counts(),
+    ///     vec![("compute".to_string(), 17), ("login".to_string(), 4)]
+    /// );
+    /// ```
+    pub fn prefix_counts(&self) -> Vec<(String, u64)> {
+        let mut counts: BTreeMap<&str, u64> = BTreeMap::new();
+
+        for (dims, set) in &self.bases {
+            let count = counts.entry(dims.dimnames[0].as_str()).or_default();
+            *count = count.saturating_add(set.len());
         }
 
-        NodeSet::from_dims(dimnames, self.lazy)
+        counts
+            .into_iter()
+            .map(|(prefix, count)| (prefix.to_string(), count))
+            .collect()
     }
 
-    /// Returns a new set containing elements that are in both `self` and `other`
-    pub fn intersection(&self, other: &Self) -> Self {
-        let mut dimnames = BTreeMap::<NodeSetDimensions, IdSetKind<T>>::new();
-        for (dimname, set) in self.bases.iter() {
-            if let Some(oset) = other.bases.get(dimname) {
-                match (set, oset) {
-                    (IdSetKind::None, IdSetKind::None) => continue,
-                    (_, IdSetKind::None) => {
-                        dimnames.insert(dimname.clone(), set.clone());
-                    }
-                    (IdSetKind::Single(set), IdSetKind::Single(oset)) => {
-                        let result = T::from_sorted(set.intersection(oset));
-                        if !result.is_empty() {
-                            dimnames.insert(dimname.clone(), IdSetKind::Single(result));
-                        }
-                    }
-                    (IdSetKind::Multiple(set), IdSetKind::Multiple(oset)) => {
-                        if let Some(nset) = set.intersection(oset) {
-                            dimnames.insert(dimname.clone(), IdSetKind::Multiple(nset));
-                        }
-                    }
-                    _ => {
-                        panic!("Mismatched set kinds");
+    /// Returns the smallest and largest index of each prefix in the set
+    ///
+    /// Prefixes are defined as in [`NodeSet::prefixes`] and returned in
+    /// lexical order. The index of a node is its first numeric id (ie `1` for
+    /// `compute1b2`), and prefixes of nodes without ids (ie `login`) are
+    /// omitted. Zero-padded ids are compared by their numerical value. Bounds
+    /// are searched in the ranges without expanding the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "compute[1-8]b[1-2],compute12,login".parse().unwrap();
+    ///
+    /// assert_eq!(ns.bounds(), vec![("compute".to_string(), 1, 12)]);
+    /// ```
+    pub fn bounds(&self) -> Vec<(String, u32, u32)> {
+        let mut bounds: BTreeMap<&str, (u32, u32)> = BTreeMap::new();
+
+        for (dims, set) in &self.bases {
+            let mut add = |range: &T| {
+                let Some((min, max)) = id_bounds(range) else {
+                    return;
+                };
+                bounds
+                    .entry(dims.dimnames[0].as_str())
+                    .and_modify(|bounds| *bounds = (bounds.0.min(min), bounds.1.max(max)))
+                    .or_insert((min, max));
+            };
+
+            match set.as_ref() {
+                IdSetKind::None => {}
+                IdSetKind::Single(range) => add(range),
+                IdSetKind::Multiple(set) => {
+                    for product in &set.products {
+                        add(&product.ranges[0]);
                     }
                 }
             }
         }
 
-        NodeSet::from_dims(dimnames, self.lazy)
+        bounds
+            .into_iter()
+            .map(|(prefix, (min, max))| (prefix.to_string(), min, max))
+            .collect()
     }
 
-    /// Returns a new set containing the elements found in either `self` or `other` but not in both
-    pub fn symmetric_difference(&self, other: &Self) -> Self {
-        let mut dimnames = BTreeMap::<NodeSetDimensions, IdSetKind<T>>::new();
-        for (dimname, set) in self.bases.iter() {
-            if let Some(oset) = other.bases.get(dimname) {
-                match (set, oset) {
-                    (IdSetKind::None, IdSetKind::None) => continue,
-                    (IdSetKind::Single(set), IdSetKind::Single(oset)) => {
-                        let result = T::from_sorted(set.symmetric_difference(oset));
-                        if !result.is_empty() {
-                            dimnames.insert(dimname.clone(), IdSetKind::Single(result));
-                        }
-                    }
-                    (IdSetKind::Multiple(set), IdSetKind::Multiple(oset)) => {
-                        if let Some(nset) = set.symmetric_difference(oset) {
-                            dimnames.insert(dimname.clone(), IdSetKind::Multiple(nset));
-                        }
-                    }
-                    _ => {
-                        panic!("Mismatched set kinds");
-                    }
-                }
-            } else {
-                dimnames.insert(dimname.clone(), set.clone());
-            }
-        }
-        for (dimname, set) in other.bases.iter() {
-            if !self.bases.contains_key(dimname) {
-                dimnames.insert(dimname.clone(), set.clone());
-            }
-        }
-        NodeSet::from_dims(dimnames, self.lazy)
+    /// Returns the distinct prefixes of the nodes in the set
+    ///
+    /// The prefix of a node is the text before its first numeric id (ie
+    /// `compute` for `compute1b2`, or an empty string for `1-3`). Prefixes are
+    /// returned in lexical order, each one only once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "login[1-4],compute[1-8]b[1-2],compute9".parse().unwrap();
+    ///
+    /// assert_eq!(ns.prefixes(), vec!["compute", "login"]);
+    /// ```
+    pub fn prefixes(&self) -> Vec<String> {
+        self.bases
+            .keys()
+            .map(|dims| dims.dimnames[0].as_str())
+            .dedup()
+            .map(str::to_string)
+            .collect()
     }
 
-    /// Create a NodeSet from a mapping of NodeSetDimensions to IdSets
-    fn from_dims(dimnames: BTreeMap<NodeSetDimensions, IdSetKind<T>>, lazy: bool) -> Self {
-        let mut res = NodeSet {
-            bases: dimnames,
-            lazy,
-        };
+    /// Returns the number of nodes in the set with a given prefix
+    ///
+    /// Prefixes are defined as in [`NodeSet::prefixes`]. The count saturates
+    /// at `usize::MAX` like [`NodeSet::len`].
+    pub fn count_for_prefix(&self, prefix: impl AsRef<str>) -> usize {
+        let prefix = prefix.as_ref();
+        let count = self
+            .bases
+            .iter()
+            .filter(|(dims, _)| dims.dimnames[0] == prefix)
+            .fold(0u64, |acc, (_, set)| acc.saturating_add(set.len()));
 
-        if !lazy {
-            res.fold();
-        }
+        usize::try_from(count).unwrap_or(usize::MAX)
+    }
 
-        res
+    /// Removes all the nodes with a given prefix from the set
+    ///
+    /// Prefixes are defined as in [`NodeSet::prefixes`]. The sets of ids of
+    /// the prefix are dropped without being expanded. Returns true if the set
+    /// held nodes with this prefix.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let mut ns: NodeSet = "a[0-3],b[0-3]".parse().unwrap();
+    ///
+    /// assert!(ns.remove_prefix("a"));
+    /// assert!(!ns.remove_prefix("a"));
+    /// assert_eq!(ns.to_string(), "b[0-3]");
+    /// ```
+    pub fn remove_prefix(&mut self, prefix: impl AsRef<str>) -> bool {
+        let prefix = prefix.as_ref();
+        self.retain_dims(|dims| dims.dimnames[0] != prefix)
     }
 
-    /// Create a new lazy NodeSet which does not automatically folds after each
-    /// operation
-    pub(crate) fn lazy() -> Self {
-        NodeSet {
-            bases: BTreeMap::new(),
-            lazy: true,
-        }
+    /// Removes all the nodes which do not have a given prefix from the set
+    ///
+    /// Prefixes are defined as in [`NodeSet::prefixes`]. The sets of ids of
+    /// the other prefixes are dropped without being expanded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let mut ns: NodeSet = "a[0-3],a1b[1-2],b[0-3]".parse().unwrap();
+    ///
+    /// ns.keep_only_prefix("a");
+    /// assert_eq!(ns.to_string(), "a[0-3],a1b[1-2]");
+    /// ```
+    pub fn keep_only_prefix(&mut self, prefix: impl AsRef<str>) {
+        let prefix = prefix.as_ref();
+        self.retain_dims(|dims| dims.dimnames[0] == prefix);
     }
-}
 
-impl<T> std::str::FromStr for NodeSet<T>
-where
-    T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
-{
-    type Err = NodeSetParseError;
+    /// Returns a copy of the set with the prefix of each node replaced by
+    /// `f(prefix)`
+    ///
+    /// Prefixes are defined as in [`NodeSet::prefixes`]. The ids of the nodes
+    /// and their padding are kept, and the ids of prefixes which are renamed
+    /// to the same name are merged without expanding the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "oldnode[01-04],newnode[05-08],login1".parse().unwrap();
+    /// let renamed = ns.map_prefix(|prefix| prefix.replace("oldnode", "newnode"));
+    ///
+    /// assert_eq!(renamed.to_string(), "login1,newnode[01-08]");
+    /// ```
+    pub fn map_prefix(&self, f: impl Fn(&str) -> String) -> Self {
+        let mut res = Self::lazy();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        #[cfg(feature = "groups")]
-        {
-            let resolver = Resolver::get_global();
-            Parser::with_resolver(resolver, None).parse::<T>(s)
+        for (dims, set) in self.ordered_bases(PrefixOrder::Insertion) {
+            let mut dims = dims.clone();
+            dims.dimnames[0] = f(&dims.dimnames[0]);
+            match res.bases.get_mut(&dims) {
+                None => res.insert_base(dims, self.share(set)),
+                Some(rset) => Arc::make_mut(rset).extend(set),
+            }
         }
 
-        #[cfg(not(feature = "groups"))]
-        {
-            Parser::default().parse::<T>(s)
+        res.lazy = self.lazy;
+        if !res.lazy {
+            res.fold();
         }
-    }
-}
-
-/// List of names for each dimension of a NodeSet along with an optional suffix
-#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Default, Debug)]
-pub(crate) struct NodeSetDimensions {
-    /// The names associated with each dimension of a nodeset
-    dimnames: Vec<String>,
-    /// If true, the last name is a suffix meaning that there is one more name
-    /// than ranges
-    has_suffix: bool,
-}
 
-impl NodeSetDimensions {
-    pub(crate) fn new() -> NodeSetDimensions {
-        Default::default()
+        res
     }
 
-    pub(crate) fn push(&mut self, d: &str) {
-        assert!(
-            !self.has_suffix,
-            "Cannot add a dimension name after a suffix has been added"
-        );
-        self.dimnames.push(d.into());
-    }
+    /// Returns a copy of the set with the ids of the nodes with a given
+    /// prefix shifted by `delta`
+    ///
+    /// Prefixes are defined as in [`NodeSet::prefixes`], and only the first
+    /// id of each node is shifted (ie `r1n2` becomes `r101n2` with a delta of
+    /// 100). Zero-padded ids keep their width (ie `node[001-010]` becomes
+    /// `node[006-015]` with a delta of 5) whereas other ids are not padded.
+    /// Nodes which end up with the same name are merged.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`NodeSetParseError::IdShift`] if an id would become
+    /// negative or too large.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "node[1-10],login1".parse().unwrap();
+    ///
+    /// assert_eq!(
+    ///     ns.shift_ids("node", 100).unwrap().to_string(),
+    ///     "login1,node[101-110]"
+    /// );
+    /// assert!(ns.shift_ids("node", -2).is_err());
+    /// ```
+    pub fn shift_ids(
+        &self,
+        prefix: impl AsRef<str>,
+        delta: i64,
+    ) -> Result<Self, NodeSetParseError> {
+        let prefix = prefix.as_ref();
+        let mut res = self.clone();
 
-    pub(crate) fn push_suffix(&mut self, d: &str) {
-        self.has_suffix = true;
-        self.dimnames.push(d.into());
-    }
+        for (dims, set) in res.bases.iter_mut() {
+            if dims.dimnames[0] != prefix {
+                continue;
+            }
 
-    pub(crate) fn fmt_ranges<T>(
-        &self,
-        f: &mut dyn fmt::Write,
-        ranges: impl IntoIterator<Item = T>,
-    ) -> fmt::Result
-    where
-        T: fmt::Display,
-    {
-        let mut dimnames = self.dimnames.iter();
-        for r in ranges.into_iter() {
-            f.write_str(
-                dimnames
-                    .next()
-                    .expect("should be at least as many names as ranges"),
-            )?;
-            if self.is_rangeset() {
-                write!(f, "{:#}", r)?;
-            } else {
-                write!(f, "{}", r)?;
+            match Arc::make_mut(set) {
+                IdSetKind::None => {}
+                IdSetKind::Single(range) => *range = shift_range(range, delta)?,
+                IdSetKind::Multiple(set) => {
+                    for product in &mut set.products {
+                        product.ranges[0] = shift_range(&product.ranges[0], delta)?;
+                    }
+                }
             }
         }
 
-        if let Some(suffix) = dimnames.next() {
-            f.write_str(suffix)?;
+        if !res.lazy {
+            res.fold();
         }
 
-        Ok(())
+        Ok(res)
     }
 
-    fn is_rangeset(&self) -> bool {
-        self.dimnames.len() == 1 && self.dimnames[0].is_empty()
+    /// Keeps the sets of the dimensions for which `f` returns true
+    ///
+    /// Returns true if any set was removed.
+    fn retain_dims(&mut self, mut f: impl FnMut(&NodeSetDimensions) -> bool) -> bool {
+        let len = self.bases.len();
+        self.bases.retain(|dims, _| f(dims));
+        if self.bases.len() == len {
+            return false;
+        }
+
+        Arc::make_mut(&mut self.order).retain(|dims| self.bases.contains_key(dims));
+        true
     }
-}
 
-impl<T> fmt::Display for NodeSet<T>
-where
-    T: IdRange + fmt::Display + PartialEq + Clone + fmt::Display + fmt::Debug,
-{
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut first = true;
+    /// Folds the IPv4 addresses of the set into a minimal list of CIDR blocks
+    ///
+    /// Contiguous addresses are covered by the fewest possible CIDR blocks,
+    /// which are returned in ascending order. Nodes which are not IPv4
+    /// addresses cannot be represented as CIDR blocks: they are returned
+    /// folded as a nodeset in a last element.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "10.0.0.[0-255],10.0.1.[0-2],login".parse().unwrap();
+    ///
+    /// assert_eq!(ns.to_cidrs(), vec!["10.0.0.0/24", "10.0.1.0/31", "10.0.1.2/32", "login"]);
+    /// ```
+    #[cfg(feature = "ip")]
+    pub fn to_cidrs(&self) -> Vec<String> {
+        let mut addrs = vec![];
+        let mut others = NodeSet::<T>::default();
 
-        for (dim, set) in &self.bases {
-            if !first {
-                f.write_str(",")?;
-            }
-            match set {
-                IdSetKind::None => {
-                    write!(f, "{}", dim.dimnames[0])?;
+        for (dims, set) in &self.bases {
+            let set = match set.as_ref() {
+                IdSetKind::Multiple(set) if dims.is_ipv4() => set,
+                _ => {
+                    others.insert_base(dims.clone(), set.clone());
+                    continue;
                 }
-                IdSetKind::Single(set) => {
-                    dim.fmt_ranges(f, [set])?;
-                }
-                IdSetKind::Multiple(set) => {
-                    set.fmt_dims(f, dim).expect("failed to format string");
+            };
+
+            // Addresses are computed from the ids, nodes with an octet which
+            // is too large or zero-padded are kept as they are
+            let mut products = vec![];
+            for coords in set.iter() {
+                let addr = coords.iter().try_fold(0u64, |addr, rank| {
+                    let octet = CachedTranslation::new(rank);
+                    let digits = octet.id().checked_ilog10().unwrap_or(0) + 1;
+                    (octet.id() <= 255 && octet.padding() == digits)
+                        .then_some(addr << 8 | u64::from(octet.id()))
+                });
+
+                match addr {
+                    Some(addr) => addrs.push(addr),
+                    None => products.push(IdRangeProduct {
+                        ranges: coords.iter().map(T::from).collect(),
+                    }),
                 }
             }
 
-            first = false;
+            if !products.is_empty() {
+                let mut set = IdSet { products };
+                set.fold();
+                others.insert_base(dims.clone(), Arc::new(IdSetKind::Multiple(set)));
+            }
         }
-        Ok(())
-    }
-}
+        addrs.sort_unstable();
+        addrs.dedup();
 
-/// Errors that may happen when parsing configuration files
-#[derive(thiserror::Error, Debug)]
-pub enum ConfigurationError {
-    /// A YAML configuration file cannot be parsed
-    #[error("invalid yaml file")]
-    InvalidYamlFile(#[from] serde_yaml::Error),
+        let mut res = vec![];
+        let mut it = addrs.into_iter().peekable();
+        while let Some(mut start) = it.next() {
+            let mut end = start;
+            while it.next_if_eq(&(end + 1)).is_some() {
+                end += 1;
+            }
 
-    /// An INI configuration file cannot be parsed
-    #[error("invalid ini file")]
-    InvalidIniFile(#[from] ini::Error),
+            // Split [start, end] into the largest aligned blocks
+            while start <= end {
+                let align = if start == 0 {
+                    32
+                } else {
+                    start.trailing_zeros()
+                };
+                let fit = (end - start + 1).ilog2();
+                let bits = align.min(fit);
+                res.push(format!(
+                    "{}/{}",
+                    std::net::Ipv4Addr::from(start as u32),
+                    32 - bits
+                ));
+                start += 1 << bits;
+            }
+        }
 
-    /// A property is missing in the configuration file
-    #[error("missing ini property: {0}")]
-    MissingProperty(String),
+        if !others.is_empty() {
+            res.push(others.to_string());
+        }
 
-    /// An unexpected property was found in the configuration file
-    #[error("unexpected ini property: {0}")]
-    UnexpectedProperty(String),
-}
+        res
+    }
 
-/// Errors that may happen when parsing nodesets
-#[derive(thiserror::Error, Debug)]
-pub enum NodeSetParseError {
-    /// An error occurred while parsing an integer.
-    #[error("invalid range")]
-    RangeError(#[from] RangeStepError),
+    /// Returns true if the set contains no element
+    pub fn is_empty(&self) -> bool {
+        self.bases.is_empty()
+    }
 
-    /// An error occurred while parsing an integer.
-    #[error("invalid integer")]
-    ParseIntError(#[from] std::num::ParseIntError),
+    /// Releases unused memory held by the set
+    ///
+    /// Memory held by indices shared with clones of this set is not released.
+    pub fn shrink_to_fit(&mut self) {
+        for set in self.bases.values_mut() {
+            let Some(set) = Arc::get_mut(set) else {
+                continue;
+            };
 
-    /// An index is out of range.
-    #[error("value out of range")]
-    OverFlow(#[from] std::num::TryFromIntError),
+            match set {
+                IdSetKind::None => {}
+                IdSetKind::Single(set) => set.shrink_to_fit(),
+                IdSetKind::Multiple(set) => set.shrink_to_fit(),
+            }
+        }
+    }
 
-    /// An error occurred while executing an external command as specified in the dynamic configuration file.
-    #[error("external command execution failed")]
-    Command(#[from] std::io::Error),
+    /// Returns an estimate of the number of bytes allocated on the heap by the
+    /// set
+    ///
+    /// This includes the dimension names of the nodes and the storage of their
+    /// indices including unused capacity. It does not include the overhead of
+    /// the allocator and of the internal nodes of BTrees (the map of
+    /// dimension names and `IdRangeTree`). Indices shared with clones are
+    /// counted in full in each clone.
+    #[cfg(feature = "heap-size")]
+    pub fn heap_size(&self) -> usize {
+        self.bases
+            .iter()
+            .map(|(dims, set)| {
+                let dims_size = dims.dimnames.capacity() * std::mem::size_of::<String>()
+                    + dims.dimnames.iter().map(String::capacity).sum::<usize>();
 
-    /// A parsing error which does not fit in any other category.
-    #[error("unable to parse '{0}'")]
-    Generic(String),
+                let set_size = match set.as_ref() {
+                    IdSetKind::None => 0,
+                    IdSetKind::Single(set) => set.heap_size(),
+                    IdSetKind::Multiple(set) => set.heap_size(),
+                };
 
-    /// Padding sizes at both ends of a range do not match (ie `[01-003]`).
-    #[error("mismatched padding: '{0}' and '{1}'")]
-    MismatchedPadding(String, String),
+                // Arc allocations hold a strong and a weak counter
+                dims_size
+                    + 2 * std::mem::size_of::<usize>()
+                    + std::mem::size_of::<IdSetKind<T>>()
+                    + set_size
+            })
+            .sum()
+    }
 
-    /// A reference was made to a group source that does not exist.
-    #[error("Unknown group source: '{0}'")]
-    Source(String),
-}
+    /// Returns true if the set contains `node`
+    ///
+    /// `node` is parsed on each call, use `contains_node()` with a [`Node`]
+    /// parsed once to test many sets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "node[1-4]".parse().unwrap();
+    /// let node = format!("node{}", 2);
+    ///
+    /// assert!(ns.contains(&node).unwrap());
+    /// assert!(ns.contains(node).unwrap());
+    /// assert!(!ns.contains("node5").unwrap());
+    /// ```
+    pub fn contains(&self, node: impl AsRef<str>) -> Result<bool, NodeSetParseError> {
+        Ok(self.contains_node(&node.as_ref().parse()?))
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::idrange::IdRangeList;
+    /// Returns true if the set contains `node`
+    ///
+    /// This does not allocate.
+    pub fn contains_node(&self, node: &Node) -> bool {
+        let Some(set) = self.bases.get(&node.dims) else {
+            return false;
+        };
 
-    fn parse_to_fold(ns: &str) -> Result<String, NodeSetParseError> {
-        ns.parse::<NodeSet<IdRangeList>>().map(|ns| ns.to_string())
+        match set.as_ref() {
+            IdSetKind::None => true,
+            IdSetKind::Single(set) => set.contains(node.ranks[0]),
+            IdSetKind::Multiple(set) => set.contains(&node.ranks),
+        }
     }
 
-    fn parse_to_vec(ns: &str) -> Result<Vec<String>, NodeSetParseError> {
-        ns.parse::<NodeSet<IdRangeList>>().map(|ns| {
-            let mut r: Vec<_> = ns.iter().collect();
-            r.sort();
-            r
-        })
+    /// Adds a node to the set
+    ///
+    /// Returns `Ok(true)` if the node was not in the set, `Ok(false)` if it
+    /// already was, and an error if `node` is not a single node. Only the ids
+    /// of the nodes sharing the dimensions of `node` are updated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let mut ns: NodeSet = "node[0-3]".parse().unwrap();
+    ///
+    /// assert!(!ns.insert("node2").unwrap());
+    /// assert!(ns.insert("node5").unwrap());
+    /// assert_eq!(ns.to_string(), "node[0-3,5]");
+    /// ```
+    pub fn insert(&mut self, node: &str) -> Result<bool, NodeSetParseError> {
+        let node: Node = node.parse()?;
+        if self.contains_node(&node) {
+            return Ok(false);
+        }
+
+        let Node { dims, ranks } = node;
+        let single = |rank: u32| T::from(vec![rank]);
+
+        let Some(set) = self.bases.get_mut(&dims) else {
+            let set = match ranks.as_slice() {
+                [] => IdSetKind::None,
+                [rank] => IdSetKind::Single(single(*rank)),
+                ranks => IdSetKind::Multiple(IdSet {
+                    products: vec![IdRangeProduct {
+                        ranges: ranks.iter().copied().map(single).collect(),
+                    }],
+                }),
+            };
+            self.insert_base(dims, Arc::new(set));
+            return Ok(true);
+        };
+
+        match Arc::make_mut(set) {
+            IdSetKind::None => unreachable!("set without ids contains its node"),
+            IdSetKind::Single(range) => range.push(&single(ranks[0])),
+            IdSetKind::Multiple(set) => {
+                set.products.push(IdRangeProduct {
+                    ranges: ranks.iter().copied().map(single).collect(),
+                });
+                set.fold();
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Removes a node from the set
+    ///
+    /// Returns `Ok(true)` if the node was in the set, `Ok(false)` if it was
+    /// not, and an error if `node` is not a single node. Only the ids of the
+    /// nodes sharing the dimensions of `node` are updated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let mut ns: NodeSet = "node[0-3]".parse().unwrap();
+    ///
+    /// assert!(ns.remove("node2").unwrap());
+    /// assert!(!ns.remove("node5").unwrap());
+    /// assert_eq!(ns.to_string(), "node[0-1,3]");
+    /// ```
+    pub fn remove(&mut self, node: &str) -> Result<bool, NodeSetParseError> {
+        let node: Node = node.parse()?;
+        if !self.contains_node(&node) {
+            return Ok(false);
+        }
+
+        let set = self
+            .bases
+            .get_mut(&node.dims)
+            .expect("set contains the node");
+        let single = |rank: u32| T::from(vec![rank]);
+
+        let empty = match Arc::make_mut(set) {
+            IdSetKind::None => true,
+            IdSetKind::Single(range) => {
+                *range = T::from_sorted(range.difference(&single(node.ranks[0])));
+                range.is_empty()
+            }
+            IdSetKind::Multiple(set) => {
+                let removed = IdSet {
+                    products: vec![IdRangeProduct {
+                        ranges: node.ranks.iter().copied().map(single).collect(),
+                    }],
+                };
+                match set.difference(&removed) {
+                    Some(remaining) => {
+                        *set = remaining;
+                        false
+                    }
+                    None => true,
+                }
+            }
+        };
+
+        if empty {
+            self.retain_dims(|dims| *dims != node.dims);
+        }
+
+        Ok(true)
+    }
+
+    /// Returns true if the nodes sharing each prefix form a single range
+    /// without gaps
+    ///
+    /// Nodes with several dimensions form a single range if they fold into a
+    /// single product of gap-free ranges (ie `r[1-2]n[1-4]`). An empty set is
+    /// contiguous.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "node[1-4],gpu[01-04],login".parse().unwrap();
+    /// assert!(ns.is_contiguous());
+    ///
+    /// let ns: NodeSet = "node[1-4,6]".parse().unwrap();
+    /// assert!(!ns.is_contiguous());
+    /// ```
+    pub fn is_contiguous(&self) -> bool {
+        self.gap_count() == 0
+    }
+
+    /// Returns the number of gaps between the folded ranges of the set
+    ///
+    /// Each prefix contributes one gap between each pair of consecutive
+    /// ranges (ie `node[1-2,4,6]` has 2 gaps). Nodes with several dimensions
+    /// contribute the gaps of each dimension of each folded product, plus one
+    /// gap between each pair of products.
+    pub fn gap_count(&self) -> usize {
+        self.bases.values().map(|s| s.gap_count()).sum()
+    }
+
+    /// Returns the number of contiguous ranges of the set
+    ///
+    /// This is the number of sets yielded by [`NodeSet::split_contiguous`]
+    /// without building them. The count saturates at `usize::MAX`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "node[0-999]".parse().unwrap();
+    /// assert_eq!(ns.range_count(), 1);
+    ///
+    /// let ns: NodeSet = "node[0,2,4]".parse().unwrap();
+    /// assert_eq!(ns.range_count(), 3);
+    /// ```
+    pub fn range_count(&self) -> usize {
+        self.bases
+            .values()
+            .fold(0, |acc, s| acc.saturating_add(s.range_count()))
+    }
+
+    /// Returns an iterator over the contiguous ranges of the set, each one as
+    /// a separate set
+    ///
+    /// Each yielded set is contiguous as defined by [`NodeSet::is_contiguous`]
+    /// and prefixes are yielded in lexical order. Nodes with several
+    /// dimensions are split into the products of the gap-free ranges of each
+    /// dimension.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "node[0-2,5-6],login".parse().unwrap();
+    /// let ranges: Vec<_> = ns.split_contiguous().map(|s| s.to_string()).collect();
+    ///
+    /// assert_eq!(ranges, vec!["login", "node[0-2]", "node[5-6]"]);
+    /// ```
+    pub fn split_contiguous(&self) -> impl Iterator<Item = Self> + '_ {
+        self.split_contiguous_ordered(PrefixOrder::Lexical)
+    }
+
+    /// Returns an iterator over the contiguous ranges of the set with
+    /// prefixes iterated in the specified order
+    ///
+    /// See [`NodeSet::split_contiguous`].
+    pub fn split_contiguous_ordered(&self, order: PrefixOrder) -> impl Iterator<Item = Self> + '_ {
+        let split = |range: &T| {
+            split_ranges(range.iter())
+                .into_iter()
+                .map(T::from)
+                .collect::<Vec<_>>()
+        };
+
+        self.ordered_bases(order)
+            .into_iter()
+            .flat_map(move |(dims, set)| {
+                let sets: Vec<IdSetKind<T>> = match set.as_ref() {
+                    IdSetKind::None => vec![IdSetKind::None],
+                    IdSetKind::Single(range) => {
+                        split(range).into_iter().map(IdSetKind::Single).collect()
+                    }
+                    IdSetKind::Multiple(set) => set
+                        .products
+                        .iter()
+                        .flat_map(|p| {
+                            p.ranges
+                                .iter()
+                                .map(split)
+                                .multi_cartesian_product()
+                                .map(|ranges| {
+                                    IdSetKind::Multiple(IdSet {
+                                        products: vec![IdRangeProduct { ranges }],
+                                    })
+                                })
+                        })
+                        .collect(),
+                };
+
+                sets.into_iter().map(move |set| {
+                    let mut ns = Self::default();
+                    ns.insert_base(dims.clone(), Arc::new(set));
+                    ns
+                })
+            })
+    }
+
+    /// Returns the node at `offset` in the iteration order of the set, or
+    /// None if the set has no more than `offset` nodes
+    ///
+    /// The node is located by skipping whole sets of nodes and products of
+    /// ranges by their length, without formatting the preceding nodes. In
+    /// sets with multiple dimensions, the last dimension varies the fastest,
+    /// so that the offset is decomposed as a mixed-radix number with a digit
+    /// per dimension. The id at that digit is then looked up in the range of
+    /// each dimension, which takes constant time with [`crate::IdRangeList`]
+    /// but walks the preceding ids of the range with [`crate::IdRangeTree`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "a[0-1]b[0-2],c[1-10]".parse().unwrap();
+    ///
+    /// assert_eq!(ns.node_at_offset(3).unwrap(), "a1b0");
+    /// assert_eq!(ns.node_at_offset(5).unwrap(), "a1b2");
+    /// assert_eq!(ns.node_at_offset(6).unwrap(), "c1");
+    /// assert_eq!(ns.node_at_offset(16), None);
+    /// ```
+    pub fn node_at_offset(&self, mut offset: u64) -> Option<String> {
+        for (dims, set) in &self.bases {
+            let len = set.len();
+            if offset >= len {
+                offset -= len;
+                continue;
+            }
+
+            return match set.as_ref() {
+                IdSetKind::None => Some(dims.dimnames[0].clone()),
+                IdSetKind::Single(range) => {
+                    let id = range.iter().nth(usize::try_from(offset).ok()?)?;
+                    Some(dims.fmt_single_node(id, &mut None))
+                }
+                IdSetKind::Multiple(set) => Some(dims.fmt_node(set.nth(offset)?)),
+            };
+        }
+
+        None
+    }
+
+    /// Returns an iterator over all elements of the set
+    pub fn iter(&self) -> NodeSetIter<'_, T> {
+        NodeSetIter::new(BasesIter::Lexical(self.bases.iter()))
+    }
+
+    /// Calls `f` with the name of each node of the set
+    ///
+    /// Nodes are visited in the same order as with [`NodeSet::iter`], but
+    /// their names are formatted into a single buffer reused for every node
+    /// instead of being allocated as a new `String` each. This is faster when
+    /// names do not need to outlive the call to `f`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "node[1-3],login1".parse().unwrap();
+    /// let mut len = 0;
+    ///
+    /// ns.for_each_node(|node| len += node.len());
+    /// assert_eq!(len, 21);
+    /// ```
+    pub fn for_each_node(&self, mut f: impl FnMut(&str)) {
+        let mut buf = String::new();
+
+        for (dims, set) in &self.bases {
+            match set.as_ref() {
+                IdSetKind::None => f(&dims.dimnames[0]),
+                IdSetKind::Single(range) => {
+                    let mut cache = None;
+                    for id in range.iter() {
+                        buf.clear();
+                        dims.write_single_node(&mut buf, id, &mut cache);
+                        f(&buf);
+                    }
+                }
+                IdSetKind::Multiple(set) => {
+                    for coords in set.iter() {
+                        buf.clear();
+                        dims.write_node(&mut buf, coords.iter());
+                        f(&buf);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns an iterator over the prefix and numeric id of each node of the
+    /// set with a single dimension
+    ///
+    /// Prefixes are defined as in [`NodeSet::prefixes`] and nodes are visited
+    /// in the same order as with [`NodeSet::iter`]. Ids are yielded by their
+    /// numerical value, without their zero padding. Nodes without ids (ie
+    /// `login`) or with several dimensions (ie `compute1b2`) are skipped, see
+    /// [`NodeSet::iter_node_ids`] to iterate over all nodes. Node names are
+    /// never formatted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "node[1-2],compute[01-02]b1,login,gpu010".parse().unwrap();
+    ///
+    /// assert_eq!(
+    ///     ns.iter_ids().collect::<Vec<_>>(),
+    ///     vec![("gpu", 10), ("node", 1), ("node", 2)]
+    /// );
+    /// ```
+    #[auto_enum]
+    pub fn iter_ids(&self) -> impl Iterator<Item = (&str, u32)> + '_ {
+        self.bases.iter().flat_map(|(dims, set)| {
+            let prefix = dims.dimnames[0].as_str();
+            #[auto_enum(Iterator)]
+            let ranks = match set.as_ref() {
+                IdSetKind::Single(range) => range.iter(),
+                IdSetKind::None | IdSetKind::Multiple(_) => std::iter::empty(),
+            };
+
+            ranks.map(move |rank| (prefix, CachedTranslation::new(rank).id()))
+        })
+    }
+
+    /// Returns an iterator over the prefix and numeric ids of all the nodes
+    /// of the set
+    ///
+    /// This is the multi-dimensional counterpart of [`NodeSet::iter_ids`]:
+    /// each node is yielded as a [`NodeIds`] holding the id of each of its
+    /// dimensions, which is empty for nodes without ids.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "compute[1-2]b05,login".parse().unwrap();
+    /// let nodes: Vec<_> = ns.iter_node_ids().collect();
+    ///
+    /// assert_eq!(nodes[0].prefix, "compute");
+    /// assert_eq!(nodes[0].ids, vec![1, 5]);
+    /// assert_eq!(nodes[1].ids, vec![2, 5]);
+    /// assert!(nodes[2].ids.is_empty());
+    /// ```
+    #[auto_enum]
+    pub fn iter_node_ids(&self) -> impl Iterator<Item = NodeIds<'_>> + '_ {
+        self.bases.iter().flat_map(|(dims, set)| {
+            let prefix = dims.dimnames[0].as_str();
+            #[auto_enum(Iterator)]
+            let ids = match set.as_ref() {
+                IdSetKind::None => std::iter::once(vec![]),
+                IdSetKind::Single(range) => range
+                    .iter()
+                    .map(|rank| vec![CachedTranslation::new(rank).id()]),
+                IdSetKind::Multiple(set) => set.iter().map(|coords| {
+                    coords
+                        .iter()
+                        .map(|rank| CachedTranslation::new(rank).id())
+                        .collect()
+                }),
+            };
+
+            ids.map(move |ids| NodeIds { prefix, ids })
+        })
+    }
+
+    /// Returns an iterator over all elements of the set with prefixes
+    /// iterated in the specified order
+    pub fn iter_ordered(&self, order: PrefixOrder) -> NodeSetIter<'_, T> {
+        match order {
+            PrefixOrder::Lexical => self.iter(),
+            _ => NodeSetIter::new(BasesIter::Ordered(self.ordered_bases(order).into_iter())),
+        }
+    }
+
+    /// Returns the sets of nodes sharing the same dimension names in the
+    /// specified order
+    fn ordered_bases(&self, order: PrefixOrder) -> Vec<Base<'_, T>> {
+        if self.bases.len() <= 1 {
+            return self.bases.iter().collect();
+        }
+
+        match order {
+            PrefixOrder::Lexical => self.bases.iter().collect(),
+            PrefixOrder::Numeric => {
+                let mut bases: Vec<_> = self
+                    .bases
+                    .iter()
+                    .map(|base| (first_node(base), base))
+                    .collect();
+
+                bases.sort_by(|(a, _), (b, _)| natural_cmp(a, b));
+                bases.into_iter().map(|(_, base)| base).collect()
+            }
+            PrefixOrder::Insertion => {
+                let mut bases: Vec<_> = self
+                    .order
+                    .iter()
+                    .filter_map(|dims| self.bases.get_key_value(dims))
+                    .collect();
+
+                // Bases missing from the insertion order are still listed,
+                // after the others
+                debug_assert_eq!(bases.len(), self.bases.len());
+                if bases.len() != self.bases.len() {
+                    bases.extend(
+                        self.bases
+                            .iter()
+                            .filter(|(dims, _)| !self.order.contains(dims)),
+                    );
+                }
+                bases
+            }
+        }
+    }
+
+    /// Folds and deduplicates the internal representation of the set
+    ///
+    /// This method is automatically called after each operation unless the
+    /// NodeSet is in lazy mode. When in lazy mode, the NodeSet must be folded
+    /// again before calling len() otherwise nodes may be counted multiple
+    /// times.
+    ///
+    /// Sets which are shared with other non-lazy NodeSets are already folded
+    /// and are left untouched to avoid copying them.
+    pub(crate) fn fold(&mut self) -> &mut Self {
+        self.fold_dimensions(usize::MAX)
+    }
+
+    /// Folds the nodeset, combining ranges across at most
+    /// `max_fold_dimensions` dimensions
+    ///
+    /// Only the last `max_fold_dimensions` dimensions of each name are folded
+    /// into ranges, the other ones are left expanded with one node name per
+    /// combination of their ids. This bounds the cost of folding sets with
+    /// many dimensions and controls their output. By default, nodesets are
+    /// folded across all their dimensions.
+    ///
+    /// The limit applies until the nodeset is modified, see
+    /// [`ParseOptions::max_fold_dimensions`] to apply it when parsing.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`NodeSetParseError::FoldDimensions`] if
+    /// `max_fold_dimensions` is 0
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let mut ns: NodeSet = "r[1-2]n[1-4]".parse().unwrap();
+    /// ns.try_fold_dimensions(1).unwrap();
+    ///
+    /// assert_eq!(ns.to_string(), "r1n[1-4],r2n[1-4]");
+    /// ```
+    pub fn try_fold_dimensions(
+        &mut self,
+        max_fold_dimensions: usize,
+    ) -> Result<&mut Self, NodeSetParseError> {
+        if max_fold_dimensions == 0 {
+            return Err(NodeSetParseError::FoldDimensions(max_fold_dimensions));
+        }
+
+        Ok(self.fold_dimensions(max_fold_dimensions))
+    }
+
+    /// Splits the nodeset so that each node of a name with several
+    /// dimensions is stored as its own range
+    ///
+    /// This materializes every element for algorithms which operate node by
+    /// node on the internal representation. Names with a single dimension
+    /// always store their ids one by one and are displayed folded. The set
+    /// is displayed split until [`NodeSet::merge`] is called, nodesets built
+    /// from it, such as its union with other nodes, may keep some of its
+    /// nodes split as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let mut ns: NodeSet = "r[1-2]n[1-2],login[1-2]".parse().unwrap();
+    /// ns.split_all();
+    ///
+    /// assert_eq!(ns.to_string(), "login[1-2],r1n1,r1n2,r2n1,r2n2");
+    /// assert_eq!(ns.len(), 6);
+    /// ```
+    pub fn split_all(&mut self) -> &mut Self {
+        for s in self.bases.values_mut() {
+            if let IdSetKind::Multiple(set) = Arc::make_mut(s) {
+                set.full_split();
+            }
+        }
+
+        self
+    }
+
+    /// Coalesces adjacent nodes into ranges again, across all dimensions
+    ///
+    /// This undoes [`NodeSet::split_all`] and
+    /// [`NodeSet::try_fold_dimensions`], giving the same folded form as the
+    /// one computed after each operation on the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let mut ns: NodeSet = "r[1-2]n[1-2]".parse().unwrap();
+    /// ns.split_all();
+    /// ns.merge();
+    ///
+    /// assert_eq!(ns.to_string(), "r[1-2]n[1-2]");
+    /// ```
+    pub fn merge(&mut self) -> &mut Self {
+        for s in self.bases.values_mut() {
+            match Arc::make_mut(s) {
+                IdSetKind::None => {}
+                IdSetKind::Single(set) => set.sort(),
+                IdSetKind::Multiple(set) => {
+                    set.fold();
+                }
+            }
+        }
+
+        self.lazy = false;
+        self
+    }
+
+    /// Folds the nodeset along its last `max_axis` dimensions
+    ///
+    /// Shared sets with more dimensions than `max_axis` are copied as they
+    /// were folded across all their dimensions.
+    fn fold_dimensions(&mut self, max_axis: usize) -> &mut Self {
+        let lazy = self.lazy;
+        self.bases.values_mut().for_each(|s| {
+            let limited = matches!(&**s, IdSetKind::Multiple(set) if set.num_axis() > max_axis);
+            let s = if lazy || limited {
+                Arc::make_mut(s)
+            } else if let Some(s) = Arc::get_mut(s) {
+                s
+            } else {
+                return;
+            };
+
+            match s {
+                IdSetKind::None => {}
+                IdSetKind::Single(set) => {
+                    set.sort();
+                }
+                IdSetKind::Multiple(set) => {
+                    set.fold_dimensions(max_axis);
+                }
+            }
+        });
+
+        self.lazy = false;
+        self
+    }
+
+    /// Returns a set of `self` so that it can be stored in another NodeSet
+    ///
+    /// Sets of non-lazy NodeSets are folded and can be shared, whereas sets of
+    /// lazy NodeSets are copied so that they can be folded later on.
+    fn share(&self, set: &Arc<IdSetKind<T>>) -> Arc<IdSetKind<T>> {
+        if self.lazy {
+            Arc::new(set.as_ref().clone())
+        } else {
+            set.clone()
+        }
+    }
+
+    /// Adds elements from `other` to `self`
+    pub(crate) fn extend_from_nodeset(&mut self, other: &Self) {
+        // New dimensions are recorded in the order in which they were added
+        // to `other`
+        for (dimname, oset) in other.ordered_bases(PrefixOrder::Insertion) {
+            match self.bases.get_mut(dimname) {
+                None => {
+                    self.insert_base(dimname.clone(), other.share(oset));
+                }
+                Some(set) => Arc::make_mut(set).extend(oset),
+            };
+        }
+    }
+
+    /// Returns a copy of the set using another range backend
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::{IdRangeTree, NodeSet};
+    ///
+    /// let ns: NodeSet = "node[1-4],r[1-2]n[1-2]".parse().unwrap();
+    /// let tree: NodeSet<IdRangeTree> = ns.convert();
+    ///
+    /// assert_eq!(tree.to_string(), "node[1-4],r[1-2]n[1-2]");
+    /// ```
+    pub fn convert<U>(&self) -> NodeSet<U>
+    where
+        U: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+    {
+        NodeSet::<U> {
+            bases: self
+                .bases
+                .iter()
+                .map(|(dims, set)| (dims.clone(), Arc::new(set.convert())))
+                .collect(),
+            lazy: self.lazy,
+            order: self.order.clone(),
+        }
+    }
+
+    /// Returns a new set containing elements found in `self` and `other`
+    pub fn union(&self, other: &Self) -> Self {
+        let mut added = std::collections::HashSet::new();
+
+        // Dimensions are sorted in both sets so they can be merged in a single
+        // pass, which also allows the resulting map to be built in bulk
+        let bases = self
+            .bases
+            .iter()
+            .merge_join_by(other.bases.iter(), |(a, _), (b, _)| a.cmp(b))
+            .map(|e| match e {
+                EitherOrBoth::Left((dims, set)) => (dims.clone(), self.share(set)),
+                EitherOrBoth::Right((dims, set)) => {
+                    added.insert(dims);
+                    (dims.clone(), other.share(set))
+                }
+                EitherOrBoth::Both((dims, set), (_, oset)) => {
+                    let mut set = set.as_ref().clone();
+                    set.extend(oset);
+                    (dims.clone(), Arc::new(set))
+                }
+            })
+            .collect();
+
+        let mut res = NodeSet {
+            bases,
+            lazy: self.lazy,
+            order: self.order.clone(),
+        };
+
+        // Looking up dimensions in `bases` is slow with many dimensions so the
+        // order is extended directly rather than with `set_order`
+        if !added.is_empty() {
+            Arc::make_mut(&mut res.order)
+                .extend(other.order.iter().filter(|d| added.contains(d)).cloned());
+        }
+
+        if !res.lazy {
+            res.fold();
+        }
+
+        res
+    }
+
+    /// Returns a new set containing elements found in any of `sets`
+    ///
+    /// The ids of nodes with a single range in their name are gathered from
+    /// all the sets before being folded once, which is much faster than
+    /// chaining [`NodeSet::union`] when combining many sets. Nodes with
+    /// several ranges are folded as each set is added since folding many
+    /// products at once is more expensive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let sets: Vec<NodeSet> = ["node[1-2]", "node3", "login1"]
+    ///     .iter()
+    ///     .map(|s| s.parse().unwrap())
+    ///     .collect();
+    ///
+    /// assert_eq!(NodeSet::union_all(&sets).to_string(), "login1,node[1-3]");
+    /// ```
+    pub fn union_all(sets: &[Self]) -> Self {
+        let mut res = Self::lazy();
+        for set in sets {
+            res.extend_from_nodeset(set);
+
+            for (dims, oset) in set.bases.iter() {
+                if !matches!(oset.as_ref(), IdSetKind::Multiple(_)) {
+                    continue;
+                }
+                if let Some(IdSetKind::Multiple(s)) = res.bases.get_mut(dims).map(Arc::make_mut) {
+                    s.fold();
+                }
+            }
+        }
+
+        res.fold();
+        res
+    }
+
+    /// Returns a new set containing elements found in all of `sets`
+    ///
+    /// The result is narrowed by each set in turn and stops consuming `sets`
+    /// as soon as it is empty. The intersection of no sets is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let sets: Vec<NodeSet> = ["node[1-10]", "node[5-20]", "node[1-6]"]
+    ///     .iter()
+    ///     .map(|s| s.parse().unwrap())
+    ///     .collect();
+    ///
+    /// assert_eq!(NodeSet::intersection_all(&sets).to_string(), "node[5-6]");
+    /// assert_eq!(NodeSet::intersection_all(sets).to_string(), "node[5-6]");
+    /// assert!(NodeSet::intersection_all(Vec::<NodeSet>::new()).is_empty());
+    /// ```
+    pub fn intersection_all<I>(sets: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Self>,
+    {
+        let mut sets = sets.into_iter();
+        let Some(first) = sets.next() else {
+            return Self::default();
+        };
+
+        let mut res = first.borrow().clone();
+        while !res.is_empty() {
+            let Some(set) = sets.next() else {
+                break;
+            };
+            res = res.intersection(set.borrow());
+        }
+
+        res
+    }
+
+    /// Returns a new set containing elements found in `self` but not in `other`
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut dimnames = BTreeMap::<NodeSetDimensions, Arc<IdSetKind<T>>>::new();
+        for (dimname, set) in self.bases.iter() {
+            if let Some(oset) = other.bases.get(dimname) {
+                match (set.as_ref(), oset.as_ref()) {
+                    (IdSetKind::None, IdSetKind::None) => continue,
+                    (IdSetKind::Single(set), IdSetKind::Single(oset)) => {
+                        let result = T::from_sorted(set.difference(oset));
+                        if !result.is_empty() {
+                            dimnames.insert(dimname.clone(), Arc::new(IdSetKind::Single(result)));
+                        }
+                    }
+                    (IdSetKind::Multiple(set), IdSetKind::Multiple(oset)) => {
+                        if let Some(nset) = set.difference(oset) {
+                            dimnames.insert(dimname.clone(), Arc::new(IdSetKind::Multiple(nset)));
+                        }
+                    }
+                    _ => {
+                        panic!("Mismatched set kinds");
+                    }
+                }
+            } else {
+                dimnames.insert(dimname.clone(), self.share(set));
+            }
+        }
+
+        self.with_bases(other, dimnames)
+    }
+
+    /// Returns a new set containing elements that are in both `self` and `other`
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut dimnames = BTreeMap::<NodeSetDimensions, Arc<IdSetKind<T>>>::new();
+        for (dimname, set) in self.bases.iter() {
+            if let Some(oset) = other.bases.get(dimname) {
+                match (set.as_ref(), oset.as_ref()) {
+                    (_, IdSetKind::None) => {
+                        dimnames.insert(dimname.clone(), self.share(set));
+                    }
+                    (IdSetKind::Single(set), IdSetKind::Single(oset)) => {
+                        let result = T::from_sorted(set.intersection(oset));
+                        if !result.is_empty() {
+                            dimnames.insert(dimname.clone(), Arc::new(IdSetKind::Single(result)));
+                        }
+                    }
+                    (IdSetKind::Multiple(set), IdSetKind::Multiple(oset)) => {
+                        if let Some(nset) = set.intersection(oset) {
+                            dimnames.insert(dimname.clone(), Arc::new(IdSetKind::Multiple(nset)));
+                        }
+                    }
+                    _ => {
+                        panic!("Mismatched set kinds");
+                    }
+                }
+            }
+        }
+
+        self.with_bases(other, dimnames)
+    }
+
+    /// Returns a new set containing the elements found in either `self` or `other` but not in both
+    pub fn symmetric_difference(&self, other: &Self) -> Self {
+        let mut dimnames = BTreeMap::<NodeSetDimensions, Arc<IdSetKind<T>>>::new();
+        for (dimname, set) in self.bases.iter() {
+            if let Some(oset) = other.bases.get(dimname) {
+                match (set.as_ref(), oset.as_ref()) {
+                    (IdSetKind::None, IdSetKind::None) => continue,
+                    (IdSetKind::Single(set), IdSetKind::Single(oset)) => {
+                        let result = T::from_sorted(set.symmetric_difference(oset));
+                        if !result.is_empty() {
+                            dimnames.insert(dimname.clone(), Arc::new(IdSetKind::Single(result)));
+                        }
+                    }
+                    (IdSetKind::Multiple(set), IdSetKind::Multiple(oset)) => {
+                        if let Some(nset) = set.symmetric_difference(oset) {
+                            dimnames.insert(dimname.clone(), Arc::new(IdSetKind::Multiple(nset)));
+                        }
+                    }
+                    _ => {
+                        panic!("Mismatched set kinds");
+                    }
+                }
+            } else {
+                dimnames.insert(dimname.clone(), self.share(set));
+            }
+        }
+        for (dimname, set) in other.bases.iter() {
+            if !self.bases.contains_key(dimname) {
+                dimnames.insert(dimname.clone(), other.share(set));
+            }
+        }
+        self.with_bases(other, dimnames)
+    }
+
+    /// Returns the elements found in `self` but not in `other`, and whether
+    /// `other` removed any element from `self`
+    ///
+    /// The result is the same as with [`NodeSet::difference`]. Whether it
+    /// differs from `self` is found by counting nodes from the folded ranges,
+    /// which is much cheaper than comparing the sets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "node[1-10]".parse().unwrap();
+    /// let (res, changed) = ns.difference_reporting(&"node[5-20]".parse().unwrap());
+    /// assert_eq!(res.to_string(), "node[1-4]");
+    /// assert!(changed);
+    ///
+    /// let (res, changed) = res.difference_reporting(&"node[5-20]".parse().unwrap());
+    /// assert_eq!(res.to_string(), "node[1-4]");
+    /// assert!(!changed);
+    /// ```
+    pub fn difference_reporting(&self, other: &Self) -> (Self, bool) {
+        let res = self.difference(other);
+        let changed = self.len_differs(&res);
+
+        (res, changed)
+    }
+
+    /// Returns the elements found in `self` and `other`, and whether `other`
+    /// added any element to `self`
+    ///
+    /// The result is the same as with [`NodeSet::union`]. See
+    /// [`NodeSet::difference_reporting`] for how changes are detected.
+    pub fn union_reporting(&self, other: &Self) -> (Self, bool) {
+        let res = self.union(other);
+        let changed = self.len_differs(&res);
+
+        (res, changed)
+    }
+
+    /// Returns true if `self` and `other`, one of which is a subset of the
+    /// other, do not hold the same number of nodes
+    fn len_differs(&self, other: &Self) -> bool {
+        match (self.len_u64(), other.len_u64()) {
+            // Counts saturate with huge multidimensional sets
+            (u64::MAX, _) | (_, u64::MAX) => self != other,
+            (len, olen) => len != olen,
+        }
+    }
+
+    /// Returns true if `self` and `other` contain the same nodes when the
+    /// padding of ids is ignored
+    ///
+    /// Unlike `==`, which considers `node08` and `node8` to be different nodes,
+    /// this compares nodes by their numerical ids so that `node[08-09]` and
+    /// `node[8-9]` are considered equal.
+    pub fn eq_ignore_padding(&self, other: &Self) -> bool {
+        let unpadded = |set: &IdSetKind<T>| {
+            let mut ids: Vec<Vec<u32>> = match set {
+                IdSetKind::None => vec![],
+                IdSetKind::Single(set) => set
+                    .iter()
+                    .map(|rank| vec![CachedTranslation::new(rank).id()])
+                    .collect(),
+                IdSetKind::Multiple(set) => set
+                    .iter()
+                    .map(|coords| {
+                        coords
+                            .iter()
+                            .map(|rank| CachedTranslation::new(rank).id())
+                            .collect()
+                    })
+                    .collect(),
+            };
+            ids.sort_unstable();
+            ids.dedup();
+            ids
+        };
+
+        self.bases.len() == other.bases.len()
+            && self
+                .bases
+                .iter()
+                .zip(other.bases.iter())
+                .all(|((dims, set), (odims, oset))| {
+                    dims == odims && unpadded(set) == unpadded(oset)
+                })
+    }
+
+    /// Stores the elements found in `self` and `other` into `out`
+    ///
+    /// This is equivalent to `*out = self.union(other)` but reuses the storage
+    /// of `out`: when called repeatedly with similar sets, one-dimensional
+    /// sets are computed without allocating.  `out` can alias neither `self`
+    /// nor `other`, which is guaranteed by the borrow checker.
+    pub fn union_into(&self, other: &Self, out: &mut Self) {
+        self.op_into(other, out, SetOp::Union);
+    }
+
+    /// Stores the elements found in `self` but not in `other` into `out`
+    ///
+    /// See [`NodeSet::union_into`] for details about storage reuse.
+    pub fn difference_into(&self, other: &Self, out: &mut Self) {
+        self.op_into(other, out, SetOp::Difference);
+    }
+
+    /// Stores the elements that are in both `self` and `other` into `out`
+    ///
+    /// See [`NodeSet::union_into`] for details about storage reuse.
+    pub fn intersection_into(&self, other: &Self, out: &mut Self) {
+        self.op_into(other, out, SetOp::Intersection);
+    }
+
+    /// Stores the elements found in either `self` or `other` but not in both
+    /// into `out`
+    ///
+    /// See [`NodeSet::union_into`] for details about storage reuse.
+    pub fn symmetric_difference_into(&self, other: &Self, out: &mut Self) {
+        self.op_into(other, out, SetOp::SymmetricDifference);
+    }
+
+    /// Stores the result of applying `op` to `self` and `other` into `out`,
+    /// reusing the sets of `out` which are not shared with other NodeSets
+    fn op_into(&self, other: &Self, out: &mut Self, op: SetOp) {
+        out.bases.retain(|dims, _| {
+            match (
+                self.bases.contains_key(dims),
+                other.bases.contains_key(dims),
+            ) {
+                (true, true) => true,
+                (true, false) => op.keeps_self_only(),
+                (false, true) => op.keeps_other_only(),
+                (false, false) => false,
+            }
+        });
+
+        for (dims, set) in self.bases.iter() {
+            let Some(oset) = other.bases.get(dims) else {
+                if op.keeps_self_only() {
+                    out.insert_set(dims, self.share(set));
+                }
+                continue;
+            };
+
+            match (set.as_ref(), oset.as_ref()) {
+                (IdSetKind::None, IdSetKind::None) => {
+                    if op.keeps_common_none() {
+                        out.insert_set(dims, self.share(set));
+                    } else {
+                        out.bases.remove(dims);
+                    }
+                }
+                (IdSetKind::Single(set), IdSetKind::Single(oset)) => {
+                    let reused = out.bases.get_mut(dims).and_then(Arc::get_mut);
+
+                    let mut new_range = None;
+                    let range = match reused {
+                        Some(IdSetKind::Single(range)) => range,
+                        _ => new_range.insert(T::new()),
+                    };
+
+                    match op {
+                        SetOp::Union => set.union_into(oset, range),
+                        SetOp::Difference => set.difference_into(oset, range),
+                        SetOp::Intersection => set.intersection_into(oset, range),
+                        SetOp::SymmetricDifference => set.symmetric_difference_into(oset, range),
+                    }
+
+                    if range.is_empty() {
+                        out.bases.remove(dims);
+                    } else if let Some(range) = new_range {
+                        out.insert_set(dims, Arc::new(IdSetKind::Single(range)));
+                    }
+                }
+                (IdSetKind::Multiple(set), IdSetKind::Multiple(oset)) => {
+                    let result = match op {
+                        SetOp::Union => {
+                            let mut result = set.clone();
+                            result.extend(oset);
+                            Some(result)
+                        }
+                        SetOp::Difference => set.difference(oset),
+                        SetOp::Intersection => set.intersection(oset),
+                        SetOp::SymmetricDifference => set.symmetric_difference(oset),
+                    };
+
+                    match result {
+                        Some(result) => out.insert_set(dims, Arc::new(IdSetKind::Multiple(result))),
+                        None => {
+                            out.bases.remove(dims);
+                        }
+                    }
+                }
+                _ => {
+                    panic!("Mismatched set kinds");
+                }
+            }
+        }
+
+        if op.keeps_other_only() {
+            for (dims, set) in other.bases.iter() {
+                if !self.bases.contains_key(dims) {
+                    out.insert_set(dims, other.share(set));
+                }
+            }
+        }
+
+        out.lazy = self.lazy;
+        out.set_order(self, other);
+        if !out.lazy {
+            out.fold();
+        }
+    }
+
+    /// Inserts or replaces a set without allocating a new key if one already
+    /// exists
+    fn insert_set(&mut self, dims: &NodeSetDimensions, set: Arc<IdSetKind<T>>) {
+        match self.bases.get_mut(dims) {
+            Some(s) => *s = set,
+            None => self.insert_base(dims.clone(), set),
+        }
+    }
+
+    /// Adds a set of nodes with new dimension names
+    ///
+    /// The set is left untouched if the dimension names already exist.
+    pub(crate) fn insert_base(&mut self, dims: NodeSetDimensions, set: Arc<IdSetKind<T>>) {
+        if let std::collections::btree_map::Entry::Vacant(e) = self.bases.entry(dims) {
+            Arc::make_mut(&mut self.order).push(e.key().clone());
+            e.insert(set);
+        }
+    }
+
+    /// Create a NodeSet from a mapping of NodeSetDimensions to IdSets
+    /// resulting from an operation between `self` and `other`
+    ///
+    /// The insertion order of the dimensions is taken from `self` and then
+    /// `other`.
+    fn with_bases(
+        &self,
+        other: &Self,
+        dimnames: BTreeMap<NodeSetDimensions, Arc<IdSetKind<T>>>,
+    ) -> Self {
+        let mut res = NodeSet {
+            bases: dimnames,
+            lazy: self.lazy,
+            order: self.order.clone(),
+        };
+        res.set_order(self, other);
+
+        if !res.lazy {
+            res.fold();
+        }
+
+        res
+    }
+
+    /// Sets the insertion order of dimensions to the one of `first` followed
+    /// by the one of `second`, ignoring dimensions which are not in `self`
+    fn set_order(&mut self, first: &Self, second: &Self) {
+        // Looking up dimensions is slow with many dimensions so avoid it when
+        // the dimensions are the same as in `first`
+        if self.bases.len() == first.bases.len() && self.bases.keys().eq(first.bases.keys()) {
+            if !Arc::ptr_eq(&self.order, &first.order) {
+                self.order = first.order.clone();
+            }
+            return;
+        }
+
+        let order = || {
+            first
+                .order
+                .iter()
+                .filter(|dims| self.bases.contains_key(*dims))
+                .chain(second.order.iter().filter(|dims| {
+                    self.bases.contains_key(*dims) && !first.bases.contains_key(*dims)
+                }))
+        };
+
+        // Avoid allocating when the order is unchanged
+        if !self.order.iter().eq(order()) {
+            self.order = Arc::new(order().cloned().collect());
+        }
+    }
+
+    /// Create a new lazy NodeSet which does not automatically folds after each
+    /// operation
+    pub(crate) fn lazy() -> Self {
+        NodeSet {
+            bases: BTreeMap::new(),
+            lazy: true,
+            order: Arc::default(),
+        }
+    }
+}
+
+impl<T> std::str::FromStr for NodeSet<T>
+where
+    T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+{
+    type Err = NodeSetParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(s, &ParseOptions::default())
+    }
+}
+
+impl<T> NodeSet<T>
+where
+    T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+{
+    /// Parses a nodeset using alternative syntax options
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::{NodeSet, ParseOptions};
+    ///
+    /// let options = ParseOptions::new().delimiters('{', '}');
+    /// let ns: NodeSet = NodeSet::parse_with("node{1-5,7}", &options).unwrap();
+    ///
+    /// assert_eq!(ns.to_string(), "node[1-5,7]");
+    /// assert_eq!(ns.display_with(&options).to_string(), "node{1-5,7}");
+    /// ```
+    pub fn parse_with(
+        s: impl AsRef<str>,
+        options: &ParseOptions,
+    ) -> Result<Self, NodeSetParseError> {
+        let s = s.as_ref();
+        // The global resolver is not even loaded when groups are disabled
+        #[cfg(feature = "groups")]
+        if !options.no_groups {
+            let resolver = Resolver::get_global();
+            return Parser::with_resolver(&resolver, None)
+                .with_options(*options)
+                .parse::<T>(s);
+        }
+
+        Parser::default().with_options(*options).parse::<T>(s)
+    }
+
+    /// Parses a nodeset without resolving groups
+    ///
+    /// Group terms (ie `@rack1`, `@@`, `@*` or `@source:group`) fail with
+    /// [`NodeSetParseError::GroupsDisabled`] instead of querying group
+    /// sources, which makes parsing free of side effects such as running
+    /// upcalls or fetching documents: untrusted input can be parsed safely.
+    /// See [`ParseOptions::no_groups`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = NodeSet::parse_literal("node[1-5]!node3").unwrap();
+    /// assert_eq!(ns.to_string(), "node[1-2,4-5]");
+    /// ```
+    pub fn parse_literal(s: impl AsRef<str>) -> Result<Self, NodeSetParseError> {
+        Self::parse_with(
+            s,
+            &ParseOptions {
+                no_groups: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Parses a nodeset, resolving groups with `resolver` instead of the
+    /// global resolver
+    ///
+    /// This is equivalent to [`Resolver::parse`].
+    pub fn parse_with_resolver(
+        resolver: &Resolver,
+        s: impl AsRef<str>,
+    ) -> Result<Self, NodeSetParseError> {
+        resolver.parse(s)
+    }
+
+    /// Creates the set of IPv4 addresses of a CIDR block
+    ///
+    /// Addresses are represented as nodes with one dimension per octet, so
+    /// that they can be combined with sets parsed from strings such as
+    /// `10.0.0.[0-255]`. Host bits must not be set in the network address.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = NodeSet::from_cidr("10.0.0.0/23").unwrap();
+    ///
+    /// assert_eq!(ns.to_string(), "10.0.[0-1].[0-255]");
+    /// assert_eq!(ns.len(), 512);
+    /// ```
+    #[cfg(feature = "ip")]
+    pub fn from_cidr(cidr: impl AsRef<str>) -> Result<Self, NodeSetParseError> {
+        let cidr = cidr.as_ref();
+        let invalid = || NodeSetParseError::Generic(cidr.to_string());
+
+        let (addr, len) = cidr.split_once('/').ok_or_else(invalid)?;
+        let addr: std::net::Ipv4Addr = addr.parse().map_err(|_| invalid())?;
+        let len: u32 = len.parse().map_err(|_| invalid())?;
+        if len > 32 || u32::from(addr).checked_shl(len).unwrap_or(0) != 0 {
+            return Err(invalid());
+        }
+
+        let octets = addr.octets().into_iter().enumerate().map(|(i, octet)| {
+            // Number of host bits within this octet
+            let host_bits = (32 - len).saturating_sub(8 * (3 - i as u32)).min(8);
+            match host_bits {
+                0 => octet.to_string(),
+                bits => format!("[{}-{}]", octet, octet as u32 + (1 << bits) - 1),
+            }
+        });
+
+        Parser::default().parse(&octets.collect::<Vec<_>>().join("."))
+    }
+
+    /// Parses a nodeset, failing if a node appears more than once
+    ///
+    /// This is a shorthand for [`NodeSet::parse_with`] with
+    /// `reject_duplicates` set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::{NodeSet, NodeSetParseError};
+    ///
+    /// assert!(NodeSet::<nodeset::IdRangeList>::parse_no_dups("node[0-3] login1").is_ok());
+    /// assert!(matches!(
+    ///     NodeSet::<nodeset::IdRangeList>::parse_no_dups("node[0-3] node2"),
+    ///     Err(NodeSetParseError::DuplicateNode { node, .. }) if node == "node2"
+    /// ));
+    /// ```
+    pub fn parse_no_dups(s: impl AsRef<str>) -> Result<Self, NodeSetParseError> {
+        Self::parse_with(
+            s,
+            &ParseOptions {
+                reject_duplicates: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Parses nodesets from a reader
+    ///
+    /// The input is read and folded one line at a time so that memory usage
+    /// depends on the size of the folded nodeset rather than on the size of
+    /// the input. Each line is parsed as a separate nodeset and the result is
+    /// the union of all lines.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let input = "node1\nnode2\nnode[3-5] - node4\n";
+    /// let ns: NodeSet = NodeSet::from_reader(input.as_bytes()).unwrap();
+    ///
+    /// assert_eq!(ns.to_string(), "node[1-3,5]");
+    /// ```
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, NodeSetParseError> {
+        Self::from_reader_with(reader, &ParseOptions::default())
+    }
+
+    /// Parses nodesets from a reader using alternative syntax options
+    ///
+    /// Blank lines are always ignored. When `options.lenient` is set, text
+    /// following a `#` is ignored as well.
+    pub fn from_reader_with(
+        mut reader: impl BufRead,
+        options: &ParseOptions,
+    ) -> Result<Self, NodeSetParseError> {
+        // Number of lines accumulated before folding. Folding after each line
+        // would be expensive with large nodesets.
+        const FOLD_INTERVAL: usize = 4096;
+
+        let mut res = Self::lazy();
+        let mut buf = String::new();
+        let mut lineno = 0;
+
+        // When rejecting duplicates, lines are only checked against each
+        // other when folding: the set then holds fewer nodes than the lines
+        // read if some of them overlap
+        let mut expected = 0;
+        let mut checked = Self::default();
+        let mut pending = vec![];
+
+        loop {
+            buf.clear();
+            if reader
+                .read_line(&mut buf)
+                .map_err(NodeSetParseError::Read)?
+                == 0
+            {
+                break;
+            }
+            lineno += 1;
+
+            let mut line = buf.trim_end_matches(['\n', '\r']);
+            if options.lenient {
+                line = line.split_once('#').map_or(line, |(l, _)| l);
+                if line.trim().is_empty() {
+                    continue;
+                }
+            }
+
+            let ns = Self::parse_with(line, options)
+                .map_err(|e| NodeSetParseError::Line(lineno, Box::new(e)))?;
+
+            res.extend_from_nodeset(&ns);
+            if options.reject_duplicates {
+                expected += ns.len_u64();
+                pending.push((lineno, ns));
+            }
+
+            if lineno % FOLD_INTERVAL == 0 {
+                res.fold_dimensions(options.max_fold_dimensions);
+                res.lazy = true;
+                if options.reject_duplicates {
+                    res.check_overlaps(expected, &mut checked, &mut pending)?;
+                }
+            }
+        }
+
+        res.fold_dimensions(options.max_fold_dimensions);
+        if options.reject_duplicates {
+            res.check_overlaps(expected, &mut checked, &mut pending)?;
+        }
+
+        Ok(res)
+    }
+
+    /// Fails with the first of the `lines` read since the folded set was
+    /// `checked` which overlaps the nodes read before it, if the set holds
+    /// fewer than `expected` nodes
+    fn check_overlaps(
+        &self,
+        expected: u64,
+        checked: &mut Self,
+        lines: &mut Vec<(usize, Self)>,
+    ) -> Result<(), NodeSetParseError> {
+        if self.len_u64() != expected {
+            let mut seen = std::mem::take(checked);
+            for (lineno, ns) in lines.iter() {
+                let overlap = seen.intersection(ns);
+                if let Some(node) = overlap.iter().next() {
+                    return Err(NodeSetParseError::Line(
+                        *lineno,
+                        Box::new(NodeSetParseError::DuplicateNode {
+                            node,
+                            overlap: overlap.to_string(),
+                        }),
+                    ));
+                }
+                seen.extend_from_nodeset(ns);
+                seen.fold();
+            }
+        }
+
+        *checked = self.clone();
+        lines.clear();
+        Ok(())
+    }
+
+    /// Builds a nodeset from individual nodes which are already sorted and
+    /// unique, without sorting them
+    ///
+    /// Nodes sharing the same names must be given in the order in which
+    /// [`NodeSet::iter`] lists them (ie `r1n9` before `r1n10` and `r2n1`),
+    /// nodes with different names may be interleaved. The ids of consecutive
+    /// nodes are then appended to the ranges being built instead of being
+    /// sorted and merged, which makes folding large generated lists of nodes,
+    /// especially with several dimensions, much faster.
+    ///
+    /// # Errors
+    ///
+    /// Fails if an item is not a single node. In debug builds, also fails
+    /// with [`NodeSetParseError::Unsorted`] on the first node which is not
+    /// sorted after the previous one with the same names, or which is a
+    /// duplicate.
+    ///
+    /// The order is not checked in release builds: the resulting nodeset is
+    /// then unspecified if the input is not sorted or holds duplicates, and
+    /// may be displayed wrongly, count nodes several times or yield wrong
+    /// results in later operations. Use [`NodeSet::from_reader`] when the
+    /// input cannot be trusted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let nodes = (1..=4).flat_map(|r| (1..=8).map(move |n| format!("r{r}n{n}")));
+    /// let ns: NodeSet = NodeSet::from_sorted_unchecked(nodes).unwrap();
+    ///
+    /// assert_eq!(ns.to_string(), "r[1-4]n[1-8]");
+    /// ```
+    pub fn from_sorted_unchecked<S: AsRef<str>>(
+        nodes: impl IntoIterator<Item = S>,
+    ) -> Result<Self, NodeSetParseError> {
+        let mut runs: BTreeMap<NodeSetDimensions, SortedRun<T>> = BTreeMap::new();
+        let mut order = vec![];
+
+        for node in nodes {
+            let node = node.as_ref();
+            let Node { dims, ranks } = node.parse()?;
+            let run = match runs.get_mut(&dims) {
+                Some(run) => run,
+                None => {
+                    order.push(dims.clone());
+                    runs.entry(dims).or_default()
+                }
+            };
+
+            if !run.push(&ranks) && cfg!(debug_assertions) {
+                return Err(NodeSetParseError::Unsorted(node.to_string()));
+            }
+        }
+
+        let mut res = Self::default();
+        for dims in order {
+            let run = runs.remove(&dims).expect("run for each dimension");
+            res.insert_base(dims, Arc::new(run.finish()));
+        }
+
+        Ok(res)
+    }
+
+    /// Returns an object displaying the nodeset with a custom formatter
+    ///
+    /// `ParseOptions` can be used as a formatter to display the nodeset with
+    /// alternative delimiters, and [`Separated`](crate::Separated) with
+    /// another separator. See [`NodeFormatter`] to customize how names and
+    /// ids are written.
+    pub fn display_with<'a, F>(&'a self, formatter: &'a F) -> impl fmt::Display + 'a
+    where
+        F: NodeFormatter + ?Sized,
+    {
+        self.display_ordered(formatter, PrefixOrder::Lexical)
+    }
+
+    /// Returns an object displaying the nodeset with a custom formatter and
+    /// with prefixes in the specified order
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::{NodeSet, ParseOptions, PrefixOrder};
+    ///
+    /// let ns: NodeSet = "x1,a5,a1b2".parse().unwrap();
+    /// let options = ParseOptions::default();
+    ///
+    /// assert_eq!(ns.to_string(), "a5,a1b2,x1");
+    /// assert_eq!(
+    ///     ns.display_ordered(&options, PrefixOrder::Numeric).to_string(),
+    ///     "a1b2,a5,x1"
+    /// );
+    /// assert_eq!(
+    ///     ns.display_ordered(&options, PrefixOrder::Insertion).to_string(),
+    ///     "x1,a5,a1b2"
+    /// );
+    /// ```
+    pub fn display_ordered<'a, F>(
+        &'a self,
+        formatter: &'a F,
+        order: PrefixOrder,
+    ) -> impl fmt::Display + 'a
+    where
+        F: NodeFormatter + ?Sized,
+    {
+        DisplayWith {
+            ns: self,
+            formatter,
+            order,
+        }
+    }
+
+    /// Returns the canonical string representation of the set
+    ///
+    /// The canonical string lists prefixes in lexical order with their ranges
+    /// folded across all dimensions and separated by commas, regardless of
+    /// how the set was built or folded. Equal sets always have the same
+    /// canonical string, which makes it suitable as a deduplication key and
+    /// it is what `Hash` and `Ord` are based on.
+    ///
+    /// The `Display` implementation currently gives the same result for sets
+    /// folded across all their dimensions, but other ways of displaying a set
+    /// ([`NodeSet::display_ordered`], [`NodeSet::try_fold_dimensions`]...)
+    /// may differ from it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let mut ns: NodeSet = "r[1-2]n[1-4],login".parse().unwrap();
+    /// ns.try_fold_dimensions(1).unwrap();
+    ///
+    /// assert_eq!(ns.to_string(), "login,r1n[1-4],r2n[1-4]");
+    /// assert_eq!(ns.canonical_string(), "login,r[1-2]n[1-4]");
+    /// ```
+    pub fn canonical_string(&self) -> String {
+        let folded = !self.lazy
+            && self
+                .bases
+                .values()
+                .all(|s| !matches!(s.as_ref(), IdSetKind::Multiple(_)));
+        if folded {
+            return self.to_string();
+        }
+
+        let mut ns = self.clone();
+        ns.lazy = true;
+        ns.fold();
+        ns.to_string()
+    }
+
+    /// Returns the string representation of the set, or an error if it is
+    /// longer than `max_bytes`
+    ///
+    /// The string is the same as the one of the `Display` implementation, but
+    /// formatting stops as soon as it exceeds `max_bytes`, so that large sets
+    /// from untrusted input can be echoed back without allocating unbounded
+    /// memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::NodeSet;
+    ///
+    /// let ns: NodeSet = "node[1-9],node[20-29]".parse().unwrap();
+    ///
+    /// assert_eq!(ns.try_fold_string(20).unwrap(), "node[1-9,20-29]");
+    /// assert!(ns.try_fold_string(10).is_err());
+    /// ```
+    pub fn try_fold_string(&self, max_bytes: usize) -> Result<String, FormatTooLarge> {
+        /// Writer failing instead of growing past `max_bytes`
+        struct Bounded {
+            buf: String,
+            max_bytes: usize,
+        }
+
+        impl fmt::Write for Bounded {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                if self.buf.len() + s.len() > self.max_bytes {
+                    return Err(fmt::Error);
+                }
+                self.buf.push_str(s);
+                Ok(())
+            }
+        }
+
+        let mut out = Bounded {
+            buf: String::new(),
+            max_bytes,
+        };
+        write!(out, "{self}").map_err(|_| FormatTooLarge { max_bytes })?;
+
+        Ok(out.buf)
+    }
+
+    /// Formats sets of nodes separated by `separator`
+    fn fmt_bases<'a>(
+        f: &mut fmt::Formatter,
+        bases: impl IntoIterator<Item = Base<'a, T>>,
+        separator: char,
+    ) -> fmt::Result
+    where
+        T: 'a,
+    {
+        let mut first = true;
+
+        for (dim, set) in bases {
+            if !first {
+                fmt::Write::write_char(f, separator)?;
+            }
+            match set.as_ref() {
+                IdSetKind::None => {
+                    dim.fmt_ranges(f, std::iter::empty::<u32>())?;
+                }
+                IdSetKind::Single(set) => {
+                    dim.fmt_ranges(f, [set])?;
+                }
+                IdSetKind::Multiple(set) => {
+                    set.fmt_dims(f, dim)?;
+                }
+            }
+
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+/// Shifts the ids of `range` by `delta`, keeping the width of zero-padded
+/// ids
+fn shift_range<T: IdRange>(range: &T, delta: i64) -> Result<T, NodeSetParseError> {
+    let mut res = T::new().lazy();
+
+    for rank in range.iter() {
+        let old = CachedTranslation::new(rank);
+        let invalid = || NodeSetParseError::IdShift {
+            id: old.to_string(),
+            delta,
+        };
+
+        let id = u32::try_from(i64::from(old.id()) + delta).map_err(|_| invalid())?;
+        let digits = id.checked_ilog10().map_or(1, |n| n + 1);
+        let padded = old.id() < 10u32.pow(old.padding() - 1);
+        let pad = if padded {
+            digits.max(old.padding())
+        } else {
+            digits
+        };
+
+        res.push_idrs(SingleId::new(id, pad).map_err(|_| invalid())?);
+    }
+
+    res.sort();
+    Ok(res)
+}
+
+/// Displays a NodeSet with a custom formatter
+struct DisplayWith<'a, T, F: ?Sized> {
+    ns: &'a NodeSet<T>,
+    formatter: &'a F,
+    order: PrefixOrder,
+}
+
+impl<T, F> fmt::Display for DisplayWith<'_, T, F>
+where
+    T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+    F: NodeFormatter + ?Sized,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let formatter = self.formatter;
+
+        for (i, (dims, set)) in self.ns.ordered_bases(self.order).into_iter().enumerate() {
+            if i > 0 {
+                f.write_char(formatter.separator())?;
+            }
+            match set.as_ref() {
+                IdSetKind::None => dims.write_with(f, formatter, std::iter::empty::<&T>())?,
+                IdSetKind::Single(set) => dims.write_with(f, formatter, [set])?,
+                IdSetKind::Multiple(set) => {
+                    for (j, product) in set.products.iter().enumerate() {
+                        if j > 0 {
+                            f.write_char(formatter.product_separator())?;
+                        }
+                        dims.write_with(f, formatter, &product.ranges)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Set operations which can be computed by `NodeSet::op_into`
+#[derive(Debug, Clone, Copy)]
+enum SetOp {
+    Union,
+    Difference,
+    Intersection,
+    SymmetricDifference,
+}
+
+impl SetOp {
+    /// Whether sets found only in the left operand are part of the result
+    fn keeps_self_only(self) -> bool {
+        matches!(
+            self,
+            SetOp::Union | SetOp::Difference | SetOp::SymmetricDifference
+        )
+    }
+
+    /// Whether sets found only in the right operand are part of the result
+    fn keeps_other_only(self) -> bool {
+        matches!(self, SetOp::Union | SetOp::SymmetricDifference)
+    }
+
+    /// Whether nodes without ids found in both operands are part of the result
+    fn keeps_common_none(self) -> bool {
+        matches!(self, SetOp::Union | SetOp::Intersection)
+    }
+}
+
+/// Iterator over ids which the caller guarantees to be sorted and unique
+struct AssumeSorted<I>(I);
+
+impl<I: Iterator<Item = u32>> Iterator for AssumeSorted<I> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        self.0.next()
+    }
+}
+
+impl<I: Iterator<Item = u32>> crate::SortedIterator for AssumeSorted<I> {}
+
+/// Set of nodes sharing the same names built from sorted nodes by
+/// [`NodeSet::from_sorted_unchecked`]
+///
+/// Consecutive nodes which only differ by their last id form a run whose ids
+/// are appended to `ids`. Each completed run is merged into the previous
+/// product when they only differ along one axis, so that folding the
+/// products at the end is cheap.
+#[derive(Debug)]
+struct SortedRun<T> {
+    products: Vec<IdRangeProduct<T>>,
+    leading: Vec<u32>,
+    ids: Vec<u32>,
+    /// Whether a node without ids was pushed
+    none: bool,
+}
+
+impl<T> Default for SortedRun<T> {
+    fn default() -> Self {
+        Self {
+            products: vec![],
+            leading: vec![],
+            ids: vec![],
+            none: false,
+        }
+    }
+}
+
+impl<T> SortedRun<T>
+where
+    T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+{
+    /// Adds the ids of a node, returning false if it is not sorted after the
+    /// previous node or is a duplicate
+    fn push(&mut self, ranks: &[u32]) -> bool {
+        let Some((&last, leading)) = ranks.split_last() else {
+            return !std::mem::replace(&mut self.none, true);
+        };
+
+        let sorted = match self.ids.last() {
+            Some(&prev) if leading == self.leading => last > prev,
+            prev => {
+                let sorted = prev.is_none() || leading > self.leading.as_slice();
+                self.flush();
+                self.leading = leading.to_vec();
+                sorted
+            }
+        };
+
+        self.ids.push(last);
+        sorted
+    }
+
+    /// Moves the current run into the products
+    fn flush(&mut self) {
+        if self.ids.is_empty() {
+            return;
+        }
+
+        let ids = std::mem::take(&mut self.ids);
+        let mut ranges: Vec<T> = self.leading.iter().map(|&id| T::from(id)).collect();
+        ranges.push(T::from_sorted(AssumeSorted(ids.into_iter())));
+
+        if let Some(prev) = self.products.last_mut() {
+            let mut differ = prev.ranges.iter().zip(&ranges).positions(|(a, b)| a != b);
+            if let (Some(axis), None) = (differ.next(), differ.next()) {
+                prev.ranges[axis].push(&ranges[axis]);
+                return;
+            }
+        }
+
+        self.products.push(IdRangeProduct { ranges });
+    }
+
+    fn finish(mut self) -> IdSetKind<T> {
+        self.flush();
+
+        if self.none {
+            return IdSetKind::None;
+        }
+
+        let mut products = self.products;
+        if products.len() == 1 && products[0].ranges.len() == 1 {
+            return IdSetKind::Single(products.remove(0).ranges.remove(0));
+        }
+
+        let mut set = IdSet { products };
+        set.fold();
+        IdSetKind::Multiple(set)
+    }
+}
+
+/// A single node
+///
+/// Parsing a node once and looking it up with `NodeSet::contains_node()` is
+/// faster than calling `NodeSet::contains()` with a string when the same node
+/// is looked up many times.
+///
+/// # Examples
+///
+/// ```
+/// use nodeset::{Node, NodeSet};
+///
+/// let node: Node = "node02".parse().unwrap();
+/// let ns: NodeSet = "node[01-10]".parse().unwrap();
+///
+/// assert!(ns.contains_node(&node));
+/// assert_eq!(node.to_string(), "node02");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Node {
+    dims: NodeSetDimensions,
+    ranks: Vec<u32>,
+}
+
+impl std::str::FromStr for Node {
+    type Err = NodeSetParseError;
+
+    /// Parses a single node
+    ///
+    /// Groups and operators are not allowed, the string must describe exactly
+    /// one node.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ns = Parser::default().parse::<crate::IdRangeList>(s)?;
+
+        if ns.len() != 1 {
+            return Err(NodeSetParseError::Generic(s.to_string()));
+        }
+
+        let (dims, set) = ns.bases.into_iter().next().expect("set has one node");
+        let ranks = match set.as_ref() {
+            IdSetKind::None => vec![],
+            IdSetKind::Single(set) => set.iter().collect(),
+            IdSetKind::Multiple(set) => set
+                .iter()
+                .next()
+                .expect("set has one node")
+                .iter()
+                .collect(),
+        };
+
+        Ok(Node { dims, ranks })
+    }
+}
+
+impl Node {
+    /// Returns the prefix of the node, as defined by [`NodeSet::prefixes`]
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::Node;
+    ///
+    /// let node: Node = "compute1b2".parse().unwrap();
+    /// assert_eq!(node.prefix(), "compute");
+    ///
+    /// let node: Node = "login".parse().unwrap();
+    /// assert_eq!(node.prefix(), "login");
+    /// ```
+    pub fn prefix(&self) -> &str {
+        &self.dims.dimnames[0]
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ranks.is_empty() {
+            return f.write_str(&self.dims.dimnames[0]);
+        }
+
+        self.dims.write_ranges(
+            f,
+            self.ranks.iter().map(|&r| CachedTranslation::new(r)),
+            false,
+        )
+    }
+}
+
+/// The prefix and numeric ids of a node, returned by
+/// [`NodeSet::iter_node_ids`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NodeIds<'a> {
+    /// The prefix of the node, as defined by [`NodeSet::prefixes`]
+    pub prefix: &'a str,
+    /// The numerical value of the id of each dimension of the node
+    pub ids: Vec<u32>,
+}
+
+/// List of names for each dimension of a NodeSet along with an optional suffix
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Default, Debug)]
+pub(crate) struct NodeSetDimensions {
+    /// The names associated with each dimension of a nodeset
+    dimnames: Vec<String>,
+    /// If true, the last name is a suffix meaning that there is one more name
+    /// than ranges
+    has_suffix: bool,
+}
+
+impl NodeSetDimensions {
+    pub(crate) fn new() -> NodeSetDimensions {
+        Default::default()
+    }
+
+    pub(crate) fn push(&mut self, d: &str) {
+        assert!(
+            !self.has_suffix,
+            "Cannot add a dimension name after a suffix has been added"
+        );
+        self.dimnames.push(d.into());
+    }
+
+    pub(crate) fn push_suffix(&mut self, d: &str) {
+        self.has_suffix = true;
+        self.dimnames.push(d.into());
+    }
+
+    /// Formats the ranges of a folded nodeset, quoting names which could not
+    /// be parsed back otherwise
+    pub(crate) fn fmt_ranges<T>(
+        &self,
+        f: &mut dyn fmt::Write,
+        ranges: impl IntoIterator<Item = T>,
+    ) -> fmt::Result
+    where
+        T: fmt::Display,
+    {
+        self.write_ranges(f, ranges, true)
+    }
+
+    fn write_ranges<T>(
+        &self,
+        f: &mut dyn fmt::Write,
+        ranges: impl IntoIterator<Item = T>,
+        quote: bool,
+    ) -> fmt::Result
+    where
+        T: fmt::Display,
+    {
+        let write_name = |f: &mut dyn fmt::Write, name: &str| {
+            if quote && !name.chars().all(is_nodeset_char) {
+                write_quoted(f, name)
+            } else {
+                f.write_str(name)
+            }
+        };
+
+        let mut dimnames = self.dimnames.iter();
+        for r in ranges.into_iter() {
+            write_name(
+                f,
+                dimnames
+                    .next()
+                    .expect("should be at least as many names as ranges"),
+            )?;
+            if self.is_rangeset() {
+                write!(f, "{:#}", r)?;
+            } else {
+                write!(f, "{}", r)?;
+            }
+        }
+
+        if let Some(suffix) = dimnames.next() {
+            write_name(f, suffix)?;
+        }
+
+        Ok(())
+    }
+
+    /// Formats the ranges of a folded nodeset with a custom formatter
+    fn write_with<'a, T>(
+        &self,
+        f: &mut dyn fmt::Write,
+        formatter: &(impl NodeFormatter + ?Sized),
+        ranges: impl IntoIterator<Item = &'a T>,
+    ) -> fmt::Result
+    where
+        T: IdRange + 'a,
+    {
+        let mut dimnames = self.dimnames.iter();
+        for range in ranges {
+            formatter.write_name(
+                f,
+                dimnames
+                    .next()
+                    .expect("should be at least as many names as ranges"),
+            )?;
+
+            let delimited = !self.is_rangeset() && (range.len() > 1 || formatter.bracket_single());
+            if delimited {
+                f.write_char(formatter.open())?;
+            }
+            let mut first = true;
+            fold_ranges(range.iter(), |start, end| {
+                if !first {
+                    f.write_char(',')?;
+                }
+                first = false;
+                formatter.write_id(f, start.id(), start.padding() as usize)?;
+                if start != end {
+                    f.write_char('-')?;
+                    formatter.write_id(f, end.id(), end.padding() as usize)?;
+                }
+                Ok(())
+            })?;
+            if delimited {
+                f.write_char(formatter.close())?;
+            }
+        }
+
+        if let Some(suffix) = dimnames.next() {
+            formatter.write_name(f, suffix)?;
+        }
+
+        Ok(())
+    }
+
+    /// Formats a node of a set with a single dimension, reusing the
+    /// translation of the previous node of the set when possible
+    fn fmt_single_node(&self, coord: u32, cache: &mut Option<CachedTranslation>) -> String {
+        let mut res = String::new();
+        self.write_single_node(&mut res, coord, cache);
+        res
+    }
+
+    /// Appends a node of a set with a single dimension to `buf` as with
+    /// [`NodeSetDimensions::fmt_single_node`]
+    fn write_single_node(
+        &self,
+        buf: &mut String,
+        coord: u32,
+        cache: &mut Option<CachedTranslation>,
+    ) {
+        let translation = cache
+            .as_ref()
+            .map(|c| c.interpolate(coord))
+            .unwrap_or_else(|| CachedTranslation::new(coord));
+
+        buf.reserve(
+            self.dimnames.iter().map(|s| s.len()).sum::<usize>()
+                + translation.padding() as usize
+                + 1,
+        );
+
+        self.write_ranges(buf, [&translation], false)
+            .expect("string format should succeed");
+
+        *cache = Some(translation);
+    }
+
+    /// Formats a node from its coordinates in each dimension
+    pub(crate) fn fmt_node(&self, coords: impl IntoIterator<Item = u32>) -> String {
+        let mut res = String::new();
+        self.write_node(&mut res, coords);
+        res
+    }
+
+    /// Appends a node to `buf` from its coordinates in each dimension
+    fn write_node(&self, buf: &mut String, coords: impl IntoIterator<Item = u32>) {
+        self.write_ranges(buf, coords.into_iter().map(CachedTranslation::new), false)
+            .expect("string format should succeed");
+    }
+
+    fn is_rangeset(&self) -> bool {
+        self.dimnames.len() == 1 && self.dimnames[0].is_empty()
+    }
+
+    /// Returns true if the names are those of IPv4 addresses (ie `10.0.0.1`)
+    #[cfg(feature = "ip")]
+    fn is_ipv4(&self) -> bool {
+        !self.has_suffix && self.dimnames == ["", ".", ".", "."]
+    }
+
+    /// Returns the dimension names with `[]` standing for each dimension
+    /// followed by another name (ie `r[]n` for `r[1-2]n[1-4]`)
+    fn debug_name(&self) -> String {
+        self.dimnames.join("[]")
+    }
+}
+
+/// Formats the overlap between duplicated terms when it holds other nodes
+/// than the first duplicate
+fn fmt_overlap(node: &str, overlap: &str) -> String {
+    if node == overlap {
+        return String::new();
+    }
+
+    format!(" (overlapping nodes: '{overlap}')")
+}
+
+/// Formats suggested names as a hint appended to an error message
+fn fmt_suggestions(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        " (did you mean {}?)",
+        suggestions.iter().map(|s| format!("'{s}'")).join(", ")
+    )
+}
+
+/// Writes a name between double quotes, escaping quotes and backslashes
+pub(crate) fn write_quoted(f: &mut dyn fmt::Write, name: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in name.chars() {
+        if matches!(c, '"' | '\\') {
+            f.write_char('\\')?;
+        }
+        f.write_char(c)?;
+    }
+    f.write_char('"')
+}
+
+impl<T> fmt::Display for NodeSet<T>
+where
+    T: IdRange + fmt::Display + PartialEq + Clone + fmt::Display + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Self::fmt_bases(f, &self.bases, ',')
+    }
+}
+
+/// Error returned by [`NodeSet::try_fold_string`] when the string
+/// representation of a set is longer than allowed
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("nodeset representation exceeds {max_bytes} bytes")]
+pub struct FormatTooLarge {
+    /// The maximum number of bytes which was exceeded
+    pub max_bytes: usize,
+}
+
+/// Errors that may happen when parsing configuration files
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigurationError {
+    /// A YAML configuration file cannot be parsed
+    #[error("invalid yaml file")]
+    InvalidYamlFile(#[from] serde_yaml::Error),
+
+    /// An INI configuration file cannot be parsed
+    #[error("invalid ini file")]
+    InvalidIniFile(#[from] ini::Error),
+
+    /// A property is missing in the configuration file
+    #[error("missing ini property: {0}")]
+    MissingProperty(String),
+
+    /// An unexpected property was found in the configuration file
+    #[error("unexpected ini property: {0}")]
+    UnexpectedProperty(String),
+
+    /// A property has an invalid value in the configuration file
+    #[error("invalid value for ini property {0}: {1}")]
+    InvalidValue(String, String),
+
+    /// A group definition is invalid in a group file
+    #[error("invalid group definition '{0}': {1}")]
+    InvalidGroup(String, String),
+
+    /// An environment variable used in a configuration setting is not set
+    #[error("undefined environment variable '{1}' in ini property {0}")]
+    UndefinedVariable(String, String),
+
+    /// A configuration file is invalid
+    #[error("{}: {1}", .0.display())]
+    File(PathBuf, Box<ConfigurationError>),
+
+    /// A resolver which was not built from the configuration files cannot
+    /// be reloaded
+    #[error("resolver not built from configuration files")]
+    NotReloadable,
+}
+
+/// Errors that may happen when parsing nodesets
+#[derive(thiserror::Error, Debug)]
+pub enum NodeSetParseError {
+    /// An error occurred while parsing an integer.
+    #[error("invalid range")]
+    RangeError(#[from] RangeStepError),
+
+    /// An error occurred while parsing an integer.
+    #[error("invalid integer")]
+    ParseIntError(#[from] std::num::ParseIntError),
+
+    /// An index is out of range.
+    #[deprecated(note = "ids out of range are reported with `IdOverflow`")]
+    #[error("value out of range")]
+    OverFlow(#[from] std::num::TryFromIntError),
+
+    /// An error occurred while executing an external command as specified in the dynamic configuration file.
+    #[error("external command execution failed")]
+    Command(#[from] std::io::Error),
+
+    /// A parsing error which does not fit in any other category.
+    #[error("unable to parse '{0}'")]
+    Generic(String),
+
+    /// Padding sizes at both ends of a range do not match (ie `[01-003]`).
+    #[error("mismatched padding: '{0}' and '{1}'")]
+    MismatchedPadding(String, String),
+
+    /// The start of a range is greater than its end (ie `[5-3]`).
+    #[error("empty or inverted range: '{0}-{1}'")]
+    EmptyOrInvertedRange(String, String),
+
+    /// An id or range does not fit in the supported id range (ie `[0-4294967296]`).
+    #[error("id out of range: '{0}'")]
+    IdOverflow(String),
+
+    /// Range delimiters conflict with other elements of the syntax.
+    #[error("invalid range delimiters: '{0}' and '{1}'")]
+    Delimiters(char, char),
+
+    /// A reference was made to a group source that does not exist.
+    #[deprecated(note = "unknown sources are reported with `UnknownSource`")]
+    #[error("Unknown group source: '{0}'")]
+    Source(String),
+
+    /// A reference was made to a group source that does not exist (ie
+    /// `@clustr:compute`).
+    #[error("Unknown group source: '{group_source}'{}", fmt_suggestions(.suggestions))]
+    UnknownSource {
+        /// The name of the source
+        group_source: String,
+        /// Up to three existing sources with a similar name
+        suggestions: Vec<String>,
+    },
+
+    /// A group referred to by its name is not defined by its source (ie
+    /// `@comptue`).
+    #[error(
+        "unknown group '{group}' in source '{group_source}'{}",
+        fmt_suggestions(.suggestions)
+    )]
+    UnknownGroup {
+        /// The name of the group
+        group: String,
+        /// The source in which the group was looked up
+        group_source: String,
+        /// Up to three groups of the source with a similar name
+        suggestions: Vec<String>,
+    },
+
+    /// A group is defined in terms of itself, directly or through other
+    /// groups (ie `@a` contains `@b` which contains `@a`).
+    #[error("cyclic group definition: {0}")]
+    GroupCycle(String),
+
+    /// Groups are nested deeper than the resolver allows (ie `@a` contains
+    /// `@b` which contains `@c`... over too many levels).
+    #[error("group nesting too deep: {0}")]
+    GroupDepth(String),
+
+    /// The members of a group, possibly referring to groups of other
+    /// sources, could not be resolved. Holds the groups whose definitions
+    /// were being resolved, outermost first (ie `@site:all -> @slurm:gpu`),
+    /// and the error found in the definition of the innermost one.
+    #[error("in the definition of {0}: {1}")]
+    GroupDefinition(String, Box<NodeSetParseError>),
+
+    /// A group pattern does not match any group of its sources (ie `@rack*`
+    /// without any group whose name starts with `rack`).
+    #[error("no group matches '{0}'")]
+    NoMatchingGroup(String),
+
+    /// A group term was found while group resolution is disabled (see
+    /// [`ParseOptions::no_groups`] and [`NodeSet::parse_literal`]).
+    #[error("group resolution is disabled, cannot resolve '{0}'")]
+    GroupsDisabled(String),
+
+    /// A quoted node name is invalid (ie `"rack-a` or `""`).
+    #[error("invalid quoted name '{0}': {1}")]
+    Quote(String, &'static str),
+
+    /// Range delimiters hold no range (ie `node[]`). The input is reported
+    /// from the opening delimiter.
+    #[error("empty brackets: '{0}'")]
+    EmptyBracket(String),
+
+    /// A list of ranges is not closed (ie `node[` or `node[1-2`). The input
+    /// is reported from the opening delimiter.
+    #[error("unclosed bracket: '{0}'")]
+    UnclosedBracket(String),
+
+    /// A closing delimiter does not match any opening one (ie `node]`). The
+    /// input is reported from the closing delimiter.
+    #[error("unexpected closing bracket: '{0}'")]
+    UnexpectedCloseBracket(String),
+
+    /// Shifting an id would make it negative or too large (ie `node0` shifted
+    /// by -1, see [`NodeSet::shift_ids`]).
+    #[error("cannot shift id '{id}' by {delta}")]
+    IdShift {
+        /// The id before it is shifted, with its padding
+        id: String,
+        /// The shift applied to the id
+        delta: i64,
+    },
+
+    /// The maximum number of dimensions to fold is invalid.
+    #[error("invalid maximum number of fold dimensions: {0}")]
+    FoldDimensions(usize),
+
+    /// The input could not be read.
+    #[error("failed to read input")]
+    Read(#[source] std::io::Error),
+
+    /// A line of the input could not be parsed.
+    #[error("line {0}: {1}")]
+    Line(usize, Box<NodeSetParseError>),
+
+    /// A group could not be resolved while resolving several groups at once.
+    #[error("group '{0}': {1}")]
+    Group(String, Box<NodeSetParseError>),
+
+    /// A node appears more than once in the input while duplicates are
+    /// rejected (ie `node[1-5] node[3-8]`).
+    #[error("duplicate node: '{node}'{}", fmt_overlap(.node, .overlap))]
+    DuplicateNode {
+        /// The first node found to be duplicated
+        node: String,
+        /// The duplicated nodes of the overlapping terms, folded (ie
+        /// `node[3-5]`)
+        overlap: String,
+    },
+
+    /// A node is not sorted after the previous one or is a duplicate while
+    /// the input is assumed to be sorted, see
+    /// [`NodeSet::from_sorted_unchecked`].
+    #[error("unsorted or duplicate node: '{0}'")]
+    Unsorted(String),
+
+    /// A group source failed and is not queried again until its
+    /// `failure_ttl` expires. Holds the name of the source and the message
+    /// of its last failure.
+    #[error("group source '{0}' is unavailable: {1}")]
+    SourceUnavailable(String, String),
+
+    /// A group source cannot enumerate its groups (ie netgroups), which is
+    /// needed to list them, to find the groups of a node or to refer to all
+    /// their nodes. Holds the name of the source.
+    #[error("group source '{0}' cannot list its groups")]
+    ListUnsupported(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idrange::IdRangeList;
+    use crate::idrange::IdRangeTree;
+    use crate::Separated;
+
+    fn parse_to_fold(ns: &str) -> Result<String, NodeSetParseError> {
+        ns.parse::<NodeSet<IdRangeList>>().map(|ns| ns.to_string())
+    }
+
+    fn parse_to_vec(ns: &str) -> Result<Vec<String>, NodeSetParseError> {
+        ns.parse::<NodeSet<IdRangeList>>().map(|ns| {
+            let mut r: Vec<_> = ns.iter().collect();
+            r.sort();
+            r
+        })
+    }
+
+    #[test]
+    fn test_nodeset_empty() {
+        let id1: NodeSet<IdRangeList> = "".parse().unwrap();
+        assert!(id1.is_empty());
+
+        let id1: NodeSet<IdRangeList> = "x[1-2],b".parse().unwrap();
+        let id2: NodeSet<IdRangeList> = "x[3-4],c".parse().unwrap();
+
+        assert!(id1.intersection(&id2).is_empty());
+    }
+
+    #[test]
+    fn test_nodeset_default() {
+        let ns: NodeSet<IdRangeList> = NodeSet::default();
+        assert!(ns.is_empty());
+        assert_eq!(ns, "".parse().unwrap());
+        assert_eq!(ns, NodeSet::new());
+
+        let ns = ns.union(&"x[1-2],b".parse().unwrap());
+        assert_eq!(ns.to_string(), "b,x[1-2]");
+
+        let mut out = NodeSet::default();
+        ns.union_into(&NodeSet::default(), &mut out);
+        assert_eq!(out, ns);
+
+        let invalid = "x[1-".parse::<NodeSet<IdRangeList>>().ok();
+        assert!(invalid.unwrap_or_default().is_empty());
+    }
+
+    #[test]
+    fn test_nodeset_parse() {
+        let id1: NodeSet<IdRangeList> = "x[1-10/2,5]y[1-7]z3,x[1-10/2,5]y[1-7]z2".parse().unwrap();
+        let id2: NodeSet<IdRangeList> = "x[2-5]y7z[2,3]".parse().unwrap();
+
+        assert_eq!(id1.to_string(), "x[1,3,5,7,9]y[1-7]z[2-3]");
+        assert_eq!(id2.to_string(), "x[2-5]y7z[2-3]");
+        assert_eq!(id1.intersection(&id2).to_string(), "x[3,5]y7z[2-3]");
+    }
+
+    #[test]
+    fn test_nodeset_union_all() {
+        let sets: Vec<NodeSet<IdRangeList>> = ["x[1-3]", "r1n[1-2]", "x[3-5]", "", "r2n[1-2]"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+
+        let expected = sets
+            .iter()
+            .fold(NodeSet::default(), |acc, set| acc.union(set));
+        assert_eq!(NodeSet::union_all(&sets), expected);
+        assert_eq!(NodeSet::union_all(&sets).to_string(), "r[1-2]n[1-2],x[1-5]");
+        assert_eq!(NodeSet::union_all(&sets).len(), 9);
+        assert!(NodeSet::<IdRangeList>::union_all(&[]).is_empty());
+
+        let sets: Vec<NodeSet<IdRangeList>> = ["x[1-10],y[1-2]", "x[5-20],y2", "x[1-6],y[2-3]"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert_eq!(NodeSet::intersection_all(&sets).to_string(), "x[5-6],y2");
+        assert_eq!(NodeSet::intersection_all(&sets[..1]), sets[0]);
+        assert!(NodeSet::<IdRangeList>::intersection_all(Vec::<NodeSet<_>>::new()).is_empty());
+
+        // Sets after the result becomes empty are not consumed
+        let mut consumed = 0;
+        let inter = NodeSet::intersection_all(
+            ["x[1-5]", "y1", "x[1-5]", "x[1-5]"]
+                .iter()
+                .inspect(|_| consumed += 1)
+                .map(|s| s.parse::<NodeSet<IdRangeList>>().unwrap()),
+        );
+        assert!(inter.is_empty());
+        assert_eq!(consumed, 2);
+    }
+
+    #[test]
+    fn test_nodeset_node_at_offset() {
+        let ns: NodeSet = "a[0-1]b[0-2]".parse().unwrap();
+        assert_eq!(ns.node_at_offset(3).unwrap(), "a1b0");
+
+        for s in [
+            "a[0-1]b[0-2]",
+            "login,node[01-10,20],r[1-2]n[1-3]p[4,6],r1n[5-6]p[0-1]",
+            "x[1-3]y[008-010]",
+        ] {
+            let ns: NodeSet = s.parse().unwrap();
+            let nodes: Vec<_> = ns.iter().collect();
+            for (i, node) in nodes.iter().enumerate() {
+                assert_eq!(ns.node_at_offset(i as u64).as_ref(), Some(node), "{s}");
+            }
+            assert_eq!(ns.node_at_offset(nodes.len() as u64), None);
+        }
+
+        assert_eq!(NodeSet::new().node_at_offset(0), None);
+    }
+
+    #[test]
+    fn test_nodeset_for_each_node() {
+        for input in [
+            "",
+            "login",
+            "node[1-3,08-10],gpu[1-2],x",
+            "r[1-2]n[01-03]s[1-2],r3n1,[1-3]",
+            "\"rack-a,b\"[1-2]",
+        ] {
+            let ns: NodeSet<IdRangeList> = input.parse().unwrap();
+            let mut nodes = vec![];
+            ns.for_each_node(|node| nodes.push(node.to_string()));
+            assert_eq!(nodes, ns.iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_nodeset_map_prefix() {
+        let ns: NodeSet<IdRangeList> = "a[1-3],b[3-5],b1c[1-2],a1c[3-4],d,x[01-02]"
+            .parse()
+            .unwrap();
+
+        let renamed = ns.map_prefix(|p| {
+            if p == "a" {
+                "b".to_string()
+            } else {
+                p.to_string()
+            }
+        });
+        assert_eq!(renamed.to_string(), "b[1-5],b1c[1-4],d,x[01-02]");
+        assert_eq!(renamed.len(), 12);
+
+        let renamed = ns.map_prefix(|p| format!("{p}-new"));
+        assert_eq!(
+            renamed.to_string(),
+            "a-new[1-3],a-new1c[3-4],b-new[3-5],b-new1c[1-2],d-new,x-new[01-02]"
+        );
+
+        let renamed = ns.map_prefix(|_| "n".to_string());
+        assert_eq!(renamed.to_string(), "n[1-5,01-02],n,n1c[1-4]");
+        assert_eq!(renamed.len(), 1 + 2 + 5 + 4);
+    }
+
+    #[test]
+    fn test_nodeset_insert_remove() {
+        let mut ns: NodeSet = "node[0-3]".parse().unwrap();
+        assert!(!ns.insert("node2").unwrap());
+        assert!(ns.insert("node5").unwrap());
+        assert!(ns.insert("node10").unwrap());
+        assert!(ns.insert("login").unwrap());
+        assert!(ns.insert("r1n2").unwrap());
+        assert!(ns.insert("r1n3").unwrap());
+        assert!(!ns.insert("r1n3").unwrap());
+        assert_eq!(ns.to_string(), "login,node[0-3,5,10],r1n[2-3]");
+        assert!(ns.insert("node[6-7]").is_err());
+        assert!(ns.insert("node1,node2").is_err());
+
+        assert!(ns.remove("node2").unwrap());
+        assert!(!ns.remove("node2").unwrap());
+        assert!(ns.remove("login").unwrap());
+        assert!(ns.remove("r1n2").unwrap());
+        assert!(ns.remove("r1n3").unwrap());
+        assert!(!ns.remove("r1n3").unwrap());
+        assert_eq!(ns.to_string(), "node[0-1,3,5,10]");
+        assert_eq!(ns, "node[0-1,3,5,10]".parse().unwrap());
+        assert!(ns.remove("node[0-1]").is_err());
+
+        // Shared sets are copied before being modified
+        let copy = ns.clone();
+        assert!(ns.insert("node4").unwrap());
+        assert_eq!(copy.to_string(), "node[0-1,3,5,10]");
+        assert_eq!(ns.to_string(), "node[0-1,3-5,10]");
+    }
+
+    #[test]
+    fn test_nodeset_parse_mixed_items() {
+        // Bracketed and bare items are split on top-level commas only
+        assert_eq!(
+            parse_to_fold("node[1-3],othernode,gpu5").unwrap(),
+            "gpu5,node[1-3],othernode"
+        );
+        assert_eq!(
+            parse_to_vec("node[1,3],gpu[1-2,5]").unwrap(),
+            vec!["gpu1", "gpu2", "gpu5", "node1", "node3"]
+        );
+        assert_eq!(
+            parse_to_fold("node[001-003,005],login1,node004").unwrap(),
+            "login1,node[001-005]"
+        );
+        assert_eq!(
+            parse_to_vec("a[1,3]b[2,4],a2b2,c").unwrap(),
+            vec!["a1b2", "a1b4", "a2b2", "a3b2", "a3b4", "c"]
+        );
+        assert_eq!(parse_to_fold("x,y[1,2],z").unwrap(), "x,y[1-2],z");
+        assert_eq!(parse_to_fold("y[1,2],y3 , y4").unwrap(), "y[1-4]");
+
+        assert!(parse_to_fold("node[1,3,othernode").is_err());
+        assert!(parse_to_fold("node[1,],gpu5").is_err());
+    }
+
+    #[test]
+    fn test_nodeset_parse_delimiter() {
+        // Non-digit characters such as '-' are part of the prefix so ranges
+        // are folded after them without any configuration
+        assert_eq!(parse_to_fold("rack-1 rack-2").unwrap(), "rack-[1-2]");
+        assert_eq!(
+            parse_to_vec("rack-[1-2]").unwrap(),
+            vec!["rack-1", "rack-2"]
+        );
+        assert_eq!(
+            parse_to_fold("rack-1-ib rack-2-ib").unwrap(),
+            "rack-[1-2]-ib"
+        );
+        assert_eq!(
+            parse_to_fold("rack-a-01,rack-a-02,rack-b-01").unwrap(),
+            "rack-a-[01-02],rack-b-01"
+        );
+    }
+
+    #[test]
+    fn test_nodeset_parse_quoted() {
+        assert_eq!(
+            parse_to_vec("\"rack-a,b\"[1-2]").unwrap(),
+            vec!["rack-a,b1", "rack-a,b2"]
+        );
+        assert_eq!(parse_to_vec("r\"a b\"-x").unwrap(), vec!["ra b-x"]);
+        assert_eq!(parse_to_vec("\"a\\\"b\\\\\"").unwrap(), vec!["a\"b\\"]);
+
+        // Names are only quoted when needed, and quoted names parse back
+        for (input, folded) in [
+            ("\"node\"[1-2]", "node[1-2]"),
+            ("\"rack-a,b\"[1-2],rack-a3", "rack-a3,\"rack-a,b\"[1-2]"),
+            ("a\"1\"[1-2]b\" \"", "\"a1\"[1-2]\"b \""),
+            ("\"r[1]\"", "\"r[1]\""),
+            ("\"a\\\"b\\\\\"", "\"a\\\"b\\\\\""),
+        ] {
+            assert_eq!(parse_to_fold(input).unwrap(), folded);
+            assert_eq!(parse_to_fold(folded).unwrap(), folded);
+        }
+
+        let options = ParseOptions {
+            open: '{',
+            close: '}',
+            ..Default::default()
+        };
+        let ns = NodeSet::<IdRangeList>::parse_with("\"r[1]\"{1-2}", &options).unwrap();
+        assert_eq!(ns.display_with(&options).to_string(), "\"r[1]\"{1-2}");
+
+        for (input, quoted, reason) in [
+            ("\"rack-a[1-2]", "\"rack-a[1-2]", "missing closing quote"),
+            ("node1,\"\"", "\"\"", "empty name"),
+            ("\"a\\x\",b", "\"a\\x", "invalid escape sequence"),
+        ] {
+            match parse_to_fold(input) {
+                Err(NodeSetParseError::Quote(s, e)) => {
+                    assert_eq!((s.as_str(), e), (quoted, reason))
+                }
+                e => panic!("Expected Quote error for '{input}', got {e:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_rangeset_parse() {
+        let id1: NodeSet<IdRangeList> = "12,3".parse().unwrap();
+        let id2: NodeSet<IdRangeList> = "0-10/2,3".parse().unwrap();
+
+        assert_eq!(id1.to_string(), "3,12");
+        assert_eq!(id2.to_string(), "0,2-4,6,8,10");
+        assert_eq!(id1.intersection(&id2).to_string(), "3");
+
+        assert_eq!(parse_to_vec("[1,2]").unwrap(), vec!["1", "2"]);
+    }
+
+    #[test]
+    fn test_rangeset_parse_affix() {
+        assert_eq!(parse_to_vec("50[01,20]").unwrap(), vec!["5001", "5020"]);
+        assert_eq!(parse_to_vec("[01,2]50").unwrap(), vec!["0150", "250"]);
+        assert_eq!(
+            parse_to_vec("05[1,10]05").unwrap(),
+            vec!["051005", "05105",]
+        );
+        assert!(parse_to_vec("05[1,10]05[0]").is_err());
+        assert!(parse_to_vec("[1][0]").is_err());
+    }
+
+    #[test]
+    fn test_rangeset_nodeset_parse() {
+        assert_eq!(parse_to_vec("1,2a").unwrap(), vec!["1", "2a"]);
+
+        assert_eq!(parse_to_vec("1-2a").unwrap(), vec!["1-2a"]);
+
+        assert_eq!(parse_to_vec("0-4/20a").unwrap(), vec!["0-4/20a"]);
+        assert_eq!(
+            parse_to_vec("0-4/2,0a").unwrap(),
+            vec!["0", "0a", "2", "4",]
+        );
+    }
+
+    #[test]
+    fn test_nodeset_parse_with_suffix_component() {
+        assert_eq!(parse_to_fold("a[1-3]_e,a4_e").unwrap(), "a[1-4]_e");
+        assert_eq!(
+            parse_to_vec("a[1-3]_e,a4_e").unwrap(),
+            vec!["a1_e", "a2_e", "a3_e", "a4_e"]
+        );
+
+        assert_eq!(
+            parse_to_fold("a[10-11]b[2-3]_cd").unwrap(),
+            "a[10-11]b[2-3]_cd"
+        );
+        assert_eq!(
+            parse_to_vec("a[10-11]b[2-3]_cd").unwrap(),
+            vec!["a10b2_cd", "a10b3_cd", "a11b2_cd", "a11b3_cd"]
+        );
+    }
+
+    #[test]
+    fn test_nodeset_parse_with_affix_id() {
+        assert_eq!(
+            parse_to_vec("a10[1-3]").unwrap(),
+            vec!["a101", "a102", "a103"]
+        );
+
+        assert_eq!(
+            parse_to_vec("a[1-3]10").unwrap(),
+            vec!["a110", "a210", "a310"]
+        );
+
+        assert_eq!(
+            parse_to_vec("a10[1-3]10").unwrap(),
+            vec!["a10110", "a10210", "a10310"]
+        );
+
+        assert_eq!(
+            parse_to_vec("a00[1-3]00").unwrap(),
+            vec!["a00100", "a00200", "a00300"]
+        );
+
+        assert_eq!(
+            parse_to_vec("a20[0-100/20]5").unwrap(),
+            vec!["a2005", "a201005", "a20205", "a20405", "a20605", "a20805"]
+        );
+
+        assert_eq!(
+            parse_to_vec("a02[0-100/20]05").unwrap(),
+            vec!["a02005", "a0210005", "a022005", "a024005", "a026005", "a028005",]
+        );
+    }
+
+    #[test]
+    fn test_nodeset_union() {
+        let id1: NodeSet<IdRangeList> = "a[1-2],c1,e-x[1-2]y1,login".parse().unwrap();
+        let id2: NodeSet<IdRangeList> = "b1,c[2-3],e-x3y[1-2],f,login".parse().unwrap();
+
+        assert_eq!(
+            id1.union(&id2).to_string(),
+            "a[1-2],b1,c[1-3],e-x[1-2]y1,e-x3y[1-2],f,login"
+        );
+        assert_eq!(id1.union(&id2), id2.union(&id1));
+        assert_eq!(id1.union(&NodeSet::default()), id1);
+        assert_eq!(NodeSet::default().union(&id1), id1);
+    }
+
+    #[test]
+    fn test_nodeset_intersect() {
+        let id1: NodeSet<IdRangeList> = "x[1-10/2,5]y[1-7]z3,x[1-10/2,5]y[1-7]z2".parse().unwrap();
+        let id2: NodeSet<IdRangeList> = "x[2-5]y7z[2,3]".parse().unwrap();
+
+        assert_eq!(id1.intersection(&id2).fold().to_string(), "x[3,5]y7z[2-3]");
+
+        let id1: NodeSet<IdRangeList> = "a1 a2".parse().unwrap();
+        let id2: NodeSet<IdRangeList> = "b1 b2".parse().unwrap();
+
+        assert!(id1.intersection(&id2).is_empty(),);
+
+        let id1: NodeSet<IdRangeList> = "login,a1".parse().unwrap();
+        let id2: NodeSet<IdRangeList> = "login,b1".parse().unwrap();
+
+        assert_eq!(id1.intersection(&id2).to_string(), "login");
+    }
+
+    #[test]
+    fn test_nodeset_intersect_refold() {
+        fn check<T>()
+        where
+            T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+        {
+            let cases = [
+                ("node[1-100]", "node[50-60,90]", "node[50-60,90]"),
+                ("node[1-100]", "node[0-1,100-200]", "node[1,100]"),
+                ("node[1-10,20-30]", "node[5-25]", "node[5-10,20-25]"),
+                ("node[1-100/2]", "node[50-60]", "node[51,53,55,57,59]"),
+                ("node[01-10]", "node[05-20]", "node[05-10]"),
+                (
+                    "r[1-4]n[1-100],login1",
+                    "r[2-3]n[50-60,90],r4n1,login[1-2]",
+                    "login1,r[2-3]n[50-60,90],r4n1",
+                ),
+                (
+                    "r[1-4]n[1-100]",
+                    "r[1-2]n[50-60],r[3-4]n[50-60]",
+                    "r[1-4]n[50-60]",
+                ),
+            ];
+
+            for (a, b, expected) in cases {
+                let a: NodeSet<T> = a.parse().unwrap();
+                let b: NodeSet<T> = b.parse().unwrap();
+                let expected: NodeSet<T> = expected.parse().unwrap();
+
+                let inter = a.intersection(&b);
+                assert_eq!(inter.to_string(), expected.to_string(), "{a} & {b}");
+                assert_eq!(inter, expected);
+                assert_eq!(inter.len(), expected.len());
+
+                // Further operations start from a coalesced representation
+                assert_eq!(inter.union(&expected).to_string(), expected.to_string());
+                assert_eq!(inter.to_string().parse::<NodeSet<T>>().unwrap(), expected);
+            }
+        }
+
+        check::<IdRangeList>();
+        check::<IdRangeTree>();
+    }
+
+    #[test]
+    fn test_nodeset_overflow() {
+        assert!(parse_to_vec("a10000000000").is_err());
+        assert!(parse_to_vec("a[9999999999-10000000000]").is_err());
+        assert_eq!(parse_to_vec("a3183856184").unwrap(), vec!["a3183856184"]);
+        assert!(parse_to_vec("a03183856185").is_err());
+        assert!(parse_to_vec("a[3183856184-3183856185]").is_err());
+        assert!(parse_to_vec("a00000000000").is_err());
+        assert_eq!(parse_to_vec("a0000000000").unwrap(), vec!["a0000000000"]);
+        assert_eq!(
+            parse_to_vec("a[3183856183-3183856184]").unwrap(),
+            vec!["a3183856183", "a3183856184"]
+        );
+        assert_eq!(
+            parse_to_vec("a[3183856170-3183856184/10]").unwrap(),
+            vec!["a3183856170", "a3183856180"]
+        );
+
+        assert_eq!(
+            parse_to_vec("a[3183856170-3183856184/2000000000]").unwrap(),
+            vec!["a3183856170"]
+        );
+        assert!(parse_to_vec("a[3183856170-3183856184/20000000000]").is_err(),);
+    }
+
+    #[test]
+    fn test_nodeset_overflow_affix() {
+        assert!(parse_to_vec("a10000[0,1]00000").is_err());
+        assert!(parse_to_vec("a00000[0,1]00000").is_err());
+        assert_eq!(
+            parse_to_vec("n000000000[0-0]").unwrap(),
+            vec!["n0000000000"]
+        );
+        assert!(parse_to_vec("n000000000[0-10]").is_err());
+        assert_eq!(
+            parse_to_fold("n00000000[0-10]").unwrap(),
+            "n[000000000-000000009,0000000010]"
+        );
+
+        assert_eq!(
+            parse_to_fold("n31838561[0-84]").unwrap(),
+            "n[318385610-318385619,3183856110-3183856184]"
+        );
+
+        assert_eq!("a318385[0-61]84".parse::<NodeSet>().unwrap().len(), 62);
+        assert!(parse_to_fold("a318385[0-62]84").is_err());
+        assert!(parse_to_fold("a318385[0-61]85").is_err());
+
+        assert_eq!("a[3183855-3183856]84".parse::<NodeSet>().unwrap().len(), 2);
+        assert_eq!(
+            parse_to_vec("a[31838560-31838561]84").unwrap(),
+            vec!["a3183856084", "a3183856184"]
+        );
+    }
+
+    #[test]
+    fn test_nodeset_id_overflow_error() {
+        let overflow = |ns: &str| match ns.parse::<NodeSet>() {
+            Err(NodeSetParseError::IdOverflow(input)) => input,
+            res => panic!("expected overflow error for '{ns}', got {res:?}"),
+        };
+
+        assert_eq!(overflow("node[4294967290-4294967299]"), "4294967299");
+        assert_eq!(overflow("node4294967296"), "4294967296");
+        assert_eq!(overflow("node[4294967295]"), "4294967295");
+        assert_eq!(overflow("node[3183856185]"), "3183856185");
+        assert_eq!(overflow("node[0-1]b3183856185"), "3183856185");
+        assert_eq!(overflow("node[1-2/4294967296]"), "4294967296");
+        assert_eq!(overflow("node[318385619]0"), "[318385619]0");
+        assert_eq!(overflow("node[429496730]0"), "[429496730]0");
+        assert_eq!(overflow("node[1-2]3183856184"), "[1-2]3183856184");
+        assert_eq!(overflow("node1[3183856184]"), "1[3183856184]");
+        assert_eq!(overflow("3183856185"), "3183856185");
+
+        assert_eq!(
+            parse_to_fold("node[3183856183-3183856184]").unwrap(),
+            "node[3183856183-3183856184]"
+        );
+        assert_eq!(parse_to_fold("node[318385618]4").unwrap(), "node3183856184");
+        assert_eq!(
+            parse_to_fold("node[0-3183856184/3183856184]").unwrap(),
+            "node[0,3183856184]"
+        );
+        assert_eq!(
+            parse_to_fold("node[3183856184-3183856184/4294967295]").unwrap(),
+            "node3183856184"
+        );
+
+        assert!(matches!(
+            "node[1-5/0]".parse::<NodeSet>(),
+            Err(NodeSetParseError::RangeError(RangeStepError::ZeroStep))
+        ));
+        assert!(matches!(
+            "node[1-5/0]9".parse::<NodeSet>(),
+            Err(NodeSetParseError::RangeError(RangeStepError::ZeroStep))
+        ));
+    }
+
+    #[test]
+    fn test_nodeset_symmetric_diff() {
+        let id1: NodeSet<IdRangeList> = "x[1-10/2,5]y[1-7]z3,x[1-10/2,5]y[1-7]z2".parse().unwrap();
+        let id2: NodeSet<IdRangeList> = "x[2-5]y7z[2,3]".parse().unwrap();
+
+        assert_eq!(
+            id1.symmetric_difference(&id2).fold().to_string(),
+            "x[1,7,9]y[1-7]z[2-3],x[2,4]y7z[2-3],x[3,5]y[1-6]z[2-3]"
+        );
+
+        let id1: NodeSet<IdRangeList> = "a1 b1 a2".parse().unwrap();
+        let id2: NodeSet<IdRangeList> = "b1 a2 a3".parse().unwrap();
+
+        assert_eq!(id1.symmetric_difference(&id2).to_string(), "a[1,3]");
+    }
+
+    #[test]
+    fn test_nodeset_len() {
+        let id1: NodeSet<IdRangeList> = "a b".parse().unwrap();
+        let id2: NodeSet<IdRangeList> = "a[0-9]b".parse().unwrap();
+        let id3: NodeSet<IdRangeList> = "a[0-9]b[0-8]".parse().unwrap();
+        let id4: NodeSet<IdRangeList> = "a[0-10000] b[0-100]".parse().unwrap();
+        let id5: NodeSet<IdRangeList> = "a[00-20]".parse().unwrap();
+
+        assert_eq!(id1.len(), 2);
+        assert_eq!(id2.len(), 10);
+        assert_eq!(id3.len(), 90);
+        assert_eq!(id4.len(), 10102);
+        assert_eq!(id5.len(), 21);
+    }
+
+    #[test]
+    fn test_nodeset_fold() {
+        assert_eq!(
+            parse_to_fold("a[1-10/2,5]b[1-7]c3,a[1-10/2,5]b[1-7]c2").unwrap(),
+            "a[1,3,5,7,9]b[1-7]c[2-3]"
+        );
+        assert_eq!(
+            parse_to_fold("a[0-10]b[0-10],a[0-20]b[0-10]").unwrap(),
+            "a[0-20]b[0-10]"
+        );
+        assert_eq!(
+            parse_to_fold("x[0-10]y[0-10],x[8-18]y[8-18],x[11-18]y[0-7]").unwrap(),
+            "x[0-7]y[0-10],x[8-18]y[0-18]"
+        );
+    }
+
+    #[test]
+    fn test_nodeset_fold_prefixes() {
+        assert_eq!(parse_to_fold("a1,b1,a2,b2").unwrap(), "a[1-2],b[1-2]");
+        assert_eq!(
+            parse_to_fold("node1,node-ib1,node2,node-ib2").unwrap(),
+            "node[1-2],node-ib[1-2]"
+        );
+        assert_eq!(
+            parse_to_fold("node[1-2],node-[1-2],nod[1-2]").unwrap(),
+            "nod[1-2],node[1-2],node-[1-2]"
+        );
+        assert_eq!(
+            parse_to_fold("node1-ib,node1,node2-ib").unwrap(),
+            "node1,node[1-2]-ib"
+        );
+        assert_eq!(parse_to_fold("a1b1,a1,ab1").unwrap(), "a1,a1b1,ab1");
+        assert_eq!(parse_to_fold("x1y2,x1y,xy2").unwrap(), "x1y2,x1y,xy2");
+        assert_eq!(parse_to_fold("node1,Node1").unwrap(), "Node1,node1");
+        assert_eq!("a[1-2],b[1-2]".parse::<NodeSet>().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_nodeset_clone_shares_sets() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<NodeSet<IdRangeList>>();
+        assert_send_sync::<NodeSet<IdRangeTree>>();
+
+        let shared = |a: &NodeSet, b: &NodeSet| {
+            a.bases
+                .values()
+                .zip(b.bases.values())
+                .filter(|(a, b)| Arc::ptr_eq(a, b))
+                .count()
+        };
+
+        let base: NodeSet = "a[1-100],b[1-100],c[1-10]d[1-10]".parse().unwrap();
+        let clone = base.clone();
+        assert_eq!(shared(&base, &clone), 3);
+
+        let removed = clone.difference(&"b[5-6],e1".parse().unwrap());
+        assert_eq!(removed.to_string(), "a[1-100],b[1-4,7-100],c[1-10]d[1-10]");
+        assert_eq!(shared(&base, &removed), 2);
+        assert_eq!(base.to_string(), "a[1-100],b[1-100],c[1-10]d[1-10]");
+
+        let added = base.union(&"b[101-102]".parse().unwrap());
+        assert_eq!(added.to_string(), "a[1-100],b[1-102],c[1-10]d[1-10]");
+        assert_eq!(shared(&base, &added), 2);
+        assert_eq!(base.to_string(), "a[1-100],b[1-100],c[1-10]d[1-10]");
+
+        let intersected = base.intersection(&"a[1-2],b[1-100]".parse().unwrap());
+        assert_eq!(intersected.to_string(), "a[1-2],b[1-100]");
+        assert_eq!(base.len(), 300);
+    }
+
+    #[test]
+    fn test_nodeset_shrink_to_fit() {
+        let parsed: NodeSet = "a[1-100],a[1-100],b[1-10]c[1-10],b[1-10]c[1-10],d"
+            .parse()
+            .unwrap();
+        let clone = parsed.clone();
+        let mut ns = parsed.union(&"a101".parse().unwrap());
+
+        #[cfg(feature = "heap-size")]
+        let before = ns.heap_size();
+
+        ns.shrink_to_fit();
+        assert_eq!(ns.to_string(), "a[1-101],b[1-10]c[1-10],d");
+        assert_eq!(clone.to_string(), "a[1-100],b[1-10]c[1-10],d");
+
+        #[cfg(feature = "heap-size")]
+        {
+            assert!(ns.heap_size() < before);
+            assert!(ns.heap_size() >= 101 * std::mem::size_of::<u32>());
+            assert_eq!(NodeSet::new().heap_size(), 0);
+        }
+    }
+
+    #[test]
+    fn test_nodeset_op_into() {
+        let sets: Vec<NodeSet> = [
+            "a[1-10],b[1-5],c[1-3]d[1-3],login",
+            "a[5-15],c[2-4]d[2-4],e1,login",
+            "b[1-5],login",
+            "",
+            "a[1-10]",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+
+        let mut out = NodeSet::new();
+        for _ in 0..2 {
+            for (a, b) in sets.iter().cartesian_product(sets.iter()) {
+                a.union_into(b, &mut out);
+                assert_eq!(out, a.union(b));
+                a.difference_into(b, &mut out);
+                assert_eq!(out, a.difference(b));
+                a.intersection_into(b, &mut out);
+                assert_eq!(out, a.intersection(b));
+                a.symmetric_difference_into(b, &mut out);
+                assert_eq!(out, a.symmetric_difference(b));
+            }
+        }
+
+        // Sets shared with other NodeSets are not modified
+        let mut out = sets[0].clone();
+        sets[1].intersection_into(&sets[2], &mut out);
+        assert_eq!(out.to_string(), "login");
+        assert_eq!(sets[0].to_string(), "a[1-10],b[1-5],c[1-3]d[1-3],login");
+
+        let a: NodeSet<IdRangeTree> = "a[1-10]".parse().unwrap();
+        let b: NodeSet<IdRangeTree> = "a[5-15]".parse().unwrap();
+        let mut out = NodeSet::default();
+        a.intersection_into(&b, &mut out);
+        assert_eq!(out.to_string(), "a[5-10]");
+    }
+
+    #[test]
+    fn test_nodeset_op_reporting() {
+        let sets: Vec<NodeSet> = [
+            "a[1-10],b[1-5],c[1-3]d[1-3],login",
+            "a[5-15],c[2-4]d[2-4],e1,login",
+            "b[1-5],login",
+            "",
+            "a[1-10]",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+
+        for (a, b) in sets.iter().cartesian_product(sets.iter()) {
+            let (res, changed) = a.difference_reporting(b);
+            assert_eq!(res, a.difference(b));
+            assert_eq!(changed, res != *a, "{a} - {b}");
+
+            let (res, changed) = a.union_reporting(b);
+            assert_eq!(res, a.union(b));
+            assert_eq!(changed, res != *a, "{a} + {b}");
+        }
+
+        let huge: NodeSet = "a[0-65535]b[0-65535]c[0-65535]d[0-65535]e[0-1]"
+            .parse()
+            .unwrap();
+        assert_eq!(huge.len_u64(), u64::MAX);
+        let (res, changed) = huge.union_reporting(&"a0b0c0d0e0".parse().unwrap());
+        assert!(!changed);
+        assert_eq!(res, huge);
+    }
+
+    #[test]
+    fn test_nodeset_display_with_formatter() {
+        use crate::{DefaultFormatter, NodeFormatter};
+
+        /// Writes hexadecimal ids between parentheses, always delimited
+        struct Hex;
+
+        impl NodeFormatter for Hex {
+            fn write_id(&self, f: &mut dyn fmt::Write, id: u32, _pad: usize) -> fmt::Result {
+                write!(f, "{id:x}")
+            }
+
+            fn open(&self) -> char {
+                '('
+            }
+
+            fn close(&self) -> char {
+                ')'
+            }
+
+            fn bracket_single(&self) -> bool {
+                true
+            }
+
+            fn separator(&self) -> char {
+                ' '
+            }
+
+            fn product_separator(&self) -> char {
+                ';'
+            }
+        }
+
+        for ns in [
+            "",
+            "login",
+            "1-3,5",
+            "node[1-5,7],gpu1",
+            "node[08-11]-ib,node[1-2]",
+            "r[1-2]n[1-4],r3n[1-2]",
+            "\"rack-a,b\"[1-2],x[01-02]",
+        ] {
+            let ns: NodeSet<IdRangeList> = ns.parse().unwrap();
+            assert_eq!(
+                ns.display_with(&DefaultFormatter).to_string(),
+                ns.to_string()
+            );
+        }
+
+        let ns: NodeSet = "node[09-12,255],gpu1,login,r[1-2]n[1-4],r3n[1-2]"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            ns.display_with(&Hex).to_string(),
+            "gpu(1) login node(9-c,ff) r(1-2)n(1-4);r(3)n(1-2)"
+        );
+        assert_eq!(
+            ns.display_ordered(&Hex, PrefixOrder::Insertion).to_string(),
+            "node(9-c,ff) gpu(1) login r(1-2)n(1-4);r(3)n(1-2)"
+        );
+    }
+
+    #[test]
+    fn test_nodeset_parse_with_options() {
+        let braces = ParseOptions {
+            open: '{',
+            close: '}',
+            ..Default::default()
+        };
+
+        let ns: NodeSet =
+            NodeSet::parse_with("node{1-5},node{3-9}b{01-02},login", &braces).unwrap();
+        assert_eq!(ns.to_string(), "login,node[1-5],node[3-9]b[01-02]");
+        assert_eq!(
+            ns.display_with(&braces).to_string(),
+            "login,node{1-5},node{3-9}b{01-02}"
+        );
+        assert_eq!(
+            ns.display_with(&Separated::new(braces, ' ')).to_string(),
+            "login node{1-5} node{3-9}b{01-02}"
+        );
+        assert_eq!(
+            NodeSet::<IdRangeList>::parse_with("{1-3},4", &braces)
+                .unwrap()
+                .display_with(&braces)
+                .to_string(),
+            "1-4"
+        );
+        assert_eq!(
+            NodeSet::<IdRangeList>::parse_with(
+                "n<1-3> - n2",
+                &ParseOptions {
+                    open: '<',
+                    close: '>',
+                    ..Default::default()
+                }
+            )
+            .unwrap()
+            .to_string(),
+            "n[1,3]"
+        );
+        assert!(NodeSet::<IdRangeList>::parse_with("node[1-5]", &braces).is_err());
+
+        let default = ParseOptions::default();
+        assert_eq!(
+            NodeSet::<IdRangeList>::parse_with("node[1-5]", &default)
+                .unwrap()
+                .display_with(&default)
+                .to_string(),
+            "node[1-5]"
+        );
+
+        for (open, close) in [
+            ('{', '{'),
+            ('a', ']'),
+            ('[', '1'),
+            (',', ';'),
+            ('(', ')'),
+            (' ', '|'),
+            ('-', '+'),
+        ] {
+            assert!(matches!(
+                NodeSet::<IdRangeList>::parse_with(
+                    "node1",
+                    &ParseOptions {
+                        open,
+                        close,
+                        ..Default::default()
+                    }
+                ),
+                Err(NodeSetParseError::Delimiters(o, c)) if o == open && c == close
+            ));
+        }
+    }
+
+    #[test]
+    fn test_nodeset_from_reader() {
+        let input = "b1\nb[2-3]\n  \na[1-5] - a3\n\r\nb[5-6] & b6\n";
+        let ns = NodeSet::<IdRangeList>::from_reader(input.as_bytes()).unwrap();
+        assert_eq!(ns.to_string(), "a[1-2,4-5],b[1-3,6]");
+
+        let input = (0..10000).map(|i| format!("n{}\n", i * 2)).join("");
+        let ns = NodeSet::<IdRangeTree>::from_reader(input.as_bytes()).unwrap();
+        assert_eq!(ns.len(), 10000);
+        assert_eq!(
+            ns,
+            (0..10000)
+                .map(|i| format!("n{}", i * 2))
+                .join(",")
+                .parse()
+                .unwrap()
+        );
+
+        let input = "# comment\nn1 # comment\n\n#\nn2\n";
+        assert!(NodeSet::<IdRangeList>::from_reader(input.as_bytes()).is_err());
+        let lenient = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let ns = NodeSet::<IdRangeList>::from_reader_with(input.as_bytes(), &lenient).unwrap();
+        assert_eq!(ns.to_string(), "n[1-2]");
+
+        assert!(matches!(
+            NodeSet::<IdRangeList>::from_reader("n1\nn2\nn[3-1]\n".as_bytes()),
+            Err(NodeSetParseError::Line(3, e)) if matches!(*e, NodeSetParseError::EmptyOrInvertedRange(_, _))
+        ));
+        assert!(matches!(
+            NodeSet::<IdRangeList>::from_reader(&[b'n', 0xff, b'\n'][..]),
+            Err(NodeSetParseError::Read(_))
+        ));
+    }
+
+    #[test]
+    fn test_nodeset_reject_duplicates() {
+        let duplicate = |s: &str| match NodeSet::<IdRangeList>::parse_no_dups(s) {
+            Err(NodeSetParseError::DuplicateNode { node, .. }) => Some(node),
+            Ok(_) => None,
+            Err(e) => panic!("unexpected error {e:?}"),
+        };
+        let overlap = |s: &str| match NodeSet::<IdRangeList>::parse_no_dups(s) {
+            Err(e @ NodeSetParseError::DuplicateNode { .. }) => e.to_string(),
+            res => panic!("unexpected result {res:?}"),
+        };
+
+        // Within a list of ranges
+        assert_eq!(duplicate("node[0-3,2]"), Some("node2".to_string()));
+        assert_eq!(duplicate("node[0-3,3-5]"), Some("node3".to_string()));
+        assert_eq!(duplicate("r[1-2]n[01-10,05]"), Some("r1n05".to_string()));
+        assert_eq!(duplicate("[1-3,2]"), Some("2".to_string()));
+        assert_eq!(duplicate("node[1,01,001]"), None);
+
+        // Across terms of a union
+        assert_eq!(duplicate("node[0-3] node2"), Some("node2".to_string()));
+        assert_eq!(duplicate("node[0-3],login,node[4-5]"), None);
+        assert_eq!(duplicate("a1,b1,(c1,a1)"), Some("a1".to_string()));
+        assert_eq!(duplicate("1-3,3"), Some("3".to_string()));
+
+        // All the overlapping nodes are reported
+        assert_eq!(overlap("node2 node[0-3]"), "duplicate node: 'node2'");
+        assert_eq!(
+            overlap("node[1-5] node[3-8]"),
+            "duplicate node: 'node3' (overlapping nodes: 'node[3-5]')"
+        );
+        assert_eq!(
+            overlap("node[1-5,3-8,7]"),
+            "duplicate node: 'node3' (overlapping nodes: 'node[3-5,7]')"
+        );
+        assert_eq!(
+            overlap("r[1-2]n[01-10,05-06]"),
+            "duplicate node: 'r1n05' (overlapping nodes: 'r[1-2]n[05-06]')"
+        );
+
+        // Other operators naturally combine overlapping sets
+        assert_eq!(duplicate("node[0-3] - node2,node2"), None);
+        assert_eq!(duplicate("node[0-3] & node[2-5]"), None);
+
+        // Duplicates are merged by default
+        assert_eq!(
+            "node[0-3,2] node2".parse::<NodeSet>().unwrap().to_string(),
+            "node[0-3]"
+        );
+
+        let options = ParseOptions {
+            reject_duplicates: true,
+            ..Default::default()
+        };
+        match NodeSet::<IdRangeList>::from_reader_with("n[1-3]\nn4\nn[2-4]\n".as_bytes(), &options)
+        {
+            Err(NodeSetParseError::Line(3, e)) => {
+                assert_eq!(
+                    e.to_string(),
+                    "duplicate node: 'n2' (overlapping nodes: 'n[2-4]')"
+                );
+            }
+            e => panic!("unexpected result {e:?}"),
+        }
+
+        // Duplicates are found across folds of the input
+        let input = (0..5000).map(|i| format!("n{i}\n")).collect::<String>() + "login\nn4100\n";
+        match NodeSet::<IdRangeTree>::from_reader_with(input.as_bytes(), &options) {
+            Err(NodeSetParseError::Line(5002, e)) => {
+                assert_eq!(e.to_string(), "duplicate node: 'n4100'");
+            }
+            e => panic!("unexpected result {e:?}"),
+        }
+        assert!(matches!(
+            NodeSet::<IdRangeList>::from_reader_with("login\nlogin\n".as_bytes(), &options),
+            Err(NodeSetParseError::Line(2, _))
+        ));
+        assert_eq!(
+            NodeSet::<IdRangeList>::from_reader_with(
+                &input.as_bytes()[..input.len() - 6],
+                &options
+            )
+            .unwrap()
+            .to_string(),
+            "login,n[0-4999]"
+        );
+    }
+
+    #[cfg(feature = "ip")]
+    #[test]
+    fn test_nodeset_cidr() {
+        let ns: NodeSet = NodeSet::from_cidr("10.0.0.0/24").unwrap();
+        assert_eq!(ns, "10.0.0.[0-255]".parse().unwrap());
+        assert_eq!(ns.to_cidrs(), vec!["10.0.0.0/24"]);
+
+        let ns: NodeSet = NodeSet::from_cidr("192.168.4.0/22").unwrap();
+        assert_eq!(ns.to_string(), "192.168.[4-7].[0-255]");
+        assert_eq!(ns.to_cidrs(), vec!["192.168.4.0/22"]);
+
+        assert_eq!(
+            NodeSet::<IdRangeList>::from_cidr("10.1.2.3/32")
+                .unwrap()
+                .to_string(),
+            "10.1.2.3"
+        );
+        assert_eq!(
+            NodeSet::<IdRangeList>::from_cidr("0.0.0.0/0")
+                .unwrap()
+                .len(),
+            1 << 32
+        );
+        assert!(NodeSet::<IdRangeList>::from_cidr("10.0.0.1/24").is_err());
+        assert!(NodeSet::<IdRangeList>::from_cidr("10.0.0.0/33").is_err());
+        assert!(NodeSet::<IdRangeList>::from_cidr("10.0.0.0").is_err());
+
+        // Non-aligned ranges are split into minimal blocks
+        let ns: NodeSet = "10.0.0.[3-17]".parse().unwrap();
+        assert_eq!(
+            ns.to_cidrs(),
+            vec!["10.0.0.3/32", "10.0.0.4/30", "10.0.0.8/29", "10.0.0.16/31"]
+        );
+
+        let ns: NodeSet = "10.0.0.255,10.0.1.0,node1,10.0.[2-3].[0-255]"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            ns.to_cidrs(),
+            vec!["10.0.0.255/32", "10.0.1.0/32", "10.0.2.0/23", "node1"]
+        );
+
+        // Zero-padded octets are not IPv4 addresses
+        let ns: NodeSet = "10.0.0.[01-02]".parse().unwrap();
+        assert_eq!(ns.to_cidrs(), vec!["10.0.0.[01-02]"]);
+        let ns: NodeSet = "10.0.0.[0,00,1-2,255-256]".parse().unwrap();
+        assert_eq!(
+            ns.to_cidrs(),
+            vec![
+                "10.0.0.0/31",
+                "10.0.0.2/32",
+                "10.0.0.255/32",
+                "10.0.0.[00,256]"
+            ]
+        );
+
+        // Names which need quoting are kept as they are
+        let ns: NodeSet = r#""node[1]",10.0.0.1,"a b"[1-2]"#.parse().unwrap();
+        assert_eq!(
+            ns.to_cidrs(),
+            vec![
+                "10.0.0.1/32".to_string(),
+                ns.difference(&"10.0.0.1".parse().unwrap()).to_string()
+            ]
+        );
     }
 
     #[test]
-    fn test_nodeset_empty() {
-        let id1: NodeSet<IdRangeList> = "".parse().unwrap();
-        assert!(id1.is_empty());
+    fn test_nodeset_len_u64() {
+        let ns: NodeSet = "a[0-65535]b[0-65535]c[0-65535]d[0-65534]".parse().unwrap();
+        assert_eq!(ns.len_u64(), u64::MAX - (1 << 48) + 1);
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(ns.len(), usize::MAX - (1 << 48) + 1);
 
-        let id1: NodeSet<IdRangeList> = "x[1-2],b".parse().unwrap();
-        let id2: NodeSet<IdRangeList> = "x[3-4],c".parse().unwrap();
+        // Adding nodes to a set close to the maximum saturates the count
+        let ns = ns.union(&"x[0-65535]y[0-65535]z[0-65535]".parse().unwrap());
+        assert_eq!(ns.len_u64(), u64::MAX);
+        assert_eq!(ns.len(), usize::MAX);
 
-        assert!(id1.intersection(&id2).is_empty());
+        let ns: NodeSet = "a[0-65535]b[0-65535]c[0-65535]d[0-65535]".parse().unwrap();
+        assert_eq!(ns.len_u64(), u64::MAX);
+        assert_eq!(ns.prefix_counts(), vec![("a".to_string(), u64::MAX)]);
+
+        assert_eq!("n[1-10],m".parse::<NodeSet>().unwrap().len_u64(), 11);
     }
 
     #[test]
-    fn test_nodeset_parse() {
-        let id1: NodeSet<IdRangeList> = "x[1-10/2,5]y[1-7]z3,x[1-10/2,5]y[1-7]z2".parse().unwrap();
-        let id2: NodeSet<IdRangeList> = "x[2-5]y7z[2,3]".parse().unwrap();
+    fn test_nodeset_prefix_counts() {
+        let ns: NodeSet = "compute[0-511],login[0-3]".parse().unwrap();
+        assert_eq!(
+            ns.prefix_counts(),
+            vec![("compute".to_string(), 512), ("login".to_string(), 4)]
+        );
 
-        assert_eq!(id1.to_string(), "x[1,3,5,7,9]y[1-7]z[2-3]");
-        assert_eq!(id2.to_string(), "x[2-5]y7z[2-3]");
-        assert_eq!(id1.intersection(&id2).to_string(), "x[3,5]y7z[2-3]");
+        let ns: NodeSet = "login,r[1-2]n[1-10],r3,1-3".parse().unwrap();
+        assert_eq!(
+            ns.prefix_counts(),
+            vec![
+                ("".to_string(), 3),
+                ("login".to_string(), 1),
+                ("r".to_string(), 21)
+            ]
+        );
+
+        assert!(NodeSet::<IdRangeList>::new().prefix_counts().is_empty());
     }
 
     #[test]
-    fn test_rangeset_parse() {
-        let id1: NodeSet<IdRangeList> = "12,3".parse().unwrap();
-        let id2: NodeSet<IdRangeList> = "0-10/2,3".parse().unwrap();
+    fn test_nodeset_bounds() {
+        let ns: NodeSet = "node[3-7,20],host[0-0]".parse().unwrap();
+        assert_eq!(
+            ns.bounds(),
+            vec![("host".to_string(), 0, 0), ("node".to_string(), 3, 20)]
+        );
 
-        assert_eq!(id1.to_string(), "3,12");
-        assert_eq!(id2.to_string(), "0,2-4,6,8,10");
-        assert_eq!(id1.intersection(&id2).to_string(), "3");
+        let ns: NodeSet<IdRangeTree> = "login,r[2-3]n[1-10],r[1,9]b1,r12,5-8".parse().unwrap();
+        assert_eq!(
+            ns.bounds(),
+            vec![("".to_string(), 5, 8), ("r".to_string(), 1, 12)]
+        );
 
-        assert_eq!(parse_to_vec("[1,2]").unwrap(), vec!["1", "2"]);
+        let mut ns: NodeSet = "a1b[1-2],a[3-4]b[3-4]".parse().unwrap();
+        ns.try_fold_dimensions(1).unwrap();
+        assert_eq!(ns.bounds(), vec![("a".to_string(), 1, 4)]);
+
+        // Zero-padded ids are compared by value
+        for input in ["n[05,9]", "n[5,009]", "n[0009,5-7]", "n[5,07,9]"] {
+            let ns: NodeSet = input.parse().unwrap();
+            assert_eq!(ns.bounds(), vec![("n".to_string(), 5, 9)], "{input}");
+            let ns: NodeSet<IdRangeTree> = input.parse().unwrap();
+            assert_eq!(ns.bounds(), vec![("n".to_string(), 5, 9)], "{input}");
+        }
+
+        assert!(NodeSet::<IdRangeList>::new().bounds().is_empty());
+        assert!("login".parse::<NodeSet>().unwrap().bounds().is_empty());
     }
 
     #[test]
-    fn test_rangeset_parse_affix() {
-        assert_eq!(parse_to_vec("50[01,20]").unwrap(), vec!["5001", "5020"]);
-        assert_eq!(parse_to_vec("[01,2]50").unwrap(), vec!["0150", "250"]);
+    fn test_nodeset_shift_ids() {
+        let shift = |ns: &str, prefix: &str, delta: i64| {
+            ns.parse::<NodeSet>()
+                .unwrap()
+                .shift_ids(prefix, delta)
+                .map(|ns| ns.to_string())
+        };
+
+        assert_eq!(shift("node[1-10]", "node", 100).unwrap(), "node[101-110]");
+        assert_eq!(shift("node[101-110]", "node", -100).unwrap(), "node[1-10]");
+        assert_eq!(shift("node[001-010]", "node", 5).unwrap(), "node[006-015]");
+        assert_eq!(shift("node[098-099]", "node", 2).unwrap(), "node[100-101]");
         assert_eq!(
-            parse_to_vec("05[1,10]05").unwrap(),
-            vec!["051005", "05105",]
+            shift("node[06-07]", "node", 1000).unwrap(),
+            "node[1006-1007]"
         );
-        assert!(parse_to_vec("05[1,10]05[0]").is_err());
-        assert!(parse_to_vec("[1][0]").is_err());
+        assert_eq!(shift("node[08-12]", "node", -5).unwrap(), "node[5-7,03-04]");
+        assert_eq!(
+            shift("r[1-2]n[1-4],r3n[1-4],rack,login1", "r", 10).unwrap(),
+            "login1,r[11-13]n[1-4],rack"
+        );
+        assert_eq!(
+            shift("node[1-4],gpu[1-2]", "gpu", 3).unwrap(),
+            "gpu[4-5],node[1-4]"
+        );
+        assert_eq!(shift("node[1-4]", "gpu", -10).unwrap(), "node[1-4]");
+        // node05 and node5 are both renamed to node15
+        assert_eq!(shift("node[05,5]", "node", 10).unwrap(), "node15");
+
+        match shift("node[3-8]", "node", -4) {
+            Err(NodeSetParseError::IdShift { id, delta }) => {
+                assert_eq!((id.as_str(), delta), ("3", -4));
+            }
+            e => panic!("Expected IdShift error, got {e:?}"),
+        }
+        assert!(shift("node3000000000", "node", 1_000_000_000).is_err());
+        assert!(shift("node1", "node", i64::MIN).is_err());
     }
 
     #[test]
-    fn test_rangeset_nodeset_parse() {
-        assert_eq!(parse_to_vec("1,2a").unwrap(), vec!["1", "2a"]);
+    fn test_nodeset_fold_dimensions() {
+        let input = "a[1-2]b[1-2]c[1-2],a3b1c1";
+        let mut ns: NodeSet<IdRangeList> = input.parse().unwrap();
+        assert_eq!(ns.to_string(), "a[1-2]b[1-2]c[1-2],a3b1c1");
 
-        assert_eq!(parse_to_vec("1-2a").unwrap(), vec!["1-2a"]);
+        ns.try_fold_dimensions(2).unwrap();
+        assert_eq!(ns.to_string(), "a1b[1-2]c[1-2],a2b[1-2]c[1-2],a3b1c1");
+        assert_eq!(ns.len(), 9);
 
-        assert_eq!(parse_to_vec("0-4/20a").unwrap(), vec!["0-4/20a"]);
+        ns.try_fold_dimensions(1).unwrap();
         assert_eq!(
-            parse_to_vec("0-4/2,0a").unwrap(),
-            vec!["0", "0a", "2", "4",]
+            ns.to_string(),
+            "a1b1c[1-2],a1b2c[1-2],a2b1c[1-2],a2b2c[1-2],a3b1c1"
         );
+        assert_eq!(ns.len(), 9);
+
+        ns.try_fold_dimensions(3).unwrap();
+        assert_eq!(ns.to_string(), "a[1-2]b[1-2]c[1-2],a3b1c1");
+
+        assert!(matches!(
+            ns.try_fold_dimensions(0),
+            Err(NodeSetParseError::FoldDimensions(0))
+        ));
+
+        for (max_fold_dimensions, expected) in [
+            (1, "a1b1c[1-2],a1b2c[1-2],a2b1c[1-2],a2b2c[1-2],a3b1c1"),
+            (2, "a1b[1-2]c[1-2],a2b[1-2]c[1-2],a3b1c1"),
+            (usize::MAX, "a[1-2]b[1-2]c[1-2],a3b1c1"),
+        ] {
+            let options = ParseOptions {
+                max_fold_dimensions,
+                ..Default::default()
+            };
+            let ns = NodeSet::<IdRangeList>::parse_with(input, &options).unwrap();
+            assert_eq!(ns.to_string(), expected);
+
+            let ns = NodeSet::<IdRangeTree>::from_reader_with(
+                input.replace(',', "\n").as_bytes(),
+                &options,
+            )
+            .unwrap();
+            assert_eq!(ns.to_string(), expected);
+        }
+
+        // Names with fewer dimensions than the limit are folded as usual
+        let mut ns: NodeSet<IdRangeList> = "n[1-4],r[1-2]n[1-2]".parse().unwrap();
+        ns.try_fold_dimensions(1).unwrap();
+        assert_eq!(ns.to_string(), "n[1-4],r1n[1-2],r2n[1-2]");
+
+        let options = ParseOptions {
+            max_fold_dimensions: 0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            NodeSet::<IdRangeList>::parse_with("a1b1", &options),
+            Err(NodeSetParseError::FoldDimensions(0))
+        ));
     }
 
     #[test]
-    fn test_nodeset_parse_with_suffix_component() {
-        assert_eq!(parse_to_fold("a[1-3]_e,a4_e").unwrap(), "a[1-4]_e");
+    fn test_nodeset_prefixes() {
+        let ns: NodeSet = "login,r[1-2]n[1-10],r3,1-3,compute[1-4]".parse().unwrap();
+        assert_eq!(ns.prefixes(), vec!["", "compute", "login", "r"]);
+        assert_eq!(ns.count_for_prefix("r"), 21);
+        assert_eq!(ns.count_for_prefix(""), 3);
+        assert_eq!(ns.count_for_prefix("login"), 1);
+        assert_eq!(ns.count_for_prefix("compute"), 4);
+        assert_eq!(ns.count_for_prefix("comp"), 0);
+        for node in ns.iter() {
+            let prefix = node.parse::<Node>().unwrap().prefix().to_string();
+            assert!(ns.prefixes().contains(&prefix), "{node}");
+        }
+        assert_eq!("2".parse::<Node>().unwrap().prefix(), "");
+
+        let mut ns: NodeSet = "a[0-3],b[0-3]".parse().unwrap();
+        assert!(ns.remove_prefix("a"));
+        assert_eq!(ns.to_string(), "b[0-3]");
+        assert!(!ns.remove_prefix("c"));
+        assert_eq!(ns.to_string(), "b[0-3]");
+
+        let mut ns: NodeSet = "b[0-3],a[0-3],a2b[1-2],1-3".parse().unwrap();
+        let copy = ns.clone();
+        ns.keep_only_prefix("a");
+        assert_eq!(ns.to_string(), "a[0-3],a2b[1-2]");
         assert_eq!(
-            parse_to_vec("a[1-3]_e,a4_e").unwrap(),
-            vec!["a1_e", "a2_e", "a3_e", "a4_e"]
+            ns.display_ordered(&ParseOptions::default(), PrefixOrder::Insertion)
+                .to_string(),
+            "a[0-3],a2b[1-2]"
         );
+        assert_eq!(copy.to_string(), "1-3,a[0-3],a2b[1-2],b[0-3]");
+        ns.keep_only_prefix("");
+        assert!(ns.is_empty());
+
+        assert!(NodeSet::<IdRangeList>::new().prefixes().is_empty());
+    }
+
+    #[test]
+    fn test_nodeset_prefix_order() {
+        let options = ParseOptions::default();
+        let ns: NodeSet = "n2y1,n10x[1-2],n1z1,n[1,10,2]".parse().unwrap();
+        let display = |ns: &NodeSet, order| ns.display_ordered(&options, order).to_string();
 
+        assert_eq!(display(&ns, PrefixOrder::Lexical), ns.to_string());
+        assert_eq!(ns.to_string(), "n[1-2,10],n10x[1-2],n2y1,n1z1");
         assert_eq!(
-            parse_to_fold("a[10-11]b[2-3]_cd").unwrap(),
-            "a[10-11]b[2-3]_cd"
+            display(&ns, PrefixOrder::Numeric),
+            "n[1-2,10],n1z1,n2y1,n10x[1-2]"
         );
         assert_eq!(
-            parse_to_vec("a[10-11]b[2-3]_cd").unwrap(),
-            vec!["a10b2_cd", "a10b3_cd", "a11b2_cd", "a11b3_cd"]
+            display(&ns, PrefixOrder::Insertion),
+            "n2y1,n10x[1-2],n1z1,n[1-2,10]"
         );
-    }
-
-    #[test]
-    fn test_nodeset_parse_with_affix_id() {
         assert_eq!(
-            parse_to_vec("a10[1-3]").unwrap(),
-            vec!["a101", "a102", "a103"]
+            ns.iter_ordered(PrefixOrder::Numeric).collect::<Vec<_>>(),
+            vec!["n1", "n2", "n10", "n1z1", "n2y1", "n10x1", "n10x2"]
         );
-
         assert_eq!(
-            parse_to_vec("a[1-3]10").unwrap(),
-            vec!["a110", "a210", "a310"]
+            ns.iter_ordered(PrefixOrder::Insertion).collect::<Vec<_>>(),
+            vec!["n2y1", "n10x1", "n10x2", "n1z1", "n1", "n2", "n10"]
         );
 
+        // Insertion order is kept by set operations
+        let other: NodeSet = "b1,n1z1,a1".parse().unwrap();
         assert_eq!(
-            parse_to_vec("a10[1-3]10").unwrap(),
-            vec!["a10110", "a10210", "a10310"]
+            display(&ns.union(&other), PrefixOrder::Insertion),
+            "n2y1,n10x[1-2],n1z1,n[1-2,10],b1,a1"
         );
-
         assert_eq!(
-            parse_to_vec("a00[1-3]00").unwrap(),
-            vec!["a00100", "a00200", "a00300"]
+            display(&ns.difference(&other), PrefixOrder::Insertion),
+            "n2y1,n10x[1-2],n[1-2,10]"
         );
-
         assert_eq!(
-            parse_to_vec("a20[0-100/20]5").unwrap(),
-            vec!["a2005", "a201005", "a20205", "a20405", "a20605", "a20805"]
+            display(&other.symmetric_difference(&ns), PrefixOrder::Insertion),
+            "b1,a1,n2y1,n10x[1-2],n[1-2,10]"
+        );
+        let mut out = NodeSet::new();
+        other.intersection_into(&ns, &mut out);
+        assert_eq!(display(&out, PrefixOrder::Insertion), "n1z1");
+        other.union_into(&ns, &mut out);
+        assert_eq!(
+            display(&out, PrefixOrder::Insertion),
+            "b1,n1z1,a1,n2y1,n10x[1-2],n[1-2,10]"
         );
 
+        // Insertion order is not taken into account for equality
+        assert_eq!(ns, "n[1,10,2],n1z1,n10x[1-2],n2y1".parse().unwrap());
+
         assert_eq!(
-            parse_to_vec("a02[0-100/20]05").unwrap(),
-            vec!["a02005", "a0210005", "a022005", "a024005", "a026005", "a028005",]
+            "numeric".parse::<PrefixOrder>().unwrap(),
+            PrefixOrder::Numeric
         );
+        assert!("natural".parse::<PrefixOrder>().is_err());
     }
 
     #[test]
-    fn test_nodeset_intersect() {
-        let id1: NodeSet<IdRangeList> = "x[1-10/2,5]y[1-7]z3,x[1-10/2,5]y[1-7]z2".parse().unwrap();
-        let id2: NodeSet<IdRangeList> = "x[2-5]y7z[2,3]".parse().unwrap();
+    fn test_natural_cmp() {
+        use std::cmp::Ordering::*;
 
-        assert_eq!(id1.intersection(&id2).fold().to_string(), "x[3,5]y7z[2-3]");
+        assert_eq!(natural_cmp("n2", "n10"), Less);
+        assert_eq!(natural_cmp("n10", "n2"), Greater);
+        assert_eq!(natural_cmp("n10", "n10"), Equal);
+        assert_eq!(natural_cmp("n1a", "n1b"), Less);
+        assert_eq!(natural_cmp("n02", "n2"), Greater);
+        assert_eq!(natural_cmp("n02", "n10"), Less);
+        assert_eq!(natural_cmp("n", "n1"), Less);
+        assert_eq!(natural_cmp("a10", "b1"), Less);
+    }
 
-        let id1: NodeSet<IdRangeList> = "a1 a2".parse().unwrap();
-        let id2: NodeSet<IdRangeList> = "b1 b2".parse().unwrap();
+    #[test]
+    fn test_nodeset_into_iter() {
+        for ns in [
+            "",
+            "login",
+            "node[1-3,7]",
+            "node[08-11]",
+            "1-3,5",
+            "a[1-2]b[3-4]c[5-6],a3b[1,3]c1,x[1-2]y,login",
+            "r[1-2]n[1-3]-ib,r5n1",
+        ] {
+            let ns: NodeSet<IdRangeList> = ns.parse().unwrap();
+            let expected: Vec<_> = ns.iter().collect();
 
-        assert!(id1.intersection(&id2).is_empty(),);
+            assert_eq!(ns.clone().into_iter().collect::<Vec<_>>(), expected);
+
+            // Sets shared with another NodeSet are left untouched
+            let shared = ns.clone();
+            assert_eq!(shared.into_iter().collect::<Vec<_>>(), expected);
+            assert_eq!(ns.iter().collect::<Vec<_>>(), expected);
+        }
     }
 
     #[test]
-    fn test_nodeset_overflow() {
-        assert!(parse_to_vec("a10000000000").is_err());
-        assert!(parse_to_vec("a[9999999999-10000000000]").is_err());
-        assert_eq!(parse_to_vec("a3183856184").unwrap(), vec!["a3183856184"]);
-        assert!(parse_to_vec("a03183856185").is_err());
-        assert!(parse_to_vec("a[3183856184-3183856185]").is_err());
-        assert!(parse_to_vec("a00000000000").is_err());
-        assert_eq!(parse_to_vec("a0000000000").unwrap(), vec!["a0000000000"]);
-        assert_eq!(
-            parse_to_vec("a[3183856183-3183856184]").unwrap(),
-            vec!["a3183856183", "a3183856184"]
-        );
-        assert_eq!(
-            parse_to_vec("a[3183856170-3183856184/10]").unwrap(),
-            vec!["a3183856170", "a3183856180"]
-        );
+    fn test_nodeset_gap_count() {
+        for (ns, gaps) in [
+            ("", 0),
+            ("login", 0),
+            ("node[1-10]", 0),
+            ("node[1-4,6],gpu[1-2]", 1),
+            ("node[1-2,4,6-7,9]", 3),
+            ("node[08-11]", 0),
+            ("node[8-9,00-01]", 1),
+            ("1-3,5", 1),
+            ("r[1-2]n[1-4]", 0),
+            ("r[1-2]n[1-2,4]", 1),
+            ("r[1,3]n[1-2,4]", 2),
+            ("r1n[1-2],r2n[3-4]", 1),
+            ("r1n[1-2],r2n[3,5]", 2),
+        ] {
+            let ns: NodeSet<IdRangeList> = ns.parse().unwrap();
+            assert_eq!(ns.gap_count(), gaps, "{ns}");
+            assert_eq!(ns.is_contiguous(), gaps == 0, "{ns}");
+        }
+    }
+
+    #[test]
+    fn test_nodeset_range_count() {
+        for (ns, ranges) in [
+            ("", 0),
+            ("login", 1),
+            ("node[0-999]", 1),
+            ("node[0,2,4]", 3),
+            ("node[1-4,6],gpu[1-2]", 3),
+            ("node[8-9,00-01]", 2),
+            ("1-3,5", 2),
+            ("r[1-2]n[1-4]", 1),
+            ("r[1,3]n[1-2,4]", 4),
+            ("r1n[1-2],r2n[3,5]", 3),
+        ] {
+            let parsed: NodeSet<IdRangeList> = ns.parse().unwrap();
+            assert_eq!(parsed.range_count(), ranges, "{ns}");
+            assert_eq!(parsed.split_contiguous().count(), ranges, "{ns}");
+        }
+    }
+
+    #[test]
+    fn test_nodeset_canonical_string() {
+        use std::collections::{BTreeSet, HashSet};
 
+        let folded: NodeSet<IdRangeList> = "r[1-2]n[1-4],node[1-3]".parse().unwrap();
+        let mut limited = folded.clone();
+        limited.try_fold_dimensions(1).unwrap();
+        let built: NodeSet<IdRangeList> = "node3,r2n[1-4],node[1-2],r1n[4,1-3]".parse().unwrap();
+
+        assert_eq!(limited.to_string(), "node[1-3],r1n[1-4],r2n[1-4]");
+        for ns in [&folded, &limited, &built] {
+            assert_eq!(ns.canonical_string(), "node[1-3],r[1-2]n[1-4]");
+        }
+
+        assert_eq!(folded, built);
+        assert_ne!(folded, limited);
+        assert_eq!(HashSet::from([&folded, &built]).len(), 1);
+        assert_eq!(BTreeSet::from([&folded, &limited, &built]).len(), 2);
+
+        let mut sorted: Vec<NodeSet<IdRangeTree>> = ["node2", "login", "node[1-2]"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        sorted.sort();
         assert_eq!(
-            parse_to_vec("a[3183856170-3183856184/2000000000]").unwrap(),
-            vec!["a3183856170"]
+            sorted.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+            vec!["login", "node2", "node[1-2]"]
         );
-        assert!(parse_to_vec("a[3183856170-3183856184/20000000000]").is_err(),);
     }
 
     #[test]
-    fn test_nodeset_overflow_affix() {
-        assert!(parse_to_vec("a10000[0,1]00000").is_err());
-        assert!(parse_to_vec("a00000[0,1]00000").is_err());
+    fn test_nodeset_split_all_merge() {
+        for input in [
+            "node[1-10]",
+            "node[001-010],login[1-2]",
+            "r[1-2]n[1-4],r3n[1,3]",
+            "a[1-2]b[1-2]c[1-2],a3b1c1",
+            "x[1-3]y[01-02]z[8-11],n[1-4],login",
+        ] {
+            let folded: NodeSet<IdRangeList> = input.parse().unwrap();
+            let mut ns = folded.clone();
+
+            ns.split_all();
+            assert_eq!(ns.len(), folded.len(), "{input}");
+            assert!(ns.iter().eq(folded.iter()), "{input}");
+            ns.merge();
+            assert_eq!(ns.to_string(), folded.to_string(), "{input}");
+            assert_eq!(ns, folded, "{input}");
+
+            let mut ns: NodeSet<IdRangeTree> = input.parse().unwrap();
+            ns.split_all().merge();
+            assert_eq!(ns.to_string(), folded.to_string(), "{input}");
+        }
+
+        let mut ns: NodeSet<IdRangeList> = "r[1-2]n[1-2]".parse().unwrap();
+        let shared = ns.clone();
+        ns.split_all();
+        assert_eq!(ns.to_string(), "r1n1,r1n2,r2n1,r2n2");
+        assert_eq!(shared.to_string(), "r[1-2]n[1-2]");
+
+        // Nodesets sharing the split sets are merged as well
+        let mut union = ns.union(&"login".parse().unwrap());
+        union.merge();
+        assert_eq!(union.to_string(), "login,r[1-2]n[1-2]");
+
+        ns.try_fold_dimensions(1).unwrap();
+        ns.merge();
+        assert_eq!(ns.to_string(), "r[1-2]n[1-2]");
+    }
+
+    #[test]
+    fn test_nodeset_iter_ids() {
+        let ns: NodeSet<IdRangeList> = "node[0-2]".parse().unwrap();
         assert_eq!(
-            parse_to_vec("n000000000[0-0]").unwrap(),
-            vec!["n0000000000"]
+            ns.iter_ids().collect::<Vec<_>>(),
+            vec![("node", 0), ("node", 1), ("node", 2)]
         );
-        assert!(parse_to_vec("n000000000[0-10]").is_err());
+
+        let ns: NodeSet<IdRangeTree> = "n[08-10]p,login,r[1-2]n[1-2],[3-4]".parse().unwrap();
         assert_eq!(
-            parse_to_fold("n00000000[0-10]").unwrap(),
-            "n[000000000-000000009,0000000010]"
+            ns.iter_ids().collect::<Vec<_>>(),
+            vec![("", 3), ("", 4), ("n", 8), ("n", 9), ("n", 10)]
         );
 
+        let nodes: Vec<_> = ns
+            .iter_node_ids()
+            .map(|node| (node.prefix, node.ids))
+            .collect();
+        assert_eq!(nodes.len(), ns.len());
+        assert_eq!(nodes[2], ("login", vec![]));
+        assert_eq!(nodes[3], ("n", vec![8]));
+        assert_eq!(nodes[6], ("r", vec![1, 1]));
+        assert_eq!(nodes[9], ("r", vec![2, 2]));
+    }
+
+    #[test]
+    fn test_nodeset_try_fold_string() {
+        let ns: NodeSet<IdRangeList> = "r[1-2]n[1-4],login,node[1-3]".parse().unwrap();
+        let s = ns.to_string();
+
+        assert_eq!(ns.try_fold_string(s.len()).unwrap(), s);
+        assert_eq!(ns.try_fold_string(usize::MAX).unwrap(), s);
+        for max_bytes in [0, 5, s.len() - 1] {
+            assert_eq!(
+                ns.try_fold_string(max_bytes),
+                Err(FormatTooLarge { max_bytes })
+            );
+        }
         assert_eq!(
-            parse_to_fold("n31838561[0-84]").unwrap(),
-            "n[318385610-318385619,3183856110-3183856184]"
+            NodeSet::<IdRangeList>::new().try_fold_string(0).unwrap(),
+            ""
         );
 
-        assert_eq!("a318385[0-61]84".parse::<NodeSet>().unwrap().len(), 62);
-        assert!(parse_to_fold("a318385[0-62]84").is_err());
-        assert!(parse_to_fold("a318385[0-61]85").is_err());
-
-        assert_eq!("a[3183855-3183856]84".parse::<NodeSet>().unwrap().len(), 2);
+        let large: NodeSet<IdRangeList> = (0..10_000)
+            .map(|i| format!("n{}", i * 2))
+            .join(",")
+            .parse()
+            .unwrap();
         assert_eq!(
-            parse_to_vec("a[31838560-31838561]84").unwrap(),
-            vec!["a3183856084", "a3183856184"]
+            large.try_fold_string(1024).unwrap_err().to_string(),
+            "nodeset representation exceeds 1024 bytes"
         );
     }
 
     #[test]
-    fn test_nodeset_symmetric_diff() {
-        let id1: NodeSet<IdRangeList> = "x[1-10/2,5]y[1-7]z3,x[1-10/2,5]y[1-7]z2".parse().unwrap();
-        let id2: NodeSet<IdRangeList> = "x[2-5]y7z[2,3]".parse().unwrap();
+    fn test_nodeset_debug() {
+        for (ns, debug) in [
+            ("", "NodeSet { prefixes: {} }"),
+            ("1-3,5", r#"NodeSet { prefixes: {"": [1-3, 5]} }"#),
+            (
+                "node[08-11]-ib,node[1-2]",
+                r#"NodeSet { prefixes: {"node": [1-2], "node[]-ib": [8-11 pad=2]} }"#,
+            ),
+            (
+                "r[1-2]n[1-4],r3n[1-2]",
+                r#"NodeSet { prefixes: {"r[]n": [[1-2] x [1-4], [3] x [1-2]]} }"#,
+            ),
+        ] {
+            let list: NodeSet<IdRangeList> = ns.parse().unwrap();
+            let tree: NodeSet<IdRangeTree> = ns.parse().unwrap();
+            assert_eq!(format!("{list:?}"), debug);
+            assert_eq!(format!("{tree:?}"), debug);
+        }
 
+        let ns: NodeSet<IdRangeList> = "node[00-04,10]".parse().unwrap();
         assert_eq!(
-            id1.symmetric_difference(&id2).fold().to_string(),
-            "x[1,7,9]y[1-7]z[2-3],x[2,4]y7z[2-3],x[3,5]y[1-6]z[2-3]"
+            format!("{ns:#?}"),
+            "NodeSet {\n    prefixes: {\n        \"node\": [0-4 pad=2, 10],\n    },\n}"
         );
-
-        let id1: NodeSet<IdRangeList> = "a1 b1 a2".parse().unwrap();
-        let id2: NodeSet<IdRangeList> = "b1 a2 a3".parse().unwrap();
-
-        assert_eq!(id1.symmetric_difference(&id2).to_string(), "a[1,3]");
+        assert_eq!(ns.to_string(), "node[00-04,10]");
     }
 
     #[test]
-    fn test_nodeset_len() {
-        let id1: NodeSet<IdRangeList> = "a b".parse().unwrap();
-        let id2: NodeSet<IdRangeList> = "a[0-9]b".parse().unwrap();
-        let id3: NodeSet<IdRangeList> = "a[0-9]b[0-8]".parse().unwrap();
-        let id4: NodeSet<IdRangeList> = "a[0-10000] b[0-100]".parse().unwrap();
-        let id5: NodeSet<IdRangeList> = "a[00-20]".parse().unwrap();
+    fn test_nodeset_split_contiguous() {
+        for (ns, ranges) in [
+            ("", vec![]),
+            ("login", vec!["login"]),
+            ("node[0-2,5-6]", vec!["node[0-2]", "node[5-6]"]),
+            ("node[8-9,00-01]", vec!["node[8-9]", "node[00-01]"]),
+            ("x1,a[1,3]", vec!["a1", "a3", "x1"]),
+            ("1-3,5", vec!["1-3", "5"]),
+            ("r[1-2]n[1-4]", vec!["r[1-2]n[1-4]"]),
+            (
+                "r[1,3]n[1-2,4]",
+                vec!["r1n[1-2]", "r1n4", "r3n[1-2]", "r3n4"],
+            ),
+        ] {
+            let parsed: NodeSet<IdRangeList> = ns.parse().unwrap();
+            let split: Vec<_> = parsed.split_contiguous().collect();
 
-        assert_eq!(id1.len(), 2);
-        assert_eq!(id2.len(), 10);
-        assert_eq!(id3.len(), 90);
-        assert_eq!(id4.len(), 10102);
-        assert_eq!(id5.len(), 21);
-    }
+            assert_eq!(
+                split.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+                ranges,
+                "{ns}"
+            );
+            assert!(split.iter().all(|s| s.is_contiguous()), "{ns}");
+            assert_eq!(NodeSet::union_all(&split), parsed, "{ns}");
+        }
 
-    #[test]
-    fn test_nodeset_fold() {
-        assert_eq!(
-            parse_to_fold("a[1-10/2,5]b[1-7]c3,a[1-10/2,5]b[1-7]c2").unwrap(),
-            "a[1,3,5,7,9]b[1-7]c[2-3]"
-        );
-        assert_eq!(
-            parse_to_fold("a[0-10]b[0-10],a[0-20]b[0-10]").unwrap(),
-            "a[0-20]b[0-10]"
-        );
+        let ns: NodeSet<IdRangeTree> = "x1,a[1,3]".parse().unwrap();
         assert_eq!(
-            parse_to_fold("x[0-10]y[0-10],x[8-18]y[8-18],x[11-18]y[0-7]").unwrap(),
-            "x[0-7]y[0-10],x[8-18]y[0-18]"
+            ns.split_contiguous_ordered(PrefixOrder::Insertion)
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>(),
+            vec!["x1", "a1", "a3"]
         );
     }
 
@@ -896,4 +5230,199 @@ mod tests {
             _ => panic!("Expected padding error, got {:?}", e),
         });
     }
+
+    #[test]
+    fn test_nodeset_inverted_range_error() {
+        for input in [
+            "node[5-3]",
+            "node[5-3,0-2]",
+            "node[0-2,9-4/2]",
+            "a[1-2]b[3-1]",
+            "5-3",
+            "[0-2,5-3]",
+        ] {
+            match input.parse::<NodeSet>() {
+                Err(NodeSetParseError::EmptyOrInvertedRange(_, _)) => {}
+                e => panic!("Expected inverted range error for '{input}', got {e:?}"),
+            }
+        }
+
+        match "node[0-2,10-09]".parse::<NodeSet>() {
+            Err(NodeSetParseError::EmptyOrInvertedRange(start, end)) => {
+                assert_eq!(start, "10");
+                assert_eq!(end, "09");
+            }
+            e => panic!("Expected inverted range error, got {e:?}"),
+        }
+
+        assert_eq!(parse_to_fold("node[3-3]").unwrap(), "node3");
+    }
+
+    #[test]
+    fn test_nodeset_from_sorted_unchecked() {
+        fn check<T>(input: &str)
+        where
+            T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+        {
+            let expected: NodeSet<T> = input.parse().unwrap();
+            let nodes: Vec<String> = expected.iter().collect();
+            let sorted = NodeSet::<T>::from_sorted_unchecked(&nodes).unwrap();
+            assert_eq!(sorted, expected, "{input}");
+            assert_eq!(sorted.to_string(), expected.to_string(), "{input}");
+            assert_eq!(sorted.len(), expected.len(), "{input}");
+        }
+
+        for input in [
+            "",
+            "node1",
+            "node[1-1000]",
+            "node[1-5,7,10-20/2],login[1-2]",
+            "node[01-10,100]",
+            "r[1-4]n[1-8]",
+            "r[1-2]n[1-2],r3n1",
+            "r[1-3]n[1-2],r[2-3]n[5-6]-ib",
+            "c[1-2]r[1-3]n[1-4],c3r1n[1-2]",
+            "x[1-2]y[1-2]z[1-2]w[1-2]",
+            "login,admin,node[1-3]",
+        ] {
+            check::<IdRangeList>(input);
+            check::<IdRangeTree>(input);
+        }
+
+        // Nodes with different names may be interleaved
+        let ns: NodeSet =
+            NodeSet::from_sorted_unchecked(["a1", "b1", "r1n1", "a2", "r1n2", "b3", "r2n1"])
+                .unwrap();
+        assert_eq!(ns.to_string(), "a[1-2],b[1,3],r1n[1-2],r2n1");
+
+        assert!(matches!(
+            NodeSet::<IdRangeList>::from_sorted_unchecked(["node[1-2]"]),
+            Err(NodeSetParseError::Generic(_))
+        ));
+
+        #[cfg(debug_assertions)]
+        for nodes in [
+            &["n1", "n3", "n2"][..],
+            &["n1", "n1"],
+            &["r1n1", "r2n1", "r1n2"],
+            &["r1n2", "r1n1"],
+            &["login", "login"],
+        ] {
+            assert!(
+                matches!(
+                    NodeSet::<IdRangeList>::from_sorted_unchecked(nodes),
+                    Err(NodeSetParseError::Unsorted(_))
+                ),
+                "{nodes:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_nodeset_bracket_errors() {
+        for (input, expected) in [
+            ("node[]", "empty brackets: '[]'"),
+            ("node[]-ib", "empty brackets: '[]-ib'"),
+            ("node[", "unclosed bracket: '['"),
+            ("node[1-2", "unclosed bracket: '[1-2'"),
+            ("a[1-2]b[3,", "unclosed bracket: '[3,'"),
+            ("node]", "unexpected closing bracket: ']'"),
+            ("node[1]]", "unexpected closing bracket: ']'"),
+            ("node1,]", "unexpected closing bracket: ']'"),
+        ] {
+            let err = input.parse::<NodeSet>().unwrap_err();
+            assert_eq!(err.to_string(), expected, "{input}");
+        }
+
+        assert!(matches!(
+            "node[]".parse::<NodeSet>(),
+            Err(NodeSetParseError::EmptyBracket(_))
+        ));
+        assert!(matches!(
+            "node[1".parse::<NodeSet>(),
+            Err(NodeSetParseError::UnclosedBracket(_))
+        ));
+        assert!(matches!(
+            "node]".parse::<NodeSet>(),
+            Err(NodeSetParseError::UnexpectedCloseBracket(_))
+        ));
+
+        let options = ParseOptions {
+            open: '{',
+            close: '}',
+            ..Default::default()
+        };
+        assert!(matches!(
+            NodeSet::<IdRangeList>::parse_with("node{}", &options),
+            Err(NodeSetParseError::EmptyBracket(s)) if s == "{}"
+        ));
+        assert!(matches!(
+            NodeSet::<IdRangeList>::parse_with("node{1-2", &options),
+            Err(NodeSetParseError::UnclosedBracket(s)) if s == "{1-2"
+        ));
+        assert!(matches!(
+            NodeSet::<IdRangeList>::parse_with("node}", &options),
+            Err(NodeSetParseError::UnexpectedCloseBracket(s)) if s == "}"
+        ));
+    }
+
+    #[test]
+    fn test_nodeset_eq_ignore_padding() {
+        let padded: NodeSet = "node[08-09]".parse().unwrap();
+        let unpadded: NodeSet = "node[8-9]".parse().unwrap();
+        assert_ne!(padded, unpadded);
+        assert!(padded.eq_ignore_padding(&unpadded));
+        assert!(unpadded.eq_ignore_padding(&padded));
+
+        let padded: NodeSet = "node[008,9,010]".parse().unwrap();
+        let unpadded: NodeSet = "node[8-10]".parse().unwrap();
+        assert_ne!(padded, unpadded);
+        assert!(padded.eq_ignore_padding(&unpadded));
+
+        let padded: NodeSet = "node[08,8]".parse().unwrap();
+        let unpadded: NodeSet = "node8".parse().unwrap();
+        assert!(padded.eq_ignore_padding(&unpadded));
+
+        let padded: NodeSet = "a[01-02]b[1-2],c".parse().unwrap();
+        let unpadded: NodeSet = "a[1-2]b[001-002],c".parse().unwrap();
+        assert_ne!(padded, unpadded);
+        assert!(padded.eq_ignore_padding(&unpadded));
+
+        let set: NodeSet = "node[08-09]".parse().unwrap();
+        assert!(set.eq_ignore_padding(&set));
+        assert!(!set.eq_ignore_padding(&"node[8-10]".parse().unwrap()));
+        assert!(!set.eq_ignore_padding(&"other[8-9]".parse().unwrap()));
+        assert!(!set.eq_ignore_padding(&"node[8-9],other".parse().unwrap()));
+        assert!(!set.eq_ignore_padding(&"node[8-9]-ib".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_nodeset_contains() {
+        let ns: NodeSet = "node[01-10],node[1-2],a[1-3]b[2-4]-ib,login,[5-7]"
+            .parse()
+            .unwrap();
+
+        for node in ["node05", "node1", "a2b4-ib", "login", "6"] {
+            assert!(ns.contains(node).unwrap(), "{node}");
+            let node: Node = node.parse().unwrap();
+            assert!(ns.contains_node(&node));
+        }
+
+        for node in [
+            "node5", "node3", "node011", "a2b5-ib", "a2b4", "login1", "8",
+        ] {
+            assert!(!ns.contains(node).unwrap(), "{node}");
+            let node: Node = node.parse().unwrap();
+            assert!(!ns.contains_node(&node));
+        }
+
+        assert!(!NodeSet::new().contains("node1").unwrap());
+        assert!(ns.contains("node[1-2]").is_err());
+        assert!(ns.contains("node[").is_err());
+        assert!("".parse::<Node>().is_err());
+
+        for node in ["node05", "a2b4-ib", "login", "6", "x1y", "x1y0002z"] {
+            assert_eq!(node.parse::<Node>().unwrap().to_string(), node);
+        }
+    }
 }
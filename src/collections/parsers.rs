@@ -4,7 +4,7 @@ use crate::{
     idrange::{AffixIdRangeStep, IdRange, IdRangeOffset, IdRangeStep, RangeStepError, SingleId},
     IdSet, NodeSet, NodeSetParseError,
 };
-use std::{convert::TryInto, fmt};
+use std::{borrow::Cow, convert::TryInto, fmt, num::IntErrorKind, sync::Arc};
 use winnow::{
     self,
     ascii::{digit1, multispace0, multispace1},
@@ -24,8 +24,7 @@ use crate::idrange::IdRangeList;
 use auto_enums::auto_enum;
 #[cfg(feature = "groups")]
 use winnow::{
-    combinator::{preceded, separated_pair, terminated},
-    error::ErrMode,
+    combinator::{separated_pair, terminated},
     token::literal,
 };
 
@@ -39,10 +38,29 @@ enum FormatError<'a> {
     #[error("mismatched padding: '{0}' and '{1}'")]
     MismatchedPadding(&'a str, &'a str),
 
-    /// A reference was made to a group source that does not exist.
-    #[cfg(feature = "groups")]
-    #[error("Unknown group source: '{0}'")]
-    Source(&'a str),
+    /// The start of a range is greater than its end (ie `[5-3]`).
+    #[error("empty or inverted range: '{0}-{1}'")]
+    EmptyOrInvertedRange(&'a str, &'a str),
+
+    /// An id or range does not fit in the supported id range (ie `[0-4294967296]`).
+    #[error("id out of range: '{0}'")]
+    IdOverflow(&'a str),
+
+    /// A quoted node name is invalid.
+    #[error("invalid quoted name '{0}': {1}")]
+    Quote(&'a str, &'static str),
+
+    /// Range delimiters hold no range (ie `node[]`).
+    #[error("empty brackets: '{0}'")]
+    EmptyBracket(&'a str),
+
+    /// A list of ranges is not closed (ie `node[1-2`).
+    #[error("unclosed bracket: '{0}'")]
+    UnclosedBracket(&'a str),
+
+    /// A closing delimiter does not match any opening one (ie `node]`).
+    #[error("unexpected closing bracket: '{0}'")]
+    UnexpectedCloseBracket(&'a str),
 
     /// An error occurred while parsing an integer.
     #[error("invalid range")]
@@ -52,13 +70,29 @@ enum FormatError<'a> {
     #[error("invalid integer")]
     ParseIntError(#[from] std::num::ParseIntError),
 
-    /// An index is out of range.
-    #[error("value out of range")]
-    OverFlow(#[from] std::num::TryFromIntError),
-
     /// An error occurred while executing an external command as specified in the dynamic configuration file.
     #[error("external command execution failed")]
     Command(#[from] std::io::Error),
+
+    /// A node appears more than once when duplicates are rejected, with
+    /// all the nodes appearing more than once.
+    #[error("duplicate node: '{0}'")]
+    DuplicateNode(String, String),
+
+    /// A group could not be resolved.
+    #[cfg(feature = "groups")]
+    #[error(transparent)]
+    Group(Box<NodeSetParseError>),
+
+    /// A group pattern does not match any group.
+    #[cfg(feature = "groups")]
+    #[error("no group matches '{0}'")]
+    NoMatchingGroup(&'a str),
+
+    /// A group term was found while group resolution is disabled.
+    #[cfg(feature = "groups")]
+    #[error("group resolution is disabled: '{0}'")]
+    GroupsDisabled(&'a str),
 }
 
 impl<'a> From<FormatError<'a>> for NodeSetParseError {
@@ -68,13 +102,40 @@ impl<'a> From<FormatError<'a>> for NodeSetParseError {
             FormatError::MismatchedPadding(a, b) => {
                 NodeSetParseError::MismatchedPadding(a.to_string(), b.to_string())
             }
+            FormatError::EmptyOrInvertedRange(a, b) => {
+                NodeSetParseError::EmptyOrInvertedRange(a.to_string(), b.to_string())
+            }
+            FormatError::IdOverflow(s) => NodeSetParseError::IdOverflow(s.to_string()),
+            FormatError::Quote(s, e) => NodeSetParseError::Quote(s.to_string(), e),
+            FormatError::EmptyBracket(s) => NodeSetParseError::EmptyBracket(s.to_string()),
+            FormatError::UnclosedBracket(s) => NodeSetParseError::UnclosedBracket(s.to_string()),
+            FormatError::UnexpectedCloseBracket(s) => {
+                NodeSetParseError::UnexpectedCloseBracket(s.to_string())
+            }
             FormatError::RangeError(e) => NodeSetParseError::RangeError(e),
             FormatError::ParseIntError(e) => NodeSetParseError::ParseIntError(e),
-            FormatError::OverFlow(e) => NodeSetParseError::OverFlow(e),
             FormatError::Command(e) => NodeSetParseError::Command(e),
+            FormatError::DuplicateNode(node, overlap) => {
+                NodeSetParseError::DuplicateNode { node, overlap }
+            }
 
             #[cfg(feature = "groups")]
-            FormatError::Source(s) => NodeSetParseError::Source(s.to_string()),
+            FormatError::Group(e) => *e,
+            #[cfg(feature = "groups")]
+            FormatError::NoMatchingGroup(s) => NodeSetParseError::NoMatchingGroup(s.to_string()),
+            #[cfg(feature = "groups")]
+            FormatError::GroupsDisabled(s) => NodeSetParseError::GroupsDisabled(s.to_string()),
+        }
+    }
+}
+
+impl<'a> FormatError<'a> {
+    /// Converts a range error into a FormatError, reporting overflows along
+    /// with the offending input
+    fn from_range_error(e: RangeStepError, input: &'a str) -> Self {
+        match e {
+            RangeStepError::Overflow => FormatError::IdOverflow(input),
+            e => FormatError::RangeError(e),
         }
     }
 }
@@ -102,7 +163,7 @@ impl<'a> ParserError<&'a str> for FormatError<'a> {
     type Inner = Self;
 
     fn from_input(input: &&'a str) -> Self {
-        FormatError::Input(*input)
+        FormatError::Input(input)
     }
 
     fn into_inner(self) -> Result<Self::Inner, Self> {
@@ -125,20 +186,224 @@ impl<'a, E: std::error::Error + Send + Sync + 'static + Into<FormatError<'a>>>
     }
 }
 
+/// Returns true if `c` can be used in node names without quoting them
 #[cfg(feature = "groups")]
-fn is_nodeset_char(c: char) -> bool {
+pub(crate) fn is_nodeset_char(c: char) -> bool {
     is_source_char(c) || [':'].contains(&c)
 }
 
+/// Returns true if `c` can be used in node names without quoting them
 #[cfg(not(feature = "groups"))]
-fn is_nodeset_char(c: char) -> bool {
+pub(crate) fn is_nodeset_char(c: char) -> bool {
     is_source_char(c) || [':', '*', '@'].contains(&c)
 }
 
+/// Returns true if `c` can introduce group references instead of `@`
+///
+/// The sigil must not be confused with node names or with other elements of
+/// the syntax.
+pub(crate) fn is_valid_group_sigil(c: char) -> bool {
+    c == '@'
+        || !(c.is_alphanumeric()
+            || c.is_whitespace()
+            || c.is_control()
+            || is_nodeset_char(c)
+            || [
+                ',', '&', '!', '^', '(', ')', '[', ']', '*', '#', '"', '\\', '=',
+            ]
+            .contains(&c))
+}
+
+/// Returns true if `c` can be used in group names without quoting them
+///
+/// Bare group names are made of letters, digits and `-_./+=:`, `=` being
+/// only allowed in group names (ie `@arch=x86_64`) and not in node names. As
+/// `:` separates the source from the group, group names containing it must be
+/// quoted unless a source is given (`@"state:idle"` or `@slurm:state:idle`).
+/// Other group names, such as names containing spaces or commas, must always
+/// be quoted (`@"rack 1,a"` or `@source:"rack 1,a"`).
+pub(crate) fn is_group_char(c: char) -> bool {
+    is_group_name_char(c) || c.is_ascii_digit()
+}
+
+/// Returns true if `c` can be used between the ranges of bare group names
+fn is_group_name_char(c: char) -> bool {
+    is_nodeset_char(c) || c == '='
+}
+
+/// Kinds of names parsed around ranges by [`Parser::set`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+enum Names {
+    #[default]
+    Node,
+    Group,
+    #[cfg(feature = "groups")]
+    Source,
+}
+
+/// Quotes `group` if it cannot be written bare in group references
+///
+/// `sourced` is set when the name follows a source (`@source:group`), or is
+/// listed by a group source, where `:` does not need to be quoted.
+pub(crate) fn quote_group(group: &str, sourced: bool) -> Cow<'_, str> {
+    if group
+        .chars()
+        .all(|c| is_group_char(c) && (sourced || c != ':'))
+    {
+        return Cow::Borrowed(group);
+    }
+
+    let mut quoted = String::with_capacity(group.len() + 2);
+    super::nodeset::write_quoted(&mut quoted, group).expect("writing to a String cannot fail");
+    Cow::Owned(quoted)
+}
+
 fn is_source_char(c: char) -> bool {
     char::is_alphabetic(c) || ['-', '_', '.', '/', '+'].contains(&c)
 }
 
+/// Options controlling the syntax of nodesets
+///
+/// Options are built from the defaults with the setters below, so that new
+/// options can be added without breaking existing code.
+///
+/// # Examples
+///
+/// ```
+/// use nodeset::{NodeSet, ParseOptions};
+///
+/// let options = ParseOptions::new().delimiters('{', '}').no_groups(true);
+/// let ns: NodeSet = NodeSet::parse_with("node{1-2}", &options).unwrap();
+///
+/// assert_eq!(ns.to_string(), "node[1-2]");
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParseOptions {
+    /// Delimiter opening a list of ranges (`[` by default)
+    pub open: char,
+    /// Delimiter closing a list of ranges (`]` by default)
+    pub close: char,
+    /// Ignore `#` comments when reading nodesets with
+    /// `NodeSet::from_reader_with` (disabled by default)
+    pub lenient: bool,
+    /// Fail with [`NodeSetParseError::DuplicateNode`] when a node appears
+    /// more than once in a list of ranges or in a union, instead of silently
+    /// merging duplicates (disabled by default)
+    pub reject_duplicates: bool,
+    /// Maximum number of dimensions combined into ranges when folding parsed
+    /// nodesets, starting from the last one (all of them by default). See
+    /// [`NodeSet::try_fold_dimensions`].
+    pub max_fold_dimensions: usize,
+    /// Resolve undefined groups (ie `@comptue`) and group patterns which
+    /// match no group (ie `@rack*`) to an empty set instead of failing with
+    /// [`NodeSetParseError::UnknownGroup`] or
+    /// [`NodeSetParseError::NoMatchingGroup`] (disabled by default)
+    pub ignore_missing_groups: bool,
+    /// Fail with [`NodeSetParseError::GroupsDisabled`] on group terms (ie
+    /// `@rack1`, `@@`, `@*` or `@source:group`) instead of resolving them,
+    /// so that parsing never runs upcalls nor queries group sources (disabled
+    /// by default). Without the `groups` feature, groups are never resolved.
+    pub no_groups: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            open: '[',
+            close: ']',
+            lenient: false,
+            reject_duplicates: false,
+            max_fold_dimensions: usize::MAX,
+            ignore_missing_groups: false,
+            no_groups: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Creates options with the default syntax
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the delimiters opening and closing lists of ranges
+    pub fn delimiters(mut self, open: char, close: char) -> Self {
+        self.open = open;
+        self.close = close;
+        self
+    }
+
+    /// Sets whether `#` comments are ignored when reading nodesets with
+    /// `NodeSet::from_reader_with`
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Sets whether nodes appearing more than once are rejected
+    pub fn reject_duplicates(mut self, reject_duplicates: bool) -> Self {
+        self.reject_duplicates = reject_duplicates;
+        self
+    }
+
+    /// Sets the maximum number of dimensions combined into ranges when
+    /// folding parsed nodesets
+    pub fn max_fold_dimensions(mut self, max_fold_dimensions: usize) -> Self {
+        self.max_fold_dimensions = max_fold_dimensions;
+        self
+    }
+
+    /// Sets whether undefined groups and group patterns matching no group
+    /// resolve to an empty set
+    pub fn ignore_missing_groups(mut self, ignore_missing_groups: bool) -> Self {
+        self.ignore_missing_groups = ignore_missing_groups;
+        self
+    }
+
+    /// Sets whether group terms are rejected instead of being resolved
+    pub fn no_groups(mut self, no_groups: bool) -> Self {
+        self.no_groups = no_groups;
+        self
+    }
+
+    /// Checks that the delimiters can not be confused with other elements of
+    /// the syntax
+    ///
+    /// This is done before parsing by [`Parser::parse`], but may be used to
+    /// reject invalid options earlier.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::ParseOptions;
+    ///
+    /// let options = ParseOptions::new().delimiters('{', '}');
+    /// assert!(options.validate().is_ok());
+    ///
+    /// let options = ParseOptions::new().delimiters('|', '|');
+    /// assert!(options.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), NodeSetParseError> {
+        let is_reserved = |c: char| {
+            c.is_alphanumeric()
+                || c.is_whitespace()
+                || is_nodeset_char(c)
+                || [',', '&', '!', '^', '(', ')', '@', '*', ':', '#', '"', '\\'].contains(&c)
+        };
+
+        if self.open == self.close || is_reserved(self.open) || is_reserved(self.close) {
+            return Err(NodeSetParseError::Delimiters(self.open, self.close));
+        }
+
+        if self.max_fold_dimensions == 0 {
+            return Err(NodeSetParseError::FoldDimensions(self.max_fold_dimensions));
+        }
+
+        Ok(())
+    }
+}
+
 /// Parse strings into nodesets
 #[derive(Debug, Copy, Clone, Default)]
 pub struct Parser<'a> {
@@ -146,6 +411,8 @@ pub struct Parser<'a> {
     resolver: Option<&'a Resolver>,
     #[cfg(feature = "groups")]
     default_source: Option<&'a str>,
+    options: ParseOptions,
+    names: Names,
 
     ghost: std::marker::PhantomData<&'a ()>,
 }
@@ -157,19 +424,57 @@ impl<'a> Parser<'a> {
         Self {
             resolver: Some(resolver),
             default_source,
+            options: ParseOptions::default(),
+            names: Names::Node,
 
             ghost: std::marker::PhantomData,
         }
     }
 
+    /// Creates a parser of the group names listed by group sources, which
+    /// may hold characters not allowed in node names (ie `arch=x86_64`)
+    pub(crate) fn for_group_names() -> Self {
+        Self {
+            names: Names::Group,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the character introducing group references, `@` unless the
+    /// resolver sets another one
+    #[cfg(feature = "groups")]
+    fn sigil(&self) -> char {
+        self.resolver.map_or('@', Resolver::group_sigil)
+    }
+
+    /// Use alternative syntax options
+    pub fn with_options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
     /// Parse a string into a nodeset
     pub fn parse<T>(self, i: &'a str) -> Result<NodeSet<T>, NodeSetParseError>
     where
         T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
     {
-        let mut ns = self.expr().parse(i).map_err(|e| e.into_inner())?;
+        self.options.validate()?;
+        #[cfg(feature = "groups")]
+        if [self.options.open, self.options.close].contains(&self.sigil()) {
+            return Err(NodeSetParseError::Delimiters(
+                self.options.open,
+                self.options.close,
+            ));
+        }
 
-        ns.fold();
+        let mut ns = self.expr().parse(i).map_err(|e| match e.into_inner() {
+            FormatError::Input(rest) if rest.starts_with(self.options.close) => {
+                FormatError::UnexpectedCloseBracket(rest)
+            }
+            e => e,
+        })?;
+
+        ns.try_fold_dimensions(self.options.max_fold_dimensions)?;
         Ok(ns)
     }
 
@@ -182,23 +487,37 @@ impl<'a> Parser<'a> {
                 multispace0,
                 repeat(0.., (self.term(), opt(Self::op)))
                     .fold(
-                        || (None, None, false),
-                        |acc, t| {
-                            let (ns, op, err) = acc;
+                        || (None, None, false, None),
+                        |acc, mut t| {
+                            let (ns, op, err, duplicate) = acc;
 
                             if err {
-                                return (None, None, true);
+                                return (None, None, true, None);
                             }
 
-                            let Some(mut ns) = ns else {
-                                return (Some(t.0), t.1, false);
+                            let Some(mut ns): Option<NodeSet<T>> = ns else {
+                                return (Some(t.0), t.1, false, None);
                             };
 
                             let Some(op) = op else {
-                                return (None, None, true);
+                                return (None, None, true, None);
                             };
 
+                            if duplicate.is_some() {
+                                return (Some(ns), t.1, false, duplicate);
+                            }
+
                             match op {
+                                ',' | ' ' if self.options.reject_duplicates => {
+                                    ns.fold();
+                                    t.0.fold();
+                                    let overlap = ns.intersection(&t.0);
+                                    if let Some(node) = overlap.iter().next() {
+                                        let duplicate = (node, overlap.to_string());
+                                        return (Some(ns), t.1, false, Some(duplicate));
+                                    }
+                                    ns.extend_from_nodeset(&t.0);
+                                }
                                 ',' | ' ' => {
                                     ns.extend_from_nodeset(&t.0);
                                 }
@@ -213,13 +532,18 @@ impl<'a> Parser<'a> {
                                 }
                                 _ => unreachable!(),
                             }
-                            (Some(ns), t.1, false)
+                            (Some(ns), t.1, false, None)
                         },
                     )
-                    .map(|(ns, _, _err)| ns.unwrap_or_default()),
+                    .map(|(ns, _, _err, duplicate)| match duplicate {
+                        Some((node, overlap)) => {
+                            Err(ErrMode::Cut(FormatError::DuplicateNode(node, overlap)))
+                        }
+                        None => Ok(ns.unwrap_or_default()),
+                    }),
                 multispace0,
             )
-            .parse_next(i)
+            .parse_next(i)?
         }
     }
 
@@ -230,10 +554,10 @@ impl<'a> Parser<'a> {
         move |input: &mut &str| {
             alt((
                 alt((
-                    Self::rangeset,
-                    Self::nodeset,
+                    self.rangeset(),
+                    self.nodeset(),
                     #[cfg(feature = "groups")]
-                    self.group(),
+                    self.group_term(),
                 )),
                 delimited("(", self.expr(), ")"),
             ))
@@ -251,87 +575,160 @@ impl<'a> Parser<'a> {
     }
 
     #[cfg(feature = "groups")]
-    fn nodeset_or_rangeset<T>(i: &mut &'a str) -> ModalResult<'a, NodeSet<T>>
+    fn groupset_or_rangeset<T>(self) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, NodeSet<T>>
     where
         T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
     {
-        alt((Self::nodeset, Self::rangeset)).parse_next(i)
+        move |i: &mut &str| alt((self.set(Names::Group), self.rangeset())).parse_next(i)
     }
 
-    fn rangeset<T>(i: &mut &'a str) -> ModalResult<'a, NodeSet<T>>
+    fn rangeset<T>(self) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, NodeSet<T>>
     where
         T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
     {
-        (
-            alt((Self::id_range_bracketed_affix, Self::id_range_step_rangeset)),
-            peek(alt((",", "&", "!", "^", "(", ")", multispace1, eof))),
-        )
-            .map(
-                |(idrs, _)| -> Result<NodeSet<T>, ErrMode<FormatError<'a>>> {
-                    let mut ns = NodeSet::lazy();
-                    let mut dims = NodeSetDimensions::new();
-                    dims.push("");
-                    let mut range = T::new().lazy();
-
-                    match idrs {
-                        IdRangeComponent::Single(id) => range.push_idrs(id),
-                        IdRangeComponent::IdRange((high, rng, low)) => {
-                            for r in rng {
-                                range.push_idrs(AffixIdRangeStep::new(r, low, high).cut()?)
-                            }
+        move |i: &mut &str| {
+            (
+                alt((
+                    self.id_range_bracketed_affix(),
+                    Self::id_range_step_rangeset,
+                ))
+                .with_taken(),
+                peek(alt((",", "&", "!", "^", "(", ")", multispace1, eof))),
+            )
+                .map(
+                    |((idrs, input), _)| -> Result<NodeSet<T>, ErrMode<FormatError<'a>>> {
+                        let mut ns = NodeSet::lazy();
+                        let mut dims = NodeSetDimensions::new();
+                        dims.push("");
+                        let (range, duplicates) = self.id_range(idrs, input)?;
+
+                        if let Some(duplicates) = duplicates {
+                            return Err(ErrMode::Cut(Self::duplicate_error(
+                                dims,
+                                vec![duplicates],
+                            )));
                         }
-                    }
 
-                    range.sort();
-                    ns.bases.entry(dims).or_insert(IdSetKind::Single(range));
-                    Ok(ns)
-                },
-            )
-            .parse_next(i)?
+                        ns.insert_base(dims, Arc::new(IdSetKind::Single(range)));
+                        Ok(ns)
+                    },
+                )
+                .parse_next(i)?
+        }
     }
 
-    fn nodeset<T>(i: &mut &'a str) -> ModalResult<'a, NodeSet<T>>
+    fn nodeset<T>(self) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, NodeSet<T>>
     where
         T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
     {
-        Self::set(false).parse_next(i)
+        self.set(self.names)
     }
 
     #[cfg(feature = "groups")]
-    fn sourceset<T>(i: &mut &'a str) -> ModalResult<'a, NodeSet<T>>
+    fn sourceset<T>(self) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, NodeSet<T>>
     where
         T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
     {
-        Self::set(true).parse_next(i)
+        self.set(Names::Source)
     }
 
-    fn source_or_node_component(
-        source: bool,
-    ) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, &'a str> {
+    /// Parses a part of the names of a set, which may be quoted unless it
+    /// is the name of a source
+    fn name_component(
+        names: Names,
+    ) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, Cow<'a, str>> {
         move |i: &mut &str| {
-            if source {
-                take_while(1.., is_source_char).parse_next(i)
-            } else {
-                take_while(1.., is_nodeset_char).parse_next(i)
+            let is_name_char = match names {
+                Names::Node => is_nodeset_char,
+                Names::Group => is_group_name_char,
+                #[cfg(feature = "groups")]
+                Names::Source => {
+                    return take_while(1.., is_source_char)
+                        .map(Cow::Borrowed)
+                        .parse_next(i)
+                }
+            };
+
+            repeat(
+                1..,
+                alt((
+                    take_while(1.., is_name_char).map(Cow::Borrowed),
+                    Self::quoted_name.map(Cow::Owned),
+                )),
+            )
+            .fold(
+                || Cow::Borrowed(""),
+                |name: Cow<str>, part| {
+                    if name.is_empty() {
+                        part
+                    } else {
+                        Cow::Owned(name.into_owned() + &part)
+                    }
+                },
+            )
+            .parse_next(i)
+        }
+    }
+
+    /// Parses a part of a node name between double quotes (ie `"rack-a,b"`)
+    ///
+    /// Quoted names may hold any character, with `"` and `\` escaped by a
+    /// backslash.
+    fn quoted_name(i: &mut &'a str) -> ModalResult<'a, String> {
+        let input = *i;
+        let Some(quoted) = input.strip_prefix('"') else {
+            return Err(ErrMode::from_input(i));
+        };
+
+        let mut name = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((pos, c)) = chars.next() {
+            match c {
+                '"' if name.is_empty() => {
+                    return Err(ErrMode::Cut(FormatError::Quote(
+                        &input[..pos + 2],
+                        "empty name",
+                    )))
+                }
+                '"' => {
+                    *i = &quoted[pos + 1..];
+                    return Ok(name);
+                }
+                '\\' => match chars.next() {
+                    Some((_, c @ ('"' | '\\'))) => name.push(c),
+                    Some((pos, c)) => {
+                        return Err(ErrMode::Cut(FormatError::Quote(
+                            &input[..pos + 1 + c.len_utf8()],
+                            "invalid escape sequence",
+                        )))
+                    }
+                    None => break,
+                },
+                c => name.push(c),
             }
         }
+
+        Err(ErrMode::Cut(FormatError::Quote(
+            input,
+            "missing closing quote",
+        )))
     }
 
-    fn set<T>(source: bool) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, NodeSet<T>>
+    fn set<T>(self, names: Names) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, NodeSet<T>>
     where
         T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
     {
         move |i: &mut &str| {
             (
-                opt(alt((Self::id_range_bracketed_affix, Self::id_standalone))),
+                opt(alt((self.id_range_bracketed_affix(), Self::id_standalone)).with_taken()),
                 repeat(
                     0..,
                     (
-                        Self::source_or_node_component(source),
-                        alt((Self::id_range_bracketed_affix, Self::id_standalone)),
+                        Self::name_component(names),
+                        alt((self.id_range_bracketed_affix(), Self::id_standalone)).with_taken(),
                     ),
                 ),
-                opt(Self::source_or_node_component(source)),
+                opt(Self::name_component(names)),
             )
                 .verify(|(_, components, suffix): &(_, Vec<_>, _)| {
                     // This is a rangeset
@@ -340,44 +737,48 @@ impl<'a> Parser<'a> {
                 .map(
                     |(prefix, components, suffix)| -> Result<NodeSet<T>, ErrMode<FormatError<'a>>> {
                         let mut dims = NodeSetDimensions::new();
-                        let mut ranges = vec![];
+                        let mut ranges: Vec<T> = vec![];
 
                         let it = prefix
                             .into_iter()
-                            .map(|prefix| ("", prefix))
+                            .map(|prefix| (Cow::Borrowed(""), prefix))
                             .chain(components);
 
-                        for (dim, rng) in it {
-                            let mut range = T::new().lazy();
+                        let mut duplicate = None;
 
-                            match rng {
-                                IdRangeComponent::Single(id) => range.push_idrs(id),
-                                IdRangeComponent::IdRange((high, rng, low)) => {
-                                    for r in rng {
-                                        range.push_idrs(AffixIdRangeStep::new(r, low, high).cut()?)
-                                    }
-                                }
+                        for (dim, (rng, input)) in it {
+                            let (range, dup) = self.id_range(rng, input)?;
+                            if let Some(dup) = dup {
+                                duplicate.get_or_insert((ranges.len(), dup));
                             }
-                            range.sort();
                             ranges.push(range);
-                            dims.push(dim);
+                            dims.push(&dim);
                         }
 
                         if let Some(dim) = suffix {
-                            dims.push_suffix(dim);
+                            dims.push_suffix(&dim);
+                        }
+
+                        if let Some((axis, duplicates)) = duplicate {
+                            // Duplicate ids of an axis are duplicated with
+                            // all the ids of the other axes
+                            let mut ranges = ranges;
+                            ranges[axis] = duplicates;
+                            return Err(ErrMode::Cut(Self::duplicate_error(dims, ranges)));
                         }
 
                         let mut ns = NodeSet::lazy();
                         if ranges.is_empty() {
-                            ns.bases.entry(dims).or_insert_with(|| IdSetKind::None);
+                            ns.insert_base(dims, Arc::new(IdSetKind::None));
                         } else if ranges.len() == 1 {
-                            ns.bases
-                                .entry(dims)
-                                .or_insert(IdSetKind::Single(ranges.pop().unwrap()));
+                            ns.insert_base(
+                                dims,
+                                Arc::new(IdSetKind::Single(ranges.pop().unwrap())),
+                            );
                         } else {
                             let mut ids = IdSet::new();
                             ids.products.push(IdRangeProduct { ranges });
-                            ns.bases.entry(dims).or_insert(IdSetKind::Multiple(ids));
+                            ns.insert_base(dims, Arc::new(IdSetKind::Multiple(ids)));
                         }
 
                         Ok(ns)
@@ -387,6 +788,31 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses any group term, failing without resolving it if group
+    /// resolution is disabled
+    #[cfg(feature = "groups")]
+    fn group_term<T>(self) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, NodeSet<T>>
+    where
+        T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+    {
+        move |i: &mut &str| {
+            let mut term = alt((self.group_names(), self.group_pattern(), self.group()));
+            if !self.options.no_groups {
+                return term.parse_next(i);
+            }
+
+            let input = term.take().parse_next(i)?;
+            Err(ErrMode::Cut(FormatError::GroupsDisabled(input)))
+        }
+    }
+
+    /// Returns the resolver of group terms, unless group resolution is
+    /// disabled
+    #[cfg(feature = "groups")]
+    fn resolver(&self) -> Option<&'a Resolver> {
+        self.resolver.filter(|_| !self.options.no_groups)
+    }
+
     #[cfg(feature = "groups")]
     #[auto_enum]
     fn group<T>(self) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, NodeSet<T>>
@@ -395,20 +821,21 @@ impl<'a> Parser<'a> {
     {
         move |i: &mut &str| {
             preceded(
-                "@",
+                self.sigil(),
                 // Match either 'sources:groups' or 'groups'
                 // Both sources and groups can be sets i.e: @source[1-4]:group[1,5]
                 alt((
                     literal("*").value((None, None)),
                     self.group_with_source(),
-                    Self::nodeset_or_rangeset.map(|s: NodeSet<IdRangeList>| (None, Some(s))),
+                    self.groupset_or_rangeset()
+                        .map(|s: NodeSet<IdRangeList>| (None, Some(s))),
                 )),
             )
             .map(
                 |(sources, groups)| -> Result<NodeSet<T>, ErrMode<FormatError<'a>>> {
                     let mut ns = NodeSet::lazy();
 
-                    let Some(resolver) = self.resolver else {
+                    let Some(resolver) = self.resolver() else {
                         return Ok(ns);
                     };
 
@@ -421,20 +848,21 @@ impl<'a> Parser<'a> {
                     // Iterate over the sources or use an iterator
                     // which yields the default source once
                     for source in sources {
-                        let all_groups;
-                        let groups = match &groups {
-                            Some(groups) => groups,
-                            None => {
-                                all_groups = resolver.list_groups(source.as_deref());
-                                &all_groups
-                            }
+                        let Some(groups) = &groups else {
+                            let nodeset = resolver
+                                .all_nodes(source.as_deref())
+                                .map_err(|e| FormatError::Group(Box::new(e)))
+                                .cut()?;
+                            ns.extend_from_nodeset(&nodeset);
+                            continue;
                         };
 
+                        // Only groups referred to by their name must exist,
+                        // not those of a set such as @rack[1-10]
+                        let required = groups.len() == 1;
                         for group in groups.iter() {
-                            let nodeset = resolver
-                                .resolve(source.as_deref(), &group)
-                                .map_err(|_| FormatError::Source(*i))
-                                .cut()?;
+                            let nodeset =
+                                self.resolve_group(resolver, source.as_deref(), &group, required)?;
                             ns.extend_from_nodeset(&nodeset);
                         }
                     }
@@ -446,60 +874,323 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Resolves a group from `source`, or from the sources consulted by
+    /// default if None
+    ///
+    /// A `required` group which is not defined is an error suggesting groups
+    /// with a similar name unless [`ParseOptions::ignore_missing_groups`] is
+    /// set. Other undefined groups are empty.
     #[cfg(feature = "groups")]
-    fn group_with_source<T>(
+    fn resolve_group<T>(
         self,
-    ) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, (Option<NodeSet<T>>, Option<NodeSet<T>>)>
+        resolver: &Resolver,
+        source: Option<&str>,
+        group: &str,
+        required: bool,
+    ) -> Result<NodeSet<T>, ErrMode<FormatError<'a>>>
+    where
+        T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+    {
+        let nodeset = resolver
+            .try_resolve(source, group)
+            .map_err(|e| FormatError::Group(Box::new(e)))
+            .cut()?;
+
+        match nodeset {
+            Some(nodeset) => Ok(nodeset),
+            None if required && !self.options.ignore_missing_groups => Err(ErrMode::Cut(
+                FormatError::Group(Box::new(resolver.unknown_group(source, group))),
+            )),
+            None => Ok(NodeSet::default()),
+        }
+    }
+
+    /// Parses a `@@sources` term into the names of the groups of the sources
+    ///
+    /// Without sources (`@@`), the names of the groups of the default source
+    /// are returned. A trailing `:` is accepted as in `@@source:`.
+    #[cfg(feature = "groups")]
+    #[auto_enum]
+    fn group_names<T>(self) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, NodeSet<T>>
     where
         T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
     {
         move |i: &mut &str| {
-            alt((
-                terminated(Self::sourceset, ":*").map(|source| (Some(source), None)),
-                separated_pair(Self::sourceset, ":", opt(Self::nodeset_or_rangeset))
-                    .map(|source| (Some(source.0), Some(source.1.unwrap_or_default()))),
-            ))
-            .parse_next(i)
+            preceded(
+                (self.sigil(), self.sigil()),
+                opt(terminated(self.sourceset::<T>(), opt(":"))),
+            )
+            .map(|sources| -> Result<NodeSet<T>, ErrMode<FormatError<'a>>> {
+                let mut ns = NodeSet::lazy();
+
+                let Some(resolver) = self.resolver() else {
+                    return Ok(ns);
+                };
+
+                #[auto_enum(Iterator)]
+                let sources = match &sources {
+                    Some(sources) => sources.iter().map(Some),
+                    None => std::iter::once(self.default_source.map(|s| s.to_string())),
+                };
+
+                for source in sources {
+                    let names = resolver
+                        .try_list_groups(source.as_deref())
+                        .map_err(|e| FormatError::Group(Box::new(e)))
+                        .cut()?;
+                    ns.extend_from_nodeset(&names);
+                }
+
+                Ok(ns)
+            })
+            .parse_next(i)?
         }
     }
 
-    fn id_range_bracketed_affix(i: &mut &'a str) -> ModalResult<'a, IdRangeComponent> {
-        (
-            opt(digit1),
-            delimited("[", separated(1.., cut_err(Self::id_range_step), ","), "]"),
-            opt(digit1),
-        )
+    /// Parses a `@sources:pattern` term into the union of the groups whose
+    /// names match the pattern
+    ///
+    /// In patterns, `*` matches any sequence of characters (ie `@rack*`) and
+    /// `\*` stands for a literal `*` so that groups with an asterisk in their
+    /// name can still be referred to (ie `@a\*b`). Patterns are matched against
+    /// the groups listed by the sources. A pattern which matches no group is
+    /// an error unless [`ParseOptions::ignore_missing_groups`] is set.
+    #[cfg(feature = "groups")]
+    #[auto_enum]
+    fn group_pattern<T>(self) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, NodeSet<T>>
+    where
+        T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+    {
+        move |i: &mut &str| {
+            preceded(
+                self.sigil(),
+                (
+                    opt(terminated(self.sourceset::<T>(), ":")),
+                    Self::group_pattern_parts,
+                ),
+            )
+            .with_taken()
             .map(
-                |(high, ranges, low)| -> Result<IdRangeComponent, ErrMode<FormatError<'a>>> {
-                    let low = low
-                        .map(|s| s.parse::<u32>().map(|value| (s.len(), value)))
-                        .transpose()
-                        .cut()?
-                        .map(|(len, value)| IdRangeOffset::new(value, len as u32))
-                        .transpose()
-                        .cut()?;
+                |((sources, parts), input)| -> Result<NodeSet<T>, ErrMode<FormatError<'a>>> {
+                    let mut ns = NodeSet::lazy();
 
-                    let high = high
-                        .map(|s| s.parse::<u32>().map(|value| (s.len(), value)))
-                        .transpose()
-                        .cut()?
-                        .map(|(len, value)| IdRangeOffset::new(value, len as u32))
-                        .transpose()
-                        .cut()?;
+                    let Some(resolver) = self.resolver() else {
+                        return Ok(ns);
+                    };
+
+                    #[auto_enum(Iterator)]
+                    let sources = match &sources {
+                        Some(sources) => sources.iter().map(Some),
+                        None => std::iter::once(self.default_source.map(|s| s.to_string())),
+                    };
 
-                    Ok(IdRangeComponent::IdRange((high, ranges, low)))
+                    let mut matched = false;
+                    for source in sources {
+                        let groups: Vec<String> = match parts.as_slice() {
+                            [name] => vec![name.clone()],
+                            parts => resolver
+                                .try_list_groups::<T>(source.as_deref())
+                                .map_err(|e| FormatError::Group(Box::new(e)))
+                                .cut()?
+                                .iter()
+                                .filter(|group| glob_matches(parts, group))
+                                .collect(),
+                        };
+
+                        let required = parts.len() == 1;
+                        for group in groups {
+                            matched = true;
+                            let nodeset =
+                                self.resolve_group(resolver, source.as_deref(), &group, required)?;
+                            ns.extend_from_nodeset(&nodeset);
+                        }
+                    }
+
+                    if !matched && !self.options.ignore_missing_groups {
+                        return Err(ErrMode::Cut(FormatError::NoMatchingGroup(input)));
+                    }
+
+                    Ok(ns)
                 },
             )
             .parse_next(i)?
+        }
+    }
+
+    /// Parses a group name pattern into its parts separated by `*`
+    ///
+    /// Fails unless the pattern holds a `*` or an escaped `\*`, or if it is a
+    /// single `*` which refers to all the nodes of a source.
+    #[cfg(feature = "groups")]
+    fn group_pattern_parts(i: &mut &'a str) -> ModalResult<'a, Vec<String>> {
+        let input = *i;
+        let mut parts = vec![String::new()];
+        let mut escaped = false;
+        let mut end = input.len();
+
+        let mut chars = input.char_indices().peekable();
+        while let Some((pos, c)) = chars.next() {
+            let part = parts.last_mut().expect("parts are never empty");
+            match c {
+                '*' => parts.push(String::new()),
+                '\\' if chars.next_if(|&(_, c)| c == '*').is_some() => {
+                    escaped = true;
+                    part.push('*');
+                }
+                c if is_group_char(c) => part.push(c),
+                _ => {
+                    end = pos;
+                    break;
+                }
+            }
+        }
+
+        let all = parts.len() == 2 && parts.iter().all(String::is_empty);
+        if (parts.len() == 1 && !escaped) || all {
+            return Err(ErrMode::from_input(i));
+        }
+
+        *i = &input[end..];
+        Ok(parts)
+    }
+
+    #[cfg(feature = "groups")]
+    fn group_with_source<T>(self) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, GroupTerm<T>>
+    where
+        T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+    {
+        move |i: &mut &str| {
+            alt((
+                terminated(self.sourceset(), ":*").map(|source| (Some(source), None)),
+                separated_pair(self.sourceset(), ":", opt(self.groupset_or_rangeset()))
+                    .map(|source| (Some(source.0), Some(source.1.unwrap_or_default()))),
+            ))
+            .parse_next(i)
+        }
+    }
+
+    /// Returns the error for the duplicate nodes with the names `dims` and
+    /// the ids `ranges`
+    fn duplicate_error<T>(dims: NodeSetDimensions, ranges: Vec<T>) -> FormatError<'a>
+    where
+        T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+    {
+        let mut overlap = NodeSet::lazy();
+        if ranges.len() == 1 {
+            overlap.insert_base(
+                dims,
+                Arc::new(IdSetKind::Single(ranges.into_iter().next().unwrap())),
+            );
+        } else {
+            let mut ids = IdSet::new();
+            ids.products.push(IdRangeProduct { ranges });
+            overlap.insert_base(dims, Arc::new(IdSetKind::Multiple(ids)));
+        }
+
+        let node = overlap.iter().next().expect("duplicates are not empty");
+        FormatError::DuplicateNode(node, overlap.to_string())
+    }
+
+    /// Builds a range from a parsed range component
+    ///
+    /// When duplicates are rejected, also returns the ids which appear more
+    /// than once in the component, if any.
+    fn id_range<T>(
+        self,
+        rng: IdRangeComponent,
+        input: &'a str,
+    ) -> Result<(T, Option<T>), ErrMode<FormatError<'a>>>
+    where
+        T: IdRange + PartialEq + Clone + fmt::Display + fmt::Debug,
+    {
+        let mut duplicates = vec![];
+
+        let range = match rng {
+            IdRangeComponent::Single(id) => {
+                let mut range = T::new();
+                range.push_idrs(id);
+                range
+            }
+            IdRangeComponent::IdRange((high, rng, low)) if self.options.reject_duplicates => {
+                let mut range = T::new();
+                for r in rng {
+                    let mut step = T::new();
+                    step.push_idrs(
+                        AffixIdRangeStep::new(r, low, high)
+                            .map_err(|e| FormatError::from_range_error(e, input))
+                            .cut()?,
+                    );
+                    duplicates.extend(range.intersection(&step));
+                    range.push(&step);
+                }
+                range
+            }
+            IdRangeComponent::IdRange((high, rng, low)) => {
+                let mut range = T::new().lazy();
+                for r in rng {
+                    range.push_idrs(
+                        AffixIdRangeStep::new(r, low, high)
+                            .map_err(|e| FormatError::from_range_error(e, input))
+                            .cut()?,
+                    )
+                }
+                range.sort();
+                range
+            }
+        };
+
+        let duplicates = (!duplicates.is_empty()).then(|| {
+            duplicates.sort_unstable();
+            duplicates.dedup();
+            T::from(duplicates)
+        });
+
+        Ok((range, duplicates))
+    }
+
+    fn id_range_bracketed_affix(
+        self,
+    ) -> impl 'a + FnMut(&mut &'a str) -> ModalResult<'a, IdRangeComponent> {
+        move |i: &mut &str| {
+            let (mut open, close) = (self.options.open, self.options.close);
+            let high = opt(digit1).parse_next(i)?;
+            let bracket = *i;
+            open.parse_next(i)?;
+
+            // Truncated input is reported precisely once an opening
+            // delimiter was found
+            if opt(close).parse_next(i)?.is_some() {
+                return Err(ErrMode::Cut(FormatError::EmptyBracket(bracket)));
+            }
+            let ranges = separated(1.., cut_err(Self::id_range_step), ",")
+                .parse_next(i)
+                .map_err(|e| match e {
+                    ErrMode::Cut(FormatError::Input("")) => {
+                        ErrMode::Cut(FormatError::UnclosedBracket(bracket))
+                    }
+                    e => e,
+                })?;
+            if opt(close).parse_next(i)?.is_none() {
+                return Err(ErrMode::Cut(FormatError::UnclosedBracket(bracket)));
+            }
+            let low = opt(digit1).parse_next(i)?;
+
+            let low = low.map(Self::id_offset).transpose().cut()?;
+            let high = high.map(Self::id_offset).transpose().cut()?;
+
+            Ok(IdRangeComponent::IdRange((high, ranges, low)))
+        }
     }
 
     fn id_standalone(i: &mut &'a str) -> ModalResult<'a, IdRangeComponent> {
         digit1
             .map(
                 |d: &str| -> Result<IdRangeComponent, ErrMode<FormatError<'a>>> {
-                    let start = d.parse::<u32>().cut()?;
+                    let start = Self::id(d).cut()?;
                     Ok(IdRangeComponent::Single(
-                        SingleId::new(start, d.len() as u32).cut()?,
+                        SingleId::new(start, d.len() as u32)
+                            .map_err(|e| FormatError::from_range_error(e, d))
+                            .cut()?,
                     ))
                 },
             )
@@ -517,13 +1208,15 @@ impl<'a> Parser<'a> {
             digit1,
             opt(preceded("-", (digit1, opt(preceded("/", digit1))))),
         )
+            .with_taken()
             .map(
-                |(start, end_step): (&str, Option<(&str, Option<&str>)>)| -> Result<_, ErrMode<FormatError<'a>>> {
-                    let start_index = start.parse::<u32>().cut()?;
-                    let len =
-                        start.len()
-                            .try_into()
-                            .cut()?;
+                |((start, end_step), input)| -> Result<_, ErrMode<FormatError<'a>>> {
+                    let start_index = Self::id(start).cut()?;
+                    let len = start
+                        .len()
+                        .try_into()
+                        .map_err(|_| FormatError::IdOverflow(start))
+                        .cut()?;
 
                     let mut padded = Self::is_padded(start);
 
@@ -536,25 +1229,71 @@ impl<'a> Parser<'a> {
                                 Err(FormatError::MismatchedPadding(start, end)).cut()?;
                             }
 
-                            let end_index = end.parse::<u32>().cut()?;
+                            let end_index = Self::id(end).cut()?;
+                            if end_index < start_index {
+                                Err(FormatError::EmptyOrInvertedRange(start, end)).cut()?;
+                            }
+
                             match step {
                                 None => (end_index, 1),
-                                Some(step) => (end_index, step.parse::<u32>().cut()?),
+                                Some(step) => (end_index, Self::id(step).cut()?),
                             }
                         }
                     };
 
-                    Ok(IdRangeStep::new(start_index, end_index, step, len).cut()?)
+                    IdRangeStep::new(start_index, end_index, step, len)
+                        .map_err(|e| FormatError::from_range_error(e, input))
+                        .cut()
                 },
             )
             .parse_next(i)?
     }
 
+    /// Parses a string of digits as an id, reporting ids which are too large
+    /// to be represented
+    fn id(s: &'a str) -> Result<u32, FormatError<'a>> {
+        s.parse::<u32>().map_err(|e| match e.kind() {
+            IntErrorKind::PosOverflow => FormatError::IdOverflow(s),
+            _ => e.into(),
+        })
+    }
+
+    /// Parses a string of digits as the offset of an affix range
+    fn id_offset(s: &'a str) -> Result<IdRangeOffset, FormatError<'a>> {
+        IdRangeOffset::new(Self::id(s)?, s.len() as u32)
+            .map_err(|e| FormatError::from_range_error(e, s))
+    }
+
     fn is_padded(s: &str) -> bool {
         s.starts_with('0') && s != "0"
     }
 }
 
+/// Returns true if `name` matches a pattern split into parts around `*`
+#[cfg(feature = "groups")]
+fn glob_matches(parts: &[String], name: &str) -> bool {
+    let [first, middle @ .., last] = parts else {
+        return parts.first().is_some_and(|part| part == name);
+    };
+
+    let Some(mut rest) = name.strip_prefix(first.as_str()) else {
+        return false;
+    };
+
+    for part in middle {
+        let Some(pos) = rest.find(part.as_str()) else {
+            return false;
+        };
+        rest = &rest[pos + part.len()..];
+    }
+
+    rest.ends_with(last.as_str())
+}
+
+/// Sources and groups referenced by a group term (`@sources:groups`)
+#[cfg(feature = "groups")]
+type GroupTerm<T> = (Option<NodeSet<T>>, Option<NodeSet<T>>);
+
 #[derive(Debug, PartialEq)]
 enum IdRangeComponent {
     Single(SingleId),
@@ -570,7 +1309,9 @@ enum IdRangeComponent {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "groups")]
     use crate::collections::config::DummySource;
+    #[cfg(feature = "groups")]
     use itertools::Itertools;
 
     #[test]
@@ -603,6 +1344,11 @@ mod tests {
             }
         );
 
+        assert!(matches!(
+            Parser::id_range_step(&mut "5-3"),
+            Err(ErrMode::Cut(FormatError::EmptyOrInvertedRange("5", "3")))
+        ));
+
         assert!(Parser::id_range_step(&mut "-34/8").is_err());
         assert!(Parser::id_range_step(&mut "/8").is_err());
         assert_eq!(
@@ -619,7 +1365,7 @@ mod tests {
     #[test]
     fn test_id_range_bracketed_affix() {
         assert_eq!(
-            Parser::id_range_bracketed_affix(&mut "[2]").unwrap(),
+            Parser::default().id_range_bracketed_affix()(&mut "[2]").unwrap(),
             IdRangeComponent::IdRange((
                 None,
                 vec![IdRangeStep {
@@ -633,7 +1379,7 @@ mod tests {
         );
 
         assert_eq!(
-            Parser::id_range_bracketed_affix(&mut "[2,3-4,5-67/8]").unwrap(),
+            Parser::default().id_range_bracketed_affix()(&mut "[2,3-4,5-67/8]").unwrap(),
             IdRangeComponent::IdRange((
                 None,
                 vec![
@@ -661,7 +1407,7 @@ mod tests {
         );
 
         assert_eq!(
-            Parser::id_range_bracketed_affix(&mut "12[2-9]").unwrap(),
+            Parser::default().id_range_bracketed_affix()(&mut "12[2-9]").unwrap(),
             (IdRangeComponent::IdRange((
                 Some(IdRangeOffset { value: 12, pad: 2 }),
                 vec![IdRangeStep {
@@ -675,7 +1421,7 @@ mod tests {
         );
 
         assert_eq!(
-            Parser::id_range_bracketed_affix(&mut "05[2-3]").unwrap(),
+            Parser::default().id_range_bracketed_affix()(&mut "05[2-3]").unwrap(),
             (IdRangeComponent::IdRange((
                 Some(IdRangeOffset { value: 5, pad: 2 }),
                 vec![IdRangeStep {
@@ -689,7 +1435,7 @@ mod tests {
         );
 
         assert_eq!(
-            Parser::id_range_bracketed_affix(&mut "[2-3]5").unwrap(),
+            Parser::default().id_range_bracketed_affix()(&mut "[2-3]5").unwrap(),
             (IdRangeComponent::IdRange((
                 None,
                 vec![IdRangeStep {
@@ -703,7 +1449,7 @@ mod tests {
         );
 
         assert_eq!(
-            Parser::id_range_bracketed_affix(&mut "[2-3]05").unwrap(),
+            Parser::default().id_range_bracketed_affix()(&mut "[2-3]05").unwrap(),
             (IdRangeComponent::IdRange((
                 None,
                 vec![IdRangeStep {
@@ -716,15 +1462,15 @@ mod tests {
             )))
         );
 
-        assert!(Parser::id_range_bracketed_affix(&mut "[2,]").is_err());
-        assert!(Parser::id_range_bracketed_affix(&mut "[/8]").is_err());
-        assert!(Parser::id_range_bracketed_affix(&mut "[34-]").is_err());
+        assert!(Parser::default().id_range_bracketed_affix()(&mut "[2,]").is_err());
+        assert!(Parser::default().id_range_bracketed_affix()(&mut "[/8]").is_err());
+        assert!(Parser::default().id_range_bracketed_affix()(&mut "[34-]").is_err());
     }
 
     #[test]
     fn test_id_range_bracketed() {
         assert_eq!(
-            Parser::id_range_bracketed_affix(&mut "[2]").unwrap(),
+            Parser::default().id_range_bracketed_affix()(&mut "[2]").unwrap(),
             (IdRangeComponent::IdRange((
                 None,
                 vec![IdRangeStep {
@@ -737,7 +1483,7 @@ mod tests {
             )))
         );
         assert_eq!(
-            Parser::id_range_bracketed_affix(&mut "[2,3-4,5-67/8]").unwrap(),
+            Parser::default().id_range_bracketed_affix()(&mut "[2,3-4,5-67/8]").unwrap(),
             (IdRangeComponent::IdRange((
                 None,
                 vec![
@@ -764,27 +1510,28 @@ mod tests {
             )))
         );
 
-        assert!(Parser::id_range_bracketed_affix(&mut "[2,]").is_err());
-        assert!(Parser::id_range_bracketed_affix(&mut "[/8]").is_err());
-        assert!(Parser::id_range_bracketed_affix(&mut "[34-]").is_err());
+        assert!(Parser::default().id_range_bracketed_affix()(&mut "[2,]").is_err());
+        assert!(Parser::default().id_range_bracketed_affix()(&mut "[/8]").is_err());
+        assert!(Parser::default().id_range_bracketed_affix()(&mut "[34-]").is_err());
     }
 
     #[test]
     fn test_node_component() {
         //test_component(Parser::node_component);
         assert_eq!(
-            Parser::source_or_node_component(false)
+            Parser::name_component(Names::Node)
                 .parse_next(&mut "http://a1a")
                 .unwrap(),
             "http://a"
         );
     }
 
+    #[cfg(feature = "groups")]
     #[test]
     fn test_source_component() {
         //test_component(Parser::source_component);
         assert_eq!(
-            Parser::source_or_node_component(true)
+            Parser::name_component(Names::Source)
                 .parse_next(&mut "http://a1a")
                 .unwrap(),
             "http"
@@ -818,7 +1565,7 @@ mod tests {
         assert_eq!(
             parser
                 .group::<crate::IdRangeList>()
-                .parse(&mut "@source:group1")
+                .parse("@source:group1")
                 .unwrap()
                 .iter()
                 .join(","),
@@ -828,7 +1575,7 @@ mod tests {
         assert_eq!(
             parser
                 .group::<crate::IdRangeList>()
-                .parse(&mut "@source:group[1,2]")
+                .parse("@source:group[1,2]")
                 .unwrap()
                 .iter()
                 .join(","),
@@ -838,7 +1585,7 @@ mod tests {
         assert_eq!(
             parser
                 .group::<crate::IdRangeList>()
-                .parse(&mut "@source:group:1")
+                .parse("@source:group:1")
                 .unwrap()
                 .iter()
                 .join(","),
@@ -848,7 +1595,7 @@ mod tests {
         assert_eq!(
             parser
                 .group::<crate::IdRangeList>()
-                .parse(&mut "@source:group:[1,2]")
+                .parse("@source:group:[1,2]")
                 .unwrap()
                 .iter()
                 .join(","),
@@ -858,7 +1605,7 @@ mod tests {
         assert_eq!(
             parser
                 .group::<crate::IdRangeList>()
-                .parse(&mut "@source:group:suffix")
+                .parse("@source:group:suffix")
                 .unwrap()
                 .iter()
                 .join(","),
@@ -868,7 +1615,7 @@ mod tests {
         assert_eq!(
             parser
                 .group::<crate::IdRangeList>()
-                .parse(&mut "@source:[2-3]")
+                .parse("@source:[2-3]")
                 .unwrap()
                 .iter()
                 .join(","),
@@ -878,11 +1625,278 @@ mod tests {
         assert_eq!(
             parser
                 .group::<crate::IdRangeList>()
-                .parse(&mut "@source:04")
+                .parse("@source:04")
                 .unwrap()
                 .iter()
                 .join(","),
             "a14,a15"
         );
     }
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn test_group_equal_sign() {
+        let mut resolver = Resolver::default();
+        let mut source = DummySource::new();
+        source.add("arch=x86_64", "a[1-2]");
+        source.add("rack=r1", "a3");
+        source.add("rack=r2", "a4");
+        resolver.add_sources(vec![("source".to_string(), source)]);
+        let parser = Parser::with_resolver(&resolver, Some("source"));
+
+        // '=' is allowed in group names but not in node names
+        assert_eq!(
+            parser
+                .parse::<crate::IdRangeList>("@arch=x86_64,@source:rack=r[1-2]")
+                .unwrap()
+                .to_string(),
+            "a[1-4]"
+        );
+        assert!(parser.parse::<crate::IdRangeList>("a=1").is_err());
+        assert!(parser.parse::<crate::IdRangeList>("@rack=*").unwrap().len() == 2);
+    }
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn test_group_pattern() {
+        let mut resolver = Resolver::default();
+        let mut source = DummySource::new();
+        source.add("rack1", "a[1-2]");
+        source.add("rack2", "a[3-4]");
+        source.add("rack1-ib", "b[1-2]");
+        source.add("login", "l1");
+        resolver.add_sources(vec![("source".to_string(), source)]);
+        let mut other = DummySource::new();
+        other.add("rack9", "z9");
+        resolver.add_sources(vec![("other".to_string(), other)]);
+        let mut literal = DummySource::new();
+        literal.add("a*b", "c1");
+        resolver.add_sources(vec![("literal".to_string(), literal)]);
+
+        let parser = Parser::with_resolver(&resolver, Some("source"));
+        let parse = |s| {
+            parser
+                .parse::<crate::IdRangeList>(s)
+                .map(|ns| ns.to_string())
+        };
+
+        assert_eq!(parse("@rack*").unwrap(), "a[1-4],b[1-2]");
+        assert_eq!(parse("@rack*-ib").unwrap(), "b[1-2]");
+        assert_eq!(parse("@r*c*1").unwrap(), "a[1-2]");
+        assert_eq!(
+            parse("@source:rack*,@other:rack*").unwrap(),
+            "a[1-4],b[1-2],z9"
+        );
+        assert_eq!(parse("@source:*1*").unwrap(), "a[1-2],b[1-2]");
+        assert_eq!(parse("@**").unwrap(), "a[1-4],b[1-2],l1");
+        assert_eq!(parse("@rack* - @rack2,l1").unwrap(), "a[1-2],b[1-2],l1");
+        assert_eq!(parse("@*").unwrap(), "a[1-4],b[1-2],l1");
+        assert_eq!(parse("@source:*").unwrap(), "a[1-4],b[1-2],l1");
+
+        // Escaped asterisks are part of the group name
+        assert_eq!(parse("@literal:a\\*b").unwrap(), "c1");
+        assert!(matches!(
+            parse("@literal:a\\*c"),
+            Err(NodeSetParseError::UnknownGroup { .. })
+        ));
+
+        match parse("@gpu*") {
+            Err(NodeSetParseError::NoMatchingGroup(s)) => assert_eq!(s, "@gpu*"),
+            e => panic!("Expected NoMatchingGroup error, got {e:?}"),
+        }
+        assert!(matches!(
+            parse("@other:login*"),
+            Err(NodeSetParseError::NoMatchingGroup(_))
+        ));
+        assert!(matches!(
+            parse("@unknown:rack*"),
+            Err(NodeSetParseError::UnknownSource { .. })
+        ));
+
+        let options = ParseOptions {
+            ignore_missing_groups: true,
+            ..Default::default()
+        };
+        let ns = parser
+            .with_options(options)
+            .parse::<crate::IdRangeList>("@gpu*,l2")
+            .unwrap();
+        assert_eq!(ns.to_string(), "l2");
+    }
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn test_quoted_groups() {
+        let mut resolver = Resolver::default();
+        let mut source = DummySource::new();
+        source.add("rack 1:a", "n[1-2]");
+        source.add("x:y", "n3");
+        source.add("a,b", "n4");
+        source.add("say \"hi\"", "n5");
+        source.add("rack.b-", "n6");
+        resolver.add_sources(vec![("source".to_string(), source)]);
+        let parser = Parser::with_resolver(&resolver, Some("source"));
+        let parse = |s: &str| {
+            parser
+                .parse::<crate::IdRangeList>(s)
+                .map(|ns| ns.to_string())
+                .unwrap()
+        };
+
+        assert!("rack.b-_+/=:1".chars().all(is_group_char));
+        assert!(!"a,b".chars().all(is_group_char));
+        assert_eq!(quote_group("rack.b-", false), "rack.b-");
+        assert_eq!(quote_group("x:y", false), "\"x:y\"");
+        assert_eq!(quote_group("x:y", true), "x:y");
+        assert_eq!(quote_group("say \"hi\"", true), "\"say \\\"hi\\\"\"");
+
+        assert_eq!(parse("@\"rack 1:a\""), "n[1-2]");
+        assert_eq!(parse("@source:\"rack 1:a\""), "n[1-2]");
+        assert_eq!(parse("@\"x:y\",@\"a,b\""), "n[3-4]");
+        assert_eq!(parse("@source:x:y"), "n3");
+        assert_eq!(parse("@\"say \\\"hi\\\"\""), "n5");
+        assert_eq!(parse("@rack.b-"), "n6");
+        assert_eq!(parse("@*"), "n[1-6]");
+        assert_eq!(
+            parse("@@"),
+            "\"a,b\",\"rack 1:a\",rack.b-,\"say \\\"hi\\\"\",x:y"
+        );
+
+        // Group references round-trip through their quoted form
+        for group in parser.parse::<crate::IdRangeList>("@@").unwrap().iter() {
+            for source in [None, Some("source")] {
+                let reference = resolver.group_reference(source, &group);
+                let nodes: NodeSet = resolver.resolve(Some("source"), &group).unwrap();
+                assert_eq!(parse(&reference), nodes.to_string(), "{reference}");
+            }
+        }
+    }
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn test_no_groups() {
+        /// A source which must not be queried
+        #[derive(Debug)]
+        struct Forbidden;
+
+        impl crate::GroupSource for Forbidden {
+            fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+                panic!("group '{group}' resolved while groups are disabled");
+            }
+
+            fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+                panic!("groups listed while groups are disabled");
+            }
+        }
+
+        let mut resolver = Resolver::default();
+        resolver.add_sources(vec![("source".to_string(), Forbidden)]);
+        let options = ParseOptions {
+            no_groups: true,
+            ..Default::default()
+        };
+        let parser = Parser::with_resolver(&resolver, Some("source")).with_options(options);
+        let parse = |s| {
+            parser
+                .parse::<crate::IdRangeList>(s)
+                .map(|ns| ns.to_string())
+        };
+
+        assert_eq!(
+            parse("node[1-4]!node2,\"a b\"").unwrap(),
+            "\"a b\",node[1,3-4]"
+        );
+        for (input, term) in [
+            ("@rack1", "@rack1"),
+            ("node1,@rack[1-2]", "@rack[1-2]"),
+            ("@source:rack1 node1", "@source:rack1"),
+            ("@@", "@@"),
+            ("@@source", "@@source"),
+            ("@*", "@*"),
+            ("@source:*", "@source:*"),
+            ("node1&(@rack*)", "@rack*"),
+        ] {
+            match parse(input) {
+                Err(NodeSetParseError::GroupsDisabled(s)) => assert_eq!(s, term),
+                e => panic!("Expected GroupsDisabled error for {input}, got {e:?}"),
+            }
+        }
+        assert_eq!(
+            parse("@rack1").unwrap_err().to_string(),
+            "group resolution is disabled, cannot resolve '@rack1'"
+        );
+
+        assert!(matches!(
+            NodeSet::<crate::IdRangeList>::parse_literal("n1,@rack1"),
+            Err(NodeSetParseError::GroupsDisabled(_))
+        ));
+        assert_eq!(
+            NodeSet::<crate::IdRangeList>::parse_literal("n[1-2],n3")
+                .unwrap()
+                .to_string(),
+            "n[1-3]"
+        );
+    }
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn test_unknown_group() {
+        let mut resolver = Resolver::default();
+        let mut source = DummySource::new();
+        source.add("compute", "c[1-4]");
+        source.add("computer", "c5");
+        source.add("rack1", "r1");
+        resolver.add_sources(vec![("cluster".to_string(), source)]);
+
+        let parser = Parser::with_resolver(&resolver, Some("cluster"));
+        let err = parser.parse::<crate::IdRangeList>("@comptue").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown group 'comptue' in source 'cluster' (did you mean 'compute'?)"
+        );
+        let err = parser.parse::<crate::IdRangeList>("@comp").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown group 'comp' in source 'cluster' (did you mean 'compute', 'computer'?)"
+        );
+        match parser.parse::<crate::IdRangeList>("@cluster:gpu") {
+            Err(NodeSetParseError::UnknownGroup {
+                group,
+                group_source,
+                suggestions,
+            }) => {
+                assert_eq!(group, "gpu");
+                assert_eq!(group_source, "cluster");
+                assert!(suggestions.is_empty());
+            }
+            e => panic!("Expected UnknownGroup error, got {e:?}"),
+        }
+
+        // Groups of a set are not required to exist
+        assert_eq!(
+            parser
+                .parse::<crate::IdRangeList>("@rack[1-3]")
+                .unwrap()
+                .to_string(),
+            "r1"
+        );
+
+        let err = parser
+            .parse::<crate::IdRangeList>("@clustr:compute")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown group source: 'clustr' (did you mean 'cluster'?)"
+        );
+
+        let options = ParseOptions {
+            ignore_missing_groups: true,
+            ..Default::default()
+        };
+        let ns = parser
+            .with_options(options)
+            .parse::<crate::IdRangeList>("@comptue,n1")
+            .unwrap();
+        assert_eq!(ns.to_string(), "n1");
+    }
 }
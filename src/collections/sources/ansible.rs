@@ -0,0 +1,500 @@
+use super::NodeGroups;
+use crate::collections::config::GroupSource;
+use crate::collections::nodeset::ConfigurationError;
+use crate::collections::parsers::Parser;
+use crate::collections::NodeSet;
+use crate::idrange::IdRangeList;
+use crate::NodeSetParseError;
+use ini::Properties;
+use itertools::Itertools;
+use log::debug;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Inventory used when the source does not configure one
+const DEFAULT_FILE: &str = "/etc/ansible/hosts";
+
+/// A group source whose groups are read from an Ansible inventory
+/// (`type = ansible` in a groups.conf section)
+///
+/// The `file` inventory (`/etc/ansible/hosts` by default) is read as YAML if
+/// its extension is `.yml` or `.yaml`, and in Ansible's INI format otherwise.
+/// Groups include the hosts of their children groups and the implicit `all`
+/// group contains every host. Host variables are ignored. Host ranges such as
+/// `web[01:50:2]` or `db-[a:c]` are translated to nodesets.
+///
+/// The inventory is read on first use.
+#[derive(Debug)]
+pub(crate) struct AnsibleSource {
+    file: PathBuf,
+    groups: OnceLock<Result<NodeGroups, (ErrorKind, String)>>,
+}
+
+impl AnsibleSource {
+    pub(crate) fn from_props(props: &Properties) -> Result<Self, ConfigurationError> {
+        Ok(Self {
+            file: PathBuf::from(props.get("file").unwrap_or(DEFAULT_FILE)),
+            groups: OnceLock::new(),
+        })
+    }
+
+    /// Returns the groups of the inventory, reading it on first use
+    fn groups(&self) -> Result<&NodeGroups, NodeSetParseError> {
+        self.groups
+            .get_or_init(|| {
+                Inventory::from_file(&self.file).map_err(|e| {
+                    (
+                        e.kind(),
+                        format!("Failed to read '{}': {}", self.file.display(), e),
+                    )
+                })
+            })
+            .as_ref()
+            .map_err(|(kind, e)| NodeSetParseError::Command(std::io::Error::new(*kind, e.clone())))
+    }
+}
+
+impl GroupSource for AnsibleSource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        Ok(self.groups()?.map(group))
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        Ok(self.groups()?.list())
+    }
+
+    fn all(&self) -> Result<Option<String>, NodeSetParseError> {
+        Ok(Some(self.groups()?.all()))
+    }
+
+    fn reverse(&self, node: &str) -> Result<Option<String>, NodeSetParseError> {
+        Ok(Some(self.groups()?.reverse(&node.parse()?)))
+    }
+
+    fn kind(&self) -> &str {
+        "ansible"
+    }
+
+    fn origin(&self) -> Option<String> {
+        Some(self.file.display().to_string())
+    }
+}
+
+/// Groups of an inventory before children are flattened
+#[derive(Debug, Default)]
+struct Inventory {
+    /// Hosts listed directly in each group
+    hosts: BTreeMap<String, NodeSet<IdRangeList>>,
+    /// Children of each group
+    children: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Inventory {
+    fn from_file(path: &Path) -> std::io::Result<NodeGroups> {
+        let content = std::fs::read_to_string(path)?;
+        let invalid = |e: String| std::io::Error::new(ErrorKind::InvalidData, e);
+
+        let inventory = match path.extension().and_then(|e| e.to_str()) {
+            Some("yml" | "yaml") => Self::parse_yaml(&content),
+            _ => Self::parse_ini(&content),
+        }
+        .map_err(invalid)?;
+        let groups = inventory.flatten().map_err(invalid)?;
+
+        debug!(
+            "Read {} groups from Ansible inventory '{}'",
+            groups.len(),
+            path.display()
+        );
+
+        Ok(groups)
+    }
+
+    /// Parses an inventory in Ansible's INI format
+    ///
+    /// Hosts listed before the first section belong to the `ungrouped` group.
+    fn parse_ini(content: &str) -> Result<Self, String> {
+        enum Section {
+            Hosts(String),
+            Children(String),
+            Vars,
+        }
+
+        let mut inventory = Self::default();
+        let mut section = Section::Hosts("ungrouped".to_string());
+
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+
+            let err = |e: String| format!("line {}: {}", n + 1, e);
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = match name.split_once(':') {
+                    None => Section::Hosts(name.to_string()),
+                    Some((group, "children")) => Section::Children(group.to_string()),
+                    Some((_, "vars")) => Section::Vars,
+                    Some(_) => return Err(err(format!("invalid section '{line}'"))),
+                };
+                if let Section::Hosts(group) | Section::Children(group) = &section {
+                    inventory.hosts.entry(group.clone()).or_default();
+                }
+                continue;
+            }
+
+            // Variables follow the host or group name
+            let name = line.split_whitespace().next().unwrap_or_default();
+            match &section {
+                Section::Hosts(group) => {
+                    let hosts = host_pattern(name).map_err(err)?;
+                    inventory.add_hosts(group, &hosts);
+                }
+                Section::Children(group) => inventory.add_child(group, name),
+                Section::Vars => {}
+            }
+        }
+
+        Ok(inventory)
+    }
+
+    /// Parses an inventory in YAML format
+    fn parse_yaml(content: &str) -> Result<Self, String> {
+        use serde_yaml::Value;
+
+        let mut inventory = Self::default();
+        let groups = match serde_yaml::from_str(content).map_err(|e| e.to_string())? {
+            Value::Mapping(groups) => groups,
+            Value::Null => Default::default(),
+            _ => return Err("expected a mapping of groups".to_string()),
+        };
+
+        for (name, group) in &groups {
+            let Value::String(name) = name else {
+                return Err(format!("invalid group name {name:?}"));
+            };
+            inventory.add_yaml_group(name, group)?;
+        }
+
+        Ok(inventory)
+    }
+
+    fn add_yaml_group(&mut self, name: &str, group: &serde_yaml::Value) -> Result<(), String> {
+        use serde_yaml::Value;
+
+        let invalid = |key: &str| Err(format!("group '{name}': invalid '{key}'"));
+
+        self.hosts.entry(name.to_string()).or_default();
+
+        let group = match group {
+            Value::Mapping(group) => group,
+            Value::Null => return Ok(()),
+            _ => return invalid(name),
+        };
+
+        match group.get("hosts") {
+            None | Some(Value::Null) => {}
+            Some(Value::Mapping(hosts)) => {
+                for host in hosts.keys() {
+                    let Value::String(host) = host else {
+                        return invalid("hosts");
+                    };
+                    let hosts = host_pattern(host).map_err(|e| format!("group '{name}': {e}"))?;
+                    self.add_hosts(name, &hosts);
+                }
+            }
+            Some(_) => return invalid("hosts"),
+        }
+
+        match group.get("children") {
+            None | Some(Value::Null) => {}
+            Some(Value::Mapping(children)) => {
+                for (child, definition) in children {
+                    let Value::String(child) = child else {
+                        return invalid("children");
+                    };
+                    self.add_child(name, child);
+                    self.add_yaml_group(child, definition)?;
+                }
+            }
+            Some(_) => return invalid("children"),
+        }
+
+        Ok(())
+    }
+
+    fn add_hosts(&mut self, group: &str, hosts: &NodeSet<IdRangeList>) {
+        self.hosts
+            .entry(group.to_string())
+            .or_default()
+            .extend_from_nodeset(hosts);
+    }
+
+    fn add_child(&mut self, group: &str, child: &str) {
+        self.children
+            .entry(group.to_string())
+            .or_default()
+            .insert(child.to_string());
+        self.hosts.entry(child.to_string()).or_default();
+    }
+
+    /// Adds the hosts of children to each group
+    ///
+    /// Fails if a group is its own descendant.
+    fn flatten(&self) -> Result<NodeGroups, String> {
+        let mut groups = NodeGroups::default();
+        let mut flattened = BTreeMap::new();
+
+        for group in self.hosts.keys() {
+            let hosts = self.flatten_group(group, &mut flattened, &mut vec![])?;
+            groups.add(group.clone(), &hosts);
+        }
+
+        // Like in Ansible, all hosts belong to the `all` group
+        if !self.hosts.contains_key("all") {
+            let all = groups.nodes.clone();
+            groups.add("all".to_string(), &all);
+        }
+        groups.fold();
+
+        Ok(groups)
+    }
+
+    fn flatten_group(
+        &self,
+        group: &str,
+        flattened: &mut BTreeMap<String, NodeSet<IdRangeList>>,
+        stack: &mut Vec<String>,
+    ) -> Result<NodeSet<IdRangeList>, String> {
+        if let Some(hosts) = flattened.get(group) {
+            return Ok(hosts.clone());
+        }
+
+        if let Some(start) = stack.iter().position(|g| g == group) {
+            return Err(format!(
+                "cyclic children groups: {} -> {}",
+                stack[start..].iter().join(" -> "),
+                group
+            ));
+        }
+
+        stack.push(group.to_string());
+        let mut hosts = self.hosts.get(group).cloned().unwrap_or_default();
+        for child in self.children.get(group).into_iter().flatten() {
+            hosts.extend_from_nodeset(&self.flatten_group(child, flattened, stack)?);
+        }
+        stack.pop();
+
+        hosts.fold();
+        flattened.insert(group.to_string(), hosts.clone());
+
+        Ok(hosts)
+    }
+}
+
+/// Converts an Ansible host pattern into a nodeset
+///
+/// Numeric ranges (`[01:50]` or `[1:50:2]`) are translated to ranges of ids
+/// and alphabetic ranges (`[a:f]`) are expanded. A trailing port (`:22`) is
+/// ignored.
+fn host_pattern(pattern: &str) -> Result<NodeSet<IdRangeList>, String> {
+    let invalid = || format!("invalid host pattern '{pattern}'");
+
+    let host = match pattern.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') && port.chars().all(|c| c.is_ascii_digit()) => {
+            host
+        }
+        _ => pattern,
+    };
+
+    // Expansions of alphabetic ranges, in our syntax
+    let mut hosts = vec![String::new()];
+    let mut rest = host;
+    while let Some((before, after)) = rest.split_once('[') {
+        let (range, after) = after.split_once(']').ok_or_else(invalid)?;
+        hosts.iter_mut().for_each(|h| h.push_str(before));
+
+        let bounds: Vec<&str> = range.split(':').collect();
+        let (start, end, step) = match bounds[..] {
+            [start, end] => (start, end, None),
+            [start, end, step] => (start, end, Some(step)),
+            _ => return Err(invalid()),
+        };
+
+        let is_num = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+        let is_alpha = |s: &str| s.len() == 1 && s.chars().all(|c| c.is_ascii_alphabetic());
+
+        if is_num(start) && is_num(end) {
+            let range = match step {
+                Some(step) if is_num(step) => format!("[{start}-{end}/{step}]"),
+                Some(_) => return Err(invalid()),
+                None => format!("[{start}-{end}]"),
+            };
+            hosts.iter_mut().for_each(|h| h.push_str(&range));
+        } else if is_alpha(start) && is_alpha(end) && step.is_none() {
+            let letters = (start.as_bytes()[0]..=end.as_bytes()[0]).map(char::from);
+            hosts = hosts
+                .iter()
+                .cartesian_product(letters)
+                .map(|(h, c)| format!("{h}{c}"))
+                .collect();
+        } else {
+            return Err(invalid());
+        }
+
+        rest = after;
+    }
+    hosts.iter_mut().for_each(|h| h.push_str(rest));
+
+    Parser::default()
+        .parse(&hosts.join(","))
+        .map_err(|e| format!("{}: {}", invalid(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::sources::testing::{command_error, section};
+
+    fn groups(inventory: &str, ext: &str) -> Result<NodeGroups, std::io::Error> {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let file = tmp_dir.path().join(format!("hosts.{ext}"));
+        std::fs::write(&file, inventory).unwrap();
+        Inventory::from_file(&file)
+    }
+
+    #[test]
+    fn test_host_pattern() {
+        for (pattern, expected) in [
+            ("web1.example.com", "web1.example.com"),
+            ("web[01:50].example.com", "web[01-50].example.com"),
+            ("web[1:10:3]", "web[1,4,7,10]"),
+            ("db-[a:c]", "db-a,db-b,db-c"),
+            ("r[1:2]n[a:b]", "r[1-2]na,r[1-2]nb"),
+            ("host[1:2]:2222", "host[1-2]"),
+        ] {
+            assert_eq!(
+                host_pattern(pattern).unwrap(),
+                Parser::default().parse::<IdRangeList>(expected).unwrap(),
+                "{pattern}"
+            );
+        }
+
+        for pattern in [
+            "web[1:",
+            "web[1-2]",
+            "web[a:10]",
+            "web[1:2:x]",
+            "web[1:2:3:4]",
+        ] {
+            assert!(host_pattern(pattern).is_err(), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn test_ansible_ini() {
+        let groups = groups(
+            "mail.example.com\n\
+             \n\
+             [webservers]\n\
+             web[01:04].example.com ansible_port=2222\n\
+             ; comment\n\
+             [dbservers]\n\
+             db-[a:b].example.com\n\
+             \n\
+             [production:children]\n\
+             webservers\n\
+             dbservers\n\
+             \n\
+             [all_servers:children]\n\
+             production\n\
+             ungrouped\n\
+             \n\
+             [production:vars]\n\
+             ntp_server=ntp.example.com\n",
+            "ini",
+        )
+        .unwrap();
+
+        assert_eq!(
+            groups.map("webservers").as_deref(),
+            Some("web[01-04].example.com")
+        );
+        assert_eq!(
+            groups.map("production").as_deref(),
+            Some("db-a.example.com,db-b.example.com,web[01-04].example.com")
+        );
+        assert_eq!(
+            groups.map("all_servers").as_deref(),
+            Some("db-a.example.com,db-b.example.com,mail.example.com,web[01-04].example.com")
+        );
+        assert_eq!(
+            groups.list().join(" "),
+            "all all_servers dbservers production ungrouped webservers"
+        );
+        assert_eq!(
+            groups.reverse(&"web02.example.com".parse().unwrap()),
+            "all all_servers production webservers"
+        );
+    }
+
+    #[test]
+    fn test_ansible_yaml() {
+        let groups = groups(
+            "all:\n  \
+               hosts:\n    \
+                 mail.example.com:\n  \
+               children:\n    \
+                 webservers:\n      \
+                   hosts:\n        \
+                     web[01:04].example.com:\n          \
+                       http_port: 80\n    \
+                 production:\n      \
+                   children:\n        \
+                     webservers:\n        \
+                     dbservers:\n          \
+                       hosts:\n            \
+                         db1.example.com:\n",
+            "yml",
+        )
+        .unwrap();
+
+        assert_eq!(
+            groups.map("production").as_deref(),
+            Some("db1.example.com,web[01-04].example.com")
+        );
+        assert_eq!(
+            groups.map("all").as_deref(),
+            Some("db1.example.com,mail.example.com,web[01-04].example.com")
+        );
+        assert_eq!(
+            groups.list(),
+            vec!["all", "dbservers", "production", "webservers"]
+        );
+    }
+
+    #[test]
+    fn test_ansible_errors() {
+        let e = groups("[a:children]\nb\n[b:children]\nc\n[c:children]\na\n", "ini").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(e.to_string().contains("a -> b -> c -> a"), "{e}");
+
+        let e = groups("a:\n  children:\n    a:\n", "yaml").unwrap_err();
+        assert!(e.to_string().contains("a -> a"), "{e}");
+
+        let e = groups("[web]\nweb[1:\n", "ini").unwrap_err();
+        assert!(e.to_string().starts_with("line 2"), "{e}");
+
+        let e = groups("[web:other]\n", "ini").unwrap_err();
+        assert!(e.to_string().contains("invalid section"), "{e}");
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let missing = tmp_dir.path().join("missing");
+        let conf = format!("[ansible]\nfile = {}\n", missing.display());
+        let source = AnsibleSource::from_props(&section(&conf, "ansible")).unwrap();
+        assert_eq!(command_error(source.list()).kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,352 @@
+use super::{default_cache_dir, parse_bool, parse_timeout, write_cache};
+use crate::collections::config::GroupSource;
+use crate::collections::nodeset::ConfigurationError;
+use crate::NodeSetParseError;
+use ini::Properties;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A group source whose answers are kept for `cache_ttl` seconds
+///
+/// Caching is enabled on any section of groups.conf with `cache_ttl`. Answers
+/// are kept in memory for the current process and, with `cache_disk = yes`,
+/// in files under `$XDG_CACHE_HOME/nodeset/groups/<source>` so that they are
+/// reused by later invocations as well.
+///
+/// Only successful answers are cached: a failed resolution is attempted again
+/// on the next request.
+#[derive(Debug)]
+pub(crate) struct CachedSource {
+    name: String,
+    source: Box<dyn GroupSource>,
+    ttl: Duration,
+    dir: Option<PathBuf>,
+    entries: Mutex<HashMap<String, (Instant, Option<String>)>>,
+}
+
+impl CachedSource {
+    /// Wraps `source` if its section enables caching
+    pub(crate) fn from_props(
+        props: &Properties,
+        name: &str,
+        source: Box<dyn GroupSource>,
+    ) -> Result<Box<dyn GroupSource>, ConfigurationError> {
+        let Some(ttl) = props
+            .get("cache_ttl")
+            .map(|_| parse_timeout(props, "cache_ttl", Duration::ZERO))
+            .transpose()?
+            .flatten()
+        else {
+            return Ok(source);
+        };
+
+        let disk = match props.get("cache_disk") {
+            None => false,
+            Some(v) => parse_bool(v).ok_or_else(|| {
+                ConfigurationError::InvalidValue("cache_disk".to_string(), v.into())
+            })?,
+        };
+
+        let dir = disk
+            .then(default_cache_dir)
+            .flatten()
+            .map(|dir| dir.join("groups").join(escape(name)));
+
+        Ok(Box::new(Self::new(name.to_string(), source, ttl, dir)))
+    }
+
+    fn new(
+        name: String,
+        source: Box<dyn GroupSource>,
+        ttl: Duration,
+        dir: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            name,
+            source,
+            ttl,
+            dir,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the cached answer for `key`, calling `fetch` if there is no
+    /// fresh answer in memory or on disk
+    fn cached(
+        &self,
+        key: String,
+        fetch: impl FnOnce() -> Result<Option<String>, NodeSetParseError>,
+    ) -> Result<Option<String>, NodeSetParseError> {
+        if let Some(value) = self.lookup(&key) {
+            return Ok(value);
+        }
+
+        let value = fetch()?;
+        self.save(key, &value);
+
+        Ok(value)
+    }
+
+    /// Returns the fresh answer for `key` in memory or on disk, if any
+    fn lookup(&self, key: &str) -> Option<Option<String>> {
+        if let Some((time, value)) = self
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+        {
+            if time.elapsed() < self.ttl {
+                return Some(value.clone());
+            }
+        }
+
+        let path = self.dir.as_ref()?.join(escape(key));
+        let age = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| SystemTime::now().duration_since(t).ok())?;
+        let value = std::fs::read_to_string(&path).ok()?;
+        if age >= self.ttl {
+            return None;
+        }
+
+        debug!("Using cached '{}' for source '{}'", key, self.name);
+        let time = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+        self.store(key.to_string(), time, Some(value.clone()));
+        Some(Some(value))
+    }
+
+    /// Caches a fetched answer in memory and, if enabled, on disk
+    fn save(&self, key: String, value: &Option<String>) {
+        if let (Some(dir), Some(value)) = (&self.dir, value) {
+            if let Err(e) = write_cache(&dir.join(escape(&key)), value.as_bytes()) {
+                warn!(
+                    "Failed to cache '{}' for source '{}': {}",
+                    key, self.name, e
+                );
+            }
+        }
+        self.store(key, Instant::now(), value.clone());
+    }
+
+    fn store(&self, key: String, time: Instant, value: Option<String>) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, (time, value));
+    }
+}
+
+impl GroupSource for CachedSource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        self.cached(format!("map-{group}"), || self.source.map(group))
+    }
+
+    fn map_many(&self, groups: &[&str]) -> Vec<Result<Option<String>, NodeSetParseError>> {
+        let cached: Vec<_> = groups
+            .iter()
+            .map(|group| self.lookup(&format!("map-{group}")))
+            .collect();
+
+        // Groups which are not cached are fetched together
+        let missing: Vec<&str> = groups
+            .iter()
+            .zip(&cached)
+            .filter(|(_, value)| value.is_none())
+            .map(|(group, _)| *group)
+            .collect();
+        let mut fetched = self.source.map_many(&missing).into_iter();
+
+        groups
+            .iter()
+            .zip(cached)
+            .map(|(group, value)| match value {
+                Some(value) => Ok(value),
+                None => {
+                    let value = fetched.next().expect("one answer per missing group")?;
+                    self.save(format!("map-{group}"), &value);
+                    Ok(value)
+                }
+            })
+            .collect()
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        let list = self.cached("list".to_string(), || {
+            self.source.list().map(|list| Some(list.join("\n")))
+        })?;
+
+        Ok(list
+            .unwrap_or_default()
+            .lines()
+            .filter(|group| !group.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn all(&self) -> Result<Option<String>, NodeSetParseError> {
+        self.cached("all".to_string(), || self.source.all())
+    }
+
+    fn reverse(&self, node: &str) -> Result<Option<String>, NodeSetParseError> {
+        self.cached(format!("reverse-{node}"), || self.source.reverse(node))
+    }
+
+    fn set_cfgdir(&mut self, cfgdir: &str) -> Result<(), ConfigurationError> {
+        self.source.set_cfgdir(cfgdir)
+    }
+
+    fn kind(&self) -> &str {
+        self.source.kind()
+    }
+
+    fn origin(&self) -> Option<String> {
+        self.source.origin()
+    }
+}
+
+/// Escapes a source name or a cache key into a file name
+fn escape(name: &str) -> String {
+    let mut res = String::with_capacity(name.len());
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || (b"-_.".contains(&b) && !(res.is_empty() && b == b'.')) {
+            res.push(b as char);
+        } else {
+            res.push_str(&format!("%{b:02X}"));
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A source which counts its calls and fails while `failing` is set
+    #[derive(Debug, Default)]
+    struct CountingSource {
+        calls: Arc<AtomicUsize>,
+        failing: Arc<Mutex<bool>>,
+    }
+
+    impl GroupSource for CountingSource {
+        fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            if *self.failing.lock().unwrap() {
+                return Err(NodeSetParseError::Command(std::io::Error::other("down")));
+            }
+
+            Ok((group != "missing").then(|| format!("{group}[1-2]")))
+        }
+
+        fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            Ok(vec!["a".to_string(), "b[1-2]".to_string()])
+        }
+    }
+
+    fn cached_source(dir: Option<PathBuf>, ttl: Duration) -> (CachedSource, CountingSource) {
+        let source = CountingSource::default();
+        let counter = CountingSource {
+            calls: source.calls.clone(),
+            failing: source.failing.clone(),
+        };
+
+        (
+            CachedSource::new("test".to_string(), Box::new(source), ttl, dir),
+            counter,
+        )
+    }
+
+    #[test]
+    fn test_cached_source() {
+        let (source, counter) = cached_source(None, Duration::from_secs(60));
+        let calls = || counter.calls.load(Ordering::Relaxed);
+
+        assert_eq!(source.map("a").unwrap().as_deref(), Some("a[1-2]"));
+        assert_eq!(source.map("a").unwrap().as_deref(), Some("a[1-2]"));
+        assert_eq!(source.map("missing").unwrap(), None);
+        assert_eq!(source.map("missing").unwrap(), None);
+        assert_eq!(source.list().unwrap(), vec!["a", "b[1-2]"]);
+        assert_eq!(source.list().unwrap(), vec!["a", "b[1-2]"]);
+        assert_eq!(source.all().unwrap(), None);
+        assert_eq!(calls(), 3);
+
+        // Errors are not cached
+        *counter.failing.lock().unwrap() = true;
+        assert!(source.map("b").is_err());
+        *counter.failing.lock().unwrap() = false;
+        assert_eq!(source.map("b").unwrap().as_deref(), Some("b[1-2]"));
+        assert_eq!(calls(), 5);
+
+        let (source, counter) = cached_source(None, Duration::from_millis(10));
+        source.map("a").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        source.map("a").unwrap();
+        assert_eq!(counter.calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_cached_source_map_many() {
+        let (source, counter) = cached_source(None, Duration::from_secs(60));
+        let calls = || counter.calls.load(Ordering::Relaxed);
+
+        source.map("b").unwrap();
+        let members: Vec<_> = source
+            .map_many(&["a", "b", "missing"])
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            members,
+            vec![Some("a[1-2]".to_string()), Some("b[1-2]".to_string()), None]
+        );
+        assert_eq!(calls(), 3);
+
+        // Cached groups are not fetched again and failures only affect the
+        // groups being fetched
+        *counter.failing.lock().unwrap() = true;
+        let members = source.map_many(&["a", "c", "missing"]);
+        assert_eq!(members[0].as_ref().unwrap().as_deref(), Some("a[1-2]"));
+        assert!(members[1].is_err());
+        assert_eq!(members[2].as_ref().unwrap(), &None);
+        assert_eq!(calls(), 4);
+    }
+
+    #[test]
+    fn test_cached_source_disk() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path().join("test");
+
+        let (source, counter) = cached_source(Some(dir.clone()), Duration::from_secs(60));
+        assert_eq!(source.map("a/b").unwrap().as_deref(), Some("a/b[1-2]"));
+        assert_eq!(source.list().unwrap(), vec!["a", "b[1-2]"]);
+        assert_eq!(counter.calls.load(Ordering::Relaxed), 2);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("map-a%2Fb")).unwrap(),
+            "a/b[1-2]"
+        );
+
+        // Another process reuses the answers of the first one
+        let (source, counter) = cached_source(Some(dir.clone()), Duration::from_secs(60));
+        *counter.failing.lock().unwrap() = true;
+        assert_eq!(source.map("a/b").unwrap().as_deref(), Some("a/b[1-2]"));
+        assert_eq!(source.list().unwrap(), vec!["a", "b[1-2]"]);
+        assert!(source.map("c").is_err());
+        assert!(!dir.join("map-c").exists());
+        assert_eq!(counter.calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_cache_escape() {
+        assert_eq!(escape("rack-1_a.b"), "rack-1_a.b");
+        assert_eq!(escape("map-a/b c"), "map-a%2Fb%20c");
+        assert_eq!(escape(".."), "%2E.");
+    }
+}
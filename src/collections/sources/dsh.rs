@@ -0,0 +1,276 @@
+use crate::collections::config::{resolve_config_path, GroupSource};
+use crate::collections::nodeset::ConfigurationError;
+use crate::collections::parsers::Parser;
+use crate::collections::NodeSet;
+use crate::idrange::IdRangeList;
+use crate::NodeSetParseError;
+use ini::Properties;
+use itertools::Itertools;
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Group directories searched when the source does not configure them
+const DEFAULT_DIRS: &str = "$HOME/.dsh/group /etc/dsh/group";
+
+/// A group source whose groups are the files of pdsh/dsh group directories
+/// (`type = dsh` in a groups.conf section)
+///
+/// Each file of the `dirs` directories (`$HOME/.dsh/group` and
+/// `/etc/dsh/group` by default) defines a group named after the file, with a
+/// node or a nodeset per line. Text following `#` is ignored and
+/// `@include <group>` lines add the nodes of another group of the
+/// directories. When several directories hold the same group, the first one
+/// wins.
+///
+/// Files are read on each request so that changes are always visible.
+#[derive(Debug)]
+pub(crate) struct DshSource {
+    dirs: Vec<PathBuf>,
+}
+
+impl DshSource {
+    pub(crate) fn from_props(props: &Properties) -> Result<Self, ConfigurationError> {
+        let value = props.get("dirs").unwrap_or(DEFAULT_DIRS);
+        let invalid = || ConfigurationError::InvalidValue("dirs".to_string(), value.to_string());
+
+        let dirs = shlex::split(value)
+            .filter(|dirs| !dirs.is_empty())
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|dir| resolve_config_path(Path::new(dir)).ok_or_else(invalid))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { dirs })
+    }
+
+    /// Returns the file defining `group` in the first directory holding it
+    fn find(&self, group: &str) -> Option<PathBuf> {
+        if !is_group_name(group) {
+            return None;
+        }
+
+        self.dirs
+            .iter()
+            .map(|dir| dir.join(group))
+            .find(|path| path.is_file())
+    }
+
+    /// Returns the names of the groups of all the directories
+    fn groups(&self) -> BTreeSet<String> {
+        self.dirs
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| is_group_name(name))
+            .collect()
+    }
+
+    /// Reads the nodes of `group`, following `@include` lines
+    ///
+    /// `stack` holds the groups being read to detect cyclic inclusions.
+    fn read(
+        &self,
+        group: &str,
+        stack: &mut Vec<String>,
+    ) -> Result<Option<NodeSet<IdRangeList>>, NodeSetParseError> {
+        let Some(path) = self.find(group) else {
+            return Ok(None);
+        };
+
+        if let Some(pos) = stack.iter().position(|g| g == group) {
+            return Err(NodeSetParseError::GroupCycle(
+                stack[pos..].iter().chain([&group.to_string()]).join(" -> "),
+            ));
+        }
+
+        let error = |kind, e: String| {
+            NodeSetParseError::Command(std::io::Error::new(
+                kind,
+                format!("Failed to read '{}': {}", path.display(), e),
+            ))
+        };
+
+        let content = std::fs::read_to_string(&path).map_err(|e| error(e.kind(), e.to_string()))?;
+
+        stack.push(group.to_string());
+
+        let mut nodes = NodeSet::lazy();
+        for (n, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(("@include", include)) = line.split_once(char::is_whitespace) {
+                let include = include.trim();
+                let Some(included) = self.read(include, stack)? else {
+                    return Err(error(
+                        ErrorKind::NotFound,
+                        format!("line {}: unknown group '{}'", n + 1, include),
+                    ));
+                };
+                nodes.extend_from_nodeset(&included);
+                continue;
+            }
+
+            let line_nodes: NodeSet<IdRangeList> = Parser::default().parse(line).map_err(|e| {
+                error(
+                    ErrorKind::InvalidData,
+                    format!("line {}: invalid nodes '{}': {}", n + 1, line, e),
+                )
+            })?;
+            nodes.extend_from_nodeset(&line_nodes);
+        }
+
+        stack.pop();
+        nodes.fold();
+
+        Ok(Some(nodes))
+    }
+}
+
+impl GroupSource for DshSource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        Ok(self
+            .read(group, &mut vec![])?
+            .map(|nodes| nodes.to_string()))
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        Ok(self.groups().into_iter().collect())
+    }
+
+    fn all(&self) -> Result<Option<String>, NodeSetParseError> {
+        let mut all = NodeSet::<IdRangeList>::lazy();
+        for group in self.groups() {
+            if let Some(nodes) = self.read(&group, &mut vec![])? {
+                all.extend_from_nodeset(&nodes);
+            }
+        }
+        all.fold();
+
+        Ok(Some(all.to_string()))
+    }
+
+    fn reverse(&self, node: &str) -> Result<Option<String>, NodeSetParseError> {
+        let node = node.parse()?;
+        let mut groups = vec![];
+        for group in self.groups() {
+            if let Some(nodes) = self.read(&group, &mut vec![])? {
+                if nodes.contains_node(&node) {
+                    groups.push(group);
+                }
+            }
+        }
+
+        Ok(Some(groups.join(" ")))
+    }
+
+    fn kind(&self) -> &str {
+        "dsh"
+    }
+
+    fn origin(&self) -> Option<String> {
+        Some(self.dirs.iter().map(|dir| dir.display()).join(" "))
+    }
+}
+
+/// Returns true if `name` can be the name of a group file
+///
+/// Hidden files such as editor backups are not groups.
+fn is_group_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::sources::testing::{command_error, section};
+
+    fn dsh_source(dirs: &[&Path]) -> DshSource {
+        let conf = format!(
+            "[dsh]\ntype = dsh\ndirs = {}\n",
+            dirs.iter().map(|d| format!("'{}'", d.display())).join(" ")
+        );
+        DshSource::from_props(&section(&conf, "dsh")).unwrap()
+    }
+
+    #[test]
+    fn test_dsh_source() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let user = tmp_dir.path().join("user");
+        let system = tmp_dir.path().join("system");
+        std::fs::create_dir_all(&user).unwrap();
+        std::fs::create_dir_all(&system).unwrap();
+
+        std::fs::write(user.join("compute"), "# Compute nodes\nnode1\nnode2\n").unwrap();
+        std::fs::write(system.join("compute"), "node9\n").unwrap();
+        std::fs::write(system.join("login"), "login[1-2] # frontends\n\n").unwrap();
+        std::fs::write(
+            system.join("cluster"),
+            "@include compute\n@include login\nmgmt1,mgmt2\n",
+        )
+        .unwrap();
+        std::fs::write(system.join(".cluster.swp"), "ignored\n").unwrap();
+
+        let source = dsh_source(&[&user, &system]);
+        assert_eq!(source.map("compute").unwrap().as_deref(), Some("node[1-2]"));
+        assert_eq!(
+            source.map("cluster").unwrap().as_deref(),
+            Some("login[1-2],mgmt[1-2],node[1-2]")
+        );
+        assert_eq!(source.map("other").unwrap(), None);
+        assert_eq!(source.map("../user/compute").unwrap(), None);
+        assert_eq!(source.list().unwrap(), vec!["cluster", "compute", "login"]);
+        assert_eq!(
+            source.all().unwrap().as_deref(),
+            Some("login[1-2],mgmt[1-2],node[1-2]")
+        );
+        assert_eq!(
+            source.reverse("login1").unwrap().as_deref(),
+            Some("cluster login")
+        );
+
+        // Files are read again after they change
+        std::fs::write(user.join("compute"), "node[1-4]\n").unwrap();
+        assert_eq!(
+            source.map("cluster").unwrap().as_deref(),
+            Some("login[1-2],mgmt[1-2],node[1-4]")
+        );
+    }
+
+    #[test]
+    fn test_dsh_source_errors() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        std::fs::write(dir.join("a"), "@include b\n").unwrap();
+        std::fs::write(dir.join("b"), "node1\n@include a\n").unwrap();
+        std::fs::write(dir.join("c"), "node1\n@include missing\n").unwrap();
+        std::fs::write(dir.join("d"), "node[2-1]\n").unwrap();
+
+        let source = dsh_source(&[dir]);
+        match source.map("a") {
+            Err(NodeSetParseError::GroupCycle(cycle)) => assert_eq!(cycle, "a -> b -> a"),
+            e => panic!("Expected GroupCycle error, got {e:?}"),
+        }
+        let e = command_error(source.map("c"));
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert!(
+            e.to_string().contains("line 2: unknown group 'missing'"),
+            "{e}"
+        );
+        assert_eq!(
+            command_error(source.map("d")).kind(),
+            ErrorKind::InvalidData
+        );
+
+        assert!(matches!(
+            DshSource::from_props(&section("[dsh]\ndirs =\n", "dsh")),
+            Err(ConfigurationError::InvalidValue(..))
+        ));
+    }
+}
@@ -0,0 +1,347 @@
+use super::{parse_bool, parse_timeout, CommandFailed};
+use crate::collections::config::GroupSource;
+use crate::collections::nodeset::ConfigurationError;
+use crate::NodeSetParseError;
+use ini::Properties;
+use log::debug;
+use shellexpand::env_with_context_no_errors;
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Timeout applied to upcalls when the source does not configure one
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A group source whose groups are resolved by running external commands
+/// (`type = exec` in a groups.conf section)
+///
+/// Commands are split into arguments with shell quoting rules and executed
+/// directly, with `$GROUP`, `$NODE`, `$SOURCE` and `$CFGDIR` substituted in
+/// each argument. When `shell = true`, commands are substituted as a whole and
+/// run with `/bin/sh -c` instead, like ClusterShell upcalls.
+///
+/// A command which exits with a non-zero status or which runs for more than
+/// `timeout` seconds (10 by default, 0 to wait forever) fails the resolution.
+#[derive(Debug)]
+pub(crate) struct ExecSource {
+    name: String,
+    map: String,
+    all: Option<String>,
+    list: Option<String>,
+    reverse: Option<String>,
+    shell: bool,
+    timeout: Option<Duration>,
+    cfgdir: Option<String>,
+}
+
+impl ExecSource {
+    pub(crate) fn from_props(props: &Properties, name: String) -> Result<Self, ConfigurationError> {
+        let map = props
+            .get("map")
+            .ok_or_else(|| ConfigurationError::MissingProperty("map".to_string()))?
+            .to_string();
+
+        let shell = match props.get("shell") {
+            None => false,
+            Some(v) => parse_bool(v)
+                .ok_or_else(|| ConfigurationError::InvalidValue("shell".to_string(), v.into()))?,
+        };
+
+        let timeout = parse_timeout(props, "timeout", DEFAULT_TIMEOUT)?;
+
+        Ok(Self {
+            name,
+            map,
+            all: props.get("all").map(|s| s.to_string()),
+            list: props.get("list").map(|s| s.to_string()),
+            reverse: props.get("reverse").map(|s| s.to_string()),
+            shell,
+            timeout,
+            cfgdir: None,
+        })
+    }
+
+    /// Builds the command for an upcall, substituting `vars` in addition to
+    /// `$SOURCE` and `$CFGDIR`
+    fn command(&self, cmd: &str, vars: &[(&str, &str)]) -> Result<Command, NodeSetParseError> {
+        let context = |s: &str| match s {
+            "SOURCE" => Some(self.name.as_str()),
+            "CFGDIR" => self.cfgdir.as_deref(),
+            s => vars
+                .iter()
+                .find(|(var, _)| *var == s)
+                .map(|(_, value)| *value),
+        };
+
+        if self.shell {
+            let mut command = Command::new("/bin/sh");
+            command
+                .arg("-c")
+                .arg(env_with_context_no_errors(cmd, context).as_ref());
+            return Ok(command);
+        }
+
+        let args = shlex::split(cmd).ok_or_else(|| {
+            NodeSetParseError::Command(std::io::Error::other(format!(
+                "Command '{}' is not properly quoted",
+                cmd
+            )))
+        })?;
+        let mut args = args
+            .iter()
+            .map(|arg| env_with_context_no_errors(arg, context).to_string());
+
+        let Some(program) = args.next() else {
+            return Err(NodeSetParseError::Command(std::io::Error::other(
+                "Empty command",
+            )));
+        };
+
+        let mut command = Command::new(program);
+        command.args(args);
+        Ok(command)
+    }
+
+    /// Runs an upcall and returns its trimmed standard output
+    fn upcall(
+        &self,
+        kind: &str,
+        cmd: &str,
+        vars: &[(&str, &str)],
+    ) -> Result<String, NodeSetParseError> {
+        let res = run(&mut self.command(cmd, vars)?, cmd, self.timeout)?;
+
+        debug!(
+            "{} command '{}' for source '{}' returned: {}",
+            kind, cmd, self.name, res
+        );
+
+        Ok(res.trim().to_string())
+    }
+}
+
+impl GroupSource for ExecSource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        self.upcall("Map", &self.map, &[("GROUP", group)]).map(Some)
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        let Some(ref list) = self.list else {
+            return Ok(Default::default());
+        };
+
+        Ok(self
+            .upcall("List", list, &[])?
+            .split_whitespace()
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn all(&self) -> Result<Option<String>, NodeSetParseError> {
+        self.all
+            .as_ref()
+            .map(|all| self.upcall("All", all, &[]))
+            .transpose()
+    }
+
+    fn reverse(&self, node: &str) -> Result<Option<String>, NodeSetParseError> {
+        self.reverse
+            .as_ref()
+            .map(|reverse| self.upcall("Reverse", reverse, &[("NODE", node)]))
+            .transpose()
+    }
+
+    fn set_cfgdir(&mut self, cfgdir: &str) -> Result<(), ConfigurationError> {
+        self.cfgdir = Some(cfgdir.to_string());
+        Ok(())
+    }
+
+    fn kind(&self) -> &str {
+        "exec"
+    }
+
+    fn origin(&self) -> Option<String> {
+        Some(self.map.clone())
+    }
+}
+
+/// Runs `command` and returns its standard output
+///
+/// The command fails if it exits with a non-zero status, in which case its
+/// standard error is included in the error, or if it runs for longer than
+/// `timeout`. `cmd` is the command line reported in errors.
+pub(super) fn run(
+    command: &mut Command,
+    cmd: &str,
+    timeout: Option<Duration>,
+) -> Result<String, NodeSetParseError> {
+    let (status, stdout, stderr) = run_status(command, cmd, timeout)?;
+    if !status.success() {
+        return Err(status_error(cmd, status, &stderr));
+    }
+
+    Ok(stdout)
+}
+
+/// Runs `command` and returns its exit status, standard output and standard
+/// error
+///
+/// Unlike [`run`], a command exiting with a non-zero status is not a failure
+/// so that callers can handle statuses with a specific meaning.
+pub(super) fn run_status(
+    command: &mut Command,
+    cmd: &str,
+    timeout: Option<Duration>,
+) -> Result<(ExitStatus, String, String), NodeSetParseError> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            NodeSetParseError::Command(std::io::Error::new(
+                e.kind(),
+                format!("Failed to run '{}': {}", cmd, e),
+            ))
+        })?;
+
+    // Pipes are drained from separate threads so that a command writing
+    // a lot of output cannot block before exiting
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let Some(status) = wait(&mut child, timeout)? else {
+        // The reader threads are left behind as the pipes may still be
+        // held open by processes spawned by the command
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(NodeSetParseError::Command(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!(
+                "Command '{}' timed out after {:?}",
+                cmd,
+                timeout.unwrap_or_default()
+            ),
+        )));
+    };
+
+    let stdout = join(stdout)?;
+    let stderr = join(stderr)?;
+
+    Ok((
+        status,
+        String::from_utf8_lossy(&stdout).into_owned(),
+        String::from_utf8_lossy(&stderr).into_owned(),
+    ))
+}
+
+/// Returns the error for `cmd` exiting with `status`, including its standard
+/// error
+pub(super) fn status_error(cmd: &str, status: ExitStatus, stderr: &str) -> NodeSetParseError {
+    NodeSetParseError::Command(std::io::Error::other(CommandFailed(format!(
+        "Command '{}' failed with {}: {}",
+        cmd,
+        status,
+        stderr.trim()
+    ))))
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<std::io::Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buf)?;
+        }
+        Ok(buf)
+    })
+}
+
+fn join(handle: JoinHandle<std::io::Result<Vec<u8>>>) -> std::io::Result<Vec<u8>> {
+    handle
+        .join()
+        .unwrap_or_else(|_| Err(std::io::Error::other("failed to read command output")))
+}
+
+/// Waits for `child` to exit
+///
+/// Returns None if it is still running after `timeout`
+fn wait(child: &mut Child, timeout: Option<Duration>) -> std::io::Result<Option<ExitStatus>> {
+    let Some(timeout) = timeout else {
+        return child.wait().map(Some);
+    };
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep((deadline - now).min(Duration::from_millis(10)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::sources::testing::{command_error, section};
+
+    fn exec_source(conf: &str) -> ExecSource {
+        ExecSource::from_props(&section(conf, "exec"), "exec".to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_exec_source() {
+        let mut source = exec_source(
+            "[exec]\n\
+             type = exec\n\
+             map = printf '%s' \"node_$GROUP\"\n\
+             list = echo a b\n\
+             all = echo $SOURCE[1-2]\n\
+             reverse = echo $NODE $CFGDIR\n",
+        );
+        source.set_cfgdir("/etc/clustershell").unwrap();
+
+        // Without a shell, substituted values are single arguments
+        assert_eq!(
+            source.map("a b; false").unwrap().as_deref(),
+            Some("node_a b; false")
+        );
+        assert_eq!(source.list().unwrap(), vec!["a", "b"]);
+        assert_eq!(source.all().unwrap().as_deref(), Some("exec[1-2]"));
+        assert_eq!(
+            source.reverse("n1").unwrap().as_deref(),
+            Some("n1 /etc/clustershell")
+        );
+
+        let source = exec_source("[exec]\nmap = echo $GROUP | tr a-z A-Z\nshell = yes\n");
+        assert_eq!(source.map("abc").unwrap().as_deref(), Some("ABC"));
+        assert!(source.list().unwrap().is_empty());
+        assert_eq!(source.all().unwrap(), None);
+    }
+
+    #[test]
+    fn test_exec_source_errors() {
+        let source = exec_source("[exec]\nmap = echo failed >&2; exit 3\nshell = true\n");
+        let e = command_error(source.map("a"));
+        assert!(e.to_string().contains("failed"), "{e}");
+
+        let source = exec_source("[exec]\nmap = sleep 5\ntimeout = 0.1\n");
+        let start = Instant::now();
+        let e = command_error(source.map("a"));
+        assert_eq!(e.kind(), std::io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        assert!(matches!(
+            ExecSource::from_props(
+                &section("[exec]\nmap = true\nshell = maybe\n", "exec"),
+                "exec".to_string()
+            ),
+            Err(ConfigurationError::InvalidValue(..))
+        ));
+    }
+}
@@ -0,0 +1,395 @@
+use super::{parse_timeout, CommandFailed};
+use crate::collections::config::GroupSource;
+use crate::collections::nodeset::ConfigurationError;
+use crate::NodeSetParseError;
+use ini::Properties;
+use log::debug;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default number of seconds during which a failed source is not queried
+const DEFAULT_FAILURE_TTL: Duration = Duration::from_secs(10);
+
+/// A group source which fails fast for `failure_ttl` seconds after a failure
+///
+/// Every section of groups.conf is wrapped so that a source whose backend is
+/// down, such as an HTTP server timing out, is not queried again for each
+/// group during that time: requests fail immediately with
+/// [`NodeSetParseError::SourceUnavailable`] holding the original failure.
+/// `failure_ttl` defaults to 10 seconds, 0 disables this behavior.
+///
+/// Only failures of the source itself mark it as unavailable: errors listing
+/// its groups or nodes, and commands which cannot be run, time out or whose
+/// output cannot be read. A command exiting with an error status for a
+/// single group only fails that group, which is not queried again during the
+/// same time while the other groups of the source are still resolved.
+///
+/// Failures are only kept in memory for the current process, separately from
+/// the answers cached with `cache_ttl` which are still returned while the
+/// source is failing.
+#[derive(Debug)]
+pub(crate) struct FailFastSource {
+    name: String,
+    source: Box<dyn GroupSource>,
+    ttl: Duration,
+    failure: Mutex<Option<(Instant, String)>>,
+    group_failures: Mutex<HashMap<String, (Instant, String)>>,
+}
+
+impl FailFastSource {
+    /// Wraps `source` unless its section disables failure caching
+    pub(crate) fn from_props(
+        props: &Properties,
+        name: &str,
+        source: Box<dyn GroupSource>,
+    ) -> Result<Box<dyn GroupSource>, ConfigurationError> {
+        match parse_timeout(props, "failure_ttl", DEFAULT_FAILURE_TTL)? {
+            Some(ttl) => Ok(Box::new(Self::new(name.to_string(), source, ttl))),
+            None => Ok(source),
+        }
+    }
+
+    fn new(name: String, source: Box<dyn GroupSource>, ttl: Duration) -> Self {
+        Self {
+            name,
+            source,
+            ttl,
+            failure: Mutex::new(None),
+            group_failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the failure of the source if it happened less than `ttl` ago
+    fn failure(&self) -> Option<String> {
+        match &*self.failure.lock().unwrap_or_else(|e| e.into_inner()) {
+            Some((time, error)) if time.elapsed() < self.ttl => Some(error.clone()),
+            _ => None,
+        }
+    }
+
+    /// Fails if the source failed less than `ttl` ago
+    fn check(&self) -> Result<(), NodeSetParseError> {
+        match self.failure() {
+            Some(error) => Err(NodeSetParseError::SourceUnavailable(
+                self.name.clone(),
+                error,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails with the previous error for `group` if it failed less than
+    /// `ttl` ago
+    fn check_group(&self, group: &str) -> Result<(), NodeSetParseError> {
+        match self
+            .group_failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(group)
+        {
+            Some((time, error)) if time.elapsed() < self.ttl => Err(NodeSetParseError::Command(
+                std::io::Error::other(CommandFailed(error.clone())),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records the failure of a request to the source, if any
+    ///
+    /// The failure is returned as [`NodeSetParseError::SourceUnavailable`]
+    /// so that it is reported like the requests failing after it. Sources
+    /// which cannot list their groups are not failing.
+    fn record<T>(&self, res: Result<T, NodeSetParseError>) -> Result<T, NodeSetParseError> {
+        match res {
+            Err(NodeSetParseError::ListUnsupported(_)) | Ok(_) => res,
+            Err(e) => Err(NodeSetParseError::SourceUnavailable(
+                self.name.clone(),
+                self.fail(&e),
+            )),
+        }
+    }
+
+    /// Records the failure of a request for a single group or node, if any
+    ///
+    /// Commands exiting with an error status are recorded for `group` only,
+    /// or not at all if `group` is None.
+    fn record_group<T>(
+        &self,
+        group: Option<&str>,
+        res: Result<T, NodeSetParseError>,
+    ) -> Result<T, NodeSetParseError> {
+        match &res {
+            Err(NodeSetParseError::Command(e))
+                if e.get_ref().is_some_and(|e| e.is::<CommandFailed>()) =>
+            {
+                if let Some(group) = group {
+                    debug!(
+                        "Group '{group}' of source '{}' failed, not querying it again for {}s: {e}",
+                        self.name,
+                        self.ttl.as_secs_f64()
+                    );
+                    self.group_failures
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(group.to_string(), (Instant::now(), e.to_string()));
+                }
+                res
+            }
+            _ => self.record(res),
+        }
+    }
+
+    /// Marks the source as failing and returns the message of `error`,
+    /// including its causes
+    fn fail(&self, error: &NodeSetParseError) -> String {
+        let mut message = error.to_string();
+        let mut cause = error.source();
+        while let Some(e) = cause {
+            message.push_str(&format!(": {e}"));
+            cause = e.source();
+        }
+
+        let mut failure = self.failure.lock().unwrap_or_else(|e| e.into_inner());
+        if failure
+            .as_ref()
+            .is_none_or(|(time, _)| time.elapsed() >= self.ttl)
+        {
+            debug!(
+                "Source '{}' failed, not querying it again for {}s: {message}",
+                self.name,
+                self.ttl.as_secs_f64()
+            );
+        }
+        *failure = Some((Instant::now(), message.clone()));
+        message
+    }
+}
+
+impl GroupSource for FailFastSource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        self.check()?;
+        self.check_group(group)?;
+        self.record_group(Some(group), self.source.map(group))
+    }
+
+    /// Groups are mapped one at a time so that the groups after a failure
+    /// of the source are not queried
+    fn map_many(&self, groups: &[&str]) -> Vec<Result<Option<String>, NodeSetParseError>> {
+        groups.iter().map(|group| self.map(group)).collect()
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        self.check()?;
+        self.record(self.source.list())
+    }
+
+    fn all(&self) -> Result<Option<String>, NodeSetParseError> {
+        self.check()?;
+        self.record(self.source.all())
+    }
+
+    fn reverse(&self, node: &str) -> Result<Option<String>, NodeSetParseError> {
+        self.check()?;
+        self.record_group(None, self.source.reverse(node))
+    }
+
+    fn kind(&self) -> &str {
+        self.source.kind()
+    }
+
+    fn origin(&self) -> Option<String> {
+        self.source.origin()
+    }
+
+    fn set_cfgdir(&mut self, cfgdir: &str) -> Result<(), ConfigurationError> {
+        self.source.set_cfgdir(cfgdir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A source which counts its calls and fails while `failing` is set
+    #[derive(Debug, Default)]
+    struct FlakySource {
+        calls: Arc<AtomicUsize>,
+        failing: Arc<Mutex<bool>>,
+    }
+
+    impl GroupSource for FlakySource {
+        fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            if *self.failing.lock().unwrap() {
+                return Err(NodeSetParseError::Command(std::io::Error::other("down")));
+            }
+
+            Ok(Some(format!("{group}[1-2]")))
+        }
+
+        fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+            self.map("a").map(|_| vec!["a".to_string()])
+        }
+    }
+
+    fn flaky_source(ttl: Duration) -> (FailFastSource, FlakySource) {
+        let source = FlakySource::default();
+        let counter = FlakySource {
+            calls: source.calls.clone(),
+            failing: source.failing.clone(),
+        };
+
+        (
+            FailFastSource::new("flaky".to_string(), Box::new(source), ttl),
+            counter,
+        )
+    }
+
+    #[test]
+    fn test_failfast_source() {
+        let (source, counter) = flaky_source(Duration::from_secs(60));
+        let calls = || counter.calls.load(Ordering::Relaxed);
+
+        assert_eq!(source.map("a").unwrap().as_deref(), Some("a[1-2]"));
+        assert_eq!(calls(), 1);
+
+        // The source is not queried again after a failure
+        *counter.failing.lock().unwrap() = true;
+        for _ in 0..3 {
+            match source.map("b") {
+                Err(NodeSetParseError::SourceUnavailable(name, error)) => {
+                    assert_eq!(name, "flaky");
+                    assert_eq!(error, "external command execution failed: down");
+                }
+                res => panic!("unexpected result: {res:?}"),
+            }
+            *counter.failing.lock().unwrap() = false;
+        }
+        assert!(matches!(
+            source.list(),
+            Err(NodeSetParseError::SourceUnavailable(..))
+        ));
+        let res = source.map_many(&["a", "b"]);
+        assert_eq!(res.len(), 2);
+        assert!(res
+            .iter()
+            .all(|res| matches!(res, Err(NodeSetParseError::SourceUnavailable(..)))));
+        assert_eq!(calls(), 2);
+    }
+
+    #[test]
+    fn test_failfast_source_expiry() {
+        let (source, counter) = flaky_source(Duration::from_millis(50));
+        let calls = || counter.calls.load(Ordering::Relaxed);
+
+        *counter.failing.lock().unwrap() = true;
+        let res = source.map_many(&["a", "b"]);
+        assert!(res
+            .iter()
+            .all(|res| matches!(res, Err(NodeSetParseError::SourceUnavailable(..)))));
+        assert!(source.map("a").is_err());
+        assert_eq!(calls(), 1);
+
+        *counter.failing.lock().unwrap() = false;
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(source.map("a").unwrap().as_deref(), Some("a[1-2]"));
+        assert_eq!(source.list().unwrap(), vec!["a"]);
+        assert_eq!(calls(), 3);
+    }
+
+    #[test]
+    fn test_failfast_source_group_failure() {
+        /// A source whose command exits with an error status for group "bad"
+        #[derive(Debug, Default)]
+        struct PartialSource {
+            calls: Arc<AtomicUsize>,
+        }
+
+        impl GroupSource for PartialSource {
+            fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+                self.calls.fetch_add(1, Ordering::Relaxed);
+                if group == "bad" {
+                    return Err(NodeSetParseError::Command(std::io::Error::other(
+                        CommandFailed("exit status: 3".to_string()),
+                    )));
+                }
+
+                Ok(Some(format!("{group}[1-2]")))
+            }
+
+            fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+                Ok(vec!["bad".to_string(), "good".to_string()])
+            }
+        }
+
+        let partial = PartialSource::default();
+        let calls = partial.calls.clone();
+        let calls = || calls.load(Ordering::Relaxed);
+        let source = FailFastSource::new(
+            "partial".to_string(),
+            Box::new(partial),
+            Duration::from_secs(60),
+        );
+
+        // The other groups of the source are still resolved
+        let res = source.map_many(&["bad", "good"]);
+        assert!(matches!(res[0], Err(NodeSetParseError::Command(_))));
+        assert_eq!(res[1].as_ref().unwrap().as_deref(), Some("good[1-2]"));
+        assert_eq!(source.list().unwrap(), vec!["bad", "good"]);
+        assert_eq!(calls(), 2);
+
+        // The failing group is not queried again
+        match source.map("bad") {
+            Err(NodeSetParseError::Command(e)) => assert_eq!(e.to_string(), "exit status: 3"),
+            res => panic!("unexpected result: {res:?}"),
+        }
+        assert_eq!(source.map("good").unwrap().as_deref(), Some("good[1-2]"));
+        assert_eq!(calls(), 3);
+    }
+
+    #[test]
+    fn test_failfast_source_list_unsupported() {
+        #[derive(Debug)]
+        struct Unlisted;
+
+        impl GroupSource for Unlisted {
+            fn map(&self, _group: &str) -> Result<Option<String>, NodeSetParseError> {
+                Ok(Some("n1".to_string()))
+            }
+
+            fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+                Err(NodeSetParseError::ListUnsupported("unlisted".to_string()))
+            }
+        }
+
+        // Sources which cannot list their groups are still queried
+        let source = FailFastSource::new(
+            "unlisted".to_string(),
+            Box::new(Unlisted),
+            Duration::from_secs(60),
+        );
+        assert!(matches!(
+            source.list(),
+            Err(NodeSetParseError::ListUnsupported(_))
+        ));
+        assert_eq!(source.map("a").unwrap().as_deref(), Some("n1"));
+    }
+
+    #[test]
+    fn test_failfast_source_props() {
+        let mut props = Properties::new();
+        let source = FailFastSource::from_props(&props, "s", Box::new(FlakySource::default()));
+        assert!(format!("{:?}", source.unwrap()).starts_with("FailFastSource"));
+
+        props.insert("failure_ttl", "0");
+        let source = FailFastSource::from_props(&props, "s", Box::new(FlakySource::default()));
+        assert!(format!("{:?}", source.unwrap()).starts_with("FlakySource"));
+
+        props.insert("failure_ttl", "soon");
+        assert!(FailFastSource::from_props(&props, "s", Box::new(FlakySource::default())).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use super::NodeGroups;
+use crate::collections::config::GroupSource;
+use crate::collections::nodeset::ConfigurationError;
+use crate::collections::parsers::Parser;
+use crate::collections::NodeSet;
+use crate::idrange::IdRangeList;
+use crate::NodeSetParseError;
+use ini::Properties;
+use log::debug;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Genders database used when the source does not configure one
+const DEFAULT_FILE: &str = "/etc/genders";
+
+/// A group source whose groups are the attributes of a genders database
+/// (`type = genders` in a groups.conf section)
+///
+/// Each attribute of the `file` database (`/etc/genders` by default) defines
+/// a group with the nodes which have it, and each attribute with a value also
+/// defines an `attr=value` group. `%n` is replaced with the name of each node
+/// in values.
+///
+/// The database is read on first use.
+#[derive(Debug)]
+pub(crate) struct GendersSource {
+    file: PathBuf,
+    genders: OnceLock<Result<NodeGroups, (ErrorKind, String)>>,
+}
+
+impl GendersSource {
+    pub(crate) fn from_props(props: &Properties) -> Result<Self, ConfigurationError> {
+        Ok(Self {
+            file: PathBuf::from(props.get("file").unwrap_or(DEFAULT_FILE)),
+            genders: OnceLock::new(),
+        })
+    }
+
+    /// Returns the parsed database, reading it on first use
+    fn genders(&self) -> Result<&NodeGroups, NodeSetParseError> {
+        self.genders
+            .get_or_init(|| {
+                Genders::from_file(&self.file).map_err(|e| {
+                    (
+                        e.kind(),
+                        format!("Failed to read '{}': {}", self.file.display(), e),
+                    )
+                })
+            })
+            .as_ref()
+            .map_err(|(kind, e)| NodeSetParseError::Command(std::io::Error::new(*kind, e.clone())))
+    }
+}
+
+impl GroupSource for GendersSource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        Ok(self.genders()?.map(group))
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        Ok(self.genders()?.list())
+    }
+
+    fn all(&self) -> Result<Option<String>, NodeSetParseError> {
+        Ok(Some(self.genders()?.all()))
+    }
+
+    fn reverse(&self, node: &str) -> Result<Option<String>, NodeSetParseError> {
+        Ok(Some(self.genders()?.reverse(&node.parse()?)))
+    }
+
+    fn kind(&self) -> &str {
+        "genders"
+    }
+
+    fn origin(&self) -> Option<String> {
+        Some(self.file.display().to_string())
+    }
+}
+
+/// The attributes of the nodes of a genders database
+#[derive(Debug, Default)]
+struct Genders(NodeGroups);
+
+impl Genders {
+    fn from_file(path: &Path) -> std::io::Result<NodeGroups> {
+        let genders = Self::parse(&std::fs::read_to_string(path)?)?;
+
+        debug!(
+            "Read {} groups from genders database '{}'",
+            genders.len(),
+            path.display()
+        );
+
+        Ok(genders)
+    }
+
+    /// Parses a genders database
+    ///
+    /// Each line holds a nodeset followed by a comma-separated list of
+    /// attributes, with an optional value (`attr` or `attr=value`). Comments
+    /// start with `#` and lines ending with `\` continue on the next line.
+    fn parse(content: &str) -> std::io::Result<NodeGroups> {
+        let mut genders = Self::default();
+        let mut line = String::new();
+        let mut start = 0;
+
+        for (n, l) in content.lines().enumerate() {
+            if line.is_empty() {
+                start = n + 1;
+            }
+
+            let mut l = l.split('#').next().unwrap_or_default().trim_end();
+            let continued = l.strip_suffix('\\');
+            if let Some(continued) = continued {
+                l = continued;
+            }
+
+            // Continuation lines carry on the attribute list of the line
+            if !line.is_empty() {
+                l = l.trim_start();
+                if !line.ends_with([',', ' ']) {
+                    line.push(' ');
+                }
+            }
+            line.push_str(l);
+
+            if continued.is_some() {
+                continue;
+            }
+
+            genders.parse_line(&line).map_err(|e| {
+                std::io::Error::new(ErrorKind::InvalidData, format!("line {start}: {e}"))
+            })?;
+            line.clear();
+        }
+
+        genders.parse_line(&line).map_err(|e| {
+            std::io::Error::new(ErrorKind::InvalidData, format!("line {start}: {e}"))
+        })?;
+
+        genders.0.fold();
+
+        Ok(genders.0)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let mut tokens = line.split_whitespace();
+        let Some(nodes) = tokens.next() else {
+            return Ok(());
+        };
+
+        let nodes: NodeSet<IdRangeList> = Parser::default()
+            .parse(nodes)
+            .map_err(|e| format!("invalid nodes '{nodes}': {e}"))?;
+        self.0.add_nodes(&nodes);
+
+        let attrs = tokens.next().unwrap_or_default();
+        if let Some(token) = tokens.next() {
+            return Err(format!("unexpected '{token}'"));
+        }
+
+        for attr in attrs.split(',').filter(|a| !a.is_empty()) {
+            let (name, value) = match attr.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (attr, None),
+            };
+            if name.is_empty() {
+                return Err(format!("invalid attribute '{attr}'"));
+            }
+
+            self.0.add(name.to_string(), &nodes);
+
+            let Some(value) = value else {
+                continue;
+            };
+
+            if !value.contains('%') {
+                self.0.add(format!("{name}={value}"), &nodes);
+                continue;
+            }
+
+            for node in nodes.iter() {
+                let value = substitute(value, &node);
+                let node = Parser::default()
+                    .parse(&node)
+                    .map_err(|e| format!("invalid node '{node}': {e}"))?;
+                self.0.add(format!("{name}={value}"), &node);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces `%n` with `node` and `%%` with `%` in an attribute value
+fn substitute(value: &str, node: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            res.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => res.push_str(node),
+            Some('%') => res.push('%'),
+            Some(c) => {
+                res.push('%');
+                res.push(c);
+            }
+            None => res.push('%'),
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::sources::testing::{command_error, section};
+
+    const GENDERS: &str = "\
+# Compute nodes
+node[1-4]   compute,arch=x86_64,alias=%n-adm
+node[5-6]   compute,arch=aarch64,\\
+            gpu=a100  # continued
+mgmt1       mgmt,\\
+            rack=r1
+spare1
+";
+
+    #[test]
+    fn test_genders_source() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let file = tmp_dir.path().join("genders");
+        std::fs::write(&file, GENDERS).unwrap();
+
+        let conf = format!("[genders]\ntype = genders\nfile = {}\n", file.display());
+        let source = GendersSource::from_props(&section(&conf, "genders")).unwrap();
+
+        assert_eq!(source.map("compute").unwrap().as_deref(), Some("node[1-6]"));
+        assert_eq!(
+            source.map("arch=aarch64").unwrap().as_deref(),
+            Some("node[5-6]")
+        );
+        assert_eq!(
+            source.map("gpu=a100").unwrap().as_deref(),
+            Some("node[5-6]")
+        );
+        assert_eq!(
+            source.map("alias=node2-adm").unwrap().as_deref(),
+            Some("node2")
+        );
+        assert_eq!(source.map("rack=r1").unwrap().as_deref(), Some("mgmt1"));
+        assert_eq!(source.map("spare").unwrap(), None);
+        assert_eq!(
+            source.list().unwrap().join(" "),
+            "alias alias=node1-adm alias=node2-adm alias=node3-adm alias=node4-adm \
+             arch arch=aarch64 arch=x86_64 compute gpu gpu=a100 mgmt rack rack=r1"
+        );
+        assert_eq!(
+            source.all().unwrap().as_deref(),
+            Some("mgmt1,node[1-6],spare1")
+        );
+        assert_eq!(
+            source.reverse("node3").unwrap().as_deref(),
+            Some("alias alias=node3-adm arch arch=x86_64 compute")
+        );
+        assert_eq!(source.reverse("spare1").unwrap().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_genders_substitute() {
+        assert_eq!(substitute("%n-adm", "node1"), "node1-adm");
+        assert_eq!(substitute("100%%,%x%", "node1"), "100%,%x%");
+    }
+
+    #[test]
+    fn test_genders_source_errors() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let file = tmp_dir.path().join("genders");
+
+        let conf = format!("[genders]\nfile = {}\n", file.display());
+        let source = GendersSource::from_props(&section(&conf, "genders")).unwrap();
+        assert_eq!(command_error(source.list()).kind(), ErrorKind::NotFound);
+
+        for (content, line) in [
+            ("node1 a\nnode[2-1] b\n", "line 2"),
+            ("node1 a\\\n  b c\n", "line 1"),
+            ("node1 =a\n", "line 1"),
+        ] {
+            let e = Genders::parse(content).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+            assert!(e.to_string().starts_with(line), "{e}");
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use super::{default_cache_dir, parse_timeout, write_cache};
+use crate::collections::config::{GroupSource, StaticSource};
+use crate::collections::nodeset::ConfigurationError;
+use crate::NodeSetParseError;
+use ini::Properties;
+use log::{debug, warn};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+/// Time during which a cached document is used without fetching it again
+const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+/// Timeout applied to requests when the source does not configure one
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A group source whose groups are fetched from a JSON document over HTTP
+/// (`type = http` in a groups.conf section)
+///
+/// The document at `url` is an object mapping group names to a nodeset or a
+/// list of nodesets, like a single source of a JSON group file. It is
+/// fetched with `curl`, sending `header` if configured, and is kept in the
+/// `cache` file (`$XDG_CACHE_HOME/nodeset/<source>.json` by default) for
+/// `ttl` seconds (300 by default).
+///
+/// When the document cannot be fetched or decoded, an expired cached copy is
+/// used with a warning. Resolution fails if there is none.
+#[derive(Debug)]
+pub(crate) struct HttpSource {
+    name: String,
+    url: String,
+    header: Option<String>,
+    ttl: Duration,
+    timeout: Option<Duration>,
+    cache: Option<PathBuf>,
+    groups: OnceLock<Result<StaticSource, (ErrorKind, String)>>,
+}
+
+impl HttpSource {
+    pub(crate) fn from_props(props: &Properties, name: String) -> Result<Self, ConfigurationError> {
+        let url = props
+            .get("url")
+            .ok_or_else(|| ConfigurationError::MissingProperty("url".to_string()))?
+            .to_string();
+
+        let cache = match props.get("cache") {
+            Some(cache) => Some(PathBuf::from(cache)),
+            None => default_cache_dir().map(|dir| dir.join(format!("{name}.json"))),
+        };
+
+        Ok(Self {
+            url,
+            header: props.get("header").map(|s| s.to_string()),
+            ttl: parse_timeout(props, "ttl", DEFAULT_TTL)?.unwrap_or_default(),
+            timeout: parse_timeout(props, "timeout", DEFAULT_TIMEOUT)?,
+            cache,
+            name,
+            groups: OnceLock::new(),
+        })
+    }
+
+    /// Fetches the document for every process instead of using a cached copy
+    /// before it expires
+    ///
+    /// The cached copy is still used if the document cannot be fetched.
+    pub(crate) fn without_cache(mut self) -> Self {
+        self.ttl = Duration::ZERO;
+        self
+    }
+
+    /// Returns the groups of the source, loading them on first use
+    fn groups(&self) -> Result<&StaticSource, NodeSetParseError> {
+        self.groups
+            .get_or_init(|| self.load().map_err(|e| (e.kind(), e.to_string())))
+            .as_ref()
+            .map_err(|(kind, e)| NodeSetParseError::Command(std::io::Error::new(*kind, e.clone())))
+    }
+
+    fn load(&self) -> std::io::Result<StaticSource> {
+        let cached = self.cache.as_deref().and_then(|cache| {
+            let age = std::fs::metadata(cache)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|m| SystemTime::now().duration_since(m).ok())?;
+            Some((std::fs::read(cache).ok()?, age))
+        });
+
+        if let Some((doc, age)) = &cached {
+            if *age < self.ttl {
+                match self.decode(doc) {
+                    Ok(groups) => {
+                        debug!("Using cached groups for source '{}'", self.name);
+                        return Ok(groups);
+                    }
+                    Err(e) => debug!("Ignoring cached groups for source '{}': {}", self.name, e),
+                }
+            }
+        }
+
+        let err = match self.fetch().and_then(|doc| Ok((self.decode(&doc)?, doc))) {
+            Ok((groups, doc)) => {
+                if let Some(cache) = &self.cache {
+                    if let Err(e) = write_cache(cache, &doc) {
+                        warn!("Failed to cache groups for source '{}': {}", self.name, e);
+                    }
+                }
+                return Ok(groups);
+            }
+            Err(e) => e,
+        };
+
+        match cached.and_then(|(doc, _)| self.decode(&doc).ok()) {
+            Some(groups) => {
+                warn!(
+                    "Using stale cached groups for source '{}': {}",
+                    self.name, err
+                );
+                Ok(groups)
+            }
+            None => Err(err),
+        }
+    }
+
+    /// Fetches the document with curl
+    ///
+    /// The request is passed as a curl config on standard input so that the
+    /// header does not show up in the process list.
+    fn fetch(&self) -> std::io::Result<Vec<u8>> {
+        let mut config = format!("url = {}\n", curl_quote(&self.url));
+        if let Some(header) = &self.header {
+            config.push_str(&format!("header = {}\n", curl_quote(header)));
+        }
+
+        let mut command = Command::new("curl");
+        command.args(["--silent", "--show-error", "--fail", "--location"]);
+        if let Some(timeout) = self.timeout {
+            command
+                .arg("--max-time")
+                .arg(timeout.as_secs_f64().to_string());
+        }
+
+        let mut child = command
+            .args(["--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(config.as_bytes())?;
+        }
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(std::io::Error::other(format!(
+                "Failed to fetch '{}': {}",
+                self.url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        debug!(
+            "Fetched groups for source '{}' from '{}'",
+            self.name, self.url
+        );
+
+        Ok(output.stdout)
+    }
+
+    fn decode(&self, doc: &[u8]) -> std::io::Result<StaticSource> {
+        let invalid = |e: String| {
+            std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid groups from '{}': {}", self.url, e),
+            )
+        };
+
+        // JSON documents are valid YAML
+        let doc = serde_yaml::from_slice(doc).map_err(|e| invalid(e.to_string()))?;
+        StaticSource::from_value(doc, &self.name).map_err(|e| invalid(e.to_string()))
+    }
+}
+
+impl GroupSource for HttpSource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        self.groups()?.map(group)
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        self.groups()?.list()
+    }
+
+    fn kind(&self) -> &str {
+        "http"
+    }
+
+    fn origin(&self) -> Option<String> {
+        Some(self.url.clone())
+    }
+}
+
+/// Quotes a value for a curl config file
+fn curl_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::sources::testing::{command_error, section};
+    use std::path::Path;
+
+    fn http_source(url: &Path, cache: &Path, ttl: u64) -> HttpSource {
+        let conf = format!(
+            "[http]\ntype = http\nurl = file://{}\ncache = {}\nttl = {}\nheader = Authorization: Bearer \"x\"\n",
+            url.display(),
+            cache.display(),
+            ttl
+        );
+        HttpSource::from_props(&section(&conf, "http"), "http".to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_http_source() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let url = tmp_dir.path().join("groups.json");
+        let cache = tmp_dir.path().join("cache").join("http.json");
+
+        std::fs::write(
+            &url,
+            r#"{"login": "login[1-2]", "compute": ["c1", "c[2-3]"]}"#,
+        )
+        .unwrap();
+        let source = http_source(&url, &cache, 300);
+        assert_eq!(source.map("login").unwrap().as_deref(), Some("login[1-2]"));
+        assert_eq!(source.map("compute").unwrap().as_deref(), Some("c1,c[2-3]"));
+        assert_eq!(source.map("other").unwrap(), None);
+        assert!(cache.exists());
+
+        // The cache is used while it is fresh
+        std::fs::write(&url, r#"{"login": "login3"}"#).unwrap();
+        let source = http_source(&url, &cache, 300);
+        assert_eq!(source.map("login").unwrap().as_deref(), Some("login[1-2]"));
+
+        // An expired cache is refreshed
+        let source = http_source(&url, &cache, 0);
+        assert_eq!(source.map("login").unwrap().as_deref(), Some("login3"));
+        assert_eq!(
+            std::fs::read_to_string(&cache).unwrap(),
+            r#"{"login": "login3"}"#
+        );
+
+        // An expired cache is used when the document cannot be fetched or decoded
+        std::fs::write(&url, "{").unwrap();
+        let source = http_source(&url, &cache, 0);
+        assert_eq!(source.map("login").unwrap().as_deref(), Some("login3"));
+        std::fs::remove_file(&url).unwrap();
+        let source = http_source(&url, &cache, 0);
+        assert_eq!(source.list().unwrap(), vec!["login"]);
+    }
+
+    #[test]
+    fn test_http_source_errors() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let url = tmp_dir.path().join("groups.json");
+        let cache = tmp_dir.path().join("http.json");
+
+        let source = http_source(&url, &cache, 300);
+        assert!(matches!(
+            source.map("login"),
+            Err(NodeSetParseError::Command(_))
+        ));
+
+        std::fs::write(&url, r#"["login"]"#).unwrap();
+        let source = http_source(&url, &cache, 300);
+        assert_eq!(command_error(source.list()).kind(), ErrorKind::InvalidData);
+        assert!(!cache.exists());
+
+        assert!(matches!(
+            HttpSource::from_props(
+                &section("[http]\ntype = http\n", "http"),
+                "http".to_string()
+            ),
+            Err(ConfigurationError::MissingProperty(_))
+        ));
+    }
+}
@@ -0,0 +1,202 @@
+mod ansible;
+mod cache;
+mod dsh;
+mod exec;
+mod failures;
+mod genders;
+mod http;
+mod netgroup;
+mod slurm;
+
+pub(crate) use ansible::AnsibleSource;
+pub(crate) use cache::CachedSource;
+pub(crate) use dsh::DshSource;
+pub(crate) use exec::ExecSource;
+pub(crate) use failures::FailFastSource;
+pub(crate) use genders::GendersSource;
+pub(crate) use http::HttpSource;
+pub(crate) use netgroup::NetgroupSource;
+pub(crate) use slurm::SlurmSource;
+
+use crate::collections::nodeset::ConfigurationError;
+use crate::collections::parsers::quote_group;
+use crate::collections::{Node, NodeSet};
+use crate::idrange::IdRangeList;
+use crate::NodeSetParseError;
+use ini::Properties;
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Groups of nodes held in memory by sources which load them all at once
+#[derive(Debug, Default)]
+struct NodeGroups {
+    /// Nodes of each group
+    groups: BTreeMap<String, NodeSet<IdRangeList>>,
+    /// All the nodes known to the source, including nodes without groups
+    nodes: NodeSet<IdRangeList>,
+}
+
+impl NodeGroups {
+    /// Adds `nodes` to `group` and to the nodes known to the source
+    fn add(&mut self, group: String, nodes: &NodeSet<IdRangeList>) {
+        self.groups
+            .entry(group)
+            .or_insert_with(NodeSet::lazy)
+            .extend_from_nodeset(nodes);
+        self.add_nodes(nodes);
+    }
+
+    /// Adds `nodes` to the nodes known to the source without adding them to
+    /// any group
+    fn add_nodes(&mut self, nodes: &NodeSet<IdRangeList>) {
+        self.nodes.extend_from_nodeset(nodes);
+    }
+
+    /// Folds the groups once they have all been added
+    fn fold(&mut self) {
+        self.nodes.fold();
+        self.groups.values_mut().for_each(|nodes| {
+            nodes.fold();
+        });
+    }
+
+    fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    fn map(&self, group: &str) -> Option<String> {
+        self.groups.get(group).map(|nodes| nodes.to_string())
+    }
+
+    fn list(&self) -> Vec<String> {
+        self.groups
+            .keys()
+            .map(|group| quote_group(group, true).into_owned())
+            .collect()
+    }
+
+    fn all(&self) -> String {
+        self.nodes.to_string()
+    }
+
+    /// Returns the names of the groups which contain `node`
+    fn reverse(&self, node: &Node) -> String {
+        self.groups
+            .iter()
+            .filter(|(_, nodes)| nodes.contains_node(node))
+            .map(|(group, _)| quote_group(group, true))
+            .join(" ")
+    }
+}
+
+/// Reads a duration in seconds from the `key` property
+///
+/// Returns `default` if the property is not set, and None if it is 0.
+fn parse_timeout(
+    props: &Properties,
+    key: &str,
+    default: Duration,
+) -> Result<Option<Duration>, ConfigurationError> {
+    let Some(v) = props.get(key) else {
+        return Ok(Some(default));
+    };
+
+    match v.trim().parse::<f64>().map(Duration::try_from_secs_f64) {
+        Ok(Ok(Duration::ZERO)) => Ok(None),
+        Ok(Ok(timeout)) => Ok(Some(timeout)),
+        _ => Err(ConfigurationError::InvalidValue(key.to_string(), v.into())),
+    }
+}
+
+/// Reads a boolean property value (ie `yes` or `false`)
+pub(crate) fn parse_bool(v: &str) -> Option<bool> {
+    match v.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Returns the directory holding the caches of group sources
+///
+/// This is `$XDG_CACHE_HOME/nodeset`, or `$HOME/.cache/nodeset` when
+/// `XDG_CACHE_HOME` is not set.
+pub(crate) fn default_cache_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|dir| dir.join("nodeset"))
+}
+
+/// Replaces the cache file atomically so that concurrent readers never see a
+/// partial document
+fn write_cache(cache: &Path, doc: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = cache.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let mut tmp = cache.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    std::fs::write(&tmp, doc)?;
+    std::fs::rename(&tmp, cache).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
+/// Adds a hint to the error of a command which cannot be found, telling to
+/// set its path with `setting` in the section of `source` in groups.conf
+fn with_path_hint(error: NodeSetParseError, setting: &str, source: &str) -> NodeSetParseError {
+    match error {
+        NodeSetParseError::Command(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            NodeSetParseError::Command(std::io::Error::new(
+                e.kind(),
+                format!(
+                    "{e}. Set '{setting}' in the '{source}' section of groups.conf to the path of {setting}"
+                ),
+            ))
+        }
+        e => e,
+    }
+}
+
+/// A command which ran but exited with an error status
+///
+/// Sources wrap it in [`std::io::Error`] so that a command failing for the
+/// group it was given can be told apart from a source which cannot be
+/// queried at all.
+#[derive(Debug)]
+pub(crate) struct CommandFailed(pub(crate) String);
+
+impl std::fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+/// Helpers shared by the tests of the sources
+#[cfg(test)]
+mod testing {
+    use crate::NodeSetParseError;
+    use ini::{Ini, Properties};
+    use std::fmt::Debug;
+
+    /// Returns the properties of `section` in the configuration `conf`
+    pub(super) fn section(conf: &str, section: &str) -> Properties {
+        let ini = Ini::load_from_str_noescape(conf).unwrap();
+        ini.section(Some(section)).unwrap().clone()
+    }
+
+    /// Returns the error of a request which must fail with
+    /// [`NodeSetParseError::Command`]
+    pub(super) fn command_error<T: Debug>(res: Result<T, NodeSetParseError>) -> std::io::Error {
+        match res {
+            Err(NodeSetParseError::Command(e)) => e,
+            e => panic!("Expected Command error, got {e:?}"),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use super::exec::{run_status, status_error};
+use super::{parse_timeout, with_path_hint};
+use crate::collections::config::GroupSource;
+use crate::collections::nodeset::ConfigurationError;
+use crate::NodeSetParseError;
+use ini::Properties;
+use log::debug;
+use std::process::Command;
+use std::time::Duration;
+
+/// Timeout applied to getent when the source does not configure one
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Exit status of getent when the requested key is not found
+const GETENT_NOT_FOUND: i32 = 2;
+
+/// A group source whose groups are NIS or SSSD netgroups (`type = netgroup`
+/// in a groups.conf section)
+///
+/// Groups are resolved by running `getent netgroup <group>` (the `getent`
+/// command line, which may be a wrapper), whose output lists the
+/// `(host,user,domain)` triples of the netgroup, including those of nested
+/// netgroups. The hosts of the triples are the members of the group, triples
+/// without a host are ignored.
+///
+/// Netgroups cannot be enumerated, so listing the groups, finding the groups
+/// of a node or referring to all the nodes of the source fail with
+/// [`NodeSetParseError::ListUnsupported`].
+#[derive(Debug)]
+pub(crate) struct NetgroupSource {
+    name: String,
+    getent: String,
+    timeout: Option<Duration>,
+}
+
+impl NetgroupSource {
+    pub(crate) fn from_props(props: &Properties, name: String) -> Result<Self, ConfigurationError> {
+        let getent = props.get("getent").unwrap_or("getent").to_string();
+        if shlex::split(&getent).is_none_or(|args| args.is_empty()) {
+            return Err(ConfigurationError::InvalidValue(
+                "getent".to_string(),
+                getent,
+            ));
+        }
+
+        Ok(Self {
+            name,
+            getent,
+            timeout: parse_timeout(props, "timeout", DEFAULT_TIMEOUT)?,
+        })
+    }
+
+    fn list_unsupported(&self) -> NodeSetParseError {
+        NodeSetParseError::ListUnsupported(self.name.clone())
+    }
+}
+
+impl GroupSource for NetgroupSource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        // Validated when the source is configured
+        let args = shlex::split(&self.getent).unwrap_or_default();
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..]).args(["netgroup", group]);
+
+        let (status, stdout, stderr) = run_status(&mut command, &self.getent, self.timeout)
+            .map_err(|e| with_path_hint(e, "getent", &self.name))?;
+
+        if status.code() == Some(GETENT_NOT_FOUND) {
+            return Ok(None);
+        }
+        if !status.success() {
+            return Err(status_error(&self.getent, status, &stderr));
+        }
+
+        let hosts = parse_netgroup(&stdout);
+        debug!(
+            "getent returned {} hosts for netgroup '{}' of source '{}'",
+            hosts.len(),
+            group,
+            self.name
+        );
+
+        Ok(Some(hosts.join(",")))
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        Err(self.list_unsupported())
+    }
+
+    fn all(&self) -> Result<Option<String>, NodeSetParseError> {
+        Err(self.list_unsupported())
+    }
+
+    fn reverse(&self, _node: &str) -> Result<Option<String>, NodeSetParseError> {
+        Err(self.list_unsupported())
+    }
+
+    fn kind(&self) -> &str {
+        "netgroup"
+    }
+
+    fn origin(&self) -> Option<String> {
+        Some(self.getent.clone())
+    }
+}
+
+/// Returns the hosts of the `(host,user,domain)` triples in the output of
+/// `getent netgroup`, which starts with the name of the netgroup
+fn parse_netgroup(output: &str) -> Vec<&str> {
+    output
+        .split('(')
+        .skip(1)
+        .filter_map(|triple| triple.split([',', ')']).next())
+        .map(str::trim)
+        .filter(|host| !host.is_empty() && *host != "-")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::sources::testing::{command_error, section};
+    use std::io::ErrorKind;
+
+    fn netgroup_source(conf: &str) -> NetgroupSource {
+        NetgroupSource::from_props(&section(conf, "netgroup"), "net".to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_netgroup() {
+        assert_eq!(
+            parse_netgroup("compute  (node1,-,) (node2,,example.com)\n(node3, alice, ) (,bob,)"),
+            vec!["node1", "node2", "node3"]
+        );
+        assert!(parse_netgroup("empty\n").is_empty());
+    }
+
+    #[test]
+    fn test_netgroup_source() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let fake = tmp_dir.path().join("getent");
+        std::fs::write(
+            &fake,
+            "[ \"$1\" = netgroup ] || exit 1\n\
+             case \"$2\" in\n\
+             compute) echo 'compute (node1,,) (node2,-,dom) (node3,,) (,admin,)' ;;\n\
+             empty) echo 'empty' ;;\n\
+             broken) echo 'NIS server down' >&2; exit 3 ;;\n\
+             *) exit 2 ;;\n\
+             esac\n",
+        )
+        .unwrap();
+
+        let source = netgroup_source(&format!(
+            "[netgroup]\ntype = netgroup\ngetent = /bin/sh '{}'\n",
+            fake.display()
+        ));
+
+        assert_eq!(
+            source.map("compute").unwrap().as_deref(),
+            Some("node1,node2,node3")
+        );
+        assert_eq!(source.map("empty").unwrap().as_deref(), Some(""));
+        assert_eq!(source.map("missing").unwrap(), None);
+        let e = command_error(source.map("broken"));
+        assert!(e.to_string().contains("NIS server down"), "{e}");
+
+        assert!(matches!(
+            source.list(),
+            Err(NodeSetParseError::ListUnsupported(name)) if name == "net"
+        ));
+        assert!(matches!(
+            source.all(),
+            Err(NodeSetParseError::ListUnsupported(_))
+        ));
+        assert!(matches!(
+            source.reverse("node1"),
+            Err(NodeSetParseError::ListUnsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_netgroup_source_errors() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let missing = tmp_dir.path().join("getent");
+        let source = netgroup_source(&format!("[netgroup]\ngetent = {}\n", missing.display()));
+        let e = command_error(source.map("compute"));
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert!(e.to_string().contains("'net' section"), "{e}");
+
+        assert!(matches!(
+            NetgroupSource::from_props(
+                &section("[netgroup]\ngetent =\n", "netgroup"),
+                "net".to_string()
+            ),
+            Err(ConfigurationError::InvalidValue(..))
+        ));
+    }
+}
@@ -0,0 +1,270 @@
+use super::exec::run;
+use super::{parse_timeout, with_path_hint, NodeGroups};
+use crate::collections::config::GroupSource;
+use crate::collections::nodeset::ConfigurationError;
+use crate::collections::parsers::Parser;
+use crate::NodeSetParseError;
+use ini::Properties;
+use log::debug;
+use std::io::ErrorKind;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Time during which the output of sinfo is reused when the source does not
+/// configure one
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// Timeout applied to sinfo when the source does not configure one
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fields requested from sinfo: partition, compact state and nodes
+const SINFO_FORMAT: &str = "--format=%R|%t|%N";
+
+/// A group source whose groups are the partitions and node states of a Slurm
+/// cluster (`type = slurm` in a groups.conf section)
+///
+/// Groups are read from the output of `sinfo` (the `sinfo` command line, which
+/// may include arguments such as `--clusters`). Each partition defines a
+/// group, and each node state defines a `state:<state>` group (ie
+/// `state:idle` or `state:drain`) which must be referred to with its source
+/// (`@slurm:state:idle`). The output of sinfo is reused for `ttl` seconds (60
+/// by default, 0 to run it for every request).
+#[derive(Debug)]
+pub(crate) struct SlurmSource {
+    name: String,
+    sinfo: String,
+    ttl: Option<Duration>,
+    timeout: Option<Duration>,
+    groups: Mutex<Option<(Instant, Arc<NodeGroups>)>>,
+}
+
+impl SlurmSource {
+    pub(crate) fn from_props(props: &Properties, name: String) -> Result<Self, ConfigurationError> {
+        let sinfo = props.get("sinfo").unwrap_or("sinfo").to_string();
+        if shlex::split(&sinfo).is_none_or(|args| args.is_empty()) {
+            return Err(ConfigurationError::InvalidValue("sinfo".to_string(), sinfo));
+        }
+
+        Ok(Self {
+            name,
+            sinfo,
+            ttl: parse_timeout(props, "ttl", DEFAULT_TTL)?,
+            timeout: parse_timeout(props, "timeout", DEFAULT_TIMEOUT)?,
+            groups: Mutex::new(None),
+        })
+    }
+
+    /// Returns the groups of the cluster, running sinfo if they are not
+    /// cached or have expired
+    fn groups(&self) -> Result<Arc<NodeGroups>, NodeSetParseError> {
+        let mut cache = self.groups.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let (Some((time, groups)), Some(ttl)) = (cache.as_ref(), self.ttl) {
+            if time.elapsed() < ttl {
+                return Ok(groups.clone());
+            }
+        }
+
+        let groups = Arc::new(self.sinfo()?);
+        *cache = Some((Instant::now(), groups.clone()));
+
+        Ok(groups)
+    }
+
+    fn sinfo(&self) -> Result<NodeGroups, NodeSetParseError> {
+        // Validated when the source is configured
+        let args = shlex::split(&self.sinfo).unwrap_or_default();
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..]).args(["--noheader", SINFO_FORMAT]);
+
+        let output = run(&mut command, &self.sinfo, self.timeout)
+            .map_err(|e| with_path_hint(e, "sinfo", &self.name))?;
+
+        let groups = parse_sinfo(&output).map_err(|e| {
+            NodeSetParseError::Command(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Unexpected output from '{}': {}", self.sinfo, e),
+            ))
+        })?;
+
+        debug!(
+            "sinfo returned {} groups for source '{}'",
+            groups.len(),
+            self.name
+        );
+
+        Ok(groups)
+    }
+}
+
+impl GroupSource for SlurmSource {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        Ok(self.groups()?.map(group))
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        Ok(self.groups()?.list())
+    }
+
+    fn all(&self) -> Result<Option<String>, NodeSetParseError> {
+        Ok(Some(self.groups()?.all()))
+    }
+
+    fn reverse(&self, node: &str) -> Result<Option<String>, NodeSetParseError> {
+        Ok(Some(self.groups()?.reverse(&node.parse()?)))
+    }
+
+    fn kind(&self) -> &str {
+        "slurm"
+    }
+
+    fn origin(&self) -> Option<String> {
+        Some(self.sinfo.clone())
+    }
+}
+
+/// Parses `partition|state|nodes` lines from sinfo
+fn parse_sinfo(output: &str) -> Result<NodeGroups, String> {
+    let mut groups = NodeGroups::default();
+
+    for (n, line) in output.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut fields = line.trim().splitn(3, '|');
+        let (Some(partition), Some(state), Some(nodes)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(format!("line {}: '{}'", n + 1, line));
+        };
+
+        // Partitions without nodes are reported with an empty list
+        if nodes.is_empty() || nodes == "n/a" {
+            continue;
+        }
+
+        let nodes = Parser::default()
+            .parse(nodes)
+            .map_err(|e| format!("line {}: invalid nodes '{}': {}", n + 1, nodes, e))?;
+
+        // Flags such as '*' for non-responding nodes are appended to states
+        let state = state.trim_end_matches(|c: char| !c.is_ascii_alphanumeric());
+
+        groups.add(partition.to_string(), &nodes);
+        if !state.is_empty() {
+            groups.add(format!("state:{state}"), &nodes);
+        }
+    }
+
+    groups.fold();
+
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::sources::testing::{command_error, section};
+
+    const SINFO: &str = "\
+batch|idle|node[01-10]
+batch|alloc|node[11-20]
+debug|drain*|node[21-22]
+debug|idle|node[23-24]
+gpu|mix-|gpu[1-2]
+empty|n/a|
+";
+
+    fn slurm_source(conf: &str) -> SlurmSource {
+        SlurmSource::from_props(&section(conf, "slurm"), "slurm".to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_slurm_source() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let calls = tmp_dir.path().join("calls");
+        let fake = tmp_dir.path().join("sinfo");
+        std::fs::write(
+            &fake,
+            format!(
+                "echo \"$@\" >> '{}'\ncat <<EOF\n{}EOF\n",
+                calls.display(),
+                SINFO
+            ),
+        )
+        .unwrap();
+
+        let source = slurm_source(&format!(
+            "[slurm]\ntype = slurm\nsinfo = /bin/sh '{}' --cluster=c1\n",
+            fake.display()
+        ));
+
+        assert_eq!(source.map("batch").unwrap().as_deref(), Some("node[01-20]"));
+        assert_eq!(
+            source.map("state:idle").unwrap().as_deref(),
+            Some("node[01-10,23-24]")
+        );
+        assert_eq!(
+            source.map("state:drain").unwrap().as_deref(),
+            Some("node[21-22]")
+        );
+        assert_eq!(
+            source.map("state:mix").unwrap().as_deref(),
+            Some("gpu[1-2]")
+        );
+        assert_eq!(source.map("empty").unwrap(), None);
+        assert_eq!(
+            source.list().unwrap().join(" "),
+            "batch debug gpu state:alloc state:drain state:idle state:mix"
+        );
+        assert_eq!(
+            source.all().unwrap().as_deref(),
+            Some("gpu[1-2],node[01-24]")
+        );
+        assert_eq!(
+            source.reverse("node22").unwrap().as_deref(),
+            Some("debug state:drain")
+        );
+
+        // sinfo only ran once
+        assert_eq!(
+            std::fs::read_to_string(&calls).unwrap(),
+            "--cluster=c1 --noheader --format=%R|%t|%N\n"
+        );
+
+        let source = slurm_source(&format!(
+            "[slurm]\nsinfo = /bin/sh '{}'\nttl = 0\n",
+            fake.display()
+        ));
+        source.list().unwrap();
+        source.list().unwrap();
+        assert_eq!(std::fs::read_to_string(&calls).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn test_slurm_source_errors() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let missing = tmp_dir.path().join("sinfo");
+        let source = slurm_source(&format!("[slurm]\nsinfo = {}\n", missing.display()));
+        let e = command_error(source.list());
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert!(e.to_string().contains("'slurm' section"), "{e}");
+
+        let source = slurm_source(
+            "[slurm]\nsinfo = /bin/sh -c 'echo \"slurm_load_partitions: down\" >&2; exit 1'\n",
+        );
+        let e = command_error(source.map("batch"));
+        assert!(e.to_string().contains("slurm_load_partitions: down"), "{e}");
+
+        let source = slurm_source("[slurm]\nsinfo = echo unexpected\n");
+        let e = command_error(source.map("batch"));
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        assert!(matches!(
+            SlurmSource::from_props(&section("[slurm]\nsinfo =\n", "slurm"), "slurm".to_string()),
+            Err(ConfigurationError::InvalidValue(..))
+        ));
+    }
+}
@@ -3,35 +3,199 @@ mod rangetree;
 
 pub use rangelist::IdRangeList;
 pub use rangetree::IdRangeTree;
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, ops::RangeInclusive};
 
-/// Iterators implementing this trait guarantee that their elements are sorted are deduplicated
+/// Iterators implementing this trait guarantee that their elements are sorted and deduplicated
 pub trait SortedIterator: Iterator {}
 
 /// Interface for a 1-dimensional range of integers
+///
+/// This is the storage backend of the ids of each dimension of a [`NodeSet`],
+/// implemented by [`IdRangeList`] and [`IdRangeTree`]. Other backends can be
+/// used with `NodeSet<T>` by implementing this trait along with `PartialEq`,
+/// `Clone`, `Debug` and `Display`.
+///
+/// # Invariants
+///
+/// - Elements are ranks: opaque `u32` which order zero-padded ids (ie `9 <
+///   00 < 01`). Backends store them as is and never interpret them.
+/// - Unless the range is lazy, its elements are sorted and deduplicated: this
+///   is the case after `new`, the `From` conversions, `from_sorted`, `sort`
+///   and any operation adding elements.
+/// - A lazy range may hold unsorted and duplicated elements until `sort` is
+///   called. Only `push`, `push_idrs`, `iter`, `len`, `sort` and
+///   `shrink_to_fit` are called on lazy ranges. Backends which are always
+///   sorted may ignore laziness altogether.
+/// - All iterators yield sorted and deduplicated elements, `SelfIter`
+///   excepted on lazy ranges.
+/// - Two ranges are equal if they hold the same elements.
+/// - `Display` formats the range as it appears in a node name, which
+///   [`fmt_id_range`] implements.
+///
+/// [`NodeSet`]: crate::NodeSet
+///
+/// # Examples
+///
+/// A trivial backend storing ranks in a sorted `Vec`:
+///
+/// ```
+/// use nodeset::{fmt_id_range, IdRange, NodeSet, RankRanges, SortedIterator};
+/// use std::fmt;
+///
+/// #[derive(Debug, Clone, PartialEq)]
+/// struct Ranks(Vec<u32>);
+///
+/// #[derive(Debug, Clone)]
+/// struct Sorted(std::vec::IntoIter<u32>);
+///
+/// impl Iterator for Sorted {
+///     type Item = u32;
+///
+///     fn next(&mut self) -> Option<u32> {
+///         self.0.next()
+///     }
+/// }
+///
+/// impl SortedIterator for Sorted {}
+///
+/// impl Ranks {
+///     fn filter(&self, f: impl Fn(&u32) -> bool) -> Sorted {
+///         Sorted(self.0.iter().copied().filter(f).collect::<Vec<_>>().into_iter())
+///     }
+///
+///     fn merge(&self, other: &Self) -> Vec<u32> {
+///         let mut v = [&self.0[..], &other.0[..]].concat();
+///         v.sort_unstable();
+///         v.dedup();
+///         v
+///     }
+/// }
+///
+/// impl From<u32> for Ranks {
+///     fn from(rank: u32) -> Self {
+///         Ranks(vec![rank])
+///     }
+/// }
+///
+/// impl From<Vec<u32>> for Ranks {
+///     fn from(mut ranks: Vec<u32>) -> Self {
+///         ranks.sort_unstable();
+///         ranks.dedup();
+///         Ranks(ranks)
+///     }
+/// }
+///
+/// impl fmt::Display for Ranks {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         fmt_id_range(self, f)
+///     }
+/// }
+///
+/// impl IdRange for Ranks {
+///     type SelfIter<'a> = Sorted;
+///     type DifferenceIter<'a> = Sorted;
+///     type SymmetricDifferenceIter<'a> = Sorted;
+///     type IntersectionIter<'a> = Sorted;
+///     type UnionIter<'a> = Sorted;
+///
+///     fn new() -> Self {
+///         Ranks(vec![])
+///     }
+///
+///     // Ranks are always sorted
+///     fn lazy(self) -> Self {
+///         self
+///     }
+///     fn set_lazy(&mut self) {}
+///     fn sort(&mut self) {}
+///
+///     fn difference<'a>(&'a self, other: &'a Self) -> Sorted {
+///         self.filter(|r| !other.contains(*r))
+///     }
+///     fn symmetric_difference<'a>(&'a self, other: &'a Self) -> Sorted {
+///         let union = Ranks(self.merge(other));
+///         union.filter(|r| !(self.contains(*r) && other.contains(*r)))
+///     }
+///     fn intersection<'a>(&'a self, other: &'a Self) -> Sorted {
+///         self.filter(|r| other.contains(*r))
+///     }
+///     fn union<'a>(&'a self, other: &'a Self) -> Sorted {
+///         Sorted(self.merge(other).into_iter())
+///     }
+///
+///     fn contains(&self, id: u32) -> bool {
+///         self.0.binary_search(&id).is_ok()
+///     }
+///     fn iter(&self) -> Sorted {
+///         Sorted(self.0.clone().into_iter())
+///     }
+///     fn is_empty(&self) -> bool {
+///         self.0.is_empty()
+///     }
+///     fn len(&self) -> usize {
+///         self.0.len()
+///     }
+///
+///     fn push(&mut self, other: &Self) {
+///         self.0 = self.merge(other);
+///     }
+///     fn push_idrs(&mut self, ranges: impl RankRanges) {
+///         let mut ranks = std::mem::take(&mut self.0);
+///         for (start, end, step) in ranges.rank_ranges() {
+///             ranks.extend((start..=end).step_by(step as usize));
+///         }
+///         *self = Ranks::from(ranks);
+///     }
+///
+///     fn from_sorted(ranks: impl IntoIterator<Item = u32> + SortedIterator) -> Self {
+///         Ranks(ranks.into_iter().collect())
+///     }
+///     fn assign_sorted(&mut self, ranks: impl IntoIterator<Item = u32> + SortedIterator) {
+///         *self = Self::from_sorted(ranks);
+///     }
+///
+///     fn shrink_to_fit(&mut self) {
+///         self.0.shrink_to_fit();
+///     }
+/// }
+///
+/// let ns: NodeSet<Ranks> = "node[1-5,03] - node[2-3]".parse().unwrap();
+///
+/// assert_eq!(ns.to_string(), "node[1,4-5,03]");
+/// assert_eq!(ns.len(), 4);
+/// ```
 pub trait IdRange: From<Vec<u32>> + From<u32> {
+    /// Iterator over the elements of a range
     type SelfIter<'a>: Iterator<Item = u32> + Clone + fmt::Debug
     where
         Self: 'a;
 
+    /// Iterator over the elements of a range that are not in another range
     type DifferenceIter<'a>: Iterator<Item = u32> + SortedIterator
     where
         Self: 'a;
+    /// Iterator over the elements in either of two ranges but not both
     type SymmetricDifferenceIter<'a>: Iterator<Item = u32> + SortedIterator
     where
         Self: 'a;
+    /// Iterator over the elements in both of two ranges
     type IntersectionIter<'a>: Iterator<Item = u32> + SortedIterator
     where
         Self: 'a;
+    /// Iterator over the elements in either of two ranges
     type UnionIter<'a>: Iterator<Item = u32> + SortedIterator
     where
         Self: 'a;
+
+    /// Creates an empty range
     fn new() -> Self;
+
     /// Makes the range lazy, meaning that it will no longer be automatically sorted or deduplicated when adding elements.
     ///
     /// Ensemblist operations will fail while the range is in lazy mode.
     fn lazy(self) -> Self;
 
+    /// Makes the range lazy in place, see [`IdRange::lazy`]
     fn set_lazy(&mut self);
 
     /// Restores the range to a non-lazy state. Sorts and deduplicates the range.
@@ -44,7 +208,7 @@ pub trait IdRange: From<Vec<u32>> + From<u32> {
 
     /// Returns an iterator over elements in either range but not both
     ///
-    /// Fails if the range is lazy. The returned iterator is sorted and deduplicateda
+    /// Fails if the range is lazy. The returned iterator is sorted and deduplicated
     fn symmetric_difference<'a>(&'a self, other: &'a Self) -> Self::SymmetricDifferenceIter<'a>;
 
     /// Returns an iterator over elements in both ranges
@@ -79,6 +243,90 @@ pub trait IdRange: From<Vec<u32>> + From<u32> {
 
     /// Extends the range with elements from the given iterator
     fn from_sorted(indexes: impl IntoIterator<Item = u32> + SortedIterator) -> Self;
+
+    /// Replaces the elements of the range with elements from the given
+    /// iterator, reusing the storage of the range when possible
+    fn assign_sorted(&mut self, indexes: impl IntoIterator<Item = u32> + SortedIterator);
+
+    /// Replaces the elements of `out` with elements in the range that are not
+    /// in other range
+    ///
+    /// Fails if either range is lazy
+    fn difference_into(&self, other: &Self, out: &mut Self) {
+        out.assign_sorted(self.difference(other));
+    }
+
+    /// Replaces the elements of `out` with elements in either range but not
+    /// both
+    ///
+    /// Fails if either range is lazy
+    fn symmetric_difference_into(&self, other: &Self, out: &mut Self) {
+        out.assign_sorted(self.symmetric_difference(other));
+    }
+
+    /// Replaces the elements of `out` with elements in both ranges
+    ///
+    /// Fails if either range is lazy
+    fn intersection_into(&self, other: &Self, out: &mut Self) {
+        out.assign_sorted(self.intersection(other));
+    }
+
+    /// Replaces the elements of `out` with elements in either range
+    ///
+    /// Fails if either range is lazy
+    fn union_into(&self, other: &Self, out: &mut Self) {
+        out.assign_sorted(self.union(other));
+    }
+
+    /// Releases unused memory held by the range
+    fn shrink_to_fit(&mut self);
+
+    /// Returns the smallest and largest elements of the range within
+    /// `within`, or None if there are none
+    ///
+    /// Defaults to iterating over the range.
+    fn bounds(&self, within: RangeInclusive<u32>) -> Option<(u32, u32)> {
+        self.iter()
+            .filter(|id| within.contains(id))
+            .fold(None, |bounds, id| match bounds {
+                None => Some((id, id)),
+                Some((min, max)) => Some((min.min(id), max.max(id))),
+            })
+    }
+
+    /// Returns an estimate of the number of bytes allocated on the heap by the
+    /// range
+    ///
+    /// Defaults to the size of the elements of the range.
+    #[cfg(feature = "heap-size")]
+    fn heap_size(&self) -> usize {
+        self.len() * std::mem::size_of::<u32>()
+    }
+}
+
+/// Formats a range as it appears in a node name
+///
+/// A single element is written as an id, several elements as a bracketed
+/// list of folded ranges (ie `[1-3,5]`), or without brackets with the
+/// alternate flag (`{:#}`). This implements `Display` for the ranges of this
+/// crate and is meant for custom [`IdRange`] backends.
+pub fn fmt_id_range(range: &impl IdRange, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut ranks = range.iter();
+    let Some(first) = ranks.next() else {
+        return Ok(());
+    };
+
+    if range.len() == 1 {
+        return write!(f, "{}", CachedTranslation::new(first));
+    }
+
+    if f.alternate() {
+        write_ranges(f, range.iter())
+    } else {
+        fmt::Write::write_char(f, '[')?;
+        write_ranges(f, range.iter())?;
+        fmt::Write::write_char(f, ']')
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -102,6 +350,8 @@ pub enum RangeStepError {
     Reverse,
     /// An id has a rank that is larger than what can be represented by a u32
     Overflow,
+    /// The step of a range is zero (ie `[1-9/0]`)
+    ZeroStep,
 }
 
 impl Error for RangeStepError {}
@@ -111,6 +361,7 @@ impl fmt::Display for RangeStepError {
         match self {
             RangeStepError::Reverse => write!(f, "start id is greater than end id"),
             RangeStepError::Overflow => write!(f, "id is too large"),
+            RangeStepError::ZeroStep => write!(f, "step must be greater than zero"),
         }
     }
 }
@@ -174,6 +425,9 @@ impl IdRangeStep {
         if start > end {
             return Err(RangeStepError::Reverse);
         }
+        if step == 0 {
+            return Err(RangeStepError::ZeroStep);
+        }
         if pad > MAX_U32_PAD || start > MAX_U32_ID || end > MAX_U32_ID {
             return Err(RangeStepError::Overflow);
         }
@@ -244,8 +498,14 @@ impl AffixIdRangeStep {
                 return Err(RangeStepError::Overflow);
             }
             let low_bound = pow10(low.pad);
-            idrs.start = idrs.start * low_bound + low.value;
-            idrs.end = idrs.end.saturating_mul(low_bound).saturating_add(low.value);
+            let shift = |id: u32| {
+                id.checked_mul(low_bound)
+                    .and_then(|id| id.checked_add(low.value))
+                    .filter(|&id| id <= MAX_U32_ID)
+                    .ok_or(RangeStepError::Overflow)
+            };
+            idrs.start = shift(idrs.start)?;
+            idrs.end = shift(idrs.end)?;
             idrs.step = idrs.step.saturating_mul(low_bound);
         };
 
@@ -319,6 +579,8 @@ impl RankRanges for AffixIdRangeStep {
 
     fn start_rank(&self) -> u32 {
         let bound = pow10(self.idrs.pad);
+        // Cannot overflow as the offset of the end of the range has been
+        // checked when creating the range
         let offset = bound * self.high.value;
 
         padded_id_to_rank_exact(self.idrs.start + offset, self.idrs.pad + self.high.pad)
@@ -366,6 +628,11 @@ impl CachedTranslation {
         self.pad
     }
 
+    /// Returns the numerical value of the id, regardless of its padding
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
     /// Maps a rank to a zero-padded id and returns it along with cached
     /// values
     pub(crate) fn new(rank: u32) -> Self {
@@ -438,46 +705,168 @@ fn padded_id_to_rank_exact(id: u32, pad: u32) -> u32 {
     id + offset
 }
 
-/// Converts a list of ranks into a comma-separated string of contiguous ranges
-//  FIXME: this should take a sorted iterator for safety
-fn fold_into_ranges<'a>(iter: impl Iterator<Item = &'a u32>, first_rank: u32) -> String {
-    use itertools::Itertools;
+/// Writes a sorted and deduplicated list of ranks as a comma-separated list
+/// of contiguous ranges
+///
+/// Ranges are written to `f` as they are folded so that no intermediate
+/// string is allocated.
+pub(crate) fn write_ranges(
+    f: &mut impl fmt::Write,
+    ranks: impl Iterator<Item = u32>,
+) -> fmt::Result {
+    let mut first = true;
+    fold_ranges(ranks, |start, end| {
+        if !first {
+            f.write_char(',')?;
+        }
+        first = false;
+        if start.rank == end.rank {
+            write!(f, "{start}")
+        } else {
+            write!(f, "{start}-{end}")
+        }
+    })
+}
 
-    let mut cache = CachedTranslation::new(first_rank);
+/// Calls `range` with the first and last ids of each contiguous range of a
+/// sorted and deduplicated list of ranks, stopping at the first error
+pub(crate) fn fold_ranges(
+    mut ranks: impl Iterator<Item = u32>,
+    mut range: impl FnMut(&CachedTranslation, &CachedTranslation) -> fmt::Result,
+) -> fmt::Result {
+    let Some(first) = ranks.next() else {
+        return Ok(());
+    };
+
+    let mut start = CachedTranslation::new(first);
+    let mut end = start.clone();
+    let mut max_pad = start.max_pad();
+
+    for rank in ranks {
+        let next = end.interpolate(rank);
+        if end.is_mergeable(&next, max_pad) {
+            end = next;
+            continue;
+        }
 
-    let mut rngs = iter.skip(1).batching(|it| {
-        if let Some(&next) = it.next() {
-            let max_pad = cache.max_pad();
-            let mut new_cache = cache.interpolate(next);
-            let mergeable = cache.is_mergeable(&new_cache, max_pad);
+        range(&start, &end)?;
+        max_pad = next.max_pad();
+        start = next.clone();
+        end = next;
+    }
 
-            if !mergeable {
-                let res = cache.to_string();
-                cache = new_cache;
-                return Some(res);
-            }
+    range(&start, &end)
+}
 
-            let mut cur_cache = new_cache;
-            for &next in it {
-                new_cache = cur_cache.interpolate(next);
-                let mergeable = cur_cache.is_mergeable(&new_cache, max_pad);
-
-                if !mergeable {
-                    let res = format!("{cache}-{cur_cache}");
-                    cache = new_cache;
-                    return Some(res);
-                } else {
-                    std::mem::swap(&mut cur_cache, &mut new_cache);
-                }
-            }
-            // Should never be reached
-            None
+/// Writes a sorted and deduplicated list of ranks as a bracketed list of
+/// contiguous ranges of numerical ids, along with the padding of the ranges
+/// whose ids are zero-padded (ie `[0-4 pad=2, 10]` for `00-04,10`)
+pub(crate) fn debug_ranges(
+    f: &mut impl fmt::Write,
+    ranks: impl Iterator<Item = u32>,
+) -> fmt::Result {
+    f.write_char('[')?;
+    for (i, range) in split_ranges(ranks).iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+
+        let start = CachedTranslation::new(range[0]);
+        let end = CachedTranslation::new(range[range.len() - 1]);
+        if start.rank == end.rank {
+            write!(f, "{}", start.id)?;
         } else {
-            None
+            write!(f, "{}-{}", start.id, end.id)?;
+        }
+        if start.pad > 1 && start.id < start.jump_pad / 10 {
+            write!(f, " pad={}", start.pad)?;
+        }
+    }
+    f.write_char(']')
+}
+
+/// Returns the smallest and largest numeric ids of a range, regardless of
+/// their padding
+///
+/// Ranks are ordered by the length of the ids first, so that zero-padded ids
+/// may be smaller than ids with a lower rank (ie `9 < 05`). Ids are only
+/// ordered like ranks among the ids of the same length which start with a
+/// zero or not, so the bounds of each of these blocks of ranks are compared.
+pub(crate) fn id_bounds(range: &impl IdRange) -> Option<(u32, u32)> {
+    let (_, last) = range.bounds(0..=u32::MAX)?;
+
+    let mut blocks = vec![0..=9];
+    for pad in 2..=MAX_U32_PAD {
+        let start = rank_offset(pad);
+        if start > last {
+            break;
+        }
+        let nonzero = start + pow10(pad - 1);
+        blocks.push(start..=nonzero - 1);
+        blocks.push(nonzero..=rank_offset(pad + 1).saturating_sub(1));
+    }
+
+    blocks
+        .into_iter()
+        .filter_map(|block| range.bounds(block))
+        .map(|(first, last)| {
+            (
+                CachedTranslation::new(first).id(),
+                CachedTranslation::new(last).id(),
+            )
+        })
+        .reduce(|(min, max), (first, last)| (min.min(first), max.max(last)))
+}
+
+/// Returns the number of contiguous ranges a sorted and deduplicated list of
+/// ranks is folded into, as displayed by `write_ranges`
+pub(crate) fn count_ranges(mut ranks: impl Iterator<Item = u32>) -> usize {
+    let Some(first) = ranks.next() else {
+        return 0;
+    };
+
+    let mut cache = CachedTranslation::new(first);
+    let mut max_pad = cache.max_pad();
+    let mut count = 1;
+
+    for rank in ranks {
+        let new_cache = cache.interpolate(rank);
+        if !cache.is_mergeable(&new_cache, max_pad) {
+            count += 1;
+            max_pad = new_cache.max_pad();
         }
-    });
+        cache = new_cache;
+    }
 
-    rngs.join(",")
+    count
+}
+
+/// Splits a sorted and deduplicated list of ranks into the contiguous ranges
+/// counted by `count_ranges`
+pub(crate) fn split_ranges(ranks: impl Iterator<Item = u32>) -> Vec<Vec<u32>> {
+    let mut res: Vec<Vec<u32>> = vec![];
+    let mut last: Option<(CachedTranslation, u32)> = None;
+
+    for rank in ranks {
+        let new_cache = match &last {
+            Some((cache, _)) => cache.interpolate(rank),
+            None => CachedTranslation::new(rank),
+        };
+
+        match last {
+            Some((cache, max_pad)) if cache.is_mergeable(&new_cache, max_pad) => {
+                res.last_mut().expect("a range was started").push(rank);
+                last = Some((new_cache, max_pad));
+            }
+            _ => {
+                res.push(vec![rank]);
+                let max_pad = new_cache.max_pad();
+                last = Some((new_cache, max_pad));
+            }
+        }
+    }
+
+    res
 }
 
 fn pow10(n: u32) -> u32 {
@@ -666,6 +1055,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_string_of_extreme_ranks() {
+        let max_id = padded_id_to_rank_exact(MAX_U32_ID, MAX_U32_PAD);
+        assert_eq!(rank_to_string(max_id), "3183856184");
+        assert_eq!(rank_to_string(max_id - 1), "3183856183");
+        assert_eq!(max_id, u32::MAX - 1);
+        assert_eq!(rank_to_string(u32::MAX), "3183856185");
+        let mut ranges = String::new();
+        write_ranges(&mut ranges, [max_id - 1, max_id, u32::MAX].into_iter()).unwrap();
+        assert_eq!(ranges, "3183856183-3183856185");
+    }
+
+    #[test]
+    fn test_count_ranges() {
+        for ids in [
+            vec![],
+            vec!["1"],
+            vec!["1", "2", "3"],
+            vec!["1", "3"],
+            vec!["8", "9", "10", "11"],
+            vec!["8", "9", "00", "01", "10"],
+            vec!["08", "09", "10", "11", "100"],
+            vec!["1", "2", "01", "02", "001"],
+        ] {
+            let mut ranks: Vec<_> = ids.iter().map(|s| rank_of_string(s).unwrap()).collect();
+            ranks.sort();
+
+            let display = format!("{:#}", IdRangeList::from(ranks.clone()));
+            let expected = display.split(',').filter(|r| !r.is_empty()).count();
+
+            let split = split_ranges(ranks.iter().copied());
+            assert_eq!(split.len(), expected, "{ids:?}");
+            assert_eq!(split.concat(), ranks, "{ids:?}");
+
+            assert_eq!(count_ranges(ranks.into_iter()), expected, "{ids:?}");
+        }
+    }
+
+    #[test]
+    fn test_debug_ranges() {
+        for (ids, expected) in [
+            (vec![], "[]"),
+            (vec!["0", "1", "2"], "[0-2]"),
+            (vec!["00", "01", "02", "03", "04", "10"], "[0-4 pad=2, 10]"),
+            (vec!["08", "09", "10", "11"], "[8-11 pad=2]"),
+            (vec!["8", "9", "00", "01"], "[8-9, 0-1 pad=2]"),
+            (vec!["1", "3", "001"], "[1, 3, 1 pad=3]"),
+        ] {
+            let mut ranks: Vec<_> = ids.iter().map(|s| rank_of_string(s).unwrap()).collect();
+            ranks.sort();
+
+            let mut s = String::new();
+            debug_ranges(&mut s, ranks.into_iter()).unwrap();
+            assert_eq!(s, expected, "{ids:?}");
+        }
+    }
+
     #[test]
     fn test_string_of_rank() {
         assert_eq!("0", rank_to_string(0));
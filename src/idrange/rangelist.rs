@@ -1,6 +1,6 @@
-use super::{CachedTranslation, IdRange, IdRangeStep, RankRanges, SortedIterator};
+use super::{IdRange, IdRangeStep, RankRanges, SortedIterator};
 use std::fmt::{self, Debug, Display};
-use std::iter;
+use std::ops::RangeInclusive;
 
 /// A 1D set of indexes stored in a Vec
 #[derive(Debug, Clone)]
@@ -15,14 +15,19 @@ impl PartialEq for IdRangeList {
     }
 }
 
+impl Eq for IdRangeList {}
+
 pub struct VecDifference<'a, T> {
     a: std::slice::Iter<'a, T>,
     b: std::iter::Peekable<std::slice::Iter<'a, T>>,
 }
 
-pub struct VecIntersection<'a, T> {
-    a: &'a [T],
-    b: &'a [T],
+/// Lazy intersection of two sorted and deduplicated iterators, returned by
+/// [`IdRangeList::intersection_sorted_iter`]
+#[derive(Debug, Clone)]
+pub struct SortedIntersection<A, B> {
+    a: A,
+    b: B,
 }
 
 pub struct VecUnion<'a, T> {
@@ -35,10 +40,49 @@ pub struct VecSymDifference<'a, T> {
     b: &'a [T],
 }
 
-impl IdRangeList {}
+impl IdRangeList {
+    /// Returns the intersection of two sorted and deduplicated streams of
+    /// ids, computed lazily
+    ///
+    /// Ids are pulled from both inputs as the intersection is consumed so
+    /// that neither input is loaded in memory, which allows intersecting
+    /// streams too large to be stored in a range, such as ids read from
+    /// files. The result is sorted and can be collected with
+    /// [`IdRange::from_sorted`]. It is unspecified if an input is not sorted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nodeset::{IdRange, IdRangeList};
+    ///
+    /// let even = (0..u32::MAX).step_by(2);
+    /// let ids = [3, 4, 7, 10].into_iter();
+    /// let both = IdRangeList::from_sorted(IdRangeList::intersection_sorted_iter(even, ids));
+    ///
+    /// assert_eq!(both.iter().collect::<Vec<_>>(), vec![4, 10]);
+    /// ```
+    pub fn intersection_sorted_iter<A, B>(
+        a: A,
+        b: B,
+    ) -> SortedIntersection<A::IntoIter, B::IntoIter>
+    where
+        A: IntoIterator<Item = u32>,
+        B: IntoIterator<Item = u32>,
+    {
+        SortedIntersection {
+            a: a.into_iter(),
+            b: b.into_iter(),
+        }
+    }
+}
 
 impl SortedIterator for VecUnion<'_, u32> {}
-impl SortedIterator for VecIntersection<'_, u32> {}
+impl<A, B> SortedIterator for SortedIntersection<A, B>
+where
+    A: Iterator<Item = u32>,
+    B: Iterator<Item = u32>,
+{
+}
 impl SortedIterator for VecDifference<'_, u32> {}
 impl SortedIterator for VecSymDifference<'_, u32> {}
 
@@ -49,10 +93,7 @@ where
     type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut next = match self.a.next() {
-            Some(v) => v,
-            None => return None,
-        };
+        let mut next = self.a.next()?;
 
         let mut min: &T;
         loop {
@@ -67,43 +108,30 @@ where
             };
 
             while next == min {
-                next = match self.a.next() {
-                    Some(v) => v,
-                    None => return None,
-                };
+                next = self.a.next()?;
             }
         }
     }
 }
 
-impl<'a, T> Iterator for VecIntersection<'a, T>
+impl<A, B> Iterator for SortedIntersection<A, B>
 where
-    T: Ord + Debug + Copy,
+    A: Iterator<Item = u32>,
+    B: Iterator<Item = u32>,
 {
-    type Item = T;
+    type Item = u32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while !self.a.is_empty() && !self.b.is_empty() {
-            let first_a = self.a.first();
-            let first_b = self.b.first();
-
-            match first_a.cmp(&first_b) {
-                std::cmp::Ordering::Less => {
-                    self.a = &self.a[exponential_search_idx(self.a, first_b.unwrap())..];
-                }
+        let mut a = self.a.next()?;
+        let mut b = self.b.next()?;
 
-                std::cmp::Ordering::Equal => {
-                    self.a = &self.a[1..];
-                    self.b = &self.b[1..];
-                    return first_a.copied();
-                }
-                std::cmp::Ordering::Greater => {
-                    self.b = &self.b[exponential_search_idx(self.b, first_a.unwrap())..];
-                }
+        loop {
+            match a.cmp(&b) {
+                std::cmp::Ordering::Less => a = self.a.next()?,
+                std::cmp::Ordering::Equal => return Some(a),
+                std::cmp::Ordering::Greater => b = self.b.next()?,
             }
         }
-
-        None
     }
 }
 
@@ -119,6 +147,9 @@ where
 
         if first_a.is_some() && (first_a <= first_b || first_b.is_none()) {
             self.a = &self.a[1..];
+            if first_a == first_b {
+                self.b = &self.b[1..];
+            }
             return first_a.copied();
         }
 
@@ -179,16 +210,6 @@ where
     }
 }
 
-fn exponential_search_idx<T>(v: &[T], x: &T) -> usize
-where
-    T: Ord,
-{
-    match exponential_search(v, x) {
-        Ok(r) => r,
-        Err(r) => r,
-    }
-}
-
 impl From<u32> for IdRangeList {
     fn from(index: u32) -> IdRangeList {
         IdRangeList {
@@ -222,7 +243,7 @@ impl From<IdRangeStep> for IdRangeList {
 
 impl IdRange for IdRangeList {
     type DifferenceIter<'a> = VecDifference<'a, u32>;
-    type IntersectionIter<'a> = VecIntersection<'a, u32>;
+    type IntersectionIter<'a> = SortedIntersection<Self::SelfIter<'a>, Self::SelfIter<'a>>;
     type UnionIter<'a> = VecUnion<'a, u32>;
     type SymmetricDifferenceIter<'a> = VecSymDifference<'a, u32>;
     type SelfIter<'a> = std::iter::Copied<std::slice::Iter<'a, u32>>;
@@ -234,6 +255,12 @@ impl IdRange for IdRangeList {
         }
     }
 
+    fn assign_sorted(&mut self, indexes: impl IntoIterator<Item = u32>) {
+        self.indexes.clear();
+        self.indexes.extend(indexes);
+        self.sorted = true;
+    }
+
     fn new() -> Self {
         IdRangeList {
             indexes: vec![],
@@ -264,6 +291,28 @@ impl IdRange for IdRangeList {
         self.indexes.len()
     }
 
+    fn shrink_to_fit(&mut self) {
+        self.indexes.shrink_to_fit();
+    }
+
+    /// Searches the bounds of `within` unless the range is lazy
+    fn bounds(&self, within: RangeInclusive<u32>) -> Option<(u32, u32)> {
+        if !self.sorted {
+            let mut ids = self.indexes.iter().filter(|id| within.contains(id));
+            let first = *ids.next()?;
+            return Some(ids.fold((first, first), |(min, max), &id| (min.min(id), max.max(id))));
+        }
+
+        let start = self.indexes.partition_point(|id| id < within.start());
+        let end = self.indexes.partition_point(|id| id <= within.end());
+        (start < end).then(|| (self.indexes[start], self.indexes[end - 1]))
+    }
+
+    #[cfg(feature = "heap-size")]
+    fn heap_size(&self) -> usize {
+        self.indexes.capacity() * std::mem::size_of::<u32>()
+    }
+
     fn sort(&mut self) {
         if !self.sorted {
             self.indexes.sort_unstable();
@@ -296,10 +345,7 @@ impl IdRange for IdRangeList {
         assert!(self.sorted);
         assert!(other.sorted);
 
-        Self::IntersectionIter {
-            a: &self.indexes,
-            b: &other.indexes,
-        }
+        Self::intersection_sorted_iter(self.iter(), other.iter())
     }
 
     fn union<'a>(&'a self, other: &'a Self) -> Self::UnionIter<'a> {
@@ -348,26 +394,7 @@ impl IdRange for IdRangeList {
 
 impl Display for IdRangeList {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.is_empty() {
-            return fmt::Result::Ok(());
-        }
-
-        if self.len() == 1 {
-            return f.write_str(&CachedTranslation::new(self.indexes[0]).to_string());
-        }
-
-        let ranges = super::fold_into_ranges(
-            self.indexes
-                .iter()
-                .chain(iter::once(&self.indexes[self.indexes.len() - 1])),
-            self.indexes[0],
-        );
-
-        if f.alternate() {
-            write!(f, "{}", ranges)
-        } else {
-            write!(f, "[{}]", ranges)
-        }
+        super::fmt_id_range(self, f)
     }
 }
 
@@ -427,6 +454,7 @@ pub mod tests {
         validate_rangelist_union_result(vec![], vec![1, 2, 5, 7], vec![1, 2, 5, 7]);
         validate_rangelist_union_result(vec![0, 4, 9], vec![], vec![0, 4, 9]);
         validate_rangelist_union_result(vec![0, 4, 9], vec![10, 11, 12], vec![0, 4, 9, 10, 11, 12]);
+        validate_rangelist_union_result(vec![0, 4, 9], vec![0, 4, 5, 9], vec![0, 4, 5, 9]);
     }
 
     #[test]
@@ -462,6 +490,54 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn rangelist_bounds() {
+        let rl = IdRangeList {
+            indexes: vec![2, 4, 9, 12],
+            sorted: true,
+        };
+        assert_eq!(rl.bounds(0..=u32::MAX), Some((2, 12)));
+        assert_eq!(rl.bounds(3..=10), Some((4, 9)));
+        assert_eq!(rl.bounds(5..=8), None);
+        assert_eq!(IdRangeList::new().bounds(0..=u32::MAX), None);
+
+        let lazy = IdRangeList {
+            indexes: vec![9, 2, 12, 4],
+            sorted: false,
+        };
+        assert_eq!(lazy.bounds(0..=u32::MAX), Some((2, 12)));
+        assert_eq!(lazy.bounds(3..=10), Some((4, 9)));
+    }
+
+    #[test]
+    fn rangelist_intersection_sorted_iter() {
+        let intersection = |a: &[u32], b: &[u32]| {
+            IdRangeList::intersection_sorted_iter(a.iter().copied(), b.iter().copied())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(intersection(&[], &[1, 2]), vec![]);
+        assert_eq!(intersection(&[1, 2], &[]), vec![]);
+        assert_eq!(intersection(&[0, 4, 9], &[1, 2, 5, 7]), vec![]);
+        assert_eq!(
+            intersection(&[1, 3, 5, 7, 9], &[3, 4, 5, 9, 10]),
+            vec![3, 5, 9]
+        );
+        assert_eq!(intersection(&[u32::MAX], &[0, u32::MAX]), vec![u32::MAX]);
+
+        // Inputs are consumed lazily
+        let mut pulled = 0;
+        let multiples_of_3 = (0..u32::MAX).step_by(3).inspect(|_| pulled += 1);
+        let multiples_of_5 = (0..u32::MAX).step_by(5);
+        assert_eq!(
+            IdRangeList::intersection_sorted_iter(multiples_of_3, multiples_of_5)
+                .take(3)
+                .collect::<Vec<_>>(),
+            vec![0, 15, 30]
+        );
+        assert_eq!(pulled, 11);
+    }
+
     #[test]
     fn rangelist_difference() {
         let rl1 = IdRangeList {
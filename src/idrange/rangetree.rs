@@ -1,8 +1,9 @@
 use super::SortedIterator;
-use super::{CachedTranslation, IdRange, RankRanges};
+use super::{IdRange, RankRanges};
 use std::collections::btree_set;
 use std::collections::BTreeSet;
 use std::fmt::{self, Debug, Display};
+use std::ops::RangeInclusive;
 
 impl From<u32> for IdRangeTree {
     fn from(index: u32) -> Self {
@@ -20,11 +21,11 @@ impl From<Vec<u32>> for IdRangeTree {
     }
 }
 
-impl SortedIterator for <IdRangeTree as IdRange>::SelfIter<'_> {}
-impl SortedIterator for <IdRangeTree as IdRange>::IntersectionIter<'_> {}
-impl SortedIterator for <IdRangeTree as IdRange>::SymmetricDifferenceIter<'_> {}
-impl SortedIterator for <IdRangeTree as IdRange>::DifferenceIter<'_> {}
-impl SortedIterator for <IdRangeTree as IdRange>::UnionIter<'_> {}
+impl SortedIterator for std::iter::Copied<btree_set::Iter<'_, u32>> {}
+impl SortedIterator for std::iter::Copied<btree_set::Intersection<'_, u32>> {}
+impl SortedIterator for std::iter::Copied<btree_set::SymmetricDifference<'_, u32>> {}
+impl SortedIterator for std::iter::Copied<btree_set::Difference<'_, u32>> {}
+impl SortedIterator for std::iter::Copied<btree_set::Union<'_, u32>> {}
 
 impl IdRange for IdRangeTree {
     type SelfIter<'a> = std::iter::Copied<btree_set::Iter<'a, u32>>;
@@ -39,6 +40,13 @@ impl IdRange for IdRangeTree {
         IdRangeTree { indexes: bt }
     }
 
+    /// The nodes of the BTree cannot be reused so this allocates as much as
+    /// `from_sorted`
+    fn assign_sorted(&mut self, indexes: impl IntoIterator<Item = u32>) {
+        self.indexes.clear();
+        self.indexes.extend(indexes);
+    }
+
     fn new() -> Self {
         IdRangeTree {
             indexes: BTreeSet::new(),
@@ -81,6 +89,21 @@ impl IdRange for IdRangeTree {
     fn len(&self) -> usize {
         self.indexes.len()
     }
+
+    /// BTrees do not hold unused memory
+    fn shrink_to_fit(&mut self) {}
+
+    fn bounds(&self, within: RangeInclusive<u32>) -> Option<(u32, u32)> {
+        let mut ids = self.indexes.range(within);
+        let first = *ids.next()?;
+        Some((first, ids.next_back().copied().unwrap_or(first)))
+    }
+
+    /// Only accounts for the indexes, not for the internal nodes of the BTree
+    #[cfg(feature = "heap-size")]
+    fn heap_size(&self) -> usize {
+        self.indexes.len() * std::mem::size_of::<u32>()
+    }
     fn sort(&mut self) {}
     fn lazy(self) -> Self {
         self
@@ -91,25 +114,7 @@ impl IdRange for IdRangeTree {
 
 impl Display for IdRangeTree {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.is_empty() {
-            return fmt::Result::Ok(());
-        }
-
-        if self.len() == 1 {
-            return f
-                .write_str(&CachedTranslation::new(*self.indexes.first().unwrap()).to_string());
-        }
-
-        let ranges = super::fold_into_ranges(
-            self.indexes.iter().chain(self.indexes.last()),
-            *self.indexes.first().unwrap(),
-        );
-
-        if f.alternate() {
-            write!(f, "{}", ranges)
-        } else {
-            write!(f, "[{}]", ranges)
-        }
+        super::fmt_id_range(self, f)
     }
 }
 
@@ -162,6 +167,15 @@ pub mod tests {
         };
         assert_eq!(rl1.intersection(&rl2).collect::<Vec<u32>>(), c);
     }
+    #[test]
+    fn rangetree_bounds() {
+        let rt = IdRangeTree::from(vec![2, 4, 9, 12]);
+        assert_eq!(rt.bounds(0..=u32::MAX), Some((2, 12)));
+        assert_eq!(rt.bounds(3..=10), Some((4, 9)));
+        assert_eq!(rt.bounds(4..=4), Some((4, 4)));
+        assert_eq!(rt.bounds(5..=8), None);
+    }
+
     #[test]
     fn rangetree_union() {
         validate_rangetree_union_result(vec![0, 4, 9], vec![1, 2, 5, 7], vec![0, 1, 2, 4, 5, 7, 9]);
@@ -2,12 +2,32 @@
 mod collections;
 mod idrange;
 
+pub use collections::ConfigurationError;
+pub use collections::DefaultFormatter;
+pub use collections::FormatTooLarge;
+pub use collections::GlobalWatcher;
+pub use collections::GroupSource;
 pub(crate) use collections::IdSet;
 pub(crate) use collections::IdSetIter;
+pub use collections::Node;
+pub use collections::NodeFormatter;
+pub use collections::NodeIds;
 pub use collections::NodeSet;
+pub use collections::NodeSetIntoIter;
 pub use collections::NodeSetIter;
 pub use collections::NodeSetParseError;
+pub use collections::ParseOptions;
 pub use collections::Parser;
+pub use collections::PrefixOrder;
+pub use collections::PriorityMode;
 pub use collections::Resolver;
+pub use collections::ResolverBuilder;
+pub use collections::Separated;
+pub use collections::SourceInfo;
+pub use collections::StaticSource;
+pub use idrange::fmt_id_range;
+pub use idrange::IdRange;
 pub use idrange::IdRangeList;
 pub use idrange::IdRangeTree;
+pub use idrange::RankRanges;
+pub use idrange::SortedIterator;
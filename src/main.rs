@@ -2,15 +2,51 @@ use auto_enums::auto_enum;
 use clap::{Parser, Subcommand};
 use eyre::{Context, Result};
 use itertools::Itertools;
-use nodeset::{IdRangeList, NodeSet, Resolver};
+use nodeset::{
+    IdRangeList, Node, NodeFormatter, NodeSet, NodeSetParseError, ParseOptions, PrefixOrder,
+    Resolver, ResolverBuilder, Separated,
+};
+use std::collections::HashSet;
+use std::fs::File;
 use std::io;
+use std::io::BufReader;
+use std::io::IsTerminal;
 use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(about = "Operations on set of nodes")]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Query group sources without using their caches
+    #[arg(long, global = true)]
+    no_cache: bool,
+    /// Expand environment variables which are not set to an empty string in
+    /// the configuration files instead of failing
+    #[arg(long, global = true)]
+    allow_undefined_variables: bool,
+    /// Do not report the progress of long operations on the standard error
+    /// (only reported when built with the `progress` feature)
+    #[arg(long, global = true)]
+    no_progress: bool,
+    /// Resolve undefined groups and group patterns matching no group (ie
+    /// '@rack*') to no nodes instead of failing
+    #[arg(long, global = true)]
+    ignore_missing_groups: bool,
+    /// Fail on group references (ie '@rack1', '@@' or '@*') instead of
+    /// resolving them, so that parsing nodesets never queries group sources
+    #[arg(long, global = true)]
+    no_groups: bool,
+    /// Fail if a node appears more than once in the nodesets given to a
+    /// command (ie 'node[1-5] node[3-8]'), reporting the overlapping nodes
+    #[arg(long, global = true)]
+    strict: bool,
+    /// Ignore comments starting with '#' in the nodesets read from files or
+    /// from the standard input
+    #[arg(long, global = true)]
+    lenient: bool,
 }
 
 #[derive(Subcommand)]
@@ -19,19 +55,128 @@ enum Commands {
     Fold {
         /// Nodesets to fold
         nodeset: Option<Vec<String>>,
+        /// Read nodesets from a file, one per line
+        #[arg(short, value_name = "FILE")]
+        file: Option<PathBuf>,
+        /// Format of the nodesets read from FILE or from the standard input:
+        /// one per line, or a JSON or YAML array of strings
+        #[arg(long, value_name = "FORMAT", default_value = "lines")]
+        file_format: FileFormat,
+        /// Opening and closing range delimiters to use instead of '[]' for
+        /// both input and output (ie '{}')
+        #[arg(long, value_name = "DELIMITERS", value_parser = parse_brackets)]
+        brackets: Option<ParseOptions>,
+        /// Separator between nodesets with different prefixes in the output
+        #[arg(long, value_name = "CHAR", default_value_t = ',')]
+        delimiter: char,
+        /// Write nodesets with different prefixes on separate lines instead
+        /// of a single line, ie to compare folded outputs with diff
+        #[arg(long, conflicts_with_all = ["delimiter", "per_line"])]
+        multiline: bool,
+        /// Order of prefixes in the output: lexical, numeric or insertion
+        #[arg(long, value_name = "ORDER", default_value = "lexical")]
+        sort_prefixes: PrefixOrder,
+        /// Fold each line of the input separately and print one nodeset per
+        /// line
+        #[arg(long, conflicts_with = "nodeset")]
+        per_line: bool,
+        /// Trust that the input holds individual nodes separated by
+        /// whitespace, already sorted as 'list' prints them and without
+        /// duplicates, to fold large inputs faster. Debug builds fail on
+        /// unsorted input, release builds print a wrong nodeset
+        #[arg(
+            long,
+            visible_alias = "sorted-input",
+            conflicts_with_all = ["per_line", "file_format"]
+        )]
+        assume_sorted: bool,
+        /// Highlight prefixes, delimiters and range bounds: auto (when
+        /// writing to a terminal and NO_COLOR is not set), always or never
+        #[arg(long, value_name = "WHEN", default_value = "auto")]
+        color: ColorMode,
+        #[command(flatten)]
+        exclusions: Exclusions,
     },
     /// List individual nodes in nodesets
     List {
         /// Nodesets to expand into a list
         nodeset: Option<Vec<String>>,
+        /// Read nodesets from a file, one per line
+        #[arg(short, value_name = "FILE")]
+        file: Option<PathBuf>,
+        /// Format of the nodesets read from FILE or from the standard input:
+        /// one per line, or a JSON or YAML array of strings
+        #[arg(long, value_name = "FORMAT", default_value = "lines")]
+        file_format: FileFormat,
         /// Separator between nodes
         #[arg(short, default_value = " ")]
         separator: String,
+        /// Group nodes by contiguous range, writing SEP (a blank line by
+        /// default) instead of the node separator between ranges
+        #[arg(
+            long,
+            value_name = "SEP",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "\n\n"
+        )]
+        ranges_expanded: Option<String>,
+        /// Only list the first N nodes
+        #[arg(long, value_name = "N")]
+        limit: Option<NonZeroUsize>,
+        /// Order of prefixes in the output: lexical, numeric or insertion
+        #[arg(long, value_name = "ORDER", default_value = "lexical")]
+        sort_prefixes: PrefixOrder,
+        /// Write the index of each node (starting at 0) and a tab before it,
+        /// one node per line
+        #[arg(long, conflicts_with = "separator")]
+        with_index: bool,
+        /// Write a tab and the prefix of each node after it, one node per
+        /// line
+        #[arg(long, conflicts_with = "separator")]
+        with_prefix: bool,
+        #[command(flatten)]
+        exclusions: Exclusions,
     },
     /// Count nodes in nodesets
     Count {
         /// Nodesets to count
         nodeset: Option<Vec<String>>,
+        /// Read nodesets from a file, one per line
+        #[arg(short, value_name = "FILE")]
+        file: Option<PathBuf>,
+        /// Format of the nodesets read from FILE or from the standard input:
+        /// one per line, or a JSON or YAML array of strings
+        #[arg(long, value_name = "FORMAT", default_value = "lines")]
+        file_format: FileFormat,
+        /// Count distinct node name prefixes instead of nodes
+        #[arg(long)]
+        unique_prefixes: bool,
+        /// Count contiguous ranges of nodes instead of nodes
+        #[arg(long, conflicts_with = "unique_prefixes")]
+        ranges: bool,
+        /// Count groups instead of nodes, only those intersecting with the
+        /// nodesets if any are given
+        #[arg(long, conflicts_with_all = ["unique_prefixes", "ranges"])]
+        groups: bool,
+        /// Count groups from all sources
+        #[arg(short, requires = "groups")]
+        all_sources: bool,
+        /// Count groups from the specified source
+        #[arg(short, requires = "groups", conflicts_with = "all_sources")]
+        source: Option<String>,
+        /// Only count groups whose name matches PATTERN, where '*' matches any
+        /// characters
+        #[arg(long = "match", value_name = "PATTERN", requires = "groups")]
+        pattern: Option<String>,
+        #[command(flatten)]
+        exclusions: Exclusions,
+    },
+    /// Print the nodes common to all the given nodesets
+    Intersection {
+        /// Nodesets to intersect, such as groups
+        #[arg(required = true)]
+        nodeset: Vec<String>,
     },
     /// List groups of nodes
     Groups {
@@ -44,58 +189,308 @@ enum Commands {
         /// Display group members
         #[arg(short)]
         members: bool,
+        /// Only list groups whose name matches PATTERN, where '*' matches any
+        /// characters
+        #[arg(long = "match", value_name = "PATTERN")]
+        pattern: Option<String>,
         /// Display groups intersecting with provided nodesets
         nodeset: Option<Vec<String>>,
     },
     /// List group sources
-    Sources {},
+    Sources {
+        /// List the configuration files looked for instead, from the highest
+        /// to the lowest precedence
+        #[arg(long, conflicts_with_all = ["verbose", "json"])]
+        paths: bool,
+        /// Describe each source in a table with its kind, number of groups
+        /// and origin
+        #[arg(short, long)]
+        verbose: bool,
+        /// Describe each source as a JSON array of objects
+        #[arg(long, conflicts_with = "verbose")]
+        json: bool,
+    },
+    /// Manage the caches of group sources
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Remove the caches written to disk by group sources
+    Clear {},
+}
+
+/// Nodes removed from the nodesets given to a command
+#[derive(clap::Args)]
+struct Exclusions {
+    /// Exclude the nodes of NODESET (may be repeated)
+    #[arg(short = 'x', value_name = "NODESET")]
+    exclude: Vec<String>,
+    /// Exclude the nodes read from FILE, one nodeset per line as with -f
+    /// (may be repeated)
+    #[arg(long, value_name = "FILE")]
+    exclude_file: Vec<PathBuf>,
+}
+
+impl Exclusions {
+    /// Returns the union of the excluded nodes
+    fn nodeset(&self, options: &ParseOptions) -> Result<NodeSet> {
+        // Excluded nodes may overlap even in strict mode
+        let options = options.reject_duplicates(false);
+
+        let mut res = if self.exclude.is_empty() {
+            NodeSet::new()
+        } else {
+            NodeSet::parse_with(self.exclude.join(" "), &options)
+                .context("failed to parse excluded nodeset")?
+        };
+        for path in &self.exclude_file {
+            let f =
+                File::open(path).with_context(|| format!("failed to open '{}'", path.display()))?;
+            let nodeset = read_nodesets(BufReader::new(f), FileFormat::Lines, &options)
+                .with_context(|| format!("failed to parse '{}'", path.display()))?;
+            res = res.union(&nodeset);
+        }
+
+        Ok(res)
+    }
+}
+
+/// Format of the nodesets read from a file or from the standard input
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum FileFormat {
+    /// One nodeset per line
+    Lines,
+    /// A JSON array of nodesets
+    Json,
+    /// A YAML sequence of nodesets
+    Yaml,
+}
+
+/// When to highlight the output
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ColorMode {
+    /// When writing to a terminal, unless NO_COLOR is set
+    Auto,
+    /// Always
+    Always,
+    /// Never
+    Never,
+}
+
+impl ColorMode {
+    /// Returns true if the standard output should be highlighted
+    fn enabled(self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                !matches!(std::env::var_os("NO_COLOR"), Some(v) if !v.is_empty())
+                    && io::stdout().is_terminal()
+            }
+        }
+    }
 }
 
 fn main() -> Result<()> {
-    env_logger::init();
-    Resolver::set_global(Resolver::from_config()?).unwrap();
-    use std::io::Write;
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
     let args = Cli::parse();
-    match args.command {
-        Commands::Fold { nodeset } => {
-            let nodeset = nodeset_argument(nodeset)?;
-            println!("{}", nodeset);
+
+    if let Commands::Cache {
+        command: CacheCommands::Clear {},
+    } = args.command
+    {
+        if let Some(dir) = Resolver::clear_cache().context("failed to clear the cache")? {
+            println!("Removed {}", dir.display());
         }
-        Commands::List { nodeset, separator } => {
-            let nodeset = nodeset_argument(nodeset)?;
-            let mut it = nodeset.iter();
+        return Ok(());
+    }
 
-            let mut lock = io::stdout().lock();
+    let mut builder = ResolverBuilder::new().merge_config();
+    if args.no_cache {
+        builder = builder.disable_cache();
+    }
+    if args.allow_undefined_variables {
+        builder = builder.allow_undefined_variables();
+    }
+    // The global resolver is used to parse nodesets
+    Resolver::set_global(builder.build()?);
+    let global = Resolver::get_global();
+    let resolver = global.as_ref();
+    let parse_options = ParseOptions::new()
+        .ignore_missing_groups(args.ignore_missing_groups)
+        .no_groups(args.no_groups)
+        .reject_duplicates(args.strict)
+        .lenient(args.lenient);
 
-            if let Some(first) = it.next() {
-                lock.write_all(first.as_bytes())?;
+    match args.command {
+        Commands::Fold {
+            nodeset,
+            file,
+            file_format,
+            brackets,
+            delimiter,
+            multiline,
+            sort_prefixes,
+            per_line,
+            assume_sorted,
+            color,
+            exclusions,
+        } => {
+            let options = brackets
+                .unwrap_or_default()
+                .ignore_missing_groups(args.ignore_missing_groups)
+                .no_groups(args.no_groups)
+                .reject_duplicates(args.strict)
+                .lenient(args.lenient);
+            let formatter = Separated::new(options, if multiline { '\n' } else { delimiter });
+            let color = color.enabled();
+            let excluded = exclusions.nodeset(&options)?;
+            if per_line {
+                return fold_per_line(file, &options, &formatter, &excluded, sort_prefixes, color);
             }
-            for node in it {
-                lock.write_all(separator.as_bytes())?;
-                lock.write_all(node.as_bytes())?;
+
+            let nodeset = if assume_sorted {
+                sorted_nodes_argument(nodeset, file)?
+            } else {
+                nodeset_argument_with(nodeset, file, file_format, &options)?
             }
+            .difference(&excluded);
+            print_folded(
+                &mut io::stdout().lock(),
+                &nodeset,
+                &formatter,
+                sort_prefixes,
+                color,
+            )?;
+        }
+        Commands::List {
+            nodeset,
+            file,
+            file_format,
+            separator,
+            ranges_expanded,
+            limit,
+            sort_prefixes,
+            with_index,
+            with_prefix,
+            exclusions,
+        } => {
+            let nodeset = nodeset_argument_with(nodeset, file, file_format, &parse_options)?
+                .difference(&exclusions.nodeset(&parse_options)?);
+            let limit = limit.map_or(usize::MAX, NonZeroUsize::get);
+            // Progress would be mixed with the nodes on a terminal
+            let mut progress = Progress::new(
+                "Listing nodes",
+                nodeset.len_u64().min(limit as u64),
+                !args.no_progress && !io::stdout().is_terminal(),
+            );
 
-            println!();
+            let columns = ListColumns {
+                index: with_index,
+                prefix: with_prefix,
+            };
+            // Columns are written one node per line
+            let separator = if columns.any() { "\n" } else { &separator };
+
+            list_nodes(
+                &mut io::stdout().lock(),
+                &nodeset,
+                separator,
+                ranges_expanded.as_deref(),
+                limit,
+                sort_prefixes,
+                columns,
+                &mut progress,
+            )?;
+        }
+        Commands::Count {
+            nodeset,
+            file,
+            file_format,
+            groups: true,
+            all_sources,
+            source,
+            pattern,
+            exclusions,
+            ..
+        } => {
+            // Groups are only filtered by nodes when nodesets are given
+            let filter = if nodeset.is_some() || file.is_some() {
+                Some(
+                    nodeset_argument_with(nodeset, file, file_format, &parse_options)?
+                        .difference(&exclusions.nodeset(&parse_options)?),
+                )
+            } else {
+                None
+            };
+            let groups = GroupQuery {
+                all: all_sources,
+                source,
+                pattern,
+                filter,
+            };
+            println!("{}", groups.count(resolver, !args.no_progress));
+        }
+        Commands::Count {
+            nodeset,
+            file,
+            file_format,
+            unique_prefixes,
+            ranges,
+            exclusions,
+            ..
+        } => {
+            let nodeset = nodeset_argument_with(nodeset, file, file_format, &parse_options)?
+                .difference(&exclusions.nodeset(&parse_options)?);
+            if unique_prefixes {
+                println!("{}", nodeset.prefixes().len());
+            } else if ranges {
+                println!("{}", nodeset.range_count());
+            } else {
+                println!("{}", nodeset.len_u64());
+            }
         }
-        Commands::Count { nodeset } => {
-            let nodeset = nodeset_argument(nodeset)?;
-            println!("{}", nodeset.len());
+        Commands::Intersection { nodeset } => {
+            let nodesets = nodeset
+                .into_iter()
+                .map(|ns| nodeset_argument(Some(vec![ns]), &parse_options))
+                .collect::<Result<Vec<_>>>()?;
+            println!("{}", NodeSet::intersection_all(nodesets));
         }
         Commands::Groups {
             all_sources,
             members,
             source,
+            pattern,
             nodeset,
         } => {
-            let nodeset = if nodeset.is_some() {
-                Some(nodeset_argument(nodeset)?)
+            let filter = if nodeset.is_some() {
+                Some(nodeset_argument(nodeset, &parse_options)?)
             } else {
                 None
             };
-            group_cmd(all_sources, source, members, nodeset);
+            let groups = GroupQuery {
+                all: all_sources,
+                source,
+                pattern,
+                filter,
+            };
+            println!("{}", groups.lines(resolver, members, !args.no_progress));
+        }
+        Commands::Sources { paths: true, .. } => {
+            for dir in Resolver::config_paths() {
+                let path = dir.join("groups.conf");
+                let found = if path.exists() { "" } else { " (not found)" };
+                println!("{}{}", path.display(), found);
+            }
         }
-        Commands::Sources {} => {
-            let resolver = Resolver::get_global();
+        Commands::Sources { verbose: true, .. } => print_sources_table(resolver),
+        Commands::Sources { json: true, .. } => print_sources_json(resolver),
+        Commands::Sources { .. } => {
             for source in resolver.sources() {
                 println!(
                     "{}{}",
@@ -108,88 +503,697 @@ fn main() -> Result<()> {
                 );
             }
         }
+        Commands::Cache { .. } => unreachable!("handled before loading the configuration"),
     }
 
     Ok(())
 }
 
-#[auto_enum]
-fn group_cmd(
-    all: bool,
-    default_source: Option<String>,
-    display_members: bool,
-    filter: Option<NodeSet>,
-) {
-    let resolver = Resolver::get_global();
-
-    let all_groups;
-    let groups;
-
-    #[auto_enum(Iterator)]
-    let iter = if all {
-        all_groups = resolver
-            .list_all_groups::<IdRangeList>()
-            .collect::<Vec<_>>();
-        all_groups.iter().flat_map(|(source, groups)| {
-            let source = if *source == resolver.default_source() {
-                None
+/// Writes a table describing the sources of `resolver`
+fn print_sources_table(resolver: &Resolver) {
+    let rows: Vec<[String; 4]> = resolver
+        .source_info()
+        .into_iter()
+        .map(|info| {
+            let name = if info.default {
+                format!("{} (default)", info.name)
             } else {
-                Some(*source)
+                info.name.to_string()
             };
+            let groups = info
+                .group_count()
+                .map_or_else(|_| "-".to_string(), |count| count.to_string());
+            let origin = info.origin.unwrap_or_else(|| "-".to_string());
+            [name, info.kind.to_string(), groups, origin]
+        })
+        .collect();
 
-            groups.iter().map(move |group| (source, group))
+    let header = ["SOURCE", "KIND", "GROUPS", "ORIGIN"].map(str::to_string);
+    let widths: Vec<usize> = (0..3)
+        .map(|i| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .map(|row| row[i].len())
+                .max()
+                .unwrap_or_default()
         })
+        .collect();
+
+    for row in std::iter::once(&header).chain(&rows) {
+        println!(
+            "{:<w0$}  {:<w1$}  {:>w2$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        );
+    }
+}
+
+/// Writes a JSON array describing the sources of `resolver`
+fn print_sources_json(resolver: &Resolver) {
+    let sources =
+        resolver
+            .source_info()
+            .into_iter()
+            .map(|info| {
+                format!(
+                "{{\"name\": {}, \"kind\": {}, \"origin\": {}, \"default\": {}, \"groups\": {}}}",
+                json_string(info.name),
+                json_string(info.kind),
+                info.origin.as_deref().map_or("null".to_string(), json_string),
+                info.default,
+                info.group_count()
+                    .map_or_else(|_| "null".to_string(), |count| count.to_string()),
+            )
+            })
+            .join(",\n  ");
+
+    if sources.is_empty() {
+        println!("[]");
     } else {
-        groups = resolver.list_groups::<IdRangeList>(default_source.as_deref());
-        groups
-            .iter()
-            .map(|group| (default_source.as_deref(), group))
-    };
+        println!("[\n  {}\n]", sources);
+    }
+}
 
-    let s = iter
-        .filter_map(|(source, group)| {
-            let mut members = resolver.resolve::<IdRangeList>(source, &group).ok()?;
+/// Quotes a string as a JSON string
+fn json_string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            c if c.is_control() => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
 
-            if let Some(filter) = &filter {
-                members = members.intersection(filter);
-                if members.is_empty() {
-                    return None;
-                }
+/// Groups selected by the `groups` and `count --groups` commands
+struct GroupQuery {
+    /// Select the groups of all sources instead of a single one
+    all: bool,
+    /// Source of the groups, the default source if None
+    source: Option<String>,
+    /// Pattern which the names of the groups must match
+    pattern: Option<String>,
+    /// Nodes which the groups must intersect with
+    filter: Option<NodeSet>,
+}
+
+impl GroupQuery {
+    /// Returns the selected groups with their source, None for the default
+    /// source, and their members if `members` is set or groups are filtered
+    /// by nodes (only the members in the filter then)
+    #[auto_enum]
+    fn select<'a>(
+        &'a self,
+        resolver: &'a Resolver,
+        members: bool,
+        progress: bool,
+    ) -> Vec<(Option<&'a str>, String, NodeSet)> {
+        // Groups from the default source are displayed without a source
+        let sources = if self.all {
+            resolver
+                .sources()
+                .map(|s| (s != resolver.default_source()).then_some(s.as_str()))
+                .collect()
+        } else {
+            vec![self.source.as_deref()]
+        };
+
+        // A source which fails is reported once and skipped so that the
+        // groups of the other sources are still selected
+        let mut failed = HashSet::new();
+        let mut report_source = |source: Option<&str>, e: NodeSetParseError| {
+            let name = source.unwrap_or(resolver.default_source());
+            if !failed.insert(name.to_string()) {
+                return;
             }
 
-            let display_source = match &source {
-                Some(s) => format!("{}:", s),
-                None => "".to_string(),
-            };
-            if display_members {
-                Some(format!("@{}{} {}", display_source, group, members))
-            } else {
-                Some(format!("@{}{}", display_source, group))
+            match e {
+                // Sources such as netgroups cannot list their groups, which
+                // is expected when selecting the groups of all sources
+                NodeSetParseError::ListUnsupported(_) if self.all => {
+                    log::debug!("Skipping group source '{name}': {e}")
+                }
+                e => log::warn!("Skipping group source '{name}': {e}"),
             }
-        })
-        .sorted()
-        .join("\n");
+        };
+
+        let listed: Vec<(Option<&str>, Vec<String>)> = sources
+            .into_iter()
+            .filter_map(
+                |source| match resolver.try_list_groups::<IdRangeList>(source) {
+                    Ok(groups) => Some((source, groups)),
+                    Err(e) => {
+                        report_source(source, e);
+                        None
+                    }
+                },
+            )
+            .map(|(source, groups)| {
+                let groups = groups
+                    .iter()
+                    .filter(|group| {
+                        self.pattern
+                            .as_ref()
+                            .is_none_or(|pattern| glob_matches(pattern, group))
+                    })
+                    .collect();
+                (source, groups)
+            })
+            .collect();
+
+        let mut progress = if Progress::ENABLED && progress {
+            let total = listed.iter().map(|(_, groups)| groups.len() as u64).sum();
+            Progress::new("Resolving groups", total, true)
+        } else {
+            Progress::new("", 0, false)
+        };
+
+        // Groups are only resolved when their members are needed, which can
+        // be much slower than listing their names
+        #[auto_enum(Iterator)]
+        let iter = match members || self.filter.is_some() {
+            true => listed.into_iter().flat_map(|(source, groups)| {
+                resolver
+                    .resolve_many_iter::<IdRangeList>(source, groups)
+                    .map(move |res| {
+                        res.map(|(group, members)| (source, group, members))
+                            .map_err(|e| (source, e))
+                    })
+            }),
+            false => listed.into_iter().flat_map(|(source, groups)| {
+                groups
+                    .into_iter()
+                    .map(move |group| Ok((source, group, NodeSet::new())))
+            }),
+        };
+
+        let selected = iter
+            .inspect(|_| progress.inc())
+            .filter_map(|res| {
+                res.map_err(|(source, e)| match e {
+                    // Groups which fail to resolve are skipped one by one,
+                    // unless the source is known to be failing
+                    NodeSetParseError::Group(group, e)
+                        if !matches!(*e, NodeSetParseError::SourceUnavailable(..)) =>
+                    {
+                        let group = resolver.group_reference(source, &group);
+                        log::warn!("Skipping group {group}: {e}")
+                    }
+                    NodeSetParseError::Group(_, e) => report_source(source, *e),
+                    e => report_source(source, e),
+                })
+                .ok()
+            })
+            .filter_map(|(source, group, mut members)| {
+                if let Some(filter) = &self.filter {
+                    members = members.intersection(filter);
+                    if members.is_empty() {
+                        return None;
+                    }
+                }
 
-    println!("{}", s);
+                Some((source, group, members))
+            })
+            .collect();
+
+        progress.finish();
+        selected
+    }
+
+    /// Returns one line per selected group, with its members if `members`
+    /// is set
+    fn lines(&self, resolver: &Resolver, members: bool, progress: bool) -> String {
+        self.select(resolver, members, progress)
+            .into_iter()
+            .map(|(source, group, nodes)| {
+                let group = resolver.group_reference(source, &group);
+                if members {
+                    format!("{} {}", group, nodes)
+                } else {
+                    group
+                }
+            })
+            .sorted()
+            .join("\n")
+    }
+
+    /// Returns the number of selected groups
+    fn count(&self, resolver: &Resolver, progress: bool) -> usize {
+        self.select(resolver, false, progress).len()
+    }
 }
 
-fn nodeset_argument(ns: Option<Vec<String>>) -> Result<NodeSet> {
-    let nodeset: NodeSet = match ns {
-        Some(v) if v == vec!["-".to_string()] => read_stdin()?,
-        Some(v) => v.join(" "),
-        None => read_stdin()?,
+/// Returns true if `name` matches `pattern`, where `*` matches any characters
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        let Some(pos) = rest.find(part) else {
+            return false;
+        };
+        rest = &rest[pos + part.len()..];
     }
-    .parse()
-    .context("failed to parse nodeset")?;
 
-    Ok(nodeset)
+    rest.ends_with(last)
+}
+
+/// Writes the first `limit` nodes of `nodeset` separated by `separator`
+///
+/// With a `range_separator`, it is written instead of `separator` between the
+/// contiguous ranges of the nodeset. The fields of `columns` are written
+/// around each node, separated by tabs.
+#[auto_enum]
+#[allow(clippy::too_many_arguments)]
+fn list_nodes(
+    out: &mut impl io::Write,
+    nodeset: &NodeSet,
+    separator: &str,
+    range_separator: Option<&str>,
+    limit: usize,
+    order: PrefixOrder,
+    columns: ListColumns,
+    progress: &mut Progress,
+) -> io::Result<()> {
+    // Each node is paired with the separator written before it
+    #[auto_enum(Iterator)]
+    let it = match range_separator {
+        Some(range_separator) => nodeset
+            .split_contiguous_ordered(order)
+            .flat_map(move |range| {
+                std::iter::once(range_separator)
+                    .chain(std::iter::repeat(separator))
+                    .zip(range)
+            }),
+        None => std::iter::repeat(separator).zip(nodeset.iter_ordered(order)),
+    };
+
+    for (i, (separator, node)) in it.take(limit).enumerate() {
+        if i > 0 {
+            out.write_all(separator.as_bytes())?;
+        }
+        if columns.index {
+            write!(out, "{i}\t")?;
+        }
+        out.write_all(node.as_bytes())?;
+        if columns.prefix {
+            let node: Node = node.parse().map_err(io::Error::other)?;
+            write!(out, "\t{}", node.prefix())?;
+        }
+        progress.inc();
+    }
+
+    progress.finish();
+    writeln!(out)
+}
+
+/// Fields written in tab-separated columns along with each node by
+/// `list_nodes`
+#[derive(Debug, Clone, Copy, Default)]
+struct ListColumns {
+    /// Index of the node in iteration order, before the node
+    index: bool,
+    /// Prefix of the node, after the node
+    prefix: bool,
+}
+
+impl ListColumns {
+    fn any(&self) -> bool {
+        self.index || self.prefix
+    }
+}
+
+/// Progress of a long operation, drawn as a bar on the standard error
+///
+/// Nothing is drawn unless the standard error is a terminal and the operation
+/// lasts longer than `Progress::DELAY`, so that quick commands and redirected
+/// outputs are left untouched. The bar is cleared by `finish`.
+#[cfg(feature = "progress")]
+struct Progress {
+    bar: indicatif::ProgressBar,
+    /// Steps done since the bar was last updated
+    pending: u64,
+    /// Number of steps between two updates of the bar
+    check_every: u64,
+    /// Whether the bar is hidden until `Progress::DELAY` has elapsed
+    delayed: bool,
+}
+
+#[cfg(feature = "progress")]
+impl Progress {
+    const ENABLED: bool = true;
+    const DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+    fn new(label: &'static str, total: u64, enabled: bool) -> Self {
+        let bar = indicatif::ProgressBar::hidden();
+        bar.set_length(total);
+        bar.set_message(label);
+        bar.set_style(
+            indicatif::ProgressStyle::with_template("{msg} [{bar:30}] {pos}/{len}")
+                .expect("progress template should be valid")
+                .progress_chars("## "),
+        );
+
+        Self {
+            bar,
+            pending: 0,
+            check_every: (total / 1000).max(1),
+            delayed: enabled,
+        }
+    }
+
+    /// Records that one more step is done
+    fn inc(&mut self) {
+        self.pending += 1;
+        if self.pending < self.check_every {
+            return;
+        }
+
+        self.bar.inc(std::mem::take(&mut self.pending));
+        if self.delayed && self.bar.elapsed() >= Self::DELAY {
+            self.delayed = false;
+            self.bar
+                .set_draw_target(indicatif::ProgressDrawTarget::stderr());
+        }
+    }
+
+    /// Clears the bar if it was drawn
+    fn finish(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Progress reporting is disabled without the `progress` feature
+#[cfg(not(feature = "progress"))]
+struct Progress;
+
+#[cfg(not(feature = "progress"))]
+impl Progress {
+    const ENABLED: bool = false;
+
+    fn new(_label: &'static str, _total: u64, _enabled: bool) -> Self {
+        Self
+    }
+
+    fn inc(&mut self) {}
+
+    fn finish(&mut self) {}
 }
 
-fn read_stdin() -> Result<String> {
-    let mut s = String::new();
+fn nodeset_argument(ns: Option<Vec<String>>, options: &ParseOptions) -> Result<NodeSet> {
+    nodeset_argument_with(ns, None, FileFormat::Lines, options)
+}
+
+/// Parses nodesets from the command line, from `file` and from the standard
+/// input if neither is provided or if the only argument is '-'
+fn nodeset_argument_with(
+    ns: Option<Vec<String>>,
+    file: Option<PathBuf>,
+    format: FileFormat,
+    options: &ParseOptions,
+) -> Result<NodeSet> {
+    let nodeset = match &file {
+        Some(path) => {
+            let f =
+                File::open(path).with_context(|| format!("failed to open '{}'", path.display()))?;
+            read_nodesets(BufReader::new(f), format, options)
+                .with_context(|| format!("failed to parse '{}'", path.display()))?
+        }
+        None => NodeSet::default(),
+    };
+
+    let input = match ns {
+        Some(v) if v == vec!["-".to_string()] => read_stdin(format, options)?,
+        Some(v) => NodeSet::parse_with(v.join(" "), options).context("failed to parse nodeset")?,
+        None if file.is_none() => read_stdin(format, options)?,
+        None => NodeSet::default(),
+    };
+
+    Ok(nodeset.union(&input))
+}
+
+/// Parses the standard input as a single nodeset, so that operators may span
+/// several lines (ie 'n[1-3]' followed by '!n2' on the next line)
+///
+/// Nodesets are read as with `read_nodesets` for other formats.
+fn read_stdin(format: FileFormat, options: &ParseOptions) -> Result<NodeSet> {
+    if format != FileFormat::Lines {
+        return read_nodesets(io::stdin().lock(), format, options)
+            .context("failed to parse standard input");
+    }
+
+    let mut input = String::new();
     io::stdin()
         .lock()
-        .read_to_string(&mut s)
+        .read_to_string(&mut input)
         .context("failed to read standard input")?;
-    Ok(s)
+    if options.lenient {
+        input = input
+            .lines()
+            .map(|line| line.split_once('#').map_or(line, |(l, _)| l))
+            .join("\n");
+    }
+
+    NodeSet::parse_with(input, options).context("failed to parse standard input")
+}
+
+/// Builds a nodeset from sorted individual nodes separated by whitespace,
+/// given on the command line or read from `file` or from the standard input
+/// as with `nodeset_argument_with`
+fn sorted_nodes_argument(ns: Option<Vec<String>>, file: Option<PathBuf>) -> Result<NodeSet> {
+    use std::io::BufRead;
+
+    let reader: Box<dyn BufRead> = match (&file, ns) {
+        (Some(_), Some(_)) => eyre::bail!("sorted nodes must be read from a single input"),
+        (Some(path), None) => {
+            Box::new(BufReader::new(File::open(path).with_context(|| {
+                format!("failed to open '{}'", path.display())
+            })?))
+        }
+        (None, Some(v)) if v != vec!["-".to_string()] => {
+            return NodeSet::from_sorted_unchecked(v.iter().flat_map(|s| s.split_whitespace()))
+                .context("failed to parse nodes");
+        }
+        (None, _) => Box::new(io::stdin().lock()),
+    };
+
+    let mut error = None;
+    let nodes = reader
+        .lines()
+        .map_while(|line| line.map_err(|e| error = Some(e)).ok())
+        .flat_map(|line| {
+            line.split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        });
+    let nodeset = NodeSet::from_sorted_unchecked(nodes).context("failed to parse nodes")?;
+    if let Some(e) = error {
+        return Err(e).context("failed to read input");
+    }
+
+    Ok(nodeset)
+}
+
+/// Reads nodesets in the given format and returns their union
+fn read_nodesets(
+    reader: impl io::BufRead,
+    format: FileFormat,
+    options: &ParseOptions,
+) -> Result<NodeSet> {
+    use serde_yaml::Value;
+
+    if format == FileFormat::Lines {
+        return Ok(NodeSet::from_reader_with(reader, options)?);
+    }
+
+    // JSON documents are valid YAML
+    let Value::Sequence(items) = serde_yaml::from_reader(reader)? else {
+        eyre::bail!("expected an array of nodesets");
+    };
+
+    let mut res = NodeSet::default();
+    for (i, item) in items.iter().enumerate() {
+        let Value::String(item) = item else {
+            eyre::bail!("element {}: expected a string", i);
+        };
+        let nodeset = NodeSet::parse_with(item, options)
+            .with_context(|| format!("element {}: failed to parse '{}'", i, item))?;
+        res = res.union(&nodeset);
+    }
+
+    Ok(res)
+}
+
+/// Folds each line of `file`, or of the standard input if no file is
+/// provided, into its own nodeset
+///
+/// Empty lines are printed as empty lines so that the output stays aligned
+/// with the input.
+fn fold_per_line(
+    file: Option<PathBuf>,
+    options: &ParseOptions,
+    formatter: &impl NodeFormatter,
+    excluded: &NodeSet,
+    order: PrefixOrder,
+    color: bool,
+) -> Result<()> {
+    use std::io::BufRead;
+
+    let reader: Box<dyn BufRead> = match &file {
+        Some(path) => {
+            Box::new(BufReader::new(File::open(path).with_context(|| {
+                format!("failed to open '{}'", path.display())
+            })?))
+        }
+        None => Box::new(io::stdin().lock()),
+    };
+
+    let mut lock = io::stdout().lock();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.context("failed to read input")?;
+        let nodeset: NodeSet = NodeSet::parse_with(&line, options)
+            .with_context(|| format!("failed to parse line {}", i + 1))?;
+        print_folded(
+            &mut lock,
+            &nodeset.difference(excluded),
+            formatter,
+            order,
+            color,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes the folded `nodeset` on a line, highlighted if `color` is set
+fn print_folded(
+    out: &mut impl io::Write,
+    nodeset: &NodeSet,
+    formatter: &impl NodeFormatter,
+    order: PrefixOrder,
+    color: bool,
+) -> io::Result<()> {
+    let folded = nodeset.display_ordered(formatter, order).to_string();
+    if color {
+        writeln!(out, "{}", highlight(&folded, formatter))
+    } else {
+        writeln!(out, "{}", folded)
+    }
+}
+
+/// Parts of a folded nodeset which are highlighted differently
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Token {
+    /// Node name prefixes and suffixes
+    Name,
+    /// Range delimiters
+    Bracket,
+    /// Range bounds and single ids
+    Bound,
+    /// Separators between ranges and nodesets
+    Separator,
+}
+
+impl Token {
+    /// Returns the ANSI escape sequence starting the token
+    fn style(self) -> &'static str {
+        match self {
+            Token::Name => "\x1b[1m",
+            Token::Bracket => "\x1b[36m",
+            Token::Bound => "\x1b[32m",
+            Token::Separator => "",
+        }
+    }
+}
+
+/// Adds ANSI escape sequences to a folded nodeset to highlight its names,
+/// delimiters and range bounds
+fn highlight(folded: &str, formatter: &impl NodeFormatter) -> String {
+    const RESET: &str = "\x1b[0m";
+
+    let mut res = String::with_capacity(folded.len() * 2);
+    let mut current = Token::Separator;
+    let mut in_range = false;
+    let mut in_quote = false;
+    let mut escaped = false;
+
+    for c in folded.chars() {
+        let token = if in_quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_quote = false;
+            }
+            Token::Name
+        } else if in_range {
+            in_range = c != formatter.close();
+            match c {
+                c if c == formatter.close() => Token::Bracket,
+                c if c.is_ascii_digit() => Token::Bound,
+                _ => Token::Separator,
+            }
+        } else {
+            match c {
+                c if c == formatter.open() => {
+                    in_range = true;
+                    Token::Bracket
+                }
+                c if c == formatter.separator() => Token::Separator,
+                c if c.is_ascii_digit() => Token::Bound,
+                '"' => {
+                    in_quote = true;
+                    Token::Name
+                }
+                _ => Token::Name,
+            }
+        };
+
+        if token != current {
+            if current != Token::Separator {
+                res.push_str(RESET);
+            }
+            res.push_str(token.style());
+            current = token;
+        }
+        res.push(c);
+    }
+
+    if current != Token::Separator {
+        res.push_str(RESET);
+    }
+
+    res
+}
+
+fn parse_brackets(s: &str) -> std::result::Result<ParseOptions, String> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(open), Some(close), None) => {
+            let options = ParseOptions::new().delimiters(open, close);
+            options.validate().map_err(|e| e.to_string())?;
+            Ok(options)
+        }
+        _ => Err("expected an opening and a closing delimiter (ie '{}')".to_string()),
+    }
 }
@@ -0,0 +1,62 @@
+//! Checks that set operations writing into reusable buffers do not allocate
+//! in steady state
+
+use nodeset::NodeSet;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// This is the only test in this file so that allocations are not counted
+// concurrently with other tests
+#[test]
+fn test_op_into_steady_state() {
+    let up: NodeSet = "node[1-1000],gpu[1-100],login1".parse().unwrap();
+    let wanted: NodeSet = "node[500-1500],gpu[50-60],login1".parse().unwrap();
+    let mut out = NodeSet::new();
+
+    up.union_into(&wanted, &mut out);
+
+    let ops: [fn(&NodeSet, &NodeSet, &mut NodeSet); 4] = [
+        NodeSet::union_into,
+        NodeSet::difference_into,
+        NodeSet::intersection_into,
+        NodeSet::symmetric_difference_into,
+    ];
+
+    for op in ops {
+        op(&up, &wanted, &mut out);
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for _ in 0..10 {
+            op(&up, &wanted, &mut out);
+        }
+        let after = ALLOCATIONS.load(Ordering::Relaxed);
+
+        assert_eq!(after - before, 0);
+    }
+
+    up.intersection_into(&wanted, &mut out);
+    assert_eq!(out.to_string(), "gpu[50-60],login1,node[500-1000]");
+}
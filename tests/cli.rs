@@ -0,0 +1,51 @@
+//! Checks the output of the ns command
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs ns with `args` and `stdin` and returns its standard output
+fn ns(args: &[&str], stdin: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ns"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "ns {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_list() {
+    assert_eq!(ns(&["list", "n[1-3]"], ""), "n1 n2 n3\n");
+    assert_eq!(ns(&["list", "-s", ",", "n[1-2]", "m1"], ""), "m1,n1,n2\n");
+    assert_eq!(ns(&["list", "--limit", "1", "n[1-2]"], ""), "n1\n");
+    assert_eq!(
+        ns(&["list", "--ranges-expanded", "n[1-2,5]"], ""),
+        "n1 n2\n\nn5\n"
+    );
+}
+
+#[test]
+fn test_stdin() {
+    // The standard input is parsed as a single nodeset
+    assert_eq!(ns(&["fold"], "n[1-3]\n!n2\n"), "n[1,3]\n");
+    assert_eq!(ns(&["fold", "-"], "n1\nn2\n"), "n[1-2]\n");
+    assert_eq!(
+        ns(&["--lenient", "fold"], "n[1-3] # all\n!n2\n"),
+        "n[1,3]\n"
+    );
+}
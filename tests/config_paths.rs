@@ -0,0 +1,55 @@
+//! Checks where the default configuration is looked for
+
+use nodeset::{IdRangeList, Resolver};
+use std::path::Path;
+
+fn write_conf(dir: &Path, conf: &str) {
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(dir.join("groups.conf"), conf).unwrap();
+}
+
+#[test]
+fn test_config_paths() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let home = tmp_dir.path().join("home");
+    let xdg = tmp_dir.path().join("xdg");
+    let env = tmp_dir.path().join("env");
+
+    std::env::set_var("HOME", &home);
+    std::env::set_var("XDG_CONFIG_HOME", &xdg);
+    std::env::set_var("NODESET_CONF", &env);
+
+    assert_eq!(
+        Resolver::config_paths()[..4],
+        [
+            env.clone(),
+            xdg.join("nodeset"),
+            xdg.join("clustershell"),
+            home.join(".local/etc/clustershell"),
+        ]
+    );
+
+    write_conf(
+        &xdg.join("clustershell"),
+        "[Main]\ndefault: shared\n[shared]\nmap: echo shared\n[site]\nmap: echo clustershell\n",
+    );
+    write_conf(&xdg.join("nodeset"), "[site]\nmap: echo nodeset\n");
+    write_conf(&env, "[Main]\ndefault: site\n");
+
+    let resolver = Resolver::from_config().unwrap();
+    assert_eq!(resolver.default_source(), "site");
+    let resolve = |source| {
+        resolver
+            .resolve::<IdRangeList>(Some(source), "compute")
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(resolve("site"), "nodeset");
+    assert_eq!(resolve("shared"), "shared");
+
+    // Without NODESET_CONF, the default source of the farther layer is used
+    std::env::remove_var("NODESET_CONF");
+    assert_eq!(Resolver::config_paths()[0], xdg.join("nodeset"));
+    let resolver = Resolver::from_config().unwrap();
+    assert_eq!(resolver.default_source(), "shared");
+}
@@ -0,0 +1,101 @@
+//! Checks that the global resolver can be replaced and reloaded while it is
+//! used
+
+use nodeset::{ConfigurationError, IdRangeList, Resolver, ResolverBuilder, StaticSource};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+fn compute(resolver: &Resolver) -> String {
+    resolver
+        .resolve::<IdRangeList>(None, "compute")
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn test_global_resolver() {
+    let static_resolver = |nodes: &str| {
+        ResolverBuilder::new()
+            .add_source(
+                "local",
+                Box::new(StaticSource::from_iter([(
+                    "compute".to_string(),
+                    nodes.to_string(),
+                )])),
+            )
+            .set_default_source("local")
+            .build()
+            .unwrap()
+    };
+
+    Resolver::set_global(static_resolver("node[1-2]"));
+    let before = Resolver::get_global();
+    Resolver::set_global(static_resolver("node[3-4]"));
+
+    // Previous handles keep resolving with the resolver they were taken from
+    assert_eq!(compute(&before), "node[1-2]");
+    assert_eq!(compute(&Resolver::get_global()), "node[3-4]");
+    assert!(matches!(
+        Resolver::reload_global(),
+        Err(ConfigurationError::NotReloadable)
+    ));
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let config_dir = tmp_dir.path().join("clustershell");
+    std::fs::create_dir_all(config_dir.join("groups.d")).unwrap();
+    std::fs::write(
+        config_dir.join("groups.conf"),
+        "[Main]\ndefault: local\nautodir: $CFGDIR/groups.d\n",
+    )
+    .unwrap();
+    let write_groups = |nodes: &str| {
+        // Replace the file atomically so that reloads never read it partially
+        let tmp = config_dir.join("local.yaml.tmp");
+        std::fs::write(
+            &tmp,
+            format!("local:\n  compute: '{nodes}'\n  all: '{nodes}'\n"),
+        )
+        .unwrap();
+        std::fs::rename(tmp, config_dir.join("groups.d").join("local.yaml")).unwrap();
+    };
+    write_groups("node[1-2]");
+
+    std::env::set_var("XDG_CONFIG_HOME", tmp_dir.path());
+    Resolver::set_global(Resolver::from_config().unwrap());
+    assert_eq!(compute(&Resolver::get_global()), "node[1-2]");
+
+    write_groups("node[1-8]");
+    Resolver::reload_global().unwrap();
+    assert_eq!(compute(&Resolver::get_global()), "node[1-8]");
+
+    let watcher = Resolver::watch_global(Duration::from_millis(10));
+    write_groups("node[1-16]");
+    let start = Instant::now();
+    while compute(&Resolver::get_global()) != "node[1-16]" {
+        assert!(start.elapsed() < Duration::from_secs(10), "not reloaded");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    drop(watcher);
+
+    // Lookups running while the global resolver is reloaded see the groups
+    // from a single version of the files
+    let done = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let resolver = Resolver::get_global();
+                    let all = resolver.resolve::<IdRangeList>(None, "all").unwrap();
+                    assert_eq!(compute(&resolver), all.to_string());
+                }
+            });
+        }
+
+        for i in 1..50 {
+            write_groups(&format!("node[1-{i}]"));
+            Resolver::reload_global().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+    });
+    assert_eq!(compute(&Resolver::get_global()), "node[1-49]");
+}
@@ -0,0 +1,139 @@
+//! Checks that group sources implemented outside of the crate can be used by
+//! resolvers
+
+use nodeset::{GroupSource, NodeSet, NodeSetParseError, ResolverBuilder};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An inventory mapping roles to nodes, which counts its lookups and fails
+/// once it is taken offline
+#[derive(Debug, Default)]
+struct Inventory {
+    roles: BTreeMap<String, String>,
+    lookups: AtomicUsize,
+    offline: bool,
+}
+
+impl Inventory {
+    fn new(roles: &[(&str, &str)]) -> Self {
+        Self {
+            roles: roles
+                .iter()
+                .map(|(role, nodes)| (role.to_string(), nodes.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn check_online(&self) -> Result<(), NodeSetParseError> {
+        if self.offline {
+            return Err(NodeSetParseError::Command(std::io::Error::new(
+                ErrorKind::ConnectionRefused,
+                "inventory is offline",
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl GroupSource for Inventory {
+    fn map(&self, group: &str) -> Result<Option<String>, NodeSetParseError> {
+        self.check_online()?;
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+
+        Ok(self.roles.get(group).cloned())
+    }
+
+    fn list(&self) -> Result<Vec<String>, NodeSetParseError> {
+        self.check_online()?;
+
+        Ok(self.roles.keys().cloned().collect())
+    }
+
+    fn reverse(&self, node: &str) -> Result<Option<String>, NodeSetParseError> {
+        self.check_online()?;
+        let node = node.parse()?;
+
+        let mut roles = vec![];
+        for (role, nodes) in &self.roles {
+            if nodes.parse::<NodeSet>()?.contains_node(&node) {
+                roles.push(role.as_str());
+            }
+        }
+
+        Ok(Some(roles.join(",")))
+    }
+}
+
+#[test]
+fn test_custom_group_source() {
+    let resolver = ResolverBuilder::new()
+        .add_source(
+            "inventory",
+            Box::new(Inventory::new(&[
+                ("compute", "node[1-8]"),
+                ("gpu", "node[7-8]"),
+                ("storage", "oss[1-2]"),
+            ])),
+        )
+        .add_source(
+            "offline",
+            Box::new(Inventory {
+                offline: true,
+                ..Inventory::new(&[("compute", "node1")])
+            }),
+        )
+        .set_default_source("inventory")
+        .build()
+        .unwrap();
+
+    let gpu: NodeSet = resolver.resolve(None, "gpu").unwrap();
+    assert_eq!(gpu.to_string(), "node[7-8]");
+
+    let unknown: NodeSet = resolver.resolve(None, "login").unwrap();
+    assert!(unknown.is_empty());
+
+    let groups: NodeSet = resolver.list_groups(None);
+    assert_eq!(groups.to_string(), "compute,gpu,storage");
+
+    // Without an `all` method, the resolver resolves each group
+    let all: NodeSet = resolver.all_nodes(None).unwrap();
+    assert_eq!(all.to_string(), "node[1-8],oss[1-2]");
+
+    let roles: NodeSet = resolver.node_groups(None, "node8").unwrap();
+    assert_eq!(roles.to_string(), "compute,gpu");
+    assert_eq!(
+        resolver
+            .groups_of::<nodeset::IdRangeList>(None, "oss2")
+            .unwrap(),
+        vec!["storage"]
+    );
+
+    // Errors of the backend are returned to the caller
+    match resolver.resolve::<nodeset::IdRangeList>(Some("offline"), "compute") {
+        Err(NodeSetParseError::Command(e)) => assert_eq!(e.kind(), ErrorKind::ConnectionRefused),
+        e => panic!("Expected Command error, got {e:?}"),
+    }
+}
+
+#[cfg(feature = "groups")]
+#[test]
+fn test_custom_group_source_parse() {
+    let resolver = ResolverBuilder::new()
+        .add_source(
+            "inventory",
+            Box::new(Inventory::new(&[
+                ("compute", "node[1-8]"),
+                ("gpu", "node[7-8]"),
+            ])),
+        )
+        .set_default_source("inventory")
+        .build()
+        .unwrap();
+
+    let parser = nodeset::Parser::with_resolver(&resolver, None);
+    let nodes: NodeSet = parser.parse("@compute!@inventory:gpu").unwrap();
+    assert_eq!(nodes.to_string(), "node[1-6]");
+}